@@ -0,0 +1,418 @@
+use wgpu::util::DeviceExt;
+
+use crate::ocean::shaders::{self, Shader};
+use crate::ocean::OceanError;
+use crate::tonemap::HDR_FORMAT;
+
+pub const BLOOM_SHADER: Shader = Shader::new("src/bloom.wgsl", include_str!("bloom.wgsl"));
+
+// Levels of the downsampling chain, the first one being half the frame size.
+pub const MAX_BLOOM_LEVELS: u32 = 8;
+
+// Light spilling around whatever is much brighter than its surroundings, the
+// sun and its glints on the water first.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Bloom {
+  pub enabled: bool,
+  // Linear brightness, before exposure, from which pixels bloom.
+  pub threshold: f32,
+  // Below the threshold, over which blooming fades in.
+  pub knee: f32,
+  pub intensity: f32,
+  // How far the bloom spreads, each level doubling it. Limited to
+  // `MAX_BLOOM_LEVELS` and by the frame size.
+  pub levels: u32,
+  // How much brighter the bloom gets where the lens is dirty, 0 for a clean
+  // lens.
+  pub lens_dirt: f32,
+}
+
+impl Default for Bloom {
+  fn default() -> Bloom {
+    Bloom {
+      enabled: true,
+      threshold: 2.0,
+      knee: 1.0,
+      intensity: 0.1,
+      levels: 6,
+      lens_dirt: 0.0,
+    }
+  }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BloomUniform {
+  threshold: f32,
+  knee: f32,
+  intensity: f32,
+  lens_dirt: f32,
+}
+
+impl From<Bloom> for BloomUniform {
+  fn from(bloom: Bloom) -> Self {
+    BloomUniform {
+      threshold: bloom.threshold,
+      knee: bloom.knee.max(0.0),
+      intensity: bloom.intensity,
+      lens_dirt: bloom.lens_dirt,
+    }
+  }
+}
+
+// Smudges and specks of dust, the lens dirt used until another one is set.
+pub fn lens_dirt(size: u32) -> image::RgbaImage {
+  use rand::prelude::*;
+
+  let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+  let mut dirt = vec![0.0f32; (size * size) as usize];
+  // A few wide smudges, then many small specks.
+  let spots = (0..12)
+    .map(|_| (0.08..0.25, 0.05..0.15))
+    .chain((0..150).map(|_| (0.005..0.04, 0.1..0.6)));
+
+  for (radius, strength) in spots {
+    let radius = rng.gen_range(radius) * size as f32;
+    let strength: f32 = rng.gen_range(strength);
+    let center = [0, 1].map(|_| rng.gen_range(0.0..size as f32));
+
+    let min = |c: f32| (c - radius).max(0.0) as u32;
+    let max = |c: f32| ((c + radius).ceil() as u32).min(size);
+    for y in min(center[1])..max(center[1]) {
+      for x in min(center[0])..max(center[0]) {
+        let [dx, dy] = [x as f32 - center[0], y as f32 - center[1]];
+        let falloff = (1.0 - (dx * dx + dy * dy).sqrt() / radius).max(0.0);
+        dirt[(y * size + x) as usize] += strength * falloff * falloff;
+      }
+    }
+  }
+
+  image::RgbaImage::from_fn(size, size, |x, y| {
+    let value = (dirt[(y * size + x) as usize].min(1.0) * 255.0) as u8;
+    image::Rgba([value, value, value, 255])
+  })
+}
+
+// Blurs the bright parts of the HDR target down a chain of ever smaller
+// levels, gathers them back up and adds them onto the target before it is
+// tonemapped.
+pub(crate) struct BloomPass {
+  settings: Bloom,
+  uniform_buffer: wgpu::Buffer,
+  sampler: wgpu::Sampler,
+  lens_dirt_view: wgpu::TextureView,
+
+  bind_group_layout: wgpu::BindGroupLayout,
+  pipeline_layout: wgpu::PipelineLayout,
+  prefilter_pipeline: wgpu::RenderPipeline,
+  downsample_pipeline: wgpu::RenderPipeline,
+  upsample_pipeline: wgpu::RenderPipeline,
+  composite_pipeline: wgpu::RenderPipeline,
+
+  level_views: Vec<wgpu::TextureView>,
+  // Reading the HDR target, then each level.
+  bind_groups: Vec<wgpu::BindGroup>,
+}
+
+impl BloomPass {
+  pub fn new(
+    device: &wgpu::Device,
+    size: winit::dpi::PhysicalSize<u32>,
+    hdr_view: &wgpu::TextureView,
+    lens_dirt_view: wgpu::TextureView,
+    settings: Bloom,
+  ) -> BloomPass {
+    let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Bloom Buffer"),
+      contents: bytemuck::cast_slice(&[BloomUniform::from(settings)]),
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    });
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+      label: Some("Bloom sampler"),
+      address_mode_u: wgpu::AddressMode::ClampToEdge,
+      address_mode_v: wgpu::AddressMode::ClampToEdge,
+      mag_filter: wgpu::FilterMode::Linear,
+      min_filter: wgpu::FilterMode::Linear,
+      mipmap_filter: wgpu::FilterMode::Linear,
+      ..Default::default()
+    });
+
+    let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::FRAGMENT,
+      ty: wgpu::BindingType::Texture {
+        view_dimension: wgpu::TextureViewDimension::D2,
+        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        multisampled: false,
+      },
+      count: None,
+    };
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("bloom_bind_group_layout"),
+      entries: &[
+        texture_entry(0),
+        wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 2,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        },
+        texture_entry(3),
+      ],
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Bloom Pipeline Layout"),
+      bind_group_layouts: &[&bind_group_layout],
+      push_constant_ranges: &[],
+    });
+
+    let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+      label: Some("Bloom shader"),
+      source: wgpu::ShaderSource::Wgsl(BLOOM_SHADER.source()),
+    });
+    let [prefilter_pipeline, downsample_pipeline, upsample_pipeline, composite_pipeline] =
+      Self::create_pipelines(device, &pipeline_layout, &shader);
+
+    let mut bloom = BloomPass {
+      settings,
+      uniform_buffer,
+      sampler,
+      lens_dirt_view,
+      bind_group_layout,
+      pipeline_layout,
+      prefilter_pipeline,
+      downsample_pipeline,
+      upsample_pipeline,
+      composite_pipeline,
+      level_views: Vec::new(),
+      bind_groups: Vec::new(),
+    };
+    bloom.resize(device, size, hdr_view);
+    bloom
+  }
+
+  pub fn resize(
+    &mut self,
+    device: &wgpu::Device,
+    size: winit::dpi::PhysicalSize<u32>,
+    hdr_view: &wgpu::TextureView,
+  ) {
+    let (width, height) = ((size.width / 2).max(1), (size.height / 2).max(1));
+    let level_count = (32 - width.min(height).leading_zeros()).min(MAX_BLOOM_LEVELS);
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some("Bloom levels"),
+      size: wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+      },
+      mip_level_count: level_count,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: HDR_FORMAT,
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+
+    self.level_views = (0..level_count)
+      .map(|level| {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+          base_mip_level: level,
+          mip_level_count: std::num::NonZeroU32::new(1),
+          ..Default::default()
+        })
+      })
+      .collect();
+    self.write_bind_groups(device, hdr_view);
+  }
+
+  pub fn settings(&self) -> Bloom {
+    self.settings
+  }
+
+  pub fn set_settings(&mut self, queue: &wgpu::Queue, settings: Bloom) {
+    self.settings = settings;
+    queue.write_buffer(
+      &self.uniform_buffer,
+      0,
+      bytemuck::cast_slice(&[BloomUniform::from(settings)]),
+    );
+  }
+
+  pub fn set_lens_dirt(
+    &mut self,
+    device: &wgpu::Device,
+    lens_dirt_view: wgpu::TextureView,
+    hdr_view: &wgpu::TextureView,
+  ) {
+    self.lens_dirt_view = lens_dirt_view;
+    self.write_bind_groups(device, hdr_view);
+  }
+
+  pub async fn reload(
+    &mut self,
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+  ) -> Result<(), OceanError> {
+    let [prefilter_pipeline, downsample_pipeline, upsample_pipeline, composite_pipeline] =
+      shaders::try_create(device, || {
+        Self::create_pipelines(device, &self.pipeline_layout, shader)
+      })
+      .await?;
+    self.prefilter_pipeline = prefilter_pipeline;
+    self.downsample_pipeline = downsample_pipeline;
+    self.upsample_pipeline = upsample_pipeline;
+    self.composite_pipeline = composite_pipeline;
+    Ok(())
+  }
+
+  // Adds the bloom onto `hdr_view`, once everything is drawn into it.
+  pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, hdr_view: &wgpu::TextureView) {
+    if !self.settings.enabled {
+      return;
+    }
+    let levels = (self.settings.levels as usize).clamp(1, self.level_views.len());
+
+    let mut blit = |pipeline, source: usize, target: &wgpu::TextureView, load| {
+      let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Bloom Pass"),
+        color_attachments: &[wgpu::RenderPassColorAttachment {
+          view: target,
+          resolve_target: None,
+          ops: wgpu::Operations { load, store: true },
+        }],
+        depth_stencil_attachment: None,
+      });
+      render_pass.set_pipeline(pipeline);
+      render_pass.set_bind_group(0, &self.bind_groups[source], &[]);
+      render_pass.draw(0..3, 0..1);
+    };
+    let clear = wgpu::LoadOp::Clear(wgpu::Color::BLACK);
+
+    blit(&self.prefilter_pipeline, 0, &self.level_views[0], clear);
+    for level in 1..levels {
+      blit(
+        &self.downsample_pipeline,
+        level,
+        &self.level_views[level],
+        clear,
+      );
+    }
+    for level in (1..levels).rev() {
+      blit(
+        &self.upsample_pipeline,
+        level + 1,
+        &self.level_views[level - 1],
+        wgpu::LoadOp::Load,
+      );
+    }
+    blit(&self.composite_pipeline, 1, hdr_view, wgpu::LoadOp::Load);
+  }
+
+  fn write_bind_groups(&mut self, device: &wgpu::Device, hdr_view: &wgpu::TextureView) {
+    self.bind_groups = std::iter::once(hdr_view)
+      .chain(&self.level_views)
+      .map(|source| {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+          label: Some("Bloom bind group"),
+          layout: &self.bind_group_layout,
+          entries: &[
+            wgpu::BindGroupEntry {
+              binding: 0,
+              resource: wgpu::BindingResource::TextureView(source),
+            },
+            wgpu::BindGroupEntry {
+              binding: 1,
+              resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+            wgpu::BindGroupEntry {
+              binding: 2,
+              resource: self.uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+              binding: 3,
+              resource: wgpu::BindingResource::TextureView(&self.lens_dirt_view),
+            },
+          ],
+        })
+      })
+      .collect();
+  }
+
+  // Prefilter, downsample, upsample and composite pipelines.
+  fn create_pipelines(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+  ) -> [wgpu::RenderPipeline; 4] {
+    // The alpha of the target is left alone.
+    let additive = wgpu::BlendState {
+      color: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+      },
+      alpha: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::Zero,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+      },
+    };
+    let create_pipeline = |entry_point, blend| {
+      device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Bloom Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+          module: shader,
+          entry_point: "vs_main",
+          buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+          module: shader,
+          entry_point,
+          targets: &[wgpu::ColorTargetState {
+            format: HDR_FORMAT,
+            blend,
+            write_mask: wgpu::ColorWrites::ALL,
+          }],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+      })
+    };
+
+    [
+      create_pipeline("fs_prefilter", None),
+      create_pipeline("fs_downsample", None),
+      create_pipeline("fs_upsample", Some(additive)),
+      create_pipeline("fs_composite", Some(additive)),
+    ]
+  }
+}
+
+#[test]
+fn test_bloom() {
+  // Matches the layout of `BloomUniform` in the shader.
+  assert_eq!(std::mem::size_of::<BloomUniform>(), 16);
+
+  let dirt = lens_dirt(64);
+  assert_eq!(dirt.dimensions(), (64, 64));
+  // Some of the lens is clean and some of it dirty, the same every time.
+  assert!(dirt.pixels().any(|pixel| pixel[0] == 0));
+  assert!(dirt.pixels().any(|pixel| pixel[0] > 64));
+  assert_eq!(dirt, lens_dirt(64));
+}
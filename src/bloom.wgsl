@@ -0,0 +1,113 @@
+// Dual filter bloom (Bjørge, "Bandwidth-Efficient Rendering", SIGGRAPH 2015),
+// see `BloomPass`. Each pass reads the level above or below the one it draws.
+
+struct BloomUniform {
+    threshold: f32;
+    knee: f32;
+    intensity: f32;
+    lens_dirt: f32;
+};
+
+[[group(0), binding(0)]]
+var t_source: texture_2d<f32>;
+
+[[group(0), binding(1)]]
+var s_source: sampler;
+
+[[group(0), binding(2)]]
+var<uniform> bloom: BloomUniform;
+
+[[group(0), binding(3)]]
+var t_lens_dirt: texture_2d<f32>;
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+// A triangle covering the viewport.
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.uv = uv;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    return out;
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// Keeps what is brighter than the threshold, easing in over the knee.
+fn bright_part(color: vec3<f32>) -> vec3<f32> {
+    let brightness = max(color.r, max(color.g, color.b));
+    let knee = clamp(brightness - bloom.threshold + bloom.knee, 0.0, 2.0 * bloom.knee);
+    let soft = knee * knee / (4.0 * bloom.knee + 1e-4);
+    return color * max(soft, brightness - bloom.threshold) / max(brightness, 1e-4);
+}
+
+fn sample_source(uv: vec2<f32>) -> vec3<f32> {
+    return textureSample(t_source, s_source, uv).rgb;
+}
+
+[[stage(fragment)]]
+fn fs_downsample(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_source));
+    let sum = sample_source(in.uv) * 4.0
+        + sample_source(in.uv + vec2<f32>(-texel.x, -texel.y))
+        + sample_source(in.uv + vec2<f32>(texel.x, -texel.y))
+        + sample_source(in.uv + vec2<f32>(-texel.x, texel.y))
+        + sample_source(in.uv + vec2<f32>(texel.x, texel.y));
+    return vec4<f32>(sum / 8.0, 1.0);
+}
+
+// The first downsample out of the frame. Samples are weighted down by their
+// brightness so that a single glinting pixel does not flicker as a large
+// blob.
+[[stage(fragment)]]
+fn fs_prefilter(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_source));
+    var sum = vec3<f32>(0.0);
+    var weights = 0.0;
+    for (var i = 0; i < 5; i = i + 1) {
+        var offset = vec2<f32>(0.0);
+        if (i > 0) {
+            let corner = vec2<f32>(f32(i & 1), f32((i - 1) / 2));
+            offset = (corner * 2.0 - 1.0) * texel;
+        }
+        let color = bright_part(sample_source(in.uv + offset));
+        let weight = select(1.0, 4.0, i == 0) / (1.0 + luminance(color));
+        sum = sum + color * weight;
+        weights = weights + weight;
+    }
+    return vec4<f32>(sum / weights, 1.0);
+}
+
+fn upsample(uv: vec2<f32>) -> vec3<f32> {
+    let half_texel = 0.5 / vec2<f32>(textureDimensions(t_source));
+    let edges = sample_source(uv + vec2<f32>(-2.0 * half_texel.x, 0.0))
+        + sample_source(uv + vec2<f32>(2.0 * half_texel.x, 0.0))
+        + sample_source(uv + vec2<f32>(0.0, -2.0 * half_texel.y))
+        + sample_source(uv + vec2<f32>(0.0, 2.0 * half_texel.y));
+    let corners = sample_source(uv + vec2<f32>(-half_texel.x, -half_texel.y))
+        + sample_source(uv + vec2<f32>(half_texel.x, -half_texel.y))
+        + sample_source(uv + vec2<f32>(-half_texel.x, half_texel.y))
+        + sample_source(uv + vec2<f32>(half_texel.x, half_texel.y));
+    return (edges + corners * 2.0) / 12.0;
+}
+
+// Added onto the level it draws, so each level gathers the ones below it.
+[[stage(fragment)]]
+fn fs_upsample(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(upsample(in.uv), 1.0);
+}
+
+// Added onto the frame, through the dirt on the lens.
+[[stage(fragment)]]
+fn fs_composite(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let dirt = textureSample(t_lens_dirt, s_source, in.uv).rgb;
+    let glow = upsample(in.uv) * bloom.intensity * (1.0 + dirt * bloom.lens_dirt);
+    return vec4<f32>(glow, 1.0);
+}
@@ -14,6 +14,7 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
 const SAFE_FRAC_PI_2: f32 = FRAC_PI_2 - 0.0001;
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Camera {
   pub position: Point3<f32>,
   yaw: Rad<f32>,
@@ -42,9 +43,54 @@ impl Camera {
   }
 }
 
+// Initial camera pose, lens and controls. Angles are in degrees.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct CameraConfig {
+  pub position: [f32; 3],
+  pub yaw: f32,
+  pub pitch: f32,
+  pub fov: f32,
+  pub near: f32,
+  pub far: f32,
+  pub speed: f32,
+  pub sensitivity: f32,
+}
+
+impl Default for CameraConfig {
+  fn default() -> CameraConfig {
+    CameraConfig {
+      position: [0.0, 40.0, 0.0],
+      yaw: 160.0,
+      pitch: -20.0,
+      fov: 45.0,
+      near: 1.0,
+      far: 5000.0,
+      speed: 20.0,
+      sensitivity: 1.0,
+    }
+  }
+}
+
+impl CameraConfig {
+  pub fn camera(&self) -> Camera {
+    Camera::new(self.position, Deg(self.yaw), Deg(self.pitch))
+  }
+
+  pub fn projection(&self, width: u32, height: u32) -> Projection {
+    Projection::new(width, height, Deg(self.fov), self.near, self.far)
+  }
+
+  pub fn controller(&self) -> CameraController {
+    CameraController::new(self.speed, self.sensitivity)
+  }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Projection {
   aspect: f32,
-  fovy: Rad<f32>,
+  pub fovy: Rad<f32>,
   znear: f32,
   zfar: f32,
 }
@@ -79,8 +125,8 @@ pub struct CameraController {
   rotate_horizontal: f32,
   rotate_vertical: f32,
   scroll: f32,
-  speed: f32,
-  sensitivity: f32,
+  pub speed: f32,
+  pub sensitivity: f32,
 }
 
 impl CameraController {
@@ -202,3 +248,19 @@ impl CameraController {
     }
   }
 }
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_camera_round_trip() {
+  use crate::ocean::Versioned;
+
+  let camera = Versioned::new(CameraConfig::default().camera());
+  let json = serde_json::to_string(&camera).unwrap();
+  let parsed: Versioned<Camera> = serde_json::from_str(&json).unwrap();
+  assert_eq!(parsed.parameters.calc_matrix(), camera.parameters.calc_matrix());
+
+  let projection = Versioned::new(CameraConfig::default().projection(1280, 800));
+  let ron = ron::to_string(&projection).unwrap();
+  let parsed: Versioned<Projection> = ron::from_str(&ron).unwrap();
+  assert_eq!(ron::to_string(&parsed).unwrap(), ron);
+}
@@ -0,0 +1,325 @@
+use std::path::PathBuf;
+
+use crate::frame_export::ExportOptions;
+use crate::ocean::OceanCascadeParameters;
+use crate::scene_config::{self, ConfigError, SceneConfig};
+
+pub const USAGE: &str = "\
+usage: wgpu_ocean [--config <file>] [options] [--export <dir> [export options]]
+
+Without --export the ocean is shown in a window. With --export, frames are
+rendered offscreen and written to <dir> as frame_0000.png, frame_0001.png, ...
+
+The scene file is TOML, or RON when it ends in .ron and serde is enabled, with
+[window], [ocean], [camera], [lighting], [fog], [water], [underwater],
+[rendering], [tonemapping] and [bloom] sections.
+Options given on the command line override it. While the window is open,
+changes to the file are applied as they are saved, and F1 shows an overlay to
+tweak the scene and read frame timings.
+
+options:
+  --config <file>                scene file to start from
+  --foam <file>                  image whose brighter parts foam first where
+                                 waves break, instead of the built-in one
+  --lens-dirt <file>             image of the dirt bloom shows on the lens,
+                                 instead of the built-in one
+  --size <width>x<height>        window or frame size in pixels (default 1280x800)
+  --msaa <samples>               1 or 4 (default 4)
+  --vsync <on|off>               wait for vertical blank (default on)
+  --seed <n>                     make the generated sea reproducible
+  --preset <name>                calm, moderate, rough or storm
+  --backend <name>               auto, fft or gerstner (default auto)
+  --camera <x,y,z,yaw,pitch>     camera position and angles in degrees
+                                 (default 0,40,0,160,-20)
+  --fov <degrees>                vertical field of view (default 45)
+
+export options:
+  --frames <n>                   number of frames to render (default 60)
+  --dt <seconds>                 simulation time between frames (default 1/30)
+  --start <seconds>              simulation time of the first frame (default 0)
+  --exr                          also write the displacement and derivatives
+                                 maps of every cascade as 32-bit float EXR
+  --fallback-adapter             render on a software adapter if available";
+
+#[derive(Debug)]
+pub enum ArgsError {
+  // Malformed command line, reported together with the usage.
+  Usage(String),
+  Config(ConfigError),
+}
+
+impl std::fmt::Display for ArgsError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ArgsError::Usage(description) => write!(f, "{}", description),
+      ArgsError::Config(error) => write!(f, "{}", error),
+    }
+  }
+}
+
+impl std::error::Error for ArgsError {}
+
+impl From<String> for ArgsError {
+  fn from(description: String) -> Self {
+    ArgsError::Usage(description)
+  }
+}
+
+impl From<&str> for ArgsError {
+  fn from(description: &str) -> Self {
+    ArgsError::Usage(String::from(description))
+  }
+}
+
+// Options that override the scene file, all of which take a value.
+const SCENE_OPTIONS: [&str; 8] = [
+  "--size",
+  "--msaa",
+  "--vsync",
+  "--seed",
+  "--preset",
+  "--backend",
+  "--camera",
+  "--fov",
+];
+
+#[derive(Debug)]
+pub struct Options {
+  pub scene: SceneConfig,
+  pub config_path: Option<PathBuf>,
+  pub foam_texture: Option<PathBuf>,
+  pub lens_dirt_texture: Option<PathBuf>,
+  // The scene options given on the command line as option/value pairs, to be
+  // applied again when the scene file is reloaded.
+  pub overrides: Vec<String>,
+  // Set when frames should be exported instead of opening a window.
+  pub export: Option<ExportOptions>,
+}
+
+impl Options {
+  pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Options, ArgsError> {
+    let mut config_path = None;
+    let mut foam_texture = None;
+    let mut lens_dirt_texture = None;
+    let mut overrides = Vec::new();
+    let mut directory = None;
+    let mut export = ExportOptions::default();
+    let mut export_flags = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+      let mut value = || {
+        args
+          .next()
+          .ok_or_else(|| format!("missing value for {}", arg))
+      };
+
+      match arg.as_str() {
+        "--config" => config_path = Some(PathBuf::from(value()?)),
+        "--foam" => foam_texture = Some(PathBuf::from(value()?)),
+        "--lens-dirt" => lens_dirt_texture = Some(PathBuf::from(value()?)),
+        "--export" => directory = Some(PathBuf::from(value()?)),
+        "--frames" => {
+          export.frames = parse_number(&arg, &value()?)?;
+          if export.frames == 0 {
+            return Err("--frames must be at least 1".into());
+          }
+        }
+        "--dt" => {
+          export.dt = parse_number(&arg, &value()?)?;
+          if export.dt.partial_cmp(&0.0) != Some(std::cmp::Ordering::Greater) {
+            return Err("--dt must be greater than 0".into());
+          }
+        }
+        "--start" => export.start_time = parse_number(&arg, &value()?)?,
+        "--exr" => export.exr = true,
+        "--fallback-adapter" => export.force_fallback_adapter = true,
+        option if SCENE_OPTIONS.contains(&option) => {
+          let value = value()?;
+          overrides.push(arg);
+          overrides.push(value);
+          continue;
+        }
+        _ => return Err(format!("unknown argument: {}", arg).into()),
+      }
+
+      if !["--config", "--foam", "--lens-dirt", "--export"].contains(&arg.as_str()) {
+        export_flags.push(arg);
+      }
+    }
+
+    // The scene file is loaded first whatever its position, so that every
+    // other option overrides it.
+    let mut scene = match &config_path {
+      Some(path) => SceneConfig::load(path).map_err(ArgsError::Config)?,
+      None => SceneConfig::default(),
+    };
+    apply_overrides(&mut scene, &overrides)?;
+
+    let export = match directory {
+      Some(directory) => Some(ExportOptions {
+        directory,
+        ..export
+      }),
+      None => match export_flags.first() {
+        Some(flag) => return Err(format!("{} requires --export <dir>", flag).into()),
+        None => None,
+      },
+    };
+
+    Ok(Options {
+      scene,
+      config_path,
+      foam_texture,
+      lens_dirt_texture,
+      overrides,
+      export,
+    })
+  }
+}
+
+// Applies the option/value pairs collected in `Options::overrides`.
+pub fn apply_overrides(scene: &mut SceneConfig, overrides: &[String]) -> Result<(), String> {
+  let renderer = &mut scene.renderer;
+
+  for pair in overrides.chunks_exact(2) {
+    let (arg, value) = (pair[0].as_str(), pair[1].as_str());
+
+    match arg {
+      "--size" => {
+        let (width, height) = value
+          .split_once('x')
+          .ok_or_else(|| format!("invalid value for --size: {}", value))?;
+        scene.width = parse_number(arg, width)?;
+        scene.height = parse_number(arg, height)?;
+        if scene.width == 0 || scene.height == 0 {
+          return Err(String::from("--size must be at least 1x1"));
+        }
+      }
+      "--msaa" => renderer.sample_count = parse_number(arg, value)?,
+      "--vsync" => {
+        renderer.vsync = match value {
+          "on" => true,
+          "off" => false,
+          _ => return Err(format!("invalid value for --vsync: {}", value)),
+        }
+      }
+      "--seed" => renderer.seed = Some(parse_number(arg, value)?),
+      // A preset only picks the sea state, the simulation size is kept.
+      "--preset" => {
+        renderer.ocean = OceanCascadeParameters {
+          size: renderer.ocean.size,
+          ..scene_config::parse_preset(value)?
+        }
+      }
+      "--backend" => renderer.ocean_backend = scene_config::parse_backend(value)?,
+      "--camera" => {
+        let values = value
+          .split(',')
+          .map(|value| parse_number(arg, value))
+          .collect::<Result<Vec<f32>, String>>()?;
+        let [x, y, z, yaw, pitch]: [f32; 5] = values
+          .try_into()
+          .map_err(|_| format!("--camera takes 5 values, got {}", value))?;
+        renderer.camera.position = [x, y, z];
+        renderer.camera.yaw = yaw;
+        renderer.camera.pitch = pitch;
+      }
+      "--fov" => renderer.camera.fov = parse_number(arg, value)?,
+      _ => unreachable!("{} is not a scene option", arg),
+    }
+  }
+
+  if let Some(backend) = renderer.ocean_backend {
+    backend.validate().map_err(|e| e.to_string())?;
+  }
+  renderer.ocean.validate().map_err(|e| e.to_string())?;
+  Ok(())
+}
+
+fn parse_number<T: std::str::FromStr>(arg: &str, value: &str) -> Result<T, String> {
+  value
+    .trim()
+    .parse()
+    .map_err(|_| format!("invalid value for {}: {}", arg, value))
+}
+
+#[test]
+fn test_options_from_args() {
+  let args = |line: &str| {
+    line
+      .split_whitespace()
+      .map(String::from)
+      .collect::<Vec<_>>()
+  };
+
+  let options = Options::from_args(args("")).unwrap();
+  assert!(options.export.is_none());
+  assert!(options.foam_texture.is_none());
+  assert_eq!((options.scene.width, options.scene.height), (1280, 800));
+
+  let options = Options::from_args(args(
+    "--export out --frames 10 --dt 0.5 --size 640x480 --camera 1,2,3,90,-10 --exr --foam foam.png --lens-dirt dirt.png",
+  ))
+  .unwrap();
+  assert_eq!(options.foam_texture, Some(PathBuf::from("foam.png")));
+  assert_eq!(options.lens_dirt_texture, Some(PathBuf::from("dirt.png")));
+  let export = options.export.unwrap();
+  assert_eq!(export.directory, PathBuf::from("out"));
+  assert_eq!(export.frames, 10);
+  assert_eq!(export.dt, 0.5);
+  assert!(export.exr);
+  assert!(!export.force_fallback_adapter);
+  let scene = options.scene;
+  assert_eq!((scene.width, scene.height), (640, 480));
+  assert_eq!(scene.renderer.camera.position, [1.0, 2.0, 3.0]);
+  assert_eq!(
+    (scene.renderer.camera.yaw, scene.renderer.camera.pitch),
+    (90.0, -10.0)
+  );
+
+  let scene = Options::from_args(args(
+    "--msaa 1 --vsync off --seed 3 --preset calm --backend fft --fov 60",
+  ))
+  .unwrap()
+  .scene;
+  assert_eq!(scene.renderer.sample_count, 1);
+  assert!(!scene.renderer.vsync);
+  assert_eq!(scene.renderer.seed, Some(3));
+  assert_eq!(scene.renderer.ocean.wind_speed, 3.0);
+  assert_eq!(
+    scene.renderer.ocean_backend,
+    Some(crate::ocean::OceanBackend::Fft)
+  );
+  assert_eq!(scene.renderer.camera.fov, 60.0);
+
+  assert!(Options::from_args(args("--frames 10")).is_err());
+  for export in ["--frames 0", "--dt 0", "--dt -0.5", "--dt NaN"] {
+    let line = format!("--export out {}", export);
+    assert!(matches!(
+      Options::from_args(args(&line)),
+      Err(ArgsError::Usage(_))
+    ));
+  }
+  assert!(Options::from_args(args("--export out --camera 1,2,3")).is_err());
+  assert!(Options::from_args(args("--export out --size 640")).is_err());
+  assert!(Options::from_args(args("--export out --frames")).is_err());
+  assert!(Options::from_args(args("--export out --bogus")).is_err());
+  assert!(Options::from_args(args("--vsync maybe")).is_err());
+  assert!(Options::from_args(args("--preset glassy")).is_err());
+  assert!(Options::from_args(args("--config")).is_err());
+  assert!(matches!(
+    Options::from_args(args("--config missing.toml")),
+    Err(ArgsError::Config(_))
+  ));
+
+  let mut scene = SceneConfig::default();
+  scene.renderer.ocean.size = 512;
+  apply_overrides(&mut scene, &args("--preset storm")).unwrap();
+  assert_eq!(scene.renderer.ocean.size, 512);
+  assert_eq!(scene.renderer.ocean.wind_speed, 28.0);
+
+  scene.renderer.ocean_backend = Some(crate::ocean::OceanBackend::Gerstner { wave_count: 0 });
+  assert!(apply_overrides(&mut scene, &args("--fov 60")).is_err());
+  assert!(apply_overrides(&mut scene, &args("--backend gerstner")).is_ok());
+}
@@ -0,0 +1,219 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::cli;
+use crate::ocean::OceanCascadeParameters;
+use crate::renderer::Renderer;
+use crate::scene_config::SceneConfig;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+// Seconds over which a reloaded sea state blends in.
+const OCEAN_TRANSITION: f32 = 2.0;
+
+// Re-reads the scene file every `POLL_INTERVAL` and hands out the new config
+// when its contents changed and parse. Files that fail to parse are logged and
+// otherwise ignored, the last valid config stays current.
+pub struct ConfigWatcher {
+  path: PathBuf,
+  // Command-line options, which keep taking precedence over the file.
+  overrides: Vec<String>,
+  source: Option<String>,
+  config: SceneConfig,
+  last_poll: Instant,
+}
+
+impl ConfigWatcher {
+  // `config` is what `path` held at startup, with `overrides` applied.
+  pub fn new(path: PathBuf, overrides: Vec<String>, config: SceneConfig) -> ConfigWatcher {
+    let source = std::fs::read_to_string(&path).ok();
+
+    ConfigWatcher {
+      path,
+      overrides,
+      source,
+      config,
+      last_poll: Instant::now(),
+    }
+  }
+
+  pub fn config(&self) -> &SceneConfig {
+    &self.config
+  }
+
+  // Returns the previous and the new config when the file changed.
+  pub fn poll(&mut self) -> Option<(SceneConfig, SceneConfig)> {
+    if self.last_poll.elapsed() < POLL_INTERVAL {
+      return None;
+    }
+    self.last_poll = Instant::now();
+    self.reload()
+  }
+
+  fn reload(&mut self) -> Option<(SceneConfig, SceneConfig)> {
+    // Editors often replace the file, so it can briefly be missing.
+    let source = std::fs::read_to_string(&self.path).ok()?;
+    if self.source.as_ref() == Some(&source) {
+      return None;
+    }
+    self.source = Some(source);
+
+    let config = SceneConfig::from_source(&self.path, self.source.as_ref().unwrap())
+      .map_err(|e| e.to_string())
+      .and_then(|mut config| {
+        cli::apply_overrides(&mut config, &self.overrides)?;
+        Ok(config)
+      });
+
+    match config {
+      Ok(config) => {
+        let previous = std::mem::replace(&mut self.config, config);
+        Some((previous, config))
+      }
+      Err(e) => {
+        log::error!("Ignoring {}: {}", self.path.display(), e);
+        None
+      }
+    }
+  }
+}
+
+// Applies everything that can change while running and warns about the rest.
+pub fn apply(renderer: &mut Renderer, previous: &SceneConfig, config: &SceneConfig) {
+  let (old, new) = (&previous.renderer, &config.renderer);
+
+  let restart_required = [
+    (
+      "window size",
+      (previous.width, previous.height) != (config.width, config.height),
+    ),
+    ("ocean.size", old.ocean.size != new.ocean.size),
+    ("ocean.backend", old.ocean_backend != new.ocean_backend),
+    ("ocean.seed", old.seed != new.seed),
+    (
+      "rendering.sample_count",
+      old.sample_count != new.sample_count,
+    ),
+  ];
+  for (name, changed) in restart_required {
+    if changed {
+      log::warn!("Changing {} requires a restart", name);
+    }
+  }
+
+  // The running simulation keeps its size, only the sea state blends over.
+  let running = renderer.ocean().parameters();
+  let ocean = OceanCascadeParameters {
+    size: running.size,
+    ..new.ocean
+  };
+  if ocean != running {
+    match renderer.transition_ocean(ocean, OCEAN_TRANSITION) {
+      Ok(()) => log::info!("Ocean: {:?} -> {:?}", running, ocean),
+      Err(e) => log::error!("Ignoring ocean parameters: {}", e),
+    }
+  }
+
+  let (old_camera, new_camera) = (&old.camera, &new.camera);
+  if (old_camera.position, old_camera.yaw, old_camera.pitch)
+    != (new_camera.position, new_camera.yaw, new_camera.pitch)
+  {
+    log::info!(
+      "Camera: moved to {:?}, yaw {}, pitch {}",
+      new_camera.position,
+      new_camera.yaw,
+      new_camera.pitch
+    );
+    renderer.set_camera(new_camera.camera());
+  }
+  if (old_camera.fov, old_camera.near, old_camera.far)
+    != (new_camera.fov, new_camera.near, new_camera.far)
+  {
+    log::info!(
+      "Camera: fov {}, near {}, far {}",
+      new_camera.fov,
+      new_camera.near,
+      new_camera.far
+    );
+    renderer.set_projection(new_camera.projection(renderer.size.width, renderer.size.height));
+  }
+  if (old_camera.speed, old_camera.sensitivity) != (new_camera.speed, new_camera.sensitivity) {
+    log::info!(
+      "Camera: speed {}, sensitivity {}",
+      new_camera.speed,
+      new_camera.sensitivity
+    );
+    renderer.set_camera_controller(new_camera.controller());
+  }
+
+  if old.lighting != new.lighting {
+    log::info!("Lighting: {:?} -> {:?}", old.lighting, new.lighting);
+    renderer.set_lighting(new.lighting);
+  }
+
+  if old.water_material != new.water_material {
+    log::info!(
+      "Water: {:?} -> {:?}",
+      old.water_material,
+      new.water_material
+    );
+    renderer.set_water_material(new.water_material);
+  }
+
+  if old.underwater != new.underwater {
+    log::info!("Underwater: {:?} -> {:?}", old.underwater, new.underwater);
+    renderer.set_underwater(new.underwater);
+  }
+
+  if old.reflections != new.reflections {
+    log::info!("Reflections: {}", new.reflections.name());
+    renderer.set_reflection_mode(new.reflections);
+  }
+
+  if old.vsync != new.vsync {
+    log::info!("Vsync: {}", new.vsync);
+    renderer.set_vsync(new.vsync);
+  }
+
+  if old.tonemapping != new.tonemapping {
+    log::info!(
+      "Tonemapping: {:?} -> {:?}",
+      old.tonemapping,
+      new.tonemapping
+    );
+    renderer.set_tonemapping(new.tonemapping);
+  }
+
+  if old.bloom != new.bloom {
+    log::info!("Bloom: {:?} -> {:?}", old.bloom, new.bloom);
+    renderer.set_bloom(new.bloom);
+  }
+}
+
+#[test]
+fn test_config_watcher_reload() {
+  let path = std::env::temp_dir().join(format!("wgpu_ocean_watch_{}.toml", std::process::id()));
+  std::fs::write(&path, "[ocean]\nwind_speed = 5.0\n").unwrap();
+
+  let overrides = vec![String::from("--msaa"), String::from("1")];
+  let mut config = SceneConfig::load(&path).unwrap();
+  cli::apply_overrides(&mut config, &overrides).unwrap();
+  let mut watcher = ConfigWatcher::new(path.clone(), overrides, config);
+  assert_eq!(watcher.reload(), None);
+
+  std::fs::write(&path, "[ocean]\nwind_speed = 8.0\n").unwrap();
+  let (previous, config) = watcher.reload().unwrap();
+  assert_eq!(previous.renderer.ocean.wind_speed, 5.0);
+  assert_eq!(config.renderer.ocean.wind_speed, 8.0);
+  assert_eq!(config.renderer.sample_count, 1);
+
+  // Invalid files keep the last valid config.
+  std::fs::write(&path, "[ocean]\nwind_speed = \"fast\"\n").unwrap();
+  assert_eq!(watcher.reload(), None);
+  assert_eq!(watcher.config().renderer.ocean.wind_speed, 8.0);
+  std::fs::write(&path, "[ocean]\nwind_speed = -8.0\n").unwrap();
+  assert_eq!(watcher.reload(), None);
+  assert_eq!(watcher.config().renderer.ocean.wind_speed, 8.0);
+
+  std::fs::remove_file(&path).unwrap();
+  assert_eq!(watcher.reload(), None);
+}
@@ -0,0 +1,504 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+
+use crate::bloom::{Bloom, MAX_BLOOM_LEVELS};
+use crate::debug_view::{Channels, DebugView, Placement};
+use crate::ocean::{OceanBackend, OceanCascadeParameters, SimulationTexture, LENGTH_SCALES};
+use crate::reflections::ReflectionMode;
+use crate::renderer::{Lighting, Shading, WaterMaterial};
+use crate::sky::SunPosition;
+use crate::tonemap::{Tonemapper, Tonemapping};
+use crate::underwater::Underwater;
+
+// Shows and hides the overlay.
+pub const TOGGLE_KEY: VirtualKeyCode = VirtualKeyCode::F1;
+// Frames the FPS readout is averaged over.
+const FRAME_HISTORY: usize = 60;
+// Points scrolled per line of a mouse wheel.
+const SCROLL_LINE: f32 = 50.0;
+
+// Everything the overlay edits. The renderer fills it in from its current
+// state before the overlay runs and applies what changed afterwards.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DebugSettings {
+  pub ocean: OceanCascadeParameters,
+  pub lighting: Lighting,
+  pub shading: Shading,
+  pub water_material: WaterMaterial,
+  // Degrees.
+  pub fov: f32,
+  pub camera_speed: f32,
+  pub camera_sensitivity: f32,
+  pub vsync: bool,
+  pub debug_view: Option<DebugView>,
+  pub reflections: ReflectionMode,
+  pub underwater: Underwater,
+  pub bloom: Bloom,
+  pub tonemapping: Tonemapping,
+}
+
+// Read-only state shown next to the settings.
+#[derive(Clone, Copy, Debug)]
+pub struct DebugInfo {
+  pub backend: OceanBackend,
+  pub camera_position: [f32; 3],
+}
+
+// An egui overlay drawn on top of the frame. Window events are translated
+// here rather than through egui-winit, which pins its own winit version.
+pub struct DebugUi {
+  pub visible: bool,
+  context: egui::Context,
+  render_pass: egui_wgpu_backend::RenderPass,
+  input: egui::RawInput,
+  modifiers: egui::Modifiers,
+  pointer: egui::Pos2,
+  size: winit::dpi::PhysicalSize<u32>,
+  scale_factor: f32,
+  start: Instant,
+  frame_times: VecDeque<Duration>,
+  // Milliseconds spent simulating and drawing the surface in the last frame.
+  gpu_times: Option<Vec<f32>>,
+}
+
+impl DebugUi {
+  pub fn new(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+    size: winit::dpi::PhysicalSize<u32>,
+    scale_factor: f64,
+  ) -> DebugUi {
+    DebugUi {
+      visible: false,
+      context: egui::Context::default(),
+      render_pass: egui_wgpu_backend::RenderPass::new(device, format, 1),
+      input: egui::RawInput::default(),
+      modifiers: egui::Modifiers::default(),
+      pointer: egui::Pos2::ZERO,
+      size,
+      scale_factor: scale_factor as f32,
+      start: Instant::now(),
+      frame_times: VecDeque::with_capacity(FRAME_HISTORY),
+      gpu_times: None,
+    }
+  }
+
+  pub fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
+    self.size = size;
+  }
+
+  // Returns whether the overlay used the event, in which case the camera
+  // should not see it.
+  pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+    match event {
+      WindowEvent::KeyboardInput { input, .. } if input.virtual_keycode == Some(TOGGLE_KEY) => {
+        if input.state == ElementState::Pressed {
+          self.visible = !self.visible;
+        }
+        return true;
+      }
+      WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+        self.scale_factor = *scale_factor as f32;
+        return false;
+      }
+      WindowEvent::ModifiersChanged(state) => {
+        self.modifiers = egui::Modifiers {
+          alt: state.alt(),
+          ctrl: state.ctrl(),
+          shift: state.shift(),
+          mac_cmd: cfg!(target_os = "macos") && state.logo(),
+          command: if cfg!(target_os = "macos") {
+            state.logo()
+          } else {
+            state.ctrl()
+          },
+        };
+        return false;
+      }
+      _ => {}
+    }
+
+    if !self.visible {
+      return false;
+    }
+
+    match event {
+      WindowEvent::CursorMoved { position, .. } => {
+        self.pointer = egui::pos2(
+          position.x as f32 / self.scale_factor,
+          position.y as f32 / self.scale_factor,
+        );
+        self
+          .input
+          .events
+          .push(egui::Event::PointerMoved(self.pointer));
+        false
+      }
+      WindowEvent::CursorLeft { .. } => {
+        self.input.events.push(egui::Event::PointerGone);
+        false
+      }
+      WindowEvent::MouseInput { state, button, .. } => {
+        let button = match button {
+          MouseButton::Left => egui::PointerButton::Primary,
+          MouseButton::Right => egui::PointerButton::Secondary,
+          MouseButton::Middle => egui::PointerButton::Middle,
+          MouseButton::Other(_) => return false,
+        };
+        self.input.events.push(egui::Event::PointerButton {
+          pos: self.pointer,
+          button,
+          pressed: *state == ElementState::Pressed,
+          modifiers: self.modifiers,
+        });
+        self.context.wants_pointer_input()
+      }
+      WindowEvent::MouseWheel { delta, .. } => {
+        let delta = match delta {
+          MouseScrollDelta::LineDelta(x, y) => egui::vec2(*x, *y) * SCROLL_LINE,
+          MouseScrollDelta::PixelDelta(delta) => {
+            egui::vec2(delta.x as f32, delta.y as f32) / self.scale_factor
+          }
+        };
+        self.input.events.push(egui::Event::Scroll(delta));
+        self.context.wants_pointer_input()
+      }
+      WindowEvent::ReceivedCharacter(c) if !c.is_control() => {
+        self.input.events.push(egui::Event::Text(c.to_string()));
+        self.context.wants_keyboard_input()
+      }
+      WindowEvent::KeyboardInput { input, .. } => {
+        if let Some(key) = input.virtual_keycode.and_then(translate_key) {
+          self.input.events.push(egui::Event::Key {
+            key,
+            pressed: input.state == ElementState::Pressed,
+            modifiers: self.modifiers,
+          });
+        }
+        self.context.wants_keyboard_input()
+      }
+      _ => false,
+    }
+  }
+
+  pub fn record_frame(&mut self, dt: Duration) {
+    if self.frame_times.len() == FRAME_HISTORY {
+      self.frame_times.pop_front();
+    }
+    self.frame_times.push_back(dt);
+  }
+
+  pub fn record_gpu_times(&mut self, gpu_times: Option<Vec<f32>>) {
+    self.gpu_times = gpu_times;
+  }
+
+  // Lays out the overlay, edits `settings` and records drawing it onto
+  // `view`.
+  pub fn draw(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    encoder: &mut wgpu::CommandEncoder,
+    view: &wgpu::TextureView,
+    settings: &mut DebugSettings,
+    info: &DebugInfo,
+  ) -> Result<(), egui_wgpu_backend::BackendError> {
+    let mut input = std::mem::take(&mut self.input);
+    input.screen_rect = Some(egui::Rect::from_min_size(
+      egui::Pos2::ZERO,
+      egui::vec2(self.size.width as f32, self.size.height as f32) / self.scale_factor,
+    ));
+    input.pixels_per_point = Some(self.scale_factor);
+    input.time = Some(self.start.elapsed().as_secs_f64());
+    input.modifiers = self.modifiers;
+
+    let frame_stats = self.frame_stats();
+    let gpu_times = self.gpu_times.as_deref();
+    let output = self.context.run(input, |context| {
+      egui::Window::new("Debug")
+        .default_pos([10.0, 10.0])
+        .resizable(false)
+        .show(context, |ui| {
+          show_stats(ui, frame_stats, gpu_times);
+          show_settings(ui, settings, info);
+        });
+    });
+
+    let paint_jobs = self.context.tessellate(output.shapes);
+    let screen_descriptor = egui_wgpu_backend::ScreenDescriptor {
+      physical_width: self.size.width,
+      physical_height: self.size.height,
+      scale_factor: self.scale_factor,
+    };
+
+    self
+      .render_pass
+      .add_textures(device, queue, &output.textures_delta)?;
+    self
+      .render_pass
+      .update_buffers(device, queue, &paint_jobs, &screen_descriptor);
+    self
+      .render_pass
+      .execute(encoder, view, &paint_jobs, &screen_descriptor, None)?;
+    self.render_pass.remove_textures(output.textures_delta)
+  }
+
+  // Average frames per second and milliseconds per frame.
+  fn frame_stats(&self) -> Option<(f32, f32)> {
+    if self.frame_times.is_empty() {
+      return None;
+    }
+
+    let total: Duration = self.frame_times.iter().sum();
+    let frame_time = total.as_secs_f32() / self.frame_times.len() as f32;
+    Some((1.0 / frame_time, frame_time * 1000.0))
+  }
+}
+
+fn show_stats(ui: &mut egui::Ui, frame_stats: Option<(f32, f32)>, gpu_times: Option<&[f32]>) {
+  if let Some((fps, frame_time)) = frame_stats {
+    ui.label(format!("{:.0} FPS ({:.2} ms)", fps, frame_time));
+  }
+
+  match gpu_times {
+    Some([simulation, surface, ..]) => ui.label(format!(
+      "GPU: simulation {:.2} ms, surface {:.2} ms",
+      simulation, surface
+    )),
+    _ => ui.label("GPU: timestamp queries unsupported"),
+  };
+}
+
+fn show_settings(ui: &mut egui::Ui, settings: &mut DebugSettings, info: &DebugInfo) {
+  egui::CollapsingHeader::new("Ocean")
+    .default_open(true)
+    .show(ui, |ui| {
+      let ocean = &mut settings.ocean;
+      ui.label(format!(
+        "{:?} backend, size {} (fixed at startup)",
+        info.backend, ocean.size
+      ));
+      ui.add(egui::Slider::new(&mut ocean.wind_speed, 0.5..=40.0).text("wind speed (m/s)"));
+      ui.add(egui::Slider::new(&mut ocean.wind_direction, 0.0..=360.0).text("wind direction (°)"));
+      ui.add(egui::Slider::new(&mut ocean.swell, 0.0..=1.0).text("swell"));
+
+      ui.label("Cascades");
+      ui.horizontal(|ui| {
+        for (drawn, length_scale) in settings.shading.cascades.iter_mut().zip(LENGTH_SCALES) {
+          ui.checkbox(drawn, format!("{} m", length_scale));
+        }
+      });
+    });
+
+  egui::CollapsingHeader::new("Shading").show(ui, |ui| {
+    let shading = &mut settings.shading;
+    ui.add(egui::Slider::new(&mut shading.lod_scale, 1.0..=50.0).text("LOD scale"));
+    egui::ComboBox::from_label("object reflections")
+      .selected_text(settings.reflections.name())
+      .show_ui(ui, |ui| {
+        for mode in ReflectionMode::ALL {
+          ui.selectable_value(&mut settings.reflections, mode, mode.name());
+        }
+      });
+
+    let lighting = &mut settings.lighting;
+    let mut placed = lighting.sun.is_some();
+    ui.checkbox(&mut placed, "sun from time and place");
+    match (placed, &mut lighting.sun) {
+      (true, Some(sun)) => {
+        ui.add(egui::Slider::new(&mut sun.time_of_day, 0.0..=24.0).text("time of day (h)"));
+        ui.add(egui::Slider::new(&mut sun.day_of_year, 1..=365).text("day of year"));
+        ui.add(egui::Slider::new(&mut sun.latitude, -90.0..=90.0).text("latitude (°)"));
+      }
+      (true, None) => lighting.sun = Some(SunPosition::default()),
+      (false, Some(_)) => lighting.sun = None,
+      (false, None) => {
+        ui.horizontal(|ui| {
+          for value in &mut lighting.sun_direction {
+            ui.add(egui::DragValue::new(value).speed(0.01));
+          }
+          ui.label("sun direction");
+        });
+      }
+    }
+    ui.add(egui::Slider::new(&mut lighting.turbidity, 1.7..=10.0).text("turbidity"));
+    color_row(ui, "sun color", &mut lighting.sun_color);
+    color_row(ui, "sky color", &mut lighting.sky_color);
+  });
+
+  egui::CollapsingHeader::new("Fog").show(ui, |ui| {
+    let fog = &mut settings.lighting.fog;
+    ui.add(
+      egui::Slider::new(&mut fog.density, 0.0..=0.01)
+        .logarithmic(true)
+        .text("density (1/m)"),
+    );
+    ui.add(
+      egui::Slider::new(&mut fog.height_falloff, 0.0..=0.1)
+        .logarithmic(true)
+        .text("height falloff (1/m)"),
+    );
+    ui.add(egui::Slider::new(&mut fog.anisotropy, 0.0..=0.95).text("sun glow"));
+  });
+
+  egui::CollapsingHeader::new("Water").show(ui, |ui| {
+    let material = &mut settings.water_material;
+    ui.add(egui::Slider::new(&mut material.ior, 1.0..=2.0).text("index of refraction"));
+    color_row(ui, "scatter color", &mut material.scatter_color);
+    ui.horizontal(|ui| {
+      for value in &mut material.absorption {
+        ui.add(
+          egui::DragValue::new(value)
+            .speed(0.005)
+            .clamp_range(0.0..=10.0),
+        );
+      }
+      ui.label("absorption (1/m)");
+    });
+    ui.add(
+      egui::Slider::new(&mut material.subsurface_strength, 0.0..=10.0).text("subsurface strength"),
+    );
+    color_row(ui, "foam albedo", &mut material.foam_albedo);
+    ui.add(egui::Slider::new(&mut material.foam_roughness, 0.0..=1.0).text("foam roughness"));
+    ui.add(egui::Slider::new(&mut material.foam_coverage, 0.0..=4.0).text("foam coverage"));
+  });
+
+  egui::CollapsingHeader::new("Underwater").show(ui, |ui| {
+    let underwater = &mut settings.underwater;
+    ui.checkbox(&mut underwater.enabled, "enabled");
+    ui.add(
+      egui::Slider::new(&mut underwater.scattering, 0.0..=1.0)
+        .logarithmic(true)
+        .text("scattering (1/m)"),
+    );
+    ui.add(egui::Slider::new(&mut underwater.anisotropy, 0.0..=0.95).text("sun glow"));
+    ui.add(egui::Slider::new(&mut underwater.waterline, 0.0..=1.0).text("waterline"));
+  });
+
+  egui::CollapsingHeader::new("Exposure").show(ui, |ui| {
+    let tonemapping = &mut settings.tonemapping;
+    egui::ComboBox::from_label("tonemapper")
+      .selected_text(tonemapping.tonemapper.name())
+      .show_ui(ui, |ui| {
+        for tonemapper in Tonemapper::ALL {
+          ui.selectable_value(&mut tonemapping.tonemapper, tonemapper, tonemapper.name());
+        }
+      });
+    ui.checkbox(&mut tonemapping.auto_exposure, "automatic exposure");
+    let label = if tonemapping.auto_exposure {
+      "compensation (stops)"
+    } else {
+      "exposure (stops)"
+    };
+    ui.add(egui::Slider::new(&mut tonemapping.exposure, -8.0..=8.0).text(label));
+    if tonemapping.auto_exposure {
+      ui.add(
+        egui::Slider::new(&mut tonemapping.adaptation_rate, 0.1..=10.0).text("adaptation rate"),
+      );
+    }
+  });
+
+  egui::CollapsingHeader::new("Bloom").show(ui, |ui| {
+    let bloom = &mut settings.bloom;
+    ui.checkbox(&mut bloom.enabled, "enabled");
+    ui.add(egui::Slider::new(&mut bloom.threshold, 0.0..=20.0).text("threshold"));
+    ui.add(egui::Slider::new(&mut bloom.knee, 0.0..=10.0).text("knee"));
+    ui.add(egui::Slider::new(&mut bloom.intensity, 0.0..=1.0).text("intensity"));
+    ui.add(egui::Slider::new(&mut bloom.levels, 1..=MAX_BLOOM_LEVELS).text("levels"));
+    ui.add(egui::Slider::new(&mut bloom.lens_dirt, 0.0..=10.0).text("lens dirt"));
+  });
+
+  egui::CollapsingHeader::new("Camera").show(ui, |ui| {
+    let [x, y, z] = info.camera_position;
+    ui.label(format!("position {:.1}, {:.1}, {:.1}", x, y, z));
+    ui.add(egui::Slider::new(&mut settings.fov, 10.0..=120.0).text("field of view (°)"));
+    ui.add(egui::Slider::new(&mut settings.camera_speed, 1.0..=500.0).text("speed"));
+    ui.add(egui::Slider::new(&mut settings.camera_sensitivity, 0.1..=5.0).text("sensitivity"));
+    ui.checkbox(&mut settings.vsync, "vsync");
+  });
+
+  egui::CollapsingHeader::new("Textures").show(ui, |ui| {
+    let mut shown = settings.debug_view.is_some();
+    ui.checkbox(&mut shown, "show a simulation texture");
+    if shown != settings.debug_view.is_some() {
+      settings.debug_view = shown.then(DebugView::default);
+    }
+    if let Some(view) = &mut settings.debug_view {
+      show_debug_view(ui, view, info.backend);
+    }
+  });
+}
+
+fn show_debug_view(ui: &mut egui::Ui, view: &mut DebugView, backend: OceanBackend) {
+  egui::ComboBox::from_label("texture")
+    .selected_text(view.texture.name())
+    .show_ui(ui, |ui| {
+      for texture in SimulationTexture::ALL {
+        if texture.is_available(backend) {
+          ui.selectable_value(&mut view.texture, texture, texture.name());
+        }
+      }
+    });
+
+  ui.horizontal(|ui| {
+    for (cascade, length_scale) in LENGTH_SCALES.iter().enumerate() {
+      ui.selectable_value(&mut view.cascade, cascade, format!("{} m", length_scale));
+    }
+    ui.label("cascade");
+  });
+
+  let mip_level_count = view.texture.mip_level_count();
+  view.mip_level = view.mip_level.min(mip_level_count - 1);
+  if mip_level_count > 1 {
+    ui.add(egui::Slider::new(&mut view.mip_level, 0..=mip_level_count - 1).text("mip level"));
+  }
+
+  ui.horizontal(|ui| {
+    let [black, white] = &mut view.range;
+    ui.add(egui::DragValue::new(black).speed(0.01));
+    ui.add(egui::DragValue::new(white).speed(0.01));
+    ui.label("range");
+  });
+
+  ui.horizontal(|ui| {
+    for channels in Channels::ALL {
+      ui.selectable_value(&mut view.channels, channels, channels.name());
+    }
+  });
+
+  ui.horizontal(|ui| {
+    ui.radio_value(
+      &mut view.placement,
+      Placement::PictureInPicture,
+      "picture in picture",
+    );
+    ui.radio_value(&mut view.placement, Placement::FullScreen, "full screen");
+  });
+}
+
+fn color_row(ui: &mut egui::Ui, label: &str, color: &mut [f32; 3]) {
+  ui.horizontal(|ui| {
+    ui.color_edit_button_rgb(color);
+    ui.label(label);
+  });
+}
+
+// The keys text fields need, the camera keeps the rest.
+fn translate_key(key: VirtualKeyCode) -> Option<egui::Key> {
+  Some(match key {
+    VirtualKeyCode::Left => egui::Key::ArrowLeft,
+    VirtualKeyCode::Right => egui::Key::ArrowRight,
+    VirtualKeyCode::Up => egui::Key::ArrowUp,
+    VirtualKeyCode::Down => egui::Key::ArrowDown,
+    VirtualKeyCode::Back => egui::Key::Backspace,
+    VirtualKeyCode::Delete => egui::Key::Delete,
+    VirtualKeyCode::Home => egui::Key::Home,
+    VirtualKeyCode::End => egui::Key::End,
+    VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => egui::Key::Enter,
+    VirtualKeyCode::Tab => egui::Key::Tab,
+    VirtualKeyCode::Escape => egui::Key::Escape,
+    _ => return None,
+  })
+}
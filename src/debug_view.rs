@@ -0,0 +1,368 @@
+use wgpu::util::DeviceExt;
+
+use crate::ocean::shaders::{self, Shader};
+use crate::ocean::{Ocean, OceanError, SimulationTexture};
+use crate::renderer::RendererError;
+
+pub const DEBUG_VIEW_SHADER: Shader =
+  Shader::new("src/debug_view.wgsl", include_str!("debug_view.wgsl"));
+
+// Gap between a picture-in-picture view and the frame's edges, in pixels.
+const MARGIN: f32 = 16.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channels {
+  Rgb,
+  Red,
+  Green,
+  Blue,
+  Alpha,
+}
+
+impl Channels {
+  pub const ALL: [Channels; 5] = [
+    Channels::Rgb,
+    Channels::Red,
+    Channels::Green,
+    Channels::Blue,
+    Channels::Alpha,
+  ];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Channels::Rgb => "RGB",
+      Channels::Red => "R",
+      Channels::Green => "G",
+      Channels::Blue => "B",
+      Channels::Alpha => "A",
+    }
+  }
+
+  // What the shader expects in `channel`.
+  fn index(self) -> u32 {
+    match self {
+      Channels::Red => 0,
+      Channels::Green => 1,
+      Channels::Blue => 2,
+      Channels::Alpha => 3,
+      Channels::Rgb => 4,
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Placement {
+  // As large as the frame allows, over a black background.
+  FullScreen,
+  // A third of the frame, in the bottom right corner.
+  PictureInPicture,
+}
+
+// One simulation texture drawn over the frame, to see what a pass produced.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DebugView {
+  pub texture: SimulationTexture,
+  // 0 is the cascade with the largest tiles.
+  pub cascade: usize,
+  pub mip_level: u32,
+  // Values shown as black and white, anything outside is clamped.
+  pub range: [f32; 2],
+  pub channels: Channels,
+  pub placement: Placement,
+}
+
+impl Default for DebugView {
+  fn default() -> DebugView {
+    DebugView {
+      texture: SimulationTexture::Displacement,
+      cascade: 0,
+      mip_level: 0,
+      range: [-1.0, 1.0],
+      channels: Channels::Rgb,
+      placement: Placement::PictureInPicture,
+    }
+  }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DebugViewUniform {
+  range: [f32; 2],
+  channel: u32,
+  _padding: u32,
+}
+
+impl From<DebugView> for DebugViewUniform {
+  fn from(view: DebugView) -> Self {
+    DebugViewUniform {
+      range: view.range,
+      channel: view.channels.index(),
+      _padding: 0,
+    }
+  }
+}
+
+pub struct DebugViewPass {
+  bind_group_layout: wgpu::BindGroupLayout,
+  pipeline_layout: wgpu::PipelineLayout,
+  pipeline: wgpu::RenderPipeline,
+  uniform_buffer: wgpu::Buffer,
+  format: wgpu::TextureFormat,
+  // The view shown and the bind group of its texture.
+  current: Option<(DebugView, wgpu::BindGroup)>,
+}
+
+impl DebugViewPass {
+  pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> DebugViewPass {
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("debug_view_bind_group_layout"),
+      entries: &[
+        wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Texture {
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            multisampled: false,
+          },
+          count: None,
+        },
+      ],
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Debug View Pipeline Layout"),
+      bind_group_layouts: &[&bind_group_layout],
+      push_constant_ranges: &[],
+    });
+
+    let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+      label: Some("Debug view shader"),
+      source: wgpu::ShaderSource::Wgsl(DEBUG_VIEW_SHADER.source()),
+    });
+    let pipeline = Self::create_pipeline(device, &pipeline_layout, &shader, format);
+
+    let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Debug View Buffer"),
+      contents: bytemuck::cast_slice(&[DebugViewUniform::from(DebugView::default())]),
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    });
+
+    DebugViewPass {
+      bind_group_layout,
+      pipeline_layout,
+      pipeline,
+      uniform_buffer,
+      format,
+      current: None,
+    }
+  }
+
+  pub fn view(&self) -> Option<DebugView> {
+    self.current.as_ref().map(|(view, _)| *view)
+  }
+
+  // Shows `view` from the next frame on, `None` hides it. Fails when the
+  // ocean has no such cascade, texture or mip level.
+  pub fn set_view(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    ocean: &Ocean,
+    view: Option<DebugView>,
+  ) -> Result<(), RendererError> {
+    let view = match view {
+      Some(view) => view,
+      None => {
+        self.current = None;
+        return Ok(());
+      }
+    };
+
+    let cascade = ocean.cascade();
+    let surface = [&cascade.cascade_0, &cascade.cascade_1, &cascade.cascade_2]
+      .get(view.cascade)
+      .copied()
+      .ok_or_else(|| {
+        RendererError::InvalidDebugView(format!("there is no cascade {}", view.cascade))
+      })?;
+    let texture = surface.texture(view.texture).ok_or_else(|| {
+      RendererError::InvalidDebugView(format!(
+        "the {:?} backend has no {} texture",
+        surface.backend(),
+        view.texture.name()
+      ))
+    })?;
+    if view.mip_level >= view.texture.mip_level_count() {
+      return Err(RendererError::InvalidDebugView(format!(
+        "the {} texture has no mip level {}",
+        view.texture.name(),
+        view.mip_level
+      )));
+    }
+
+    queue.write_buffer(
+      &self.uniform_buffer,
+      0,
+      bytemuck::cast_slice(&[DebugViewUniform::from(view)]),
+    );
+
+    // The texture only changes with the selection, the range and channels are
+    // read from the uniform.
+    if let Some((current, _)) = &mut self.current {
+      let same_texture = (current.texture, current.cascade, current.mip_level)
+        == (view.texture, view.cascade, view.mip_level);
+      if same_texture {
+        *current = view;
+        return Ok(());
+      }
+    }
+
+    let texture_view = texture.create_view(&wgpu::TextureViewDescriptor {
+      base_mip_level: view.mip_level,
+      mip_level_count: std::num::NonZeroU32::new(1),
+      ..Default::default()
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Debug view bind group"),
+      layout: &self.bind_group_layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: self.uniform_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: wgpu::BindingResource::TextureView(&texture_view),
+        },
+      ],
+    });
+
+    self.current = Some((view, bind_group));
+    Ok(())
+  }
+
+  pub async fn reload(
+    &mut self,
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+  ) -> Result<(), OceanError> {
+    self.pipeline = shaders::try_create(device, || {
+      Self::create_pipeline(device, &self.pipeline_layout, shader, self.format)
+    })
+    .await?;
+    Ok(())
+  }
+
+  // Draws the current view, if any, over `target`.
+  pub fn draw(
+    &self,
+    encoder: &mut wgpu::CommandEncoder,
+    target: &wgpu::TextureView,
+    size: winit::dpi::PhysicalSize<u32>,
+  ) {
+    let (view, bind_group) = match &self.current {
+      Some(current) => current,
+      None => return,
+    };
+
+    let (width, height) = (size.width as f32, size.height as f32);
+    let (load, side, x, y) = match view.placement {
+      Placement::FullScreen => {
+        let side = width.min(height);
+        (
+          wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+          side,
+          (width - side) / 2.0,
+          (height - side) / 2.0,
+        )
+      }
+      Placement::PictureInPicture => {
+        let side = width.min(height) / 3.0;
+        (
+          wgpu::LoadOp::Load,
+          side,
+          width - side - MARGIN,
+          height - side - MARGIN,
+        )
+      }
+    };
+    if side <= 0.0 || x < 0.0 || y < 0.0 {
+      return;
+    }
+
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some("Debug View Pass"),
+      color_attachments: &[wgpu::RenderPassColorAttachment {
+        view: target,
+        resolve_target: None,
+        ops: wgpu::Operations { load, store: true },
+      }],
+      depth_stencil_attachment: None,
+    });
+
+    render_pass.set_viewport(x, y, side, side, 0.0, 1.0);
+    render_pass.set_pipeline(&self.pipeline);
+    render_pass.set_bind_group(0, bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+  }
+
+  fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+  ) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Debug View Pipeline"),
+      layout: Some(layout),
+      vertex: wgpu::VertexState {
+        module: shader,
+        entry_point: "vs_main",
+        buffers: &[],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: shader,
+        entry_point: "fs_main",
+        targets: &[wgpu::ColorTargetState {
+          format,
+          blend: Some(wgpu::BlendState::REPLACE),
+          write_mask: wgpu::ColorWrites::ALL,
+        }],
+      }),
+      primitive: wgpu::PrimitiveState::default(),
+      depth_stencil: None,
+      multisample: wgpu::MultisampleState::default(),
+      multiview: None,
+    })
+  }
+}
+
+#[test]
+fn test_debug_view_uniform() {
+  // Matches the layout of `DebugViewUniform` in the shader.
+  assert_eq!(std::mem::size_of::<DebugViewUniform>(), 16);
+
+  let uniform = DebugViewUniform::from(DebugView {
+    range: [0.0, 4.0],
+    channels: Channels::Alpha,
+    ..Default::default()
+  });
+  assert_eq!(uniform.range, [0.0, 4.0]);
+  assert_eq!(uniform.channel, 3);
+  assert_eq!(DebugViewUniform::from(DebugView::default()).channel, 4);
+
+  let gerstner = crate::ocean::OceanBackend::gerstner();
+  assert!(SimulationTexture::Displacement.is_available(gerstner));
+  assert!(!SimulationTexture::H0.is_available(gerstner));
+}
@@ -0,0 +1,47 @@
+// Draws one simulation texture as a quad, see `DebugView`.
+
+struct DebugViewUniform {
+    // Values mapped to black and white.
+    range: vec2<f32>;
+    // The channel shown as grayscale, or 4 for RGB.
+    channel: u32;
+};
+
+[[group(0), binding(0)]]
+var<uniform> params: DebugViewUniform;
+
+[[group(0), binding(1)]]
+var t_debug: texture_2d<f32>;
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+// A triangle covering the viewport.
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    // Simulation textures are not necessarily filterable, so texels are
+    // loaded directly.
+    let size = textureDimensions(t_debug);
+    let texel = clamp(vec2<i32>(in.uv * vec2<f32>(size)), vec2<i32>(0), size - 1);
+    let value = textureLoad(t_debug, texel, 0);
+    let remapped = (value - params.range.x) / (params.range.y - params.range.x);
+
+    var color = remapped.rgb;
+    if (params.channel < 4u) {
+        color = vec3<f32>(remapped[params.channel]);
+    }
+
+    return vec4<f32>(clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)), 1.0);
+}
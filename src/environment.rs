@@ -0,0 +1,664 @@
+use std::path::Path;
+
+use wgpu::util::DeviceExt;
+
+use crate::ocean::shaders::{self, Shader};
+use crate::ocean::OceanError;
+use crate::renderer::RendererError;
+
+pub const ENVIRONMENT_SHADER: Shader =
+  Shader::new("src/environment.wgsl", include_str!("environment.wgsl"));
+
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const CUBE_SIZE: u32 = 128;
+// From a mirror at level 0 to fully rough at the last one.
+pub const MIP_LEVEL_COUNT: u32 = 6;
+// The parameters of each mip level and face sit at an offset valid for any
+// device.
+const UNIFORM_STRIDE: u64 = 256;
+const WORKGROUP_SIZE: u32 = 8;
+
+// What the water reflects.
+pub enum EnvironmentSource {
+  // The sky model, captured again whenever the lighting changes.
+  Sky,
+  // A panorama in linear HDR values, its center looking towards -z and its
+  // top row straight up.
+  Equirectangular(image::Rgba32FImage),
+}
+
+impl EnvironmentSource {
+  // Reads a panorama from any format the image crate decodes, e.g. OpenEXR or
+  // Radiance HDR.
+  pub fn open(path: &Path) -> Result<EnvironmentSource, image::ImageError> {
+    Ok(EnvironmentSource::Equirectangular(
+      image::open(path)?.into_rgba32f(),
+    ))
+  }
+}
+
+enum Capture {
+  Sky,
+  // The bind group of the panorama.
+  Equirectangular(wgpu::BindGroup),
+}
+
+enum Pipeline {
+  Compute(wgpu::ComputePipeline),
+  Render(wgpu::RenderPipeline),
+}
+
+// Where a pipeline writes one mip level of a cubemap.
+enum Target {
+  // The whole level as a storage texture, for compute pipelines.
+  Storage(wgpu::BindGroup),
+  // Each face with the uniform saying which one it is, for render pipelines.
+  Faces(Vec<(wgpu::TextureView, wgpu::BindGroup)>),
+}
+
+// A cubemap of the radiance around the ocean, prefiltered by roughness into
+// its mip levels.
+pub struct Environment {
+  pub bind_group_layout: wgpu::BindGroupLayout,
+  pub bind_group: wgpu::BindGroup,
+
+  equirectangular_layout: wgpu::BindGroupLayout,
+  sky_pipeline_layout: wgpu::PipelineLayout,
+  equirectangular_pipeline_layout: wgpu::PipelineLayout,
+  prefilter_pipeline_layout: wgpu::PipelineLayout,
+  // Compute pipelines write whole mip levels, render pipelines are used on
+  // devices without compute shaders or storage textures.
+  compute: bool,
+  capture_sky_pipeline: Pipeline,
+  capture_equirectangular_pipeline: Pipeline,
+  prefilter_pipeline: Pipeline,
+
+  sky_bind_group: wgpu::BindGroup,
+  // Writes to the unfiltered cubemap.
+  source_target: Target,
+  // Write to each mip level of the filtered cubemap, with the roughness it
+  // holds and the unfiltered cubemap to read from.
+  mip_targets: Vec<(Target, wgpu::BindGroup)>,
+  capture: Capture,
+}
+
+impl Environment {
+  // Starts out capturing the sky, `lighting_buffer` holding the renderer's
+  // `LightingUniform`. Nothing is drawn into it before `capture`. Without
+  // `compute` the cubemap is filled with render passes.
+  pub fn new(device: &wgpu::Device, lighting_buffer: &wgpu::Buffer, compute: bool) -> Environment {
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("environment_bind_group_layout"),
+      entries: &[
+        wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Texture {
+            view_dimension: wgpu::TextureViewDimension::Cube,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            multisampled: false,
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+          count: None,
+        },
+      ],
+    });
+
+    let stage = if compute {
+      wgpu::ShaderStages::COMPUTE
+    } else {
+      wgpu::ShaderStages::FRAGMENT
+    };
+    let target_entry = if compute {
+      wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: stage,
+        ty: wgpu::BindingType::StorageTexture {
+          access: wgpu::StorageTextureAccess::WriteOnly,
+          format: FORMAT,
+          view_dimension: wgpu::TextureViewDimension::D2Array,
+        },
+        count: None,
+      }
+    } else {
+      wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: stage,
+        ty: wgpu::BindingType::Buffer {
+          ty: wgpu::BufferBindingType::Uniform,
+          has_dynamic_offset: false,
+          min_binding_size: None,
+        },
+        count: None,
+      }
+    };
+    let target_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("environment_target_bind_group_layout"),
+      entries: &[target_entry],
+    });
+    let sky_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("environment_sky_bind_group_layout"),
+      entries: &[wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: stage,
+        ty: wgpu::BindingType::Buffer {
+          ty: wgpu::BufferBindingType::Uniform,
+          has_dynamic_offset: false,
+          min_binding_size: None,
+        },
+        count: None,
+      }],
+    });
+    let equirectangular_layout =
+      device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("environment_equirectangular_bind_group_layout"),
+        entries: &[wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: stage,
+          ty: wgpu::BindingType::Texture {
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            multisampled: false,
+          },
+          count: None,
+        }],
+      });
+    let prefilter_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("environment_prefilter_bind_group_layout"),
+      entries: &[
+        wgpu::BindGroupLayoutEntry {
+          binding: 2,
+          visibility: stage,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 3,
+          visibility: stage,
+          ty: wgpu::BindingType::Texture {
+            view_dimension: wgpu::TextureViewDimension::Cube,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            multisampled: false,
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 4,
+          visibility: stage,
+          ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+          count: None,
+        },
+      ],
+    });
+
+    let create_layout = |label, layout| {
+      device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts: &[&target_layout, layout],
+        push_constant_ranges: &[],
+      })
+    };
+    let sky_pipeline_layout = create_layout("Environment Sky Pipeline Layout", &sky_layout);
+    let equirectangular_pipeline_layout = create_layout(
+      "Environment Equirectangular Pipeline Layout",
+      &equirectangular_layout,
+    );
+    let prefilter_pipeline_layout =
+      create_layout("Environment Prefilter Pipeline Layout", &prefilter_layout);
+
+    let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+      label: Some("Environment shader"),
+      source: wgpu::ShaderSource::Wgsl(ENVIRONMENT_SHADER.source()),
+    });
+    let capture_sky_pipeline = Self::create_pipeline(
+      device,
+      &sky_pipeline_layout,
+      &shader,
+      "capture_sky",
+      compute,
+    );
+    let capture_equirectangular_pipeline = Self::create_pipeline(
+      device,
+      &equirectangular_pipeline_layout,
+      &shader,
+      "capture_equirectangular",
+      compute,
+    );
+    let prefilter_pipeline = Self::create_pipeline(
+      device,
+      &prefilter_pipeline_layout,
+      &shader,
+      "prefilter",
+      compute,
+    );
+
+    let create_cubemap = |label, mip_level_count| {
+      device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+          width: CUBE_SIZE,
+          height: CUBE_SIZE,
+          depth_or_array_layers: 6,
+        },
+        mip_level_count,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING
+          | if compute {
+            wgpu::TextureUsages::STORAGE_BINDING
+          } else {
+            wgpu::TextureUsages::RENDER_ATTACHMENT
+          },
+      })
+    };
+    let source = create_cubemap("Environment source texture", 1);
+    let filtered = create_cubemap("Environment texture", MIP_LEVEL_COUNT);
+
+    let face_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Environment Face Buffer"),
+      contents: &face_uniforms(),
+      usage: wgpu::BufferUsages::UNIFORM,
+    });
+    let create_target = |texture: &wgpu::Texture, mip_level: u32| {
+      if compute {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+          dimension: Some(wgpu::TextureViewDimension::D2Array),
+          base_mip_level: mip_level,
+          mip_level_count: std::num::NonZeroU32::new(1),
+          ..Default::default()
+        });
+        return Target::Storage(device.create_bind_group(&wgpu::BindGroupDescriptor {
+          label: Some("Environment target bind group"),
+          layout: &target_layout,
+          entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&view),
+          }],
+        }));
+      }
+
+      let faces = (0..6)
+        .map(|face| {
+          let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_mip_level: mip_level,
+            mip_level_count: std::num::NonZeroU32::new(1),
+            base_array_layer: face,
+            array_layer_count: std::num::NonZeroU32::new(1),
+            ..Default::default()
+          });
+          let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Environment face bind group"),
+            layout: &target_layout,
+            entries: &[wgpu::BindGroupEntry {
+              binding: 1,
+              resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                buffer: &face_buffer,
+                offset: (mip_level * 6 + face) as u64 * UNIFORM_STRIDE,
+                size: wgpu::BufferSize::new(2 * std::mem::size_of::<u32>() as u64),
+              }),
+            }],
+          });
+          (view, bind_group)
+        })
+        .collect();
+      Target::Faces(faces)
+    };
+    let cube_view = |texture: &wgpu::Texture| {
+      texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        ..Default::default()
+      })
+    };
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+      label: Some("Environment sampler"),
+      mag_filter: wgpu::FilterMode::Linear,
+      min_filter: wgpu::FilterMode::Linear,
+      mipmap_filter: wgpu::FilterMode::Linear,
+      ..Default::default()
+    });
+
+    let prefilter_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Environment Prefilter Buffer"),
+      contents: &prefilter_uniforms(),
+      usage: wgpu::BufferUsages::UNIFORM,
+    });
+    let source_view = cube_view(&source);
+    let mip_targets = (0..MIP_LEVEL_COUNT)
+      .map(|mip_level| {
+        let prefilter = device.create_bind_group(&wgpu::BindGroupDescriptor {
+          label: Some("Environment prefilter bind group"),
+          layout: &prefilter_layout,
+          entries: &[
+            wgpu::BindGroupEntry {
+              binding: 2,
+              resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                buffer: &prefilter_buffer,
+                offset: mip_level as u64 * UNIFORM_STRIDE,
+                size: wgpu::BufferSize::new(std::mem::size_of::<f32>() as u64),
+              }),
+            },
+            wgpu::BindGroupEntry {
+              binding: 3,
+              resource: wgpu::BindingResource::TextureView(&source_view),
+            },
+            wgpu::BindGroupEntry {
+              binding: 4,
+              resource: wgpu::BindingResource::Sampler(&sampler),
+            },
+          ],
+        });
+        (create_target(&filtered, mip_level), prefilter)
+      })
+      .collect();
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Environment bind group"),
+      layout: &bind_group_layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: wgpu::BindingResource::TextureView(&cube_view(&filtered)),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: wgpu::BindingResource::Sampler(&sampler),
+        },
+      ],
+    });
+    let sky_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Environment sky bind group"),
+      layout: &sky_layout,
+      entries: &[wgpu::BindGroupEntry {
+        binding: 0,
+        resource: lighting_buffer.as_entire_binding(),
+      }],
+    });
+
+    Environment {
+      bind_group_layout,
+      bind_group,
+      equirectangular_layout,
+      sky_pipeline_layout,
+      equirectangular_pipeline_layout,
+      prefilter_pipeline_layout,
+      compute,
+      capture_sky_pipeline,
+      capture_equirectangular_pipeline,
+      prefilter_pipeline,
+      sky_bind_group,
+      source_target: create_target(&source, 0),
+      mip_targets,
+      capture: Capture::Sky,
+    }
+  }
+
+  // Whether the cubemap has to be captured again when the lighting changes.
+  pub fn follows_sky(&self) -> bool {
+    matches!(self.capture, Capture::Sky)
+  }
+
+  // Reflects `source` from now on. Fails when a panorama is larger than the
+  // device supports.
+  pub fn set_source(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    source: EnvironmentSource,
+  ) -> Result<(), RendererError> {
+    self.capture = match source {
+      EnvironmentSource::Sky => Capture::Sky,
+      EnvironmentSource::Equirectangular(image) => {
+        let (width, height) = image.dimensions();
+        let max_size = device.limits().max_texture_dimension_2d;
+        if width == 0 || height == 0 || width > max_size || height > max_size {
+          return Err(RendererError::InvalidEnvironment(format!(
+            "a {}x{} panorama does not fit in a texture of at most {}x{}",
+            width, height, max_size, max_size
+          )));
+        }
+
+        let texture = device.create_texture_with_data(
+          queue,
+          &wgpu::TextureDescriptor {
+            label: Some("Environment panorama"),
+            size: wgpu::Extent3d {
+              width,
+              height,
+              depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+          },
+          bytemuck::cast_slice(image.as_raw()),
+        );
+        Capture::Equirectangular(device.create_bind_group(&wgpu::BindGroupDescriptor {
+          label: Some("Environment equirectangular bind group"),
+          layout: &self.equirectangular_layout,
+          entries: &[wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::TextureView(
+              &texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            ),
+          }],
+        }))
+      }
+    };
+
+    self.capture(device, queue);
+    Ok(())
+  }
+
+  // Redraws the cubemap from its source and filters it.
+  pub fn capture(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+      label: Some("Environment Encoder"),
+    });
+
+    let (pipeline, source) = match &self.capture {
+      Capture::Sky => (&self.capture_sky_pipeline, &self.sky_bind_group),
+      Capture::Equirectangular(bind_group) => (&self.capture_equirectangular_pipeline, bind_group),
+    };
+    Self::fill(
+      &mut encoder,
+      "Capture environment",
+      pipeline,
+      source,
+      &self.source_target,
+      CUBE_SIZE,
+    );
+
+    for (mip_level, (target, prefilter)) in self.mip_targets.iter().enumerate() {
+      Self::fill(
+        &mut encoder,
+        "Prefilter environment",
+        &self.prefilter_pipeline,
+        prefilter,
+        target,
+        (CUBE_SIZE >> mip_level).max(1),
+      );
+    }
+
+    queue.submit(std::iter::once(encoder.finish()));
+  }
+
+  // Rebuilds the pipelines from `shader`, the caller captures the cubemap
+  // again to see the result.
+  pub async fn reload(
+    &mut self,
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+  ) -> Result<(), OceanError> {
+    let compute = self.compute;
+    let (capture_sky, capture_equirectangular, prefilter) = shaders::try_create(device, || {
+      (
+        Self::create_pipeline(
+          device,
+          &self.sky_pipeline_layout,
+          shader,
+          "capture_sky",
+          compute,
+        ),
+        Self::create_pipeline(
+          device,
+          &self.equirectangular_pipeline_layout,
+          shader,
+          "capture_equirectangular",
+          compute,
+        ),
+        Self::create_pipeline(
+          device,
+          &self.prefilter_pipeline_layout,
+          shader,
+          "prefilter",
+          compute,
+        ),
+      )
+    })
+    .await?;
+    self.capture_sky_pipeline = capture_sky;
+    self.capture_equirectangular_pipeline = capture_equirectangular;
+    self.prefilter_pipeline = prefilter;
+    Ok(())
+  }
+
+  // Records writing `target`, a mip level `size` texels wide, with `pipeline`
+  // reading from `source`.
+  fn fill(
+    encoder: &mut wgpu::CommandEncoder,
+    label: &str,
+    pipeline: &Pipeline,
+    source: &wgpu::BindGroup,
+    target: &Target,
+    size: u32,
+  ) {
+    match (pipeline, target) {
+      (Pipeline::Compute(pipeline), Target::Storage(target)) => {
+        let mut compute_pass =
+          encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some(label) });
+        compute_pass.set_pipeline(pipeline);
+        compute_pass.set_bind_group(0, target, &[]);
+        compute_pass.set_bind_group(1, source, &[]);
+        let groups = (size + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE;
+        compute_pass.dispatch(groups, groups, 6);
+      }
+      (Pipeline::Render(pipeline), Target::Faces(faces)) => {
+        for (view, face) in faces {
+          let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(label),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+              view,
+              resolve_target: None,
+              ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                store: true,
+              },
+            }],
+            depth_stencil_attachment: None,
+          });
+          render_pass.set_pipeline(pipeline);
+          render_pass.set_bind_group(0, face, &[]);
+          render_pass.set_bind_group(1, source, &[]);
+          render_pass.draw(0..3, 0..1);
+        }
+      }
+      _ => unreachable!("pipelines and targets are created for the same kind of pass"),
+    }
+  }
+
+  // The fragment entry points are named after the compute ones, prefixed with
+  // fs_.
+  fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    entry_point: &str,
+    compute: bool,
+  ) -> Pipeline {
+    if compute {
+      return Pipeline::Compute(
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+          label: Some("Environment pipeline"),
+          layout: Some(layout),
+          module: shader,
+          entry_point,
+        }),
+      );
+    }
+
+    Pipeline::Render(
+      device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Environment pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+          module: shader,
+          entry_point: "vs_main",
+          buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+          module: shader,
+          entry_point: &format!("fs_{}", entry_point),
+          targets: &[wgpu::ColorTargetState {
+            format: FORMAT,
+            blend: None,
+            write_mask: wgpu::ColorWrites::ALL,
+          }],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+      }),
+    )
+  }
+}
+
+// The roughness of every mip level, each at its own aligned offset.
+fn prefilter_uniforms() -> Vec<u8> {
+  let mut contents = vec![0; (MIP_LEVEL_COUNT as u64 * UNIFORM_STRIDE) as usize];
+  for mip_level in 0..MIP_LEVEL_COUNT {
+    let roughness = mip_level as f32 / (MIP_LEVEL_COUNT - 1) as f32;
+    let offset = (mip_level as u64 * UNIFORM_STRIDE) as usize;
+    contents[offset..offset + 4].copy_from_slice(bytemuck::bytes_of(&roughness));
+  }
+  contents
+}
+
+// The face and size of every face of every mip level, which the fragment
+// entry points draw one at a time.
+fn face_uniforms() -> Vec<u8> {
+  let mut contents = vec![0; (MIP_LEVEL_COUNT as u64 * 6 * UNIFORM_STRIDE) as usize];
+  for mip_level in 0..MIP_LEVEL_COUNT {
+    for face in 0..6 {
+      let uniform = [face, (CUBE_SIZE >> mip_level).max(1)];
+      let offset = ((mip_level * 6 + face) as u64 * UNIFORM_STRIDE) as usize;
+      contents[offset..offset + 8].copy_from_slice(bytemuck::cast_slice(&uniform));
+    }
+  }
+  contents
+}
+
+#[test]
+fn test_prefilter_uniforms() {
+  let contents = prefilter_uniforms();
+  let roughness = |mip_level: u64| {
+    let offset = (mip_level * UNIFORM_STRIDE) as usize;
+    bytemuck::pod_read_unaligned::<f32>(&contents[offset..offset + 4])
+  };
+  assert_eq!(roughness(0), 0.0);
+  assert_eq!(roughness(MIP_LEVEL_COUNT as u64 - 1), 1.0);
+  assert!(roughness(1) < roughness(2));
+}
@@ -0,0 +1,234 @@
+// Fills the environment cubemap reflected by the water, see `Environment`.
+// The capture entry points write the radiance of every direction into the
+// source cubemap, `prefilter` blurs it into each mip level of the cubemap the
+// ocean samples, rougher with every level. The compute entry points write a
+// whole mip level at once, the fragment ones, prefixed with fs_, draw a single
+// face on devices without compute shaders.
+
+// Cube faces as array layers, in the order +x, -x, +y, -y, +z, -z.
+[[group(0), binding(0)]]
+var t_target: texture_storage_2d_array<rgba16float, write>;
+
+// The face drawn by the fragment entry points.
+struct FaceUniform {
+    face: u32;
+    size: u32;
+};
+
+[[group(0), binding(1)]]
+var<uniform> target_face: FaceUniform;
+
+// Perez coefficients and zenith values of luminance and chromaticity, as Yxy
+// in each vec3.
+struct SkyUniform {
+    a: vec3<f32>;
+    b: vec3<f32>;
+    c: vec3<f32>;
+    d: vec3<f32>;
+    e: vec3<f32>;
+    zenith: vec3<f32>;
+};
+
+struct LightingUniform {
+    sun_direction: vec3<f32>;
+    sun_color: vec3<f32>;
+    sky_color: vec3<f32>;
+    sky: SkyUniform;
+};
+
+[[group(1), binding(0)]]
+var<uniform> lighting: LightingUniform;
+
+[[group(1), binding(1)]]
+var t_equirectangular: texture_2d<f32>;
+
+struct PrefilterUniform {
+    roughness: f32;
+};
+
+[[group(1), binding(2)]]
+var<uniform> prefilter_params: PrefilterUniform;
+
+[[group(1), binding(3)]]
+var t_source: texture_cube<f32>;
+
+[[group(1), binding(4)]]
+var s_source: sampler;
+
+let PI: f32 = 3.14159265358979323846264338;
+let SAMPLE_COUNT: u32 = 256u;
+
+fn face_direction(id: vec3<u32>, size: vec2<i32>) -> vec3<f32> {
+    let uv = (vec2<f32>(id.xy) + 0.5) / vec2<f32>(size) * 2.0 - 1.0;
+    var dir: vec3<f32>;
+    switch (i32(id.z)) {
+        case 0: { dir = vec3<f32>(1.0, -uv.y, -uv.x); }
+        case 1: { dir = vec3<f32>(-1.0, -uv.y, uv.x); }
+        case 2: { dir = vec3<f32>(uv.x, 1.0, uv.y); }
+        case 3: { dir = vec3<f32>(uv.x, -1.0, -uv.y); }
+        case 4: { dir = vec3<f32>(uv.x, -uv.y, 1.0); }
+        default: { dir = vec3<f32>(-uv.x, -uv.y, -1.0); }
+    }
+    return normalize(dir);
+}
+
+fn perez(cos_theta: f32, gamma: f32, cos_gamma: f32) -> vec3<f32> {
+    let sky = lighting.sky;
+    return (1.0 + sky.a * exp(sky.b / cos_theta))
+        * (1.0 + sky.c * exp(sky.d * gamma) + sky.e * cos_gamma * cos_gamma);
+}
+
+// The Preetham sky of sky_shader.wgsl, without the sun disk.
+fn sky_rgb(dir: vec3<f32>) -> vec3<f32> {
+    let cos_theta = max(dir.y, 0.01);
+    let cos_gamma = clamp(dot(dir, normalize(lighting.sun_direction)), -1.0, 1.0);
+    let yxy = lighting.sky.zenith * perez(cos_theta, acos(cos_gamma), cos_gamma);
+
+    let xyz = vec3<f32>(yxy.y / yxy.z * yxy.x, yxy.x, (1.0 - yxy.y - yxy.z) / yxy.z * yxy.x);
+    let rgb = vec3<f32>(
+        3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+        -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+        0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z,
+    );
+    return max(rgb, vec3<f32>(0.0));
+}
+
+fn sky_color(id: vec3<u32>, size: vec2<i32>) -> vec4<f32> {
+    return vec4<f32>(sky_rgb(face_direction(id, size)), 1.0);
+}
+
+fn load_equirectangular(texel: vec2<i32>, size: vec2<i32>) -> vec3<f32> {
+    // Wraps around horizontally, clamps at the poles.
+    let x = (texel.x % size.x + size.x) % size.x;
+    let y = clamp(texel.y, 0, size.y - 1);
+    return textureLoad(t_equirectangular, vec2<i32>(x, y), 0).rgb;
+}
+
+// The panorama's center looks towards -z, its top row straight up.
+fn equirectangular_color(id: vec3<u32>, size: vec2<i32>) -> vec4<f32> {
+    let dir = face_direction(id, size);
+    let uv = vec2<f32>(atan2(dir.x, -dir.z) / (2.0 * PI) + 0.5, acos(clamp(dir.y, -1.0, 1.0)) / PI);
+
+    // The texture is not filterable, so it is interpolated by hand.
+    let source_size = textureDimensions(t_equirectangular);
+    let position = uv * vec2<f32>(source_size) - 0.5;
+    let texel = vec2<i32>(floor(position));
+    let t = fract(position);
+    let top = mix(
+        load_equirectangular(texel, source_size),
+        load_equirectangular(texel + vec2<i32>(1, 0), source_size),
+        t.x,
+    );
+    let bottom = mix(
+        load_equirectangular(texel + vec2<i32>(0, 1), source_size),
+        load_equirectangular(texel + vec2<i32>(1, 1), source_size),
+        t.x,
+    );
+    return vec4<f32>(mix(top, bottom, t.y), 1.0);
+}
+
+fn radical_inverse(bits_in: u32) -> f32 {
+    var bits = bits_in;
+    bits = (bits << 16u) | (bits >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return f32(bits) * 2.3283064365386963e-10;
+}
+
+// A GGX distributed half vector around `n`.
+fn importance_sample_ggx(xi: vec2<f32>, n: vec3<f32>, alpha: f32) -> vec3<f32> {
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    let h = vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+
+    var up = vec3<f32>(1.0, 0.0, 0.0);
+    if (abs(n.z) < 0.999) {
+        up = vec3<f32>(0.0, 0.0, 1.0);
+    }
+    let tangent = normalize(cross(up, n));
+    let bitangent = cross(n, tangent);
+    return normalize(tangent * h.x + bitangent * h.y + n * h.z);
+}
+
+// Split sum prefiltering, assuming the view direction is the normal.
+fn prefiltered_color(id: vec3<u32>, size: vec2<i32>) -> vec4<f32> {
+    let n = face_direction(id, size);
+    let roughness = prefilter_params.roughness;
+    if (roughness <= 0.0) {
+        return textureSampleLevel(t_source, s_source, n, 0.0);
+    }
+
+    let alpha = roughness * roughness;
+    var color = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < SAMPLE_COUNT; i = i + 1u) {
+        let xi = vec2<f32>(f32(i) / f32(SAMPLE_COUNT), radical_inverse(i));
+        let h = importance_sample_ggx(xi, n, alpha);
+        let l = normalize(2.0 * dot(n, h) * h - n);
+        let n_dot_l = dot(n, l);
+        if (n_dot_l > 0.0) {
+            color = color + textureSampleLevel(t_source, s_source, l, 0.0).rgb * n_dot_l;
+            weight = weight + n_dot_l;
+        }
+    }
+
+    return vec4<f32>(color / weight, 1.0);
+}
+
+fn in_target(id: vec3<u32>, size: vec2<i32>) -> bool {
+    return i32(id.x) < size.x && i32(id.y) < size.y;
+}
+
+[[stage(compute), workgroup_size(8, 8, 1)]]
+fn capture_sky([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let size = textureDimensions(t_target);
+    if (in_target(id, size)) {
+        textureStore(t_target, vec2<i32>(id.xy), i32(id.z), sky_color(id, size));
+    }
+}
+
+[[stage(compute), workgroup_size(8, 8, 1)]]
+fn capture_equirectangular([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let size = textureDimensions(t_target);
+    if (in_target(id, size)) {
+        textureStore(t_target, vec2<i32>(id.xy), i32(id.z), equirectangular_color(id, size));
+    }
+}
+
+[[stage(compute), workgroup_size(8, 8, 1)]]
+fn prefilter([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let size = textureDimensions(t_target);
+    if (in_target(id, size)) {
+        textureStore(t_target, vec2<i32>(id.xy), i32(id.z), prefiltered_color(id, size));
+    }
+}
+
+// A triangle covering the face.
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32) -> [[builtin(position)]] vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+// The texel drawn, as a compute invocation would see it.
+fn face_texel(position: vec4<f32>) -> vec3<u32> {
+    return vec3<u32>(vec2<u32>(position.xy), target_face.face);
+}
+
+[[stage(fragment)]]
+fn fs_capture_sky([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {
+    return sky_color(face_texel(position), vec2<i32>(i32(target_face.size)));
+}
+
+[[stage(fragment)]]
+fn fs_capture_equirectangular([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {
+    return equirectangular_color(face_texel(position), vec2<i32>(i32(target_face.size)));
+}
+
+[[stage(fragment)]]
+fn fs_prefilter([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {
+    return prefiltered_color(face_texel(position), vec2<i32>(i32(target_face.size)));
+}
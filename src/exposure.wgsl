@@ -0,0 +1,93 @@
+// Meters the HDR frame for automatic exposure, see `TonemapPass`. A histogram
+// of log luminance is built in tiles, then averaged and eased towards by a
+// single workgroup.
+
+struct TonemapUniform {
+    min_log_luminance: f32;
+    log_luminance_range: f32;
+    adaptation: f32;
+    exposure: f32;
+    auto_exposure: u32;
+    tonemapper: u32;
+    encode_srgb: u32;
+};
+
+struct Histogram {
+    bins: array<atomic<u32>, 256>;
+};
+
+struct Luminance {
+    average: f32;
+};
+
+[[group(0), binding(0)]]
+var t_hdr: texture_2d<f32>;
+
+[[group(0), binding(1)]]
+var<uniform> params: TonemapUniform;
+
+[[group(0), binding(2)]]
+var<storage, read_write> histogram: Histogram;
+
+[[group(0), binding(3)]]
+var<storage, read_write> luminance: Luminance;
+
+var<workgroup> tile_bins: array<atomic<u32>, 256>;
+var<workgroup> weighted_bins: array<f32, 256>;
+
+// Bin 0 counts the pixels too dark to be metered, the others split the
+// metered range evenly in log2.
+fn luminance_bin(color: vec3<f32>) -> u32 {
+    let l = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+    if (l < exp2(params.min_log_luminance)) {
+        return 0u;
+    }
+    let t = clamp((log2(l) - params.min_log_luminance) / params.log_luminance_range, 0.0, 1.0);
+    return u32(t * 254.0 + 1.0);
+}
+
+[[stage(compute), workgroup_size(16, 16, 1)]]
+fn build_histogram(
+    [[builtin(global_invocation_id)]] id: vec3<u32>,
+    [[builtin(local_invocation_index)]] index: u32,
+) {
+    atomicStore(&tile_bins[index], 0u);
+    workgroupBarrier();
+
+    let size = textureDimensions(t_hdr);
+    if (i32(id.x) < size.x && i32(id.y) < size.y) {
+        let color = textureLoad(t_hdr, vec2<i32>(id.xy), 0).rgb;
+        atomicAdd(&tile_bins[luminance_bin(color)], 1u);
+    }
+    workgroupBarrier();
+
+    atomicAdd(&histogram.bins[index], atomicLoad(&tile_bins[index]));
+}
+
+[[stage(compute), workgroup_size(256, 1, 1)]]
+fn average_luminance([[builtin(local_invocation_index)]] index: u32) {
+    let count = atomicLoad(&histogram.bins[index]);
+    // Emptied for the next frame.
+    atomicStore(&histogram.bins[index], 0u);
+    weighted_bins[index] = f32(count) * f32(index);
+    workgroupBarrier();
+
+    for (var stride = 128u; stride > 0u; stride = stride >> 1u) {
+        if (index < stride) {
+            weighted_bins[index] = weighted_bins[index] + weighted_bins[index + stride];
+        }
+        workgroupBarrier();
+    }
+
+    if (index == 0u) {
+        // `count` is the first bin here, the pixels left out.
+        let size = textureDimensions(t_hdr);
+        let metered = max(f32(size.x * size.y) - f32(count), 1.0);
+        let bin = max(weighted_bins[0] / metered - 1.0, 0.0);
+        let target = exp2(bin / 254.0 * params.log_luminance_range + params.min_log_luminance);
+
+        // The first frame starts adapted.
+        let previous = luminance.average;
+        luminance.average = select(target, previous + (target - previous) * params.adaptation, previous > 0.0);
+    }
+}
@@ -0,0 +1,279 @@
+use crate::ocean::shaders::{self, Shader};
+use crate::ocean::OceanError;
+use crate::scene::PassInfo;
+use crate::tonemap::HDR_FORMAT;
+
+pub const FOG_SHADER: Shader = Shader::new("src/fog.wgsl", include_str!("fog.wgsl"));
+
+// Haze between the camera and everything in front of the sky, thinning out
+// with altitude. Its color comes from the sky and the sun.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Fog {
+  // Extinction per meter at sea level, 0 for clear air.
+  pub density: f32,
+  // Per meter of altitude, how quickly the density drops.
+  pub height_falloff: f32,
+  // Henyey-Greenstein asymmetry of the light scattered from the sun, from 0
+  // for as much in every direction to 1 for a tight glow around it.
+  pub anisotropy: f32,
+}
+
+impl Default for Fog {
+  fn default() -> Fog {
+    Fog {
+      density: 1.5e-4,
+      height_falloff: 2e-3,
+      anisotropy: 0.7,
+    }
+  }
+}
+
+impl Fog {
+  // What the fog shader expects after the sky model in `LightingUniform`.
+  pub(crate) fn uniform(&self) -> [f32; 4] {
+    [
+      self.density.max(0.0),
+      self.height_falloff.max(0.0),
+      self.anisotropy.clamp(0.0, 0.99),
+      0.0,
+    ]
+  }
+
+  // Fraction of the light left after travelling from `from` to `to`, as the
+  // shader computes it.
+  pub fn transmittance(&self, mut from: [f32; 3], mut to: [f32; 3]) -> f32 {
+    // Only the air above the sea level is foggy.
+    if from[1] < 0.0 && to[1] <= 0.0 {
+      return 1.0;
+    }
+    let crossing = from[1] / (from[1] - to[1]);
+    let surface = [0, 1, 2].map(|i| from[i] + (to[i] - from[i]) * crossing);
+    if from[1] < 0.0 {
+      from = surface;
+    } else if to[1] < 0.0 {
+      to = surface;
+    }
+
+    let [density, falloff, ..] = self.uniform();
+    let ray = [0, 1, 2].map(|i| to[i] - from[i]);
+    let length = ray.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let climb = falloff * ray[1];
+    let average = if climb.abs() > 1e-4 {
+      (1.0 - (-climb).exp()) / climb
+    } else {
+      1.0
+    };
+    (-density * (-falloff * from[1]).exp() * average * length).exp()
+  }
+}
+
+// Fogs the HDR target once everything is drawn, reading back the depth of
+// the frame so that custom drawables are fogged like the ocean.
+pub(crate) struct FogPass {
+  depth_bind_group_layout: wgpu::BindGroupLayout,
+  depth_bind_group: wgpu::BindGroup,
+  pipeline_layout: wgpu::PipelineLayout,
+  pipeline: wgpu::RenderPipeline,
+  pass_info: PassInfo,
+}
+
+impl FogPass {
+  // `scene_depth_view` is the depth everything is drawn with, see
+  // `scene::create_depth_texture`.
+  pub fn new(
+    device: &wgpu::Device,
+    camera_bind_group_layout: &wgpu::BindGroupLayout,
+    environment_bind_group_layout: &wgpu::BindGroupLayout,
+    pass_info: PassInfo,
+    scene_depth_view: &wgpu::TextureView,
+  ) -> FogPass {
+    let multisampled = pass_info.sample_count > 1;
+    let depth_bind_group_layout =
+      device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("fog_depth_bind_group_layout"),
+        entries: &[wgpu::BindGroupLayoutEntry {
+          // Each entry point reads its own binding.
+          binding: if multisampled { 0 } else { 1 },
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Texture {
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Depth,
+            multisampled,
+          },
+          count: None,
+        }],
+      });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Fog Pipeline Layout"),
+      bind_group_layouts: &[
+        camera_bind_group_layout,
+        &depth_bind_group_layout,
+        environment_bind_group_layout,
+      ],
+      push_constant_ranges: &[],
+    });
+
+    let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+      label: Some("Fog shader"),
+      source: wgpu::ShaderSource::Wgsl(FOG_SHADER.source()),
+    });
+    let pipeline = Self::create_pipeline(device, &pipeline_layout, &shader, multisampled);
+    let depth_bind_group = Self::create_depth_bind_group(
+      device,
+      &depth_bind_group_layout,
+      pass_info,
+      scene_depth_view,
+    );
+
+    FogPass {
+      depth_bind_group_layout,
+      depth_bind_group,
+      pipeline_layout,
+      pipeline,
+      pass_info,
+    }
+  }
+
+  pub fn resize(&mut self, device: &wgpu::Device, scene_depth_view: &wgpu::TextureView) {
+    self.depth_bind_group = Self::create_depth_bind_group(
+      device,
+      &self.depth_bind_group_layout,
+      self.pass_info,
+      scene_depth_view,
+    );
+  }
+
+  pub async fn reload(
+    &mut self,
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+  ) -> Result<(), OceanError> {
+    self.pipeline = shaders::try_create(device, || {
+      Self::create_pipeline(
+        device,
+        &self.pipeline_layout,
+        shader,
+        self.pass_info.sample_count > 1,
+      )
+    })
+    .await?;
+    Ok(())
+  }
+
+  pub fn draw(
+    &self,
+    encoder: &mut wgpu::CommandEncoder,
+    hdr_view: &wgpu::TextureView,
+    camera_bind_group: &wgpu::BindGroup,
+    environment_bind_group: &wgpu::BindGroup,
+  ) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some("Fog Pass"),
+      color_attachments: &[wgpu::RenderPassColorAttachment {
+        view: hdr_view,
+        resolve_target: None,
+        ops: wgpu::Operations {
+          load: wgpu::LoadOp::Load,
+          store: true,
+        },
+      }],
+      depth_stencil_attachment: None,
+    });
+    render_pass.set_pipeline(&self.pipeline);
+    render_pass.set_bind_group(0, camera_bind_group, &[]);
+    render_pass.set_bind_group(1, &self.depth_bind_group, &[]);
+    render_pass.set_bind_group(2, environment_bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+  }
+
+  fn create_depth_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    pass_info: PassInfo,
+    scene_depth_view: &wgpu::TextureView,
+  ) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Fog depth bind group"),
+      layout,
+      entries: &[wgpu::BindGroupEntry {
+        binding: if pass_info.sample_count > 1 { 0 } else { 1 },
+        resource: wgpu::BindingResource::TextureView(scene_depth_view),
+      }],
+    })
+  }
+
+  fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    multisampled: bool,
+  ) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Fog Pipeline"),
+      layout: Some(layout),
+      vertex: wgpu::VertexState {
+        module: shader,
+        entry_point: "vs_main",
+        buffers: &[],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: shader,
+        entry_point: if multisampled {
+          "fs_multisampled"
+        } else {
+          "fs_main"
+        },
+        targets: &[wgpu::ColorTargetState {
+          format: HDR_FORMAT,
+          // Premultiplied in-scattering over what is left of the frame, whose
+          // alpha is kept.
+          blend: Some(wgpu::BlendState {
+            color: wgpu::BlendComponent {
+              src_factor: wgpu::BlendFactor::One,
+              dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+              operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent {
+              src_factor: wgpu::BlendFactor::Zero,
+              dst_factor: wgpu::BlendFactor::One,
+              operation: wgpu::BlendOperation::Add,
+            },
+          }),
+          write_mask: wgpu::ColorWrites::ALL,
+        }],
+      }),
+      primitive: wgpu::PrimitiveState::default(),
+      depth_stencil: None,
+      multisample: wgpu::MultisampleState::default(),
+      multiview: None,
+    })
+  }
+}
+
+#[test]
+fn test_fog_transmittance() {
+  let fog = Fog::default();
+  let camera = [0.0, 10.0, 0.0];
+  let near = fog.transmittance(camera, [100.0, 0.0, 0.0]);
+  let far = fog.transmittance(camera, [5000.0, 0.0, 0.0]);
+  assert!(near > far && near < 1.0 && far > 0.0);
+  // The air thins out with altitude.
+  let up = fog.transmittance(camera, [0.0, 5010.0, 0.0]);
+  assert!(up > far);
+  // Going up or down the same ray loses the same light.
+  let down = fog.transmittance([0.0, 5010.0, 0.0], camera);
+  assert!((up - down).abs() < 1e-4);
+  // Under the sea level the water takes over.
+  assert_eq!(fog.transmittance([0.0, -5.0, 0.0], [100.0, -5.0, 0.0]), 1.0);
+  let surfacing = fog.transmittance([0.0, -10.0, 0.0], [0.0, 5010.0, 0.0]);
+  assert!((surfacing - fog.transmittance([0.0, 0.0, 0.0], [0.0, 5010.0, 0.0])).abs() < 1e-6);
+
+  let clear = Fog {
+    density: 0.0,
+    ..fog
+  };
+  assert_eq!(clear.transmittance(camera, [5000.0, 0.0, 0.0]), 1.0);
+}
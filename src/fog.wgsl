@@ -0,0 +1,127 @@
+// Aerial perspective over everything drawn in front of the sky, see
+// `FogPass`. The air thins out exponentially with height, light is lost
+// along the view ray and the sky and sun scatter back into it.
+
+struct CameraUniform {
+    pos: vec3<f32>;
+    view_proj: mat4x4<f32>;
+    view: mat4x4<f32>;
+    proj: mat4x4<f32>;
+    inverse_view_proj: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+struct SkyUniform {
+    a: vec3<f32>;
+    b: vec3<f32>;
+    c: vec3<f32>;
+    d: vec3<f32>;
+    e: vec3<f32>;
+    zenith: vec3<f32>;
+};
+
+struct LightingUniform {
+    sun_direction: vec3<f32>;
+    sun_color: vec3<f32>;
+    sky_color: vec3<f32>;
+    sky: SkyUniform;
+    // The `Fog`, density being at sea level.
+    fog_density: f32;
+    fog_height_falloff: f32;
+    fog_anisotropy: f32;
+};
+
+[[group(0), binding(1)]]
+var<uniform> lighting: LightingUniform;
+
+[[group(1), binding(0)]]
+var t_depth_multisampled: texture_depth_multisampled_2d;
+
+[[group(1), binding(1)]]
+var t_depth: texture_depth_2d;
+
+[[group(2), binding(0)]]
+var t_environment: texture_cube<f32>;
+
+[[group(2), binding(1)]]
+var s_environment: sampler;
+
+let PI: f32 = 3.14159265359;
+
+// Optical depth between `start` and `ray` away from it.
+fn optical_depth(start: vec3<f32>, ray: vec3<f32>) -> f32 {
+    let falloff = lighting.fog_height_falloff;
+    let at_start = lighting.fog_density * exp(-falloff * start.y);
+    // The density integrated along the ray, over its length.
+    let climb = falloff * ray.y;
+    var average = 1.0;
+    if (abs(climb) > 1e-4) {
+        average = (1.0 - exp(-climb)) / climb;
+    }
+    return at_start * average * length(ray);
+}
+
+fn henyey_greenstein(cos_theta: f32, g: f32) -> f32 {
+    let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    return (1.0 - g * g) / (4.0 * PI * denominator * sqrt(denominator));
+}
+
+// Light scattered towards the camera by the air seen in `dir`: the sky as
+// seen along the horizon, and the sun, brighter looking towards it.
+fn in_scattering(dir: vec3<f32>) -> vec3<f32> {
+    let horizon = normalize(vec3<f32>(dir.x, max(dir.y, 0.0), dir.z) + vec3<f32>(0.0, 1e-4, 0.0));
+    let sky = textureSampleLevel(t_environment, s_environment, horizon, 0.0).rgb;
+    let cos_theta = dot(dir, normalize(lighting.sun_direction));
+    return sky + lighting.sun_color * henyey_greenstein(cos_theta, lighting.fog_anisotropy);
+}
+
+// `size` is the frame's.
+fn fog(position: vec4<f32>, size: vec2<f32>, depth: f32) -> vec4<f32> {
+    // The sky already has its atmosphere.
+    if (depth >= 1.0) {
+        return vec4<f32>(0.0);
+    }
+    let ndc = vec2<f32>(position.x / size.x * 2.0 - 1.0, 1.0 - position.y / size.y * 2.0);
+    let w = camera.inverse_view_proj * vec4<f32>(ndc, depth, 1.0);
+    var end = w.xyz / w.w;
+    var start = camera.pos;
+
+    // Only the air above the sea level is foggy, the water below is left to
+    // the underwater pass.
+    if (start.y < 0.0 && end.y <= 0.0) {
+        return vec4<f32>(0.0);
+    }
+    let crossing = start.y / (start.y - end.y);
+    if (start.y < 0.0) {
+        start = mix(start, end, crossing);
+    } else if (end.y < 0.0) {
+        end = mix(start, end, crossing);
+    }
+    let ray = end - start;
+
+    let opacity = 1.0 - exp(-optical_depth(start, ray));
+    return vec4<f32>(in_scattering(normalize(w.xyz / w.w - camera.pos)) * opacity, opacity);
+}
+
+// A triangle covering the viewport.
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32) -> [[builtin(position)]] vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+// Blended over the frame, which keeps `1 - opacity` of its light. Only the
+// first sample is fogged, far away edges are hazy anyway.
+[[stage(fragment)]]
+fn fs_multisampled([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {
+    let size = vec2<f32>(textureDimensions(t_depth_multisampled));
+    return fog(position, size, textureLoad(t_depth_multisampled, vec2<i32>(position.xy), 0));
+}
+
+[[stage(fragment)]]
+fn fs_main([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {
+    let size = vec2<f32>(textureDimensions(t_depth));
+    return fog(position, size, textureLoad(t_depth, vec2<i32>(position.xy), 0));
+}
@@ -0,0 +1,153 @@
+use std::path::{Path, PathBuf};
+
+use crate::ocean::OceanError;
+use crate::renderer::{Renderer, RendererError};
+use crate::scene_config::SceneConfig;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExportOptions {
+  pub directory: PathBuf,
+  pub frames: u32,
+  pub dt: f32,
+  pub start_time: f32,
+  pub exr: bool,
+  pub force_fallback_adapter: bool,
+}
+
+impl Default for ExportOptions {
+  fn default() -> ExportOptions {
+    ExportOptions {
+      directory: PathBuf::new(),
+      frames: 60,
+      dt: 1.0 / 30.0,
+      start_time: 0.0,
+      exr: false,
+      force_fallback_adapter: false,
+    }
+  }
+}
+
+// Images replacing the built-in ones, given on the command line.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Textures<'a> {
+  pub foam: Option<&'a Path>,
+  pub lens_dirt: Option<&'a Path>,
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+  Renderer(RendererError),
+  Ocean(OceanError),
+  Io(std::io::Error),
+  Image(PathBuf, image::ImageError),
+}
+
+impl std::fmt::Display for ExportError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ExportError::Renderer(error) => write!(f, "{}", error),
+      ExportError::Ocean(error) => write!(f, "{}", error),
+      ExportError::Io(error) => write!(f, "failed to create output directory: {}", error),
+      ExportError::Image(path, error) => {
+        write!(f, "failed to write {}: {}", path.display(), error)
+      }
+    }
+  }
+}
+
+impl std::error::Error for ExportError {}
+
+impl From<RendererError> for ExportError {
+  fn from(error: RendererError) -> Self {
+    ExportError::Renderer(error)
+  }
+}
+
+impl From<OceanError> for ExportError {
+  fn from(error: OceanError) -> Self {
+    ExportError::Ocean(error)
+  }
+}
+
+// Renders `options.frames` frames at a fixed timestep and writes them to
+// `options.directory`, the scene size being the frame size.
+pub fn export_frames(
+  options: &ExportOptions,
+  scene: &SceneConfig,
+  textures: Textures,
+) -> Result<(), ExportError> {
+  std::fs::create_dir_all(&options.directory).map_err(ExportError::Io)?;
+
+  let mut renderer = pollster::block_on(Renderer::new_headless(
+    scene.width,
+    scene.height,
+    options.force_fallback_adapter,
+    &scene.renderer,
+  ))?;
+  if let Some(path) = textures.foam {
+    renderer.set_foam_texture(path)?;
+  }
+  if let Some(path) = textures.lens_dirt {
+    renderer.set_lens_dirt_texture(path)?;
+  }
+
+  for frame in 0..options.frames {
+    let time = options.start_time + frame as f32 * options.dt;
+    let pixels = renderer.render_frame(time)?;
+
+    save(
+      &options.directory.join(format!("frame_{:04}.png", frame)),
+      &pixels,
+      scene.width,
+      scene.height,
+      image::ColorType::Rgba8,
+    )?;
+
+    if options.exr {
+      export_maps(&renderer, &options.directory, frame)?;
+    }
+
+    log::info!("Exported frame {}/{}", frame + 1, options.frames);
+  }
+
+  Ok(())
+}
+
+// Writes the raw displacement and derivatives maps of every cascade as
+// displacement_<cascade>_<frame>.exr and derivatives_<cascade>_<frame>.exr.
+fn export_maps(renderer: &Renderer, directory: &Path, frame: u32) -> Result<(), ExportError> {
+  let (device, queue) = (renderer.device(), renderer.queue());
+  let cascade = renderer.ocean().cascade();
+  let surfaces = [&cascade.cascade_0, &cascade.cascade_1, &cascade.cascade_2];
+  let size = cascade.parameters().size;
+
+  for (index, surface) in surfaces.iter().enumerate() {
+    let maps = [
+      ("displacement", surface.read_displacement(device, queue)?),
+      ("derivatives", surface.read_derivatives(device, queue)?),
+    ];
+
+    for (name, texels) in maps {
+      save(
+        &directory.join(format!("{}_{}_{:04}.exr", name, index, frame)),
+        bytemuck::cast_slice(&texels),
+        size,
+        size,
+        image::ColorType::Rgba32F,
+      )?;
+    }
+  }
+
+  Ok(())
+}
+
+fn save(
+  path: &Path,
+  bytes: &[u8],
+  width: u32,
+  height: u32,
+  color: image::ColorType,
+) -> Result<(), ExportError> {
+  image::save_buffer(path, bytes, width, height, color)
+    .map_err(|error| ExportError::Image(path.to_path_buf(), error))
+}
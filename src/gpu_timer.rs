@@ -0,0 +1,159 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+
+// Frames whose timestamps can be on their way back at once. Frames resolved
+// while all of them are in use are not timed.
+const READBACK_BUFFERS: usize = 3;
+
+type Mapping = Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>> + Send>>;
+
+enum ReadbackState {
+  Free,
+  // Holds the timestamps of a frame that is not submitted yet.
+  Copied,
+  Mapping(Mapping),
+}
+
+struct Readback {
+  buffer: wgpu::Buffer,
+  state: ReadbackState,
+}
+
+// Polled mappings finish on `Device::poll`, nothing needs waking.
+struct NoopWaker;
+
+impl Wake for NoopWaker {
+  fn wake(self: Arc<Self>) {}
+}
+
+// Measures how long the GPU spends on each part of a frame with timestamp
+// queries. Only available when the adapter supports `TIMESTAMP_QUERY`.
+pub struct GpuTimer {
+  query_set: wgpu::QuerySet,
+  resolve_buffer: wgpu::Buffer,
+  // A ring, `next` being the oldest.
+  readbacks: Vec<Readback>,
+  next: usize,
+  // Nanoseconds per timestamp tick.
+  period: f32,
+  count: u32,
+}
+
+impl GpuTimer {
+  // Room for `count` timestamps per frame, i.e. `count - 1` spans.
+  pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, count: u32) -> Option<GpuTimer> {
+    if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+      return None;
+    }
+
+    let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+      label: Some("GPU timer queries"),
+      ty: wgpu::QueryType::Timestamp,
+      count,
+    });
+
+    let size = Self::buffer_size(count);
+    let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("GPU timer resolve buffer"),
+      size,
+      usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+      mapped_at_creation: false,
+    });
+    let readbacks = (0..READBACK_BUFFERS)
+      .map(|_| Readback {
+        buffer: device.create_buffer(&wgpu::BufferDescriptor {
+          label: Some("GPU timer readback buffer"),
+          size,
+          usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+          mapped_at_creation: false,
+        }),
+        state: ReadbackState::Free,
+      })
+      .collect();
+
+    Some(GpuTimer {
+      query_set,
+      resolve_buffer,
+      readbacks,
+      next: 0,
+      period: queue.get_timestamp_period(),
+      count,
+    })
+  }
+
+  pub fn write_timestamp(&self, encoder: &mut wgpu::CommandEncoder, index: u32) {
+    encoder.write_timestamp(&self.query_set, index);
+  }
+
+  // Copies the timestamps of the frame recorded into `encoder` to where
+  // `read` finds them, unless every readback buffer is still in use.
+  pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+    let readback = &mut self.readbacks[self.next];
+    if !matches!(readback.state, ReadbackState::Free) {
+      return;
+    }
+
+    encoder.resolve_query_set(&self.query_set, 0..self.count, &self.resolve_buffer, 0);
+    encoder.copy_buffer_to_buffer(
+      &self.resolve_buffer,
+      0,
+      &readback.buffer,
+      0,
+      Self::buffer_size(self.count),
+    );
+    readback.state = ReadbackState::Copied;
+    self.next = (self.next + 1) % READBACK_BUFFERS;
+  }
+
+  // Returns the duration of each span between consecutive timestamps in
+  // milliseconds, for the most recent frame the GPU finished since the last
+  // call. Never waits, frames resolved since are read on later calls.
+  pub fn read(&mut self, device: &wgpu::Device) -> Option<Vec<f32>> {
+    device.poll(wgpu::Maintain::Poll);
+    let waker = Waker::from(Arc::new(NoopWaker));
+    let mut context = Context::from_waker(&waker);
+    let period = self.period;
+
+    let mut spans = None;
+    // Oldest first, so the most recent frame is read last.
+    for offset in 0..READBACK_BUFFERS {
+      let readback = &mut self.readbacks[(self.next + offset) % READBACK_BUFFERS];
+      let result = match &mut readback.state {
+        ReadbackState::Free => continue,
+        // Submitted since the last call.
+        ReadbackState::Copied => {
+          let mapping = readback.buffer.slice(..).map_async(wgpu::MapMode::Read);
+          readback.state = ReadbackState::Mapping(Box::pin(mapping));
+          continue;
+        }
+        ReadbackState::Mapping(mapping) => match mapping.as_mut().poll(&mut context) {
+          Poll::Pending => continue,
+          Poll::Ready(result) => result,
+        },
+      };
+
+      readback.state = ReadbackState::Free;
+      if let Err(e) = result {
+        log::warn!("Failed to read GPU timestamps: {}", e);
+        continue;
+      }
+      spans = Some({
+        let data = readback.buffer.slice(..).get_mapped_range();
+        let timestamps: &[u64] = bytemuck::cast_slice(&data);
+        timestamps
+          .windows(2)
+          .map(|pair| pair[1].wrapping_sub(pair[0]) as f32 * period / 1_000_000.0)
+          .collect()
+      });
+      readback.buffer.unmap();
+    }
+
+    spans
+  }
+
+  fn buffer_size(count: u32) -> wgpu::BufferAddress {
+    (count as usize * std::mem::size_of::<u64>()) as wgpu::BufferAddress
+  }
+}
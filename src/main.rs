@@ -1,11 +1,26 @@
 #![allow(dead_code)]
 
+mod bloom;
 mod camera;
+mod cli;
+mod config_watcher;
+mod debug_ui;
+mod debug_view;
+mod environment;
+mod fog;
+mod frame_export;
 mod generate_plane;
-mod ocean;
+mod gpu_timer;
+mod reflections;
 mod renderer;
+mod scene;
+mod scene_config;
+mod sky;
+mod tonemap;
+mod underwater;
 mod vertex;
 
+use wgpu_ocean::ocean;
 use winit::dpi::LogicalSize;
 use winit::{
   event::*,
@@ -14,26 +29,98 @@ use winit::{
   window::WindowBuilder,
 };
 
+use config_watcher::ConfigWatcher;
 use renderer::Renderer;
 
 fn main() {
-  pollster::block_on(run());
+  // Errors only, unless RUST_LOG says otherwise. Reloading shaders also reports
+  // what it reloaded and which files it could not read.
+  #[cfg(not(feature = "shader-hot-reload"))]
+  let default_filter = "error";
+  #[cfg(feature = "shader-hot-reload")]
+  let default_filter = "error,wgpu_ocean=info";
+  env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(default_filter))
+    .init();
+
+  let mut options = match cli::Options::from_args(std::env::args().skip(1)) {
+    Ok(options) => options,
+    Err(cli::ArgsError::Usage(e)) => {
+      eprintln!("{}\n\n{}", e, cli::USAGE);
+      std::process::exit(2);
+    }
+    Err(e) => {
+      eprintln!("{}", e);
+      std::process::exit(2);
+    }
+  };
+
+  match options.export.take() {
+    None => pollster::block_on(run(options)),
+    Some(export) => {
+      let textures = frame_export::Textures {
+        foam: options.foam_texture.as_deref(),
+        lens_dirt: options.lens_dirt_texture.as_deref(),
+      };
+      if let Err(e) = frame_export::export_frames(&export, &options.scene, textures) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+      }
+    }
+  }
 }
 
-pub async fn run() {
+pub async fn run(options: cli::Options) {
   use std::time::Instant;
 
-  env_logger::init();
+  let scene = options.scene;
+  // Edits to the scene file are picked up while running.
+  let mut watcher = options
+    .config_path
+    .map(|path| ConfigWatcher::new(path, options.overrides, scene));
 
   let event_loop = EventLoop::new();
   let window = WindowBuilder::new()
-    .with_inner_size(LogicalSize::new(1280.0, 800.0))
+    .with_inner_size(LogicalSize::new(scene.width, scene.height))
     .build(&event_loop)
     .unwrap();
 
-  let mut state = Renderer::new(&window).await;
+  let mut state = match Renderer::new(&window, &scene.renderer).await {
+    Ok(renderer) => renderer,
+    Err(e) => {
+      eprintln!("{}", e);
+      std::process::exit(1);
+    }
+  };
+  if let Some(path) = &options.foam_texture {
+    if let Err(e) = state.set_foam_texture(path) {
+      eprintln!("{}", e);
+      std::process::exit(1);
+    }
+  }
+  if let Some(path) = &options.lens_dirt_texture {
+    if let Err(e) = state.set_lens_dirt_texture(path) {
+      eprintln!("{}", e);
+      std::process::exit(1);
+    }
+  }
   let mut is_focused = false;
 
+  #[cfg(feature = "shader-hot-reload")]
+  let mut shader_watcher =
+    ocean::ShaderWatcher::new(ocean::shaders::OCEAN_SHADERS.into_iter().chain([
+      renderer::OCEAN_SHADER,
+      debug_view::DEBUG_VIEW_SHADER,
+      environment::ENVIRONMENT_SHADER,
+      reflections::REFLECTIONS_SHADER,
+      scene::MESH_SHADER,
+      sky::SKY_SHADER,
+      tonemap::TONEMAP_SHADER,
+      tonemap::EXPOSURE_SHADER,
+      bloom::BLOOM_SHADER,
+      fog::FOG_SHADER,
+      underwater::UNDERWATER_SHADER,
+    ]));
+
   let start_instant = Instant::now();
   let mut last_frame_instant = Instant::now();
 
@@ -84,7 +171,7 @@ pub async fn run() {
       state.update(dt);
 
       let time = start_instant.elapsed().as_secs_f32();
-      match state.render(time, dt) {
+      match state.render(time) {
         Ok(_) => {}
         // Reconfigure the surface if lost
         Err(wgpu::SurfaceError::Lost) => state.resize(state.size),
@@ -96,6 +183,19 @@ pub async fn run() {
     }
 
     Event::MainEventsCleared => {
+      #[cfg(feature = "shader-hot-reload")]
+      for (shader, source) in shader_watcher.poll() {
+        match pollster::block_on(state.reload_shader(shader, &source)) {
+          Ok(true) => log::info!("Reloaded {}", shader.path),
+          Ok(false) => {}
+          Err(e) => log::error!("Keeping the current {} pipelines: {}", shader.path, e),
+        }
+      }
+
+      if let Some((previous, config)) = watcher.as_mut().and_then(ConfigWatcher::poll) {
+        config_watcher::apply(&mut state, &previous, &config);
+      }
+
       // RedrawRequested will only trigger once, unless we manually
       // request it.
       let visible = window.is_visible().unwrap_or(true);
@@ -0,0 +1,60 @@
+// Lit meshes drawn together with the ocean, see `Renderer::add_mesh`.
+
+struct CameraUniform {
+    pos: vec3<f32>;
+    view_proj: mat4x4<f32>;
+    view: mat4x4<f32>;
+    proj: mat4x4<f32>;
+    inverse_view_proj: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+struct LightingUniform {
+    sun_direction: vec3<f32>;
+    sun_color: vec3<f32>;
+    sky_color: vec3<f32>;
+};
+
+[[group(0), binding(1)]]
+var<uniform> lighting: LightingUniform;
+
+struct MeshUniform {
+    model: mat4x4<f32>;
+    color: vec3<f32>;
+};
+
+[[group(1), binding(0)]]
+var<uniform> mesh: MeshUniform;
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] normal: vec3<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] normal: vec3<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(model: VertexInput) -> VertexOutput {
+    let world_pos = mesh.model * vec4<f32>(model.position, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * world_pos;
+    // Assumes the transform scales uniformly.
+    out.normal = (mesh.model * vec4<f32>(model.normal, 0.0)).xyz;
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let n = normalize(in.normal);
+    let l = normalize(lighting.sun_direction);
+
+    let diffuse = max(dot(n, l), 0.0) * lighting.sun_color;
+    let ambient = (n.y * 0.5 + 0.5) * lighting.sky_color * 0.3;
+    return vec4<f32>(mesh.color * (diffuse + ambient), 1.0);
+}
@@ -0,0 +1,292 @@
+use crate::ocean::ocean_parameters::OceanSpectrumParameters;
+use crate::ocean::pipelines::GenerateMipmapsPipeline;
+use crate::ocean::pipelines::InitialSpectrumPipeline;
+use crate::ocean::pipelines::TimeDependentSpectrumPipeline;
+use crate::ocean::pipelines::WavesDataMergePipeline;
+use crate::ocean::pipelines::FFT;
+use crate::ocean::shaders::{self, Shader};
+use crate::ocean::uniform_staging::UniformStaging;
+use crate::ocean::{OceanError, SimulationTexture};
+
+pub struct FftSimulation {
+  size: u32,
+
+  h0_texture: wgpu::Texture,
+  h0_target_texture: wgpu::Texture,
+  h0k_texture: wgpu::Texture,
+  waves_data_texture: wgpu::Texture,
+
+  amp_dx_dz_texture: wgpu::Texture,
+  amp_dyx_dyz_texture: wgpu::Texture,
+
+  // pipelines
+  initial_spectrum_pipeline: InitialSpectrumPipeline,
+  time_dependent_spectrum_pipeline: TimeDependentSpectrumPipeline,
+  fft: FFT,
+  waves_data_merge_pipeline: WavesDataMergePipeline,
+  generate_mipmaps_pipeline: GenerateMipmapsPipeline,
+}
+
+impl FftSimulation {
+  #[allow(clippy::too_many_arguments)]
+  pub fn new(
+    device: &wgpu::Device,
+    size: u32,
+    params: OceanSpectrumParameters,
+    seed: Option<u64>,
+    lambda: f32,
+    format: wgpu::TextureFormat,
+    displacement_texture: &wgpu::Texture,
+    derivatives_texture: &wgpu::Texture,
+  ) -> Self {
+    let texture_size = wgpu::Extent3d {
+      width: size,
+      height: size,
+      depth_or_array_layers: 1,
+    };
+
+    let h0_texture = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some("H0 texture"),
+      size: texture_size,
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: wgpu::TextureFormat::Rgba32Float,
+      usage: wgpu::TextureUsages::COPY_SRC
+        | wgpu::TextureUsages::COPY_DST
+        | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+
+    let h0_target_texture = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some("H0 target texture"),
+      size: texture_size,
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: wgpu::TextureFormat::Rgba32Float,
+      usage: wgpu::TextureUsages::COPY_SRC
+        | wgpu::TextureUsages::STORAGE_BINDING
+        | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+
+    let h0k_texture = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some("H0k texture"),
+      size: texture_size,
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: wgpu::TextureFormat::Rgba32Float,
+      usage: wgpu::TextureUsages::COPY_SRC
+        | wgpu::TextureUsages::STORAGE_BINDING
+        | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+
+    let waves_data_texture = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some("Waves Data texture"),
+      size: texture_size,
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: wgpu::TextureFormat::Rgba32Float,
+      usage: wgpu::TextureUsages::COPY_SRC
+        | wgpu::TextureUsages::STORAGE_BINDING
+        | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+
+    let amp_dx_dz_texture = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some("Dx / Dz"),
+      size: texture_size,
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: wgpu::TextureFormat::Rgba32Float,
+      usage: wgpu::TextureUsages::COPY_SRC
+        | wgpu::TextureUsages::STORAGE_BINDING
+        | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+
+    let amp_dyx_dyz_texture = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some("Dyx / Dyz"),
+      size: texture_size,
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: wgpu::TextureFormat::Rgba32Float,
+      usage: wgpu::TextureUsages::COPY_SRC
+        | wgpu::TextureUsages::STORAGE_BINDING
+        | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+
+    let initial_spectrum_pipeline = InitialSpectrumPipeline::init(
+      size,
+      params,
+      seed,
+      &device,
+      &h0k_texture,
+      &waves_data_texture,
+      &h0_target_texture,
+    );
+
+    let time_dependent_spectrum_pipeline = TimeDependentSpectrumPipeline::init(
+      size,
+      &device,
+      &h0_texture,
+      &h0_target_texture,
+      &waves_data_texture,
+      &amp_dx_dz_texture,
+      &amp_dyx_dyz_texture,
+    );
+
+    let fft = FFT::init(size, &device, &amp_dx_dz_texture, &amp_dyx_dyz_texture);
+
+    let waves_data_merge_pipeline = WavesDataMergePipeline::init(
+      &device,
+      size,
+      lambda,
+      format,
+      &amp_dx_dz_texture,
+      &amp_dyx_dyz_texture,
+      displacement_texture,
+      derivatives_texture,
+    );
+
+    let generate_mipmaps_pipeline = GenerateMipmapsPipeline::init(
+      &device,
+      size,
+      format,
+      displacement_texture,
+      derivatives_texture,
+    );
+
+    Self {
+      size,
+      h0k_texture,
+      waves_data_texture,
+      h0_texture,
+      h0_target_texture,
+      amp_dx_dz_texture,
+      amp_dyx_dyz_texture,
+
+      initial_spectrum_pipeline,
+      time_dependent_spectrum_pipeline,
+      fft,
+      waves_data_merge_pipeline,
+      generate_mipmaps_pipeline,
+    }
+  }
+
+  pub fn init(&self, encoder: &mut wgpu::CommandEncoder, queue: &wgpu::Queue) {
+    self.initial_spectrum_pipeline.upload_noise(queue);
+    self.initial_spectrum_pipeline.dispatch(encoder);
+    self.finish_transition(encoder);
+    self.fft.precompute(encoder);
+  }
+
+  // The maps written by `OceanSurface` are not owned here.
+  pub fn texture(&self, texture: SimulationTexture) -> Option<&wgpu::Texture> {
+    match texture {
+      SimulationTexture::H0 => Some(&self.h0_texture),
+      SimulationTexture::H0k => Some(&self.h0k_texture),
+      SimulationTexture::WavesData => Some(&self.waves_data_texture),
+      SimulationTexture::AmpDxDz => Some(&self.amp_dx_dz_texture),
+      SimulationTexture::AmpDyxDyz => Some(&self.amp_dyx_dyz_texture),
+      SimulationTexture::Displacement | SimulationTexture::Derivatives => None,
+    }
+  }
+
+  // Rebuilds the pipelines running `shader` and returns whether there were
+  // any. `init` has to run again before the next dispatch.
+  pub async fn reload_shader(
+    &mut self,
+    device: &wgpu::Device,
+    shader: Shader,
+    module: &wgpu::ShaderModule,
+  ) -> Result<bool, OceanError> {
+    match shader {
+      shaders::INITIAL_SPECTRUM => {
+        self
+          .initial_spectrum_pipeline
+          .reload(device, module)
+          .await?
+      }
+      shaders::TIME_DEPENDENT_SPECTRUM => {
+        self
+          .time_dependent_spectrum_pipeline
+          .reload(device, module)
+          .await?
+      }
+      shaders::FFT => self.fft.reload(device, module).await?,
+      shaders::WAVES_DATA_MERGE => {
+        self
+          .waves_data_merge_pipeline
+          .reload(device, module)
+          .await?
+      }
+      shaders::GENERATE_MIPMAPS => {
+        self
+          .generate_mipmaps_pipeline
+          .reload(device, module)
+          .await?
+      }
+      _ => return Ok(false),
+    }
+
+    Ok(true)
+  }
+
+  // The new spectrum is generated into the target texture and blended in by
+  // the time-dependent spectrum pass.
+  pub fn start_transition(
+    &self,
+    encoder: &mut wgpu::CommandEncoder,
+    staging: &mut UniformStaging,
+    params: OceanSpectrumParameters,
+  ) {
+    self
+      .initial_spectrum_pipeline
+      .update_parameters(encoder, staging, params);
+    self.initial_spectrum_pipeline.dispatch(encoder);
+  }
+
+  pub fn finish_transition(&self, encoder: &mut wgpu::CommandEncoder) {
+    encoder.copy_texture_to_texture(
+      wgpu::ImageCopyTexture {
+        texture: &self.h0_target_texture,
+        mip_level: 0,
+        origin: wgpu::Origin3d::ZERO,
+        aspect: wgpu::TextureAspect::All,
+      },
+      wgpu::ImageCopyTexture {
+        texture: &self.h0_texture,
+        mip_level: 0,
+        origin: wgpu::Origin3d::ZERO,
+        aspect: wgpu::TextureAspect::All,
+      },
+      wgpu::Extent3d {
+        width: self.size,
+        height: self.size,
+        depth_or_array_layers: 1,
+      },
+    );
+  }
+
+  pub fn dispatch(
+    &self,
+    encoder: &mut wgpu::CommandEncoder,
+    staging: &mut UniformStaging,
+    time: f32,
+    dt: std::time::Duration,
+    blend: f32,
+  ) {
+    self
+      .time_dependent_spectrum_pipeline
+      .dispatch(encoder, staging, time, blend);
+
+    self.fft.dispatch(encoder);
+
+    self
+      .waves_data_merge_pipeline
+      .dispatch(encoder, staging, dt);
+    self.generate_mipmaps_pipeline.dispatch(encoder);
+  }
+}
@@ -0,0 +1,198 @@
+use crate::ocean::ocean_parameters::OceanSpectrumParameters;
+use crate::ocean::pipelines::{GerstnerWave, GerstnerWavesPipeline};
+use crate::ocean::shaders::{self, Shader};
+use crate::ocean::spectrum::{frequency, Spectrum};
+use crate::ocean::uniform_staging::UniformStaging;
+use crate::ocean::OceanError;
+
+use std::f32::consts::PI;
+
+const DIRECTIONS_PER_BAND: usize = 4;
+
+// Picks a fixed set of wave vectors for the cascade band and weights them by
+// the spectrum. Wave vectors and phases only depend on the length scale and
+// cut-offs, so the waves of two sea states can be blended without popping.
+pub fn sample_gerstner_waves(
+  params: &OceanSpectrumParameters,
+  wave_count: u32,
+  seed: u64,
+) -> Vec<GerstnerWave> {
+  use rand::prelude::*;
+
+  let mut rng = rand::rngs::StdRng::seed_from_u64(seed ^ params.length_scale.to_bits() as u64);
+  let spectrum = Spectrum::new(params);
+
+  // Wave vectors are snapped to the tile's frequency grid so the textures
+  // still tile like the FFT output.
+  let delta_k = 2.0 * PI / params.length_scale;
+  let nyquist_k = PI * params.size as f32 / params.length_scale;
+  let k_min = f32::max(params.cut_off_low, delta_k);
+  let k_max = f32::min(params.cut_off_high, nyquist_k);
+
+  let bands = (wave_count as usize / DIRECTIONS_PER_BAND).max(1);
+  let mut waves: Vec<GerstnerWave> = Vec::with_capacity(bands * DIRECTIONS_PER_BAND);
+
+  if k_max <= k_min {
+    return waves;
+  }
+
+  for band in 0..bands {
+    let k_low = k_min * (k_max / k_min).powf(band as f32 / bands as f32);
+    let k_high = k_min * (k_max / k_min).powf((band + 1) as f32 / bands as f32);
+    let band_area = PI * (k_high * k_high - k_low * k_low) / DIRECTIONS_PER_BAND as f32;
+    let rotation: f32 = rng.gen::<f32>() * 2.0 * PI;
+
+    for direction in 0..DIRECTIONS_PER_BAND {
+      let k_length = k_low + (k_high - k_low) * rng.gen::<f32>();
+      let angle = rotation + 2.0 * PI * direction as f32 / DIRECTIONS_PER_BAND as f32;
+      let phase = rng.gen::<f32>() * 2.0 * PI;
+
+      let kx = (k_length * angle.cos() / delta_k).round() * delta_k;
+      let kz = (k_length * angle.sin() / delta_k).round() * delta_k;
+      let k_snapped = (kx * kx + kz * kz).sqrt();
+
+      if k_snapped <= 0.0 || waves.iter().any(|w| w.k == [kx, kz]) {
+        continue;
+      }
+
+      waves.push(GerstnerWave {
+        k: [kx, kz],
+        amplitude: (2.0 * spectrum.density(kx, kz) * band_area).sqrt(),
+        omega: frequency(k_snapped, params.gravity_acceleration, params.depth),
+        phase,
+        _padding: [0.0; 3],
+      });
+    }
+  }
+
+  // Fewer waves than a band has directions still take a whole band.
+  waves.truncate(wave_count as usize);
+  waves
+}
+
+// How high the waves sampled for `params` rise when they all peak at once.
+pub fn gerstner_crest_height(params: &OceanSpectrumParameters, wave_count: u32, seed: u64) -> f32 {
+  sample_gerstner_waves(params, wave_count, seed)
+    .iter()
+    .map(|wave| wave.amplitude)
+    .sum()
+}
+
+pub struct GerstnerSimulation {
+  wave_count: u32,
+  seed: u64,
+  waves: Vec<GerstnerWave>,
+  target_waves: Vec<GerstnerWave>,
+  pipeline: GerstnerWavesPipeline,
+}
+
+impl GerstnerSimulation {
+  pub fn new(
+    pipeline: GerstnerWavesPipeline,
+    params: OceanSpectrumParameters,
+    wave_count: u32,
+    seed: u64,
+  ) -> Self {
+    let waves = sample_gerstner_waves(&params, wave_count, seed);
+
+    Self {
+      wave_count,
+      seed,
+      target_waves: waves.clone(),
+      waves,
+      pipeline,
+    }
+  }
+
+  pub async fn reload_shader(
+    &mut self,
+    device: &wgpu::Device,
+    shader: Shader,
+    module: &wgpu::ShaderModule,
+  ) -> Result<bool, OceanError> {
+    if shader != shaders::GERSTNER_WAVES {
+      return Ok(false);
+    }
+
+    self.pipeline.reload(device, module).await?;
+    Ok(true)
+  }
+
+  pub fn crest_height(&self, params: &OceanSpectrumParameters) -> f32 {
+    gerstner_crest_height(params, self.wave_count, self.seed)
+  }
+
+  pub fn start_transition(&mut self, params: OceanSpectrumParameters) {
+    self.target_waves = sample_gerstner_waves(&params, self.wave_count, self.seed);
+  }
+
+  pub fn finish_transition(&mut self) {
+    self.waves = self.target_waves.clone();
+  }
+
+  // Ends the transition with the waves `blend` of the way to the target.
+  pub fn stop_transition(&mut self, blend: f32) {
+    self.waves = self.blended_waves(blend);
+  }
+
+  fn blended_waves(&self, blend: f32) -> Vec<GerstnerWave> {
+    self
+      .waves
+      .iter()
+      .zip(&self.target_waves)
+      .map(|(from, to)| GerstnerWave {
+        amplitude: from.amplitude + (to.amplitude - from.amplitude) * blend,
+        ..*from
+      })
+      .collect()
+  }
+
+  pub fn dispatch(
+    &self,
+    encoder: &mut wgpu::CommandEncoder,
+    staging: &mut UniformStaging,
+    time: f32,
+    blend: f32,
+  ) {
+    let waves = self.blended_waves(blend);
+    self.pipeline.dispatch(encoder, staging, time, &waves);
+  }
+}
+
+#[test]
+fn test_gerstner_wave_count() {
+  use crate::ocean::{OceanBackend, MAX_GERSTNER_WAVES};
+
+  let params = OceanSpectrumParameters {
+    wind_speed: 10.0,
+    ..Default::default()
+  };
+  assert!(sample_gerstner_waves(&params, 2, 0).len() <= 2);
+
+  let backend = |wave_count| OceanBackend::Gerstner { wave_count };
+  assert_eq!(backend(1).validate(), Ok(()));
+  assert_eq!(backend(MAX_GERSTNER_WAVES).validate(), Ok(()));
+  assert_eq!(backend(0).validate(), Err(OceanError::InvalidWaveCount(0)));
+  assert_eq!(
+    backend(MAX_GERSTNER_WAVES + 1).validate(),
+    Err(OceanError::InvalidWaveCount(MAX_GERSTNER_WAVES + 1))
+  );
+}
+
+#[test]
+fn test_sample_gerstner_waves_tile() {
+  let params = OceanSpectrumParameters {
+    wind_speed: 10.0,
+    ..Default::default()
+  };
+  let waves = sample_gerstner_waves(&params, 64, 0);
+  let delta_k = 2.0 * PI / params.length_scale;
+
+  assert!(!waves.is_empty() && waves.len() <= 64);
+  for wave in waves {
+    let nx = wave.k[0] / delta_k;
+    let nz = wave.k[1] / delta_k;
+    assert!((nx - nx.round()).abs() < 1e-3 && (nz - nz.round()).abs() < 1e-3);
+    assert!(wave.amplitude.is_finite());
+  }
+}
@@ -0,0 +1,454 @@
+use std::io::Write;
+use std::path::Path;
+
+use crate::ocean::ocean_cascade::LENGTH_SCALES;
+use crate::ocean::ocean_export::export_error;
+use crate::ocean::{OceanCascade, OceanError, OceanMaps};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MeshFormat {
+  Obj,
+  // Binary little-endian PLY.
+  Ply,
+  // Binary glTF 2.0 (.glb).
+  Gltf,
+}
+
+impl MeshFormat {
+  fn extension(self) -> &'static str {
+    match self {
+      MeshFormat::Obj => "obj",
+      MeshFormat::Ply => "ply",
+      MeshFormat::Gltf => "glb",
+    }
+  }
+}
+
+// The world-space rectangle a mesh covers. The surface is sampled on a regular
+// grid of `resolution` vertices along x and z.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct MeshGrid {
+  pub min: [f32; 2],
+  pub max: [f32; 2],
+  pub resolution: [u32; 2],
+}
+
+impl Default for MeshGrid {
+  fn default() -> MeshGrid {
+    MeshGrid {
+      min: [-50.0, -50.0],
+      max: [50.0, 50.0],
+      resolution: [256, 256],
+    }
+  }
+}
+
+impl MeshGrid {
+  fn validate(&self) -> Result<(), OceanError> {
+    if self.resolution[0] < 2 || self.resolution[1] < 2 {
+      return Err(OceanError::Export(String::from(
+        "mesh resolution must be at least 2x2",
+      )));
+    }
+    if !(self.min[0] < self.max[0] && self.min[1] < self.max[1]) {
+      return Err(OceanError::Export(String::from(
+        "mesh rectangle must have a positive area",
+      )));
+    }
+
+    Ok(())
+  }
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct MeshExportSettings {
+  pub format: MeshFormat,
+  pub grid: MeshGrid,
+  pub frames: u32,
+  pub start_time: f32,
+  pub time_step: f32,
+}
+
+impl Default for MeshExportSettings {
+  fn default() -> MeshExportSettings {
+    MeshExportSettings {
+      format: MeshFormat::Obj,
+      grid: MeshGrid::default(),
+      frames: 1,
+      start_time: 0.0,
+      time_step: 1.0 / 30.0,
+    }
+  }
+}
+
+// A displaced grid with y up and counter-clockwise triangles seen from above.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OceanMesh {
+  pub positions: Vec<[f32; 3]>,
+  pub normals: Vec<[f32; 3]>,
+  // Spans [0, 1] over the grid.
+  pub uvs: Vec<[f32; 2]>,
+  pub indices: Vec<u32>,
+}
+
+impl OceanMesh {
+  // Sums the displacement of every cascade at each grid vertex, the same way
+  // the renderer does close to the camera.
+  pub fn new(maps: &[OceanMaps; 3], grid: &MeshGrid) -> Result<OceanMesh, OceanError> {
+    grid.validate()?;
+
+    let [columns, rows] = grid.resolution;
+    let vertex_count = (columns * rows) as usize;
+    let mut positions = Vec::with_capacity(vertex_count);
+    let mut normals = Vec::with_capacity(vertex_count);
+    let mut uvs = Vec::with_capacity(vertex_count);
+
+    for row in 0..rows {
+      for column in 0..columns {
+        let u = column as f32 / (columns - 1) as f32;
+        let v = row as f32 / (rows - 1) as f32;
+        let x = grid.min[0] + u * (grid.max[0] - grid.min[0]);
+        let z = grid.min[1] + v * (grid.max[1] - grid.min[1]);
+
+        let mut displacement = [0.0; 4];
+        let mut derivatives = [0.0; 4];
+        for (cascade, length_scale) in maps.iter().zip(LENGTH_SCALES) {
+          let uv = [x / length_scale, z / length_scale];
+          let d = sample(&cascade.displacement, cascade.size, uv);
+          let n = sample(&cascade.derivatives, cascade.size, uv);
+          for i in 0..4 {
+            displacement[i] += d[i];
+            derivatives[i] += n[i];
+          }
+        }
+
+        let slope = [
+          derivatives[0] / (1.0 + derivatives[2]),
+          derivatives[1] / (1.0 + derivatives[3]),
+        ];
+        let length = (slope[0] * slope[0] + slope[1] * slope[1] + 1.0).sqrt();
+
+        positions.push([x + displacement[0], displacement[1], z + displacement[2]]);
+        normals.push([-slope[0] / length, 1.0 / length, -slope[1] / length]);
+        uvs.push([u, v]);
+      }
+    }
+
+    let mut indices = Vec::with_capacity(((columns - 1) * (rows - 1) * 6) as usize);
+    for row in 0..rows - 1 {
+      for column in 0..columns - 1 {
+        let i0 = row * columns + column;
+        let i1 = i0 + 1;
+        let i2 = i0 + columns;
+        let i3 = i2 + 1;
+        indices.extend_from_slice(&[i0, i2, i1, i1, i2, i3]);
+      }
+    }
+
+    Ok(OceanMesh {
+      positions,
+      normals,
+      uvs,
+      indices,
+    })
+  }
+
+  pub fn save(&self, path: &Path, format: MeshFormat) -> Result<(), OceanError> {
+    let file =
+      std::fs::File::create(path).map_err(|error| export_error(path, &error.to_string()))?;
+    let mut writer = std::io::BufWriter::new(file);
+
+    match format {
+      MeshFormat::Obj => self.write_obj(&mut writer),
+      MeshFormat::Ply => self.write_ply(&mut writer),
+      MeshFormat::Gltf => self.write_glb(&mut writer),
+    }
+    .and_then(|_| writer.flush())
+    .map_err(|error| export_error(path, &error.to_string()))
+  }
+
+  pub fn write_obj<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+    writeln!(writer, "# wgpu_ocean surface")?;
+    for [x, y, z] in &self.positions {
+      writeln!(writer, "v {} {} {}", x, y, z)?;
+    }
+    for [u, v] in &self.uvs {
+      writeln!(writer, "vt {} {}", u, v)?;
+    }
+    for [x, y, z] in &self.normals {
+      writeln!(writer, "vn {} {} {}", x, y, z)?;
+    }
+    for triangle in self.indices.chunks_exact(3) {
+      let [a, b, c] = [triangle[0] + 1, triangle[1] + 1, triangle[2] + 1];
+      writeln!(writer, "f {0}/{0}/{0} {1}/{1}/{1} {2}/{2}/{2}", a, b, c)?;
+    }
+
+    Ok(())
+  }
+
+  pub fn write_ply<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+    write!(
+      writer,
+      concat!(
+        "ply\n",
+        "format binary_little_endian 1.0\n",
+        "element vertex {}\n",
+        "property float x\n",
+        "property float y\n",
+        "property float z\n",
+        "property float nx\n",
+        "property float ny\n",
+        "property float nz\n",
+        "property float s\n",
+        "property float t\n",
+        "element face {}\n",
+        "property list uchar uint vertex_indices\n",
+        "end_header\n"
+      ),
+      self.positions.len(),
+      self.indices.len() / 3
+    )?;
+
+    for i in 0..self.positions.len() {
+      let vertex = [
+        self.positions[i][0],
+        self.positions[i][1],
+        self.positions[i][2],
+        self.normals[i][0],
+        self.normals[i][1],
+        self.normals[i][2],
+        self.uvs[i][0],
+        self.uvs[i][1],
+      ];
+      for value in vertex {
+        writer.write_all(&value.to_le_bytes())?;
+      }
+    }
+
+    for triangle in self.indices.chunks_exact(3) {
+      writer.write_all(&[3])?;
+      for index in triangle {
+        writer.write_all(&index.to_le_bytes())?;
+      }
+    }
+
+    Ok(())
+  }
+
+  // Writes a single-file binary glTF with one mesh. Positions, normals, uvs
+  // and indices live in one buffer, in that order.
+  pub fn write_glb<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+    let vertex_count = self.positions.len();
+    let positions_length = vertex_count * 12;
+    let normals_length = vertex_count * 12;
+    let uvs_length = vertex_count * 8;
+    let indices_length = self.indices.len() * 4;
+
+    let mut binary =
+      Vec::with_capacity(positions_length + normals_length + uvs_length + indices_length);
+    binary.extend_from_slice(bytemuck::cast_slice(&self.positions));
+    binary.extend_from_slice(bytemuck::cast_slice(&self.normals));
+    binary.extend_from_slice(bytemuck::cast_slice(&self.uvs));
+    binary.extend_from_slice(bytemuck::cast_slice(&self.indices));
+
+    let (min, max) =
+      self
+        .positions
+        .iter()
+        .fold(([f32::MAX; 3], [f32::MIN; 3]), |(min, max), position| {
+          (
+            [0, 1, 2].map(|i| f32::min(min[i], position[i])),
+            [0, 1, 2].map(|i| f32::max(max[i], position[i])),
+          )
+        });
+
+    let mut json = format!(
+      concat!(
+        "{{\"asset\":{{\"version\":\"2.0\",\"generator\":\"wgpu_ocean\"}},",
+        "\"scene\":0,\"scenes\":[{{\"nodes\":[0]}}],",
+        "\"nodes\":[{{\"mesh\":0,\"name\":\"Ocean\"}}],",
+        "\"meshes\":[{{\"primitives\":[{{",
+        "\"attributes\":{{\"POSITION\":0,\"NORMAL\":1,\"TEXCOORD_0\":2}},\"indices\":3}}]}}],",
+        "\"buffers\":[{{\"byteLength\":{}}}],",
+        "\"bufferViews\":[",
+        "{{\"buffer\":0,\"byteOffset\":0,\"byteLength\":{},\"target\":34962}},",
+        "{{\"buffer\":0,\"byteOffset\":{},\"byteLength\":{},\"target\":34962}},",
+        "{{\"buffer\":0,\"byteOffset\":{},\"byteLength\":{},\"target\":34962}},",
+        "{{\"buffer\":0,\"byteOffset\":{},\"byteLength\":{},\"target\":34963}}],",
+        "\"accessors\":[",
+        "{{\"bufferView\":0,\"componentType\":5126,\"count\":{},\"type\":\"VEC3\",",
+        "\"min\":[{},{},{}],\"max\":[{},{},{}]}},",
+        "{{\"bufferView\":1,\"componentType\":5126,\"count\":{},\"type\":\"VEC3\"}},",
+        "{{\"bufferView\":2,\"componentType\":5126,\"count\":{},\"type\":\"VEC2\"}},",
+        "{{\"bufferView\":3,\"componentType\":5125,\"count\":{},\"type\":\"SCALAR\"}}]}}"
+      ),
+      binary.len(),
+      positions_length,
+      positions_length,
+      normals_length,
+      positions_length + normals_length,
+      uvs_length,
+      positions_length + normals_length + uvs_length,
+      indices_length,
+      vertex_count,
+      min[0],
+      min[1],
+      min[2],
+      max[0],
+      max[1],
+      max[2],
+      vertex_count,
+      vertex_count,
+      self.indices.len(),
+    )
+    .into_bytes();
+
+    // Chunks are padded to four bytes, JSON with spaces and binary with zeros.
+    while json.len() % 4 != 0 {
+      json.push(b' ');
+    }
+    while binary.len() % 4 != 0 {
+      binary.push(0);
+    }
+
+    let total_length = 12 + 8 + json.len() + 8 + binary.len();
+
+    writer.write_all(b"glTF")?;
+    writer.write_all(&2u32.to_le_bytes())?;
+    writer.write_all(&(total_length as u32).to_le_bytes())?;
+
+    writer.write_all(&(json.len() as u32).to_le_bytes())?;
+    writer.write_all(b"JSON")?;
+    writer.write_all(&json)?;
+
+    writer.write_all(&(binary.len() as u32).to_le_bytes())?;
+    writer.write_all(b"BIN\0")?;
+    writer.write_all(&binary)
+  }
+}
+
+impl OceanCascade {
+  // Meshes the current state of the simulation. Blocks until the maps have
+  // been read back.
+  pub fn mesh(
+    &self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    grid: &MeshGrid,
+  ) -> Result<OceanMesh, OceanError> {
+    OceanMesh::new(&self.read_maps(device, queue)?, grid)
+  }
+
+  // Steps the simulation through `settings.frames` frames at a fixed time step
+  // and writes each one to `directory` as ocean_<frame>.<extension>.
+  pub fn export_mesh(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    settings: &MeshExportSettings,
+    directory: &Path,
+  ) -> Result<(), OceanError> {
+    settings.grid.validate()?;
+    if settings.frames == 0 {
+      return Err(OceanError::NonPositive("frames"));
+    }
+    if settings.time_step.partial_cmp(&0.0) != Some(std::cmp::Ordering::Greater) {
+      return Err(OceanError::NonPositive("time_step"));
+    }
+
+    std::fs::create_dir_all(directory)
+      .map_err(|error| export_error(directory, &error.to_string()))?;
+
+    let dt = std::time::Duration::from_secs_f32(settings.time_step);
+    for frame in 0..settings.frames {
+      let time = settings.start_time + frame as f32 * settings.time_step;
+      self.step(device, queue, time, dt);
+
+      let path = directory.join(format!(
+        "ocean_{:04}.{}",
+        frame,
+        settings.format.extension()
+      ));
+      self
+        .mesh(device, queue, &settings.grid)?
+        .save(&path, settings.format)?;
+    }
+
+    Ok(())
+  }
+}
+
+// Bilinearly samples RGBA `texels` at `uv`, repeating every unit like the
+// renderer's sampler.
+fn sample(texels: &[f32], size: u32, uv: [f32; 2]) -> [f32; 4] {
+  let x = uv[0] * size as f32 - 0.5;
+  let y = uv[1] * size as f32 - 0.5;
+  let (x0, y0) = (x.floor(), y.floor());
+  let (fx, fy) = (x - x0, y - y0);
+
+  let texel = |x: f32, y: f32| {
+    let x = (x as i64).rem_euclid(size as i64) as usize;
+    let y = (y as i64).rem_euclid(size as i64) as usize;
+    let i = 4 * (y * size as usize + x);
+    [texels[i], texels[i + 1], texels[i + 2], texels[i + 3]]
+  };
+
+  let (t00, t10) = (texel(x0, y0), texel(x0 + 1.0, y0));
+  let (t01, t11) = (texel(x0, y0 + 1.0), texel(x0 + 1.0, y0 + 1.0));
+
+  [0, 1, 2, 3].map(|i| {
+    let top = t00[i] + (t10[i] - t00[i]) * fx;
+    let bottom = t01[i] + (t11[i] - t01[i]) * fx;
+    top + (bottom - top) * fy
+  })
+}
+
+#[test]
+fn test_ocean_mesh() {
+  let maps = |height: f32| OceanMaps {
+    size: 2,
+    displacement: [0.0, height, 0.0, 1.0].repeat(4),
+    derivatives: vec![0.0; 16],
+  };
+  let grid = MeshGrid {
+    min: [0.0, 0.0],
+    max: [10.0, 20.0],
+    resolution: [3, 2],
+  };
+
+  let mesh = OceanMesh::new(&[maps(1.0), maps(0.5), maps(0.25)], &grid).unwrap();
+  assert_eq!(mesh.positions.len(), 6);
+  assert_eq!(mesh.positions[5], [10.0, 1.75, 20.0]);
+  assert_eq!(mesh.normals[0], [0.0, 1.0, 0.0]);
+  assert_eq!(mesh.uvs[4], [0.5, 1.0]);
+  assert_eq!(mesh.indices, vec![0, 3, 1, 1, 3, 4, 1, 4, 2, 2, 4, 5]);
+
+  let mut glb = Vec::new();
+  mesh.write_glb(&mut glb).unwrap();
+  assert_eq!(&glb[..4], b"glTF");
+  assert_eq!(glb.len() % 4, 0);
+  assert_eq!(
+    u32::from_le_bytes([glb[8], glb[9], glb[10], glb[11]]) as usize,
+    glb.len()
+  );
+
+  let flat = MeshGrid {
+    resolution: [1, 2],
+    ..grid
+  };
+  assert!(OceanMesh::new(&[maps(1.0), maps(0.5), maps(0.25)], &flat).is_err());
+}
+
+#[test]
+fn test_sample_wraps() {
+  let texels = [[0.0; 4], [1.0; 4], [0.0; 4], [1.0; 4]].concat();
+  assert_eq!(sample(&texels, 2, [0.25, 0.25]), [0.0; 4]);
+  assert_eq!(sample(&texels, 2, [0.5, 0.25]), [0.5; 4]);
+  assert_eq!(sample(&texels, 2, [1.0, 0.25]), [0.5; 4]);
+  assert_eq!(sample(&texels, 2, [-0.25, 0.25]), [1.0; 4]);
+}
@@ -1,9 +1,35 @@
+mod fft_simulation;
+mod gerstner_simulation;
+mod mesh_export;
+mod ocean_backend;
 mod ocean_cascade;
+mod ocean_error;
+mod ocean_export;
+mod ocean_simulation;
 mod ocean_surface;
 mod ocean_parameters;
+pub mod shaders;
+#[cfg(feature = "shader-hot-reload")]
+mod shader_watcher;
+#[cfg(feature = "serde")]
+mod schema;
+mod spectrum;
+mod uniform_staging;
 mod utils;
 mod pipelines;
 
+pub use mesh_export::{MeshExportSettings, MeshFormat, MeshGrid, OceanMesh};
+pub use ocean_backend::{MissingCapability, OceanBackend, MAX_GERSTNER_WAVES};
 pub use ocean_surface::*;
 pub use ocean_cascade::*;
-pub use ocean_parameters::OceanSpectrumParameters;
\ No newline at end of file
+pub use ocean_error::OceanError;
+pub use ocean_export::{ExportSettings, MapFormat, OceanMaps};
+pub use ocean_simulation::{Ocean, OceanBindings, OceanConfig};
+pub use ocean_parameters::OceanSpectrumParameters;
+#[cfg(feature = "serde")]
+pub use schema::Versioned;
+#[cfg(feature = "shader-hot-reload")]
+pub use shader_watcher::ShaderWatcher;
+
+// Bumped whenever a serialized parameter type changes incompatibly.
+pub const SCHEMA_VERSION: u32 = 1;
\ No newline at end of file
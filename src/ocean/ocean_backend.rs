@@ -0,0 +1,206 @@
+use std::fmt;
+
+use crate::ocean::OceanError;
+
+pub const MAX_GERSTNER_WAVES: u32 = 64;
+
+// The largest number of storage textures a single FFT pass binds.
+const FFT_STORAGE_TEXTURES_PER_STAGE: u32 = 3;
+// Compute passes run 16x16 workgroups.
+const FFT_INVOCATIONS_PER_WORKGROUP: u32 = 256;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OceanBackend {
+  // FFT simulation on compute shaders.
+  #[default]
+  Fft,
+  // Sum of sampled Gerstner waves rendered into the same textures with a
+  // fragment shader, for adapters without compute support.
+  Gerstner { wave_count: u32 },
+}
+
+// An adapter capability a backend needs but the adapter lacks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MissingCapability {
+  ComputeShaders,
+  StorageTexturesPerStage { required: u32, supported: u32 },
+  InvocationsPerWorkgroup { required: u32, supported: u32 },
+  TextureUsage {
+    format: wgpu::TextureFormat,
+    usage: wgpu::TextureUsages,
+  },
+}
+
+impl fmt::Display for MissingCapability {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      MissingCapability::ComputeShaders => write!(f, "compute shaders are not supported"),
+      MissingCapability::StorageTexturesPerStage {
+        required,
+        supported,
+      } => write!(
+        f,
+        "{} storage textures per shader stage required, {} supported",
+        required, supported
+      ),
+      MissingCapability::InvocationsPerWorkgroup {
+        required,
+        supported,
+      } => write!(
+        f,
+        "{} invocations per workgroup required, {} supported",
+        required, supported
+      ),
+      MissingCapability::TextureUsage { format, usage } => {
+        write!(f, "{:?} textures do not support {:?}", format, usage)
+      }
+    }
+  }
+}
+
+impl OceanBackend {
+  pub fn gerstner() -> Self {
+    OceanBackend::Gerstner {
+      wave_count: MAX_GERSTNER_WAVES,
+    }
+  }
+
+  // Picks the FFT backend when the adapter can run it and falls back to
+  // Gerstner waves otherwise.
+  pub fn select(adapter: &wgpu::Adapter) -> Self {
+    if OceanBackend::Fft.missing_capabilities(adapter).is_empty() {
+      OceanBackend::Fft
+    } else {
+      OceanBackend::gerstner()
+    }
+  }
+
+  // Rejects settings no adapter could run, see `OceanError::InvalidWaveCount`.
+  pub fn validate(self) -> Result<(), OceanError> {
+    match self {
+      OceanBackend::Gerstner { wave_count } if wave_count == 0 || wave_count > MAX_GERSTNER_WAVES => {
+        Err(OceanError::InvalidWaveCount(wave_count))
+      }
+      _ => Ok(()),
+    }
+  }
+
+  // Lists everything the backend needs beyond the core WebGPU guarantees that
+  // the adapter does not provide. An empty list means the backend can run.
+  pub fn missing_capabilities(self, adapter: &wgpu::Adapter) -> Vec<MissingCapability> {
+    let mut missing = Vec::new();
+
+    let mut require_usage = |format: wgpu::TextureFormat, usage: wgpu::TextureUsages| {
+      if !adapter
+        .get_texture_format_features(format)
+        .allowed_usages
+        .contains(usage)
+      {
+        missing.push(MissingCapability::TextureUsage { format, usage });
+      }
+    };
+
+    match self {
+      OceanBackend::Fft => {
+        require_usage(
+          wgpu::TextureFormat::Rgba32Float,
+          wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+        );
+        require_usage(
+          self.texture_format(wgpu::Features::empty()),
+          wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+        );
+
+        if !adapter
+          .get_downlevel_properties()
+          .flags
+          .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+        {
+          missing.push(MissingCapability::ComputeShaders);
+        }
+      }
+      OceanBackend::Gerstner { .. } => {
+        require_usage(
+          self.texture_format(wgpu::Features::empty()),
+          wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        );
+      }
+    }
+
+    missing.extend(self.missing_limits(&adapter.limits()));
+    missing
+  }
+
+  // Checks limits only, so it also works for a device created elsewhere.
+  pub fn missing_limits(self, limits: &wgpu::Limits) -> Vec<MissingCapability> {
+    let mut missing = Vec::new();
+
+    if let OceanBackend::Fft = self {
+      if limits.max_storage_textures_per_shader_stage < FFT_STORAGE_TEXTURES_PER_STAGE {
+        missing.push(MissingCapability::StorageTexturesPerStage {
+          required: FFT_STORAGE_TEXTURES_PER_STAGE,
+          supported: limits.max_storage_textures_per_shader_stage,
+        });
+      }
+
+      if limits.max_compute_invocations_per_workgroup < FFT_INVOCATIONS_PER_WORKGROUP {
+        missing.push(MissingCapability::InvocationsPerWorkgroup {
+          required: FFT_INVOCATIONS_PER_WORKGROUP,
+          supported: limits.max_compute_invocations_per_workgroup,
+        });
+      }
+    }
+
+    missing
+  }
+
+  // Neither backend relies on optional features: parameters are passed in
+  // uniform buffers and textures are only ever read through sampled bindings.
+  // The FFT backend asks for filterable full float maps where the adapter has
+  // them, see `texture_format`.
+  pub fn features(self, adapter: &wgpu::Adapter) -> wgpu::Features {
+    let format_features = adapter.get_texture_format_features(wgpu::TextureFormat::Rgba32Float);
+    let float_maps = format_features.filterable
+      && format_features
+        .allowed_usages
+        .contains(wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING);
+
+    match self {
+      OceanBackend::Fft if float_maps => wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+      _ => wgpu::Features::empty(),
+    }
+  }
+
+  pub fn limits(self) -> wgpu::Limits {
+    match self {
+      OceanBackend::Fft => wgpu::Limits::downlevel_defaults(),
+      OceanBackend::Gerstner { .. } => wgpu::Limits::downlevel_webgl2_defaults(),
+    }
+  }
+
+  // The format of the displacement and derivatives maps on a device with
+  // `features`. The FFT backend keeps its 32-bit output on devices that have
+  // what `OceanBackend::features` asked for. Elsewhere the maps are half floats,
+  // which are filterable, renderable and usable as write-only storage on every
+  // adapter.
+  pub(crate) fn texture_format(self, features: wgpu::Features) -> wgpu::TextureFormat {
+    match self {
+      OceanBackend::Fft
+        if features.contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES) =>
+      {
+        wgpu::TextureFormat::Rgba32Float
+      }
+      _ => wgpu::TextureFormat::Rgba16Float,
+    }
+  }
+
+  pub(crate) fn texture_usage(self) -> wgpu::TextureUsages {
+    let usage = wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::TEXTURE_BINDING;
+
+    match self {
+      OceanBackend::Fft => usage | wgpu::TextureUsages::STORAGE_BINDING,
+      OceanBackend::Gerstner { .. } => usage | wgpu::TextureUsages::RENDER_ATTACHMENT,
+    }
+  }
+}
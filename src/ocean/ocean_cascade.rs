@@ -1,13 +1,25 @@
+use crate::ocean::shaders::Shader;
+use crate::ocean::uniform_staging::UniformStaging;
+use crate::ocean::OceanBackend;
+use crate::ocean::OceanError;
 use crate::ocean::OceanSpectrumParameters;
 use crate::ocean::OceanSurface;
 
+// World-space size of the tile each cascade covers.
+pub const LENGTH_SCALES: [f32; 3] = [500.0, 85.0, 10.0];
+
 pub struct OceanCascade {
+  params: OceanCascadeParameters,
+
   pub cascade_0: OceanSurface,
   pub cascade_1: OceanSurface,
   pub cascade_2: OceanSurface,
+
+  staging: UniformStaging,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OceanCascadeParameters {
   pub size: u32,
   pub wind_speed: f32,
@@ -15,8 +27,126 @@ pub struct OceanCascadeParameters {
   pub swell: f32,
 }
 
+impl OceanCascadeParameters {
+  pub const PRESETS: [&'static str; 4] = ["calm", "moderate", "rough", "storm"];
+
+  // A named sea state from `PRESETS`, simulated at size 256.
+  pub fn preset(name: &str) -> Option<OceanCascadeParameters> {
+    let (wind_speed, swell) = match name {
+      "calm" => (3.0, 0.1),
+      "moderate" => (10.0, 0.3),
+      "rough" => (18.0, 0.5),
+      "storm" => (28.0, 0.8),
+      _ => return None,
+    };
+
+    Some(OceanCascadeParameters {
+      size: 256,
+      wind_speed,
+      wind_direction: 180.0,
+      swell,
+    })
+  }
+
+  // Checks the spectrum of every cascade, see `OceanSpectrumParameters::validate`.
+  pub fn validate(&self) -> Result<(), OceanError> {
+    for params in OceanCascade::spectrum_parameters(*self) {
+      params.validate()?;
+    }
+    Ok(())
+  }
+}
+
 impl OceanCascade {
-  pub fn new(device: &wgpu::Device, size: u32, params: OceanCascadeParameters) -> Self {
+  pub fn new(
+    device: &wgpu::Device,
+    size: u32,
+    params: OceanCascadeParameters,
+    backend: OceanBackend,
+    seed: Option<u64>,
+  ) -> Self {
+    let [params_0, params_1, params_2] = Self::spectrum_parameters(params);
+    // Each cascade gets its own noise so their waves do not line up.
+    let [seed_0, seed_1, seed_2] = [0, 1, 2].map(|i| seed.map(|seed| seed.wrapping_add(i)));
+
+    let cascade_0 = OceanSurface::new(device, size, params_0, backend, seed_0);
+    let cascade_1 = OceanSurface::new(device, size, params_1, backend, seed_1);
+    let cascade_2 = OceanSurface::new(device, size, params_2, backend, seed_2);
+
+    Self {
+      params,
+      cascade_0,
+      cascade_1,
+      cascade_2,
+      staging: UniformStaging::new(device),
+    }
+  }
+
+  pub fn init(&self, encoder: &mut wgpu::CommandEncoder, queue: &wgpu::Queue) {
+    self.cascade_0.init(encoder, queue);
+    self.cascade_1.init(encoder, queue);
+    self.cascade_2.init(encoder, queue);
+  }
+
+  pub async fn reload_shader(
+    &mut self,
+    device: &wgpu::Device,
+    shader: Shader,
+    module: &wgpu::ShaderModule,
+  ) -> Result<bool, OceanError> {
+    // The cascades share their pipeline layouts, so when the shader does not
+    // fit the first one, the others are left alone too.
+    let reloaded = self.cascade_0.reload_shader(device, shader, module).await?;
+    self.cascade_1.reload_shader(device, shader, module).await?;
+    self.cascade_2.reload_shader(device, shader, module).await?;
+    Ok(reloaded)
+  }
+
+  // `encoder` has to be submitted before the next dispatch.
+  pub fn dispatch(
+    &mut self,
+    encoder: &mut wgpu::CommandEncoder,
+    time: f32,
+    dt: std::time::Duration,
+  ) {
+    self.staging.begin();
+    self
+      .cascade_0
+      .dispatch(encoder, &mut self.staging, time, dt);
+    self
+      .cascade_1
+      .dispatch(encoder, &mut self.staging, time, dt);
+    self
+      .cascade_2
+      .dispatch(encoder, &mut self.staging, time, dt);
+    self.staging.finish();
+  }
+
+  pub fn parameters(&self) -> OceanCascadeParameters {
+    self.params
+  }
+
+  // How high above the mean level the summed cascades may rise.
+  pub fn crest_height(&self) -> f32 {
+    self.cascade_0.crest_height() + self.cascade_1.crest_height() + self.cascade_2.crest_height()
+  }
+
+  // Smoothly moves the sea state to a new wind over `duration` seconds of the
+  // time passed to `dispatch`. The simulation size cannot change after creation.
+  pub fn transition_parameters(&mut self, params: OceanCascadeParameters, duration: f32) {
+    let params = OceanCascadeParameters {
+      size: self.params.size,
+      ..params
+    };
+    let [params_0, params_1, params_2] = Self::spectrum_parameters(params);
+
+    self.cascade_0.transition_parameters(params_0, duration);
+    self.cascade_1.transition_parameters(params_1, duration);
+    self.cascade_2.transition_parameters(params_2, duration);
+    self.params = params;
+  }
+
+  pub(crate) fn spectrum_parameters(params: OceanCascadeParameters) -> [OceanSpectrumParameters; 3] {
     let surface_params = OceanSpectrumParameters {
       size: params.size,
       wind_speed: params.wind_speed,
@@ -25,9 +155,7 @@ impl OceanCascade {
       ..Default::default()
     };
 
-    let length_scale_0 = 500.0;
-    let length_scale_1 = 85.0;
-    let length_scale_2 = 10.0;
+    let [length_scale_0, length_scale_1, length_scale_2] = LENGTH_SCALES;
 
     let boundary_1 = 2.0 * std::f32::consts::PI / length_scale_1 * 6.0;
     let boundary_2 = 2.0 * std::f32::consts::PI / length_scale_2 * 6.0;
@@ -53,26 +181,18 @@ impl OceanCascade {
       ..surface_params
     };
 
-    let cascade_0 = OceanSurface::new(device, size, params_0);
-    let cascade_1 = OceanSurface::new(device, size, params_1);
-    let cascade_2 = OceanSurface::new(device, size, params_2);
-
-    Self {
-      cascade_0,
-      cascade_1,
-      cascade_2,
-    }
+    [params_0, params_1, params_2]
   }
+}
 
-  pub fn init(&self, encoder: &mut wgpu::CommandEncoder, queue: &wgpu::Queue) {
-    self.cascade_0.init(encoder, queue);
-    self.cascade_1.init(encoder, queue);
-    self.cascade_2.init(encoder, queue);
+#[test]
+fn test_presets() {
+  for name in OceanCascadeParameters::PRESETS {
+    let params = OceanCascadeParameters::preset(name).unwrap();
+    for spectrum_params in OceanCascade::spectrum_parameters(params) {
+      assert_eq!(spectrum_params.validate(), Ok(()));
+    }
   }
 
-  pub fn dispatch(&mut self, encoder: &mut wgpu::CommandEncoder, queue: &wgpu::Queue, time: f32, dt: std::time::Duration) {
-    self.cascade_0.dispatch(encoder, queue, time, dt);
-    self.cascade_1.dispatch(encoder, queue, time, dt);
-    self.cascade_2.dispatch(encoder, queue, time, dt);
-  }
+  assert!(OceanCascadeParameters::preset("tsunami").is_none());
 }
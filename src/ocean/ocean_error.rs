@@ -0,0 +1,73 @@
+use std::fmt;
+
+use crate::ocean::MissingCapability;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OceanError {
+  // The simulation size must be a power of two of at least 16.
+  InvalidSize(u32),
+  // The spectrum is undefined for calm or negative wind.
+  InvalidWindSpeed(f32),
+  InvalidCutOff { low: f32, high: f32 },
+  // A parameter that has to be strictly positive is not.
+  NonPositive(&'static str),
+  // Gerstner waves are drawn from 1 to `MAX_GERSTNER_WAVES` waves.
+  InvalidWaveCount(u32),
+  // The device cannot run the requested backend.
+  UnsupportedDevice(Vec<MissingCapability>),
+  // A shader failed to compile or a pipeline failed validation.
+  PipelineCreation(String),
+  // Mapping a map for readback failed.
+  Readback(wgpu::BufferAsyncError),
+  // Writing exported maps to disk failed.
+  Export(String),
+  // Stored parameters were written with a schema this version cannot read.
+  UnsupportedSchemaVersion(u32),
+}
+
+impl fmt::Display for OceanError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      OceanError::InvalidSize(size) => write!(
+        f,
+        "ocean size {} is not a power of two of at least 16",
+        size
+      ),
+      OceanError::InvalidWindSpeed(wind_speed) => {
+        write!(f, "wind speed must be positive, got {}", wind_speed)
+      }
+      OceanError::InvalidCutOff { low, high } => write!(
+        f,
+        "low cut-off {} must be smaller than high cut-off {}",
+        low, high
+      ),
+      OceanError::NonPositive(name) => write!(f, "{} must be positive", name),
+      OceanError::InvalidWaveCount(count) => write!(
+        f,
+        "{} Gerstner waves requested, from 1 to {} are supported",
+        count,
+        crate::ocean::MAX_GERSTNER_WAVES
+      ),
+      OceanError::UnsupportedDevice(missing) => {
+        write!(f, "device cannot run the ocean simulation:")?;
+        for capability in missing {
+          write!(f, " {};", capability)?;
+        }
+        Ok(())
+      }
+      OceanError::PipelineCreation(description) => {
+        write!(f, "failed to create ocean pipelines: {}", description)
+      }
+      OceanError::Readback(error) => write!(f, "failed to read back ocean maps: {}", error),
+      OceanError::Export(description) => write!(f, "failed to export ocean maps: {}", description),
+      OceanError::UnsupportedSchemaVersion(version) => write!(
+        f,
+        "parameters use schema version {}, only version {} is supported",
+        version,
+        crate::ocean::SCHEMA_VERSION
+      ),
+    }
+  }
+}
+
+impl std::error::Error for OceanError {}
@@ -0,0 +1,376 @@
+use std::path::{Path, PathBuf};
+
+use crate::ocean::ocean_cascade::LENGTH_SCALES;
+use crate::ocean::utils::clamp;
+use crate::ocean::{OceanCascade, OceanError};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MapFormat {
+  // 16 bits per channel. Values are remapped to [0, 1] as described in the
+  // sidecar.
+  Png16,
+  // 32-bit float, values are stored as simulated.
+  Exr,
+}
+
+impl MapFormat {
+  fn name(self) -> &'static str {
+    match self {
+      MapFormat::Png16 => "png16",
+      MapFormat::Exr => "exr",
+    }
+  }
+
+  fn extension(self) -> &'static str {
+    match self {
+      MapFormat::Png16 => "png",
+      MapFormat::Exr => "exr",
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ExportSettings {
+  pub format: MapFormat,
+  pub frames: u32,
+  pub start_time: f32,
+  pub time_step: f32,
+  // When non-zero, the sequence is simulated for this many extra frames which
+  // are cross-faded into its first frames, so the last frame flows into the
+  // first one.
+  pub loop_blend_frames: u32,
+  // Displacement mapped to the ends of the PNG range, in world units.
+  pub displacement_range: f32,
+}
+
+impl Default for ExportSettings {
+  fn default() -> ExportSettings {
+    ExportSettings {
+      format: MapFormat::Exr,
+      frames: 1,
+      start_time: 0.0,
+      time_step: 1.0 / 30.0,
+      loop_blend_frames: 0,
+      displacement_range: 10.0,
+    }
+  }
+}
+
+impl ExportSettings {
+  fn validate(&self) -> Result<(), OceanError> {
+    if self.frames == 0 {
+      return Err(OceanError::NonPositive("frames"));
+    }
+    if self.time_step.partial_cmp(&0.0) != Some(std::cmp::Ordering::Greater) {
+      return Err(OceanError::NonPositive("time_step"));
+    }
+    if self.displacement_range.partial_cmp(&0.0) != Some(std::cmp::Ordering::Greater) {
+      return Err(OceanError::NonPositive("displacement_range"));
+    }
+    if self.loop_blend_frames > self.frames {
+      return Err(OceanError::Export(format!(
+        "{} loop blend frames exceed the {} exported frames",
+        self.loop_blend_frames, self.frames
+      )));
+    }
+
+    Ok(())
+  }
+}
+
+// The simulated maps of one cascade, copied to the CPU.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OceanMaps {
+  pub size: u32,
+  // RGBA texels holding the (x, y, z) displacement and the Jacobian.
+  pub displacement: Vec<f32>,
+  // RGBA texels holding (dy/dx, dy/dz, dx/dx, dz/dz).
+  pub derivatives: Vec<f32>,
+}
+
+impl OceanMaps {
+  // Unit normals as RGB texels in tangent space: x and y follow the texture
+  // axes and z points up.
+  pub fn normals(&self) -> Vec<f32> {
+    self
+      .derivatives
+      .chunks_exact(4)
+      .flat_map(|d| {
+        let slope = [d[0] / (1.0 + d[2]), d[1] / (1.0 + d[3])];
+        let length = (slope[0] * slope[0] + slope[1] * slope[1] + 1.0).sqrt();
+        [-slope[0] / length, -slope[1] / length, 1.0 / length]
+      })
+      .collect()
+  }
+
+  // How far the surface folds over itself, 0 where the Jacobian is at least 1
+  // and 1 where it is 0 or below.
+  pub fn foam(&self) -> Vec<f32> {
+    self
+      .displacement
+      .chunks_exact(4)
+      .map(|texel| clamp(1.0 - texel[3], 0.0, 1.0))
+      .collect()
+  }
+
+  pub fn lerp(&self, other: &OceanMaps, t: f32) -> OceanMaps {
+    let lerp = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(a, b)| a + (b - a) * t).collect();
+
+    OceanMaps {
+      size: self.size,
+      displacement: lerp(&self.displacement, &other.displacement),
+      derivatives: lerp(&self.derivatives, &other.derivatives),
+    }
+  }
+
+  // Writes displacement_<cascade>_<frame>, normal_<cascade>_<frame> and
+  // foam_<cascade>_<frame> to `directory`.
+  pub fn save(
+    &self,
+    directory: &Path,
+    cascade: usize,
+    frame: u32,
+    settings: &ExportSettings,
+  ) -> Result<(), OceanError> {
+    let path = |map: &str| map_path(directory, map, cascade, frame, settings.format);
+    let normals = self.normals();
+    let foam = self.foam();
+
+    match settings.format {
+      MapFormat::Exr => {
+        let foam = foam.iter().flat_map(|&f| [f, f, f]).collect::<Vec<_>>();
+
+        save_exr(
+          &path("displacement"),
+          &self.displacement,
+          self.size,
+          image::ColorType::Rgba32F,
+        )?;
+        save_exr(
+          &path("normal"),
+          &normals,
+          self.size,
+          image::ColorType::Rgb32F,
+        )?;
+        save_exr(&path("foam"), &foam, self.size, image::ColorType::Rgb32F)
+      }
+      MapFormat::Png16 => {
+        let scale = 0.5 / settings.displacement_range;
+        let displacement = self
+          .displacement
+          .chunks_exact(4)
+          .flat_map(|texel| [texel[0], texel[1], texel[2]])
+          .map(|d| d * scale + 0.5)
+          .collect::<Vec<_>>();
+        let normals = normals.iter().map(|n| n * 0.5 + 0.5).collect::<Vec<_>>();
+
+        save_png16(
+          &path("displacement"),
+          &displacement,
+          self.size,
+          image::ColorType::Rgb16,
+        )?;
+        save_png16(
+          &path("normal"),
+          &normals,
+          self.size,
+          image::ColorType::Rgb16,
+        )?;
+        save_png16(&path("foam"), &foam, self.size, image::ColorType::L16)
+      }
+    }
+  }
+}
+
+impl OceanCascade {
+  pub fn read_maps(
+    &self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+  ) -> Result<[OceanMaps; 3], OceanError> {
+    Ok([
+      self.cascade_0.read_maps(device, queue)?,
+      self.cascade_1.read_maps(device, queue)?,
+      self.cascade_2.read_maps(device, queue)?,
+    ])
+  }
+
+  // Advances the simulation by one exported frame.
+  pub(crate) fn step(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    time: f32,
+    dt: std::time::Duration,
+  ) {
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+      label: Some("Ocean export encoder"),
+    });
+    self.dispatch(&mut encoder, time, dt);
+    queue.submit(std::iter::once(encoder.finish()));
+  }
+
+  // Steps the simulation through `settings.frames` frames at a fixed time step
+  // and writes the maps of every cascade to `directory`, together with an
+  // ocean.json sidecar. The cascade must have been initialised. Blocks until
+  // every frame has been read back.
+  pub fn export(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    settings: &ExportSettings,
+    directory: &Path,
+  ) -> Result<(), OceanError> {
+    settings.validate()?;
+    std::fs::create_dir_all(directory)
+      .map_err(|error| export_error(directory, &error.to_string()))?;
+
+    let blend_frames = settings.loop_blend_frames;
+    let dt = std::time::Duration::from_secs_f32(settings.time_step);
+    // The first frames of a looping sequence are written once the frames they
+    // are blended with have been simulated.
+    let mut head = Vec::new();
+
+    for step in 0..settings.frames + blend_frames {
+      let time = settings.start_time + step as f32 * settings.time_step;
+
+      self.step(device, queue, time, dt);
+      let maps = self.read_maps(device, queue)?;
+
+      if step < blend_frames {
+        head.push(maps);
+        continue;
+      }
+
+      let (frame, maps) = if step < settings.frames {
+        (step, maps)
+      } else {
+        let frame = step - settings.frames;
+        let t = frame as f32 / blend_frames as f32;
+        let blended = [0, 1, 2].map(|i| maps[i].lerp(&head[frame as usize][i], t));
+        (frame, blended)
+      };
+
+      for (cascade, maps) in maps.iter().enumerate() {
+        maps.save(directory, cascade, frame, settings)?;
+      }
+    }
+
+    let sidecar = directory.join("ocean.json");
+    std::fs::write(&sidecar, sidecar_json(self.parameters().size, settings))
+      .map_err(|error| export_error(&sidecar, &error.to_string()))
+  }
+}
+
+fn sidecar_json(size: u32, settings: &ExportSettings) -> String {
+  let extension = settings.format.extension();
+  let cascades = LENGTH_SCALES
+    .iter()
+    .enumerate()
+    .map(|(i, length_scale)| {
+      format!(
+        concat!(
+          "    {{\n",
+          "      \"length_scale\": {},\n",
+          "      \"displacement\": \"displacement_{}_####.{}\",\n",
+          "      \"normal\": \"normal_{}_####.{}\",\n",
+          "      \"foam\": \"foam_{}_####.{}\"\n",
+          "    }}"
+        ),
+        length_scale, i, extension, i, extension, i, extension
+      )
+    })
+    .collect::<Vec<_>>()
+    .join(",\n");
+
+  format!(
+    concat!(
+      "{{\n",
+      "  \"format\": \"{}\",\n",
+      "  \"size\": {},\n",
+      "  \"frames\": {},\n",
+      "  \"start_time\": {},\n",
+      "  \"time_step\": {},\n",
+      "  \"looping\": {},\n",
+      "  \"displacement_range\": {},\n",
+      "  \"cascades\": [\n{}\n  ]\n",
+      "}}\n"
+    ),
+    settings.format.name(),
+    size,
+    settings.frames,
+    settings.start_time,
+    settings.time_step,
+    settings.loop_blend_frames > 0,
+    settings.displacement_range,
+    cascades
+  )
+}
+
+fn map_path(directory: &Path, map: &str, cascade: usize, frame: u32, format: MapFormat) -> PathBuf {
+  directory.join(format!(
+    "{}_{}_{:04}.{}",
+    map,
+    cascade,
+    frame,
+    format.extension()
+  ))
+}
+
+fn save_exr(
+  path: &Path,
+  texels: &[f32],
+  size: u32,
+  color: image::ColorType,
+) -> Result<(), OceanError> {
+  image::save_buffer(path, bytemuck::cast_slice(texels), size, size, color)
+    .map_err(|error| export_error(path, &error.to_string()))
+}
+
+fn save_png16(
+  path: &Path,
+  values: &[f32],
+  size: u32,
+  color: image::ColorType,
+) -> Result<(), OceanError> {
+  let texels = values
+    .iter()
+    .map(|value| (clamp(*value, 0.0, 1.0) * 65535.0).round() as u16)
+    .collect::<Vec<_>>();
+
+  image::save_buffer(path, bytemuck::cast_slice(&texels), size, size, color)
+    .map_err(|error| export_error(path, &error.to_string()))
+}
+
+pub(crate) fn export_error(path: &Path, description: &str) -> OceanError {
+  OceanError::Export(format!("{}: {}", path.display(), description))
+}
+
+#[test]
+fn test_ocean_maps() {
+  let flat = OceanMaps {
+    size: 1,
+    displacement: vec![0.0, 0.0, 0.0, 1.0],
+    derivatives: vec![0.0; 4],
+  };
+  let folded = OceanMaps {
+    size: 1,
+    displacement: vec![2.0, 1.0, 0.0, -0.5],
+    derivatives: vec![1.0, 0.0, 0.0, 0.0],
+  };
+
+  assert_eq!(flat.normals(), vec![0.0, 0.0, 1.0]);
+  assert_eq!(flat.foam(), vec![0.0]);
+  assert_eq!(folded.foam(), vec![1.0]);
+
+  let normal = folded.normals();
+  assert!((normal[0] + std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+  assert!((normal[2] - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+
+  let half = flat.lerp(&folded, 0.5);
+  assert_eq!(half.displacement, vec![1.0, 0.5, 0.0, 0.25]);
+  assert_eq!(half.derivatives, vec![0.5, 0.0, 0.0, 0.0]);
+}
@@ -1,4 +1,8 @@
-#[derive(Clone, Copy)]
+use crate::ocean::OceanError;
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct OceanSpectrumParameters {
   pub size: u32,
   pub gravity_acceleration: f32,
@@ -37,3 +41,70 @@ impl Default for OceanSpectrumParameters {
     }
   }
 }
+
+impl OceanSpectrumParameters {
+  pub fn validate(&self) -> Result<(), OceanError> {
+    if self.size < 16 || !self.size.is_power_of_two() {
+      return Err(OceanError::InvalidSize(self.size));
+    }
+
+    if !is_positive(self.wind_speed) {
+      return Err(OceanError::InvalidWindSpeed(self.wind_speed));
+    }
+
+    if self.cut_off_low.partial_cmp(&self.cut_off_high) != Some(std::cmp::Ordering::Less) {
+      return Err(OceanError::InvalidCutOff {
+        low: self.cut_off_low,
+        high: self.cut_off_high,
+      });
+    }
+
+    let positive = [
+      ("gravity_acceleration", self.gravity_acceleration),
+      ("length_scale", self.length_scale),
+      ("depth", self.depth),
+      ("fetch", self.fetch),
+    ];
+
+    for (name, value) in positive {
+      if !is_positive(value) {
+        return Err(OceanError::NonPositive(name));
+      }
+    }
+
+    Ok(())
+  }
+}
+
+// False for NaN as well.
+fn is_positive(value: f32) -> bool {
+  value > 0.0
+}
+
+#[test]
+fn test_validate_parameters() {
+  let params = OceanSpectrumParameters::default();
+  assert_eq!(params.validate(), Ok(()));
+
+  let calm = OceanSpectrumParameters {
+    wind_speed: 0.0,
+    ..params
+  };
+  assert_eq!(calm.validate(), Err(OceanError::InvalidWindSpeed(0.0)));
+
+  let inverted = OceanSpectrumParameters {
+    cut_off_low: 2.0,
+    cut_off_high: 1.0,
+    ..params
+  };
+  assert_eq!(
+    inverted.validate(),
+    Err(OceanError::InvalidCutOff {
+      low: 2.0,
+      high: 1.0
+    })
+  );
+
+  let odd_size = OceanSpectrumParameters { size: 100, ..params };
+  assert_eq!(odd_size.validate(), Err(OceanError::InvalidSize(100)));
+}
@@ -0,0 +1,308 @@
+use crate::ocean::ocean_cascade::LENGTH_SCALES;
+use crate::ocean::shaders::{self, Shader};
+use crate::ocean::{
+  ExportSettings, MeshExportSettings, OceanBackend, OceanCascade, OceanCascadeParameters,
+  OceanError,
+};
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct OceanConfig {
+  pub backend: OceanBackend,
+  pub parameters: OceanCascadeParameters,
+  // Makes the generated sea reproducible. `None` picks a random one.
+  pub seed: Option<u64>,
+}
+
+impl Default for OceanConfig {
+  fn default() -> OceanConfig {
+    OceanConfig {
+      backend: OceanBackend::Fft,
+      parameters: OceanCascadeParameters {
+        size: 256,
+        wind_speed: 10.0,
+        wind_direction: 180.0,
+        swell: 0.3,
+      },
+      seed: None,
+    }
+  }
+}
+
+// Everything a renderer needs to sample the simulated maps. `bind_group` binds
+// the maps with `bind_group_layout`:
+//
+//   0: displacement 0   1: derivatives 0   2: sampler
+//   3: displacement 1   4: derivatives 1
+//   5: displacement 2   6: derivatives 2
+//
+// Displacement holds (x, y, z, jacobian), derivatives hold
+// (dy/dx, dy/dz, dx/dx, dz/dz). Cascade `i` tiles every `length_scales[i]`
+// world units.
+pub struct OceanBindings {
+  pub displacement_views: [wgpu::TextureView; 3],
+  pub derivatives_views: [wgpu::TextureView; 3],
+  pub sampler: wgpu::Sampler,
+  pub length_scales: [f32; 3],
+  pub bind_group_layout: wgpu::BindGroupLayout,
+  pub bind_group: wgpu::BindGroup,
+}
+
+// The simulated sea behind one type: `new` builds it for a backend,
+// `update` advances it each frame and `bindings` hands the maps to a renderer.
+pub struct Ocean {
+  cascade: OceanCascade,
+  bindings: OceanBindings,
+  last_time: f32,
+}
+
+impl Ocean {
+  // Resolves once the device has validated the simulation's pipelines.
+  pub async fn new(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    config: OceanConfig,
+  ) -> Result<Ocean, OceanError> {
+    config.parameters.validate()?;
+    config.backend.validate()?;
+
+    let missing = config.backend.missing_limits(&device.limits());
+    if !missing.is_empty() {
+      return Err(OceanError::UnsupportedDevice(missing));
+    }
+
+    // Shader and pipeline errors are reported through the error scope instead
+    // of the device's uncaptured error handler.
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let cascade = OceanCascade::new(
+      device,
+      config.parameters.size,
+      config.parameters,
+      config.backend,
+      config.seed,
+    );
+    if let Some(error) = device.pop_error_scope().await {
+      return Err(OceanError::PipelineCreation(error.to_string()));
+    }
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+      label: Some("Ocean init encoder"),
+    });
+    cascade.init(&mut encoder, queue);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let bindings = Self::create_bindings(device, &cascade);
+
+    Ok(Ocean {
+      cascade,
+      bindings,
+      last_time: 0.0,
+    })
+  }
+
+  // Advances the simulation to `time` seconds. The maps are up to date once
+  // `encoder` has been submitted, which has to happen before the next update.
+  pub fn update(&mut self, encoder: &mut wgpu::CommandEncoder, time: f32) {
+    let dt = std::time::Duration::from_secs_f32(f32::max(time - self.last_time, 0.0));
+    self.last_time = time;
+
+    self.cascade.dispatch(encoder, time, dt);
+  }
+
+  pub fn bindings(&self) -> &OceanBindings {
+    &self.bindings
+  }
+
+  pub fn backend(&self) -> OceanBackend {
+    self.cascade.cascade_0.backend()
+  }
+
+  pub fn parameters(&self) -> OceanCascadeParameters {
+    self.cascade.parameters()
+  }
+
+  // How high above the mean level the simulated waves may rise, also covering
+  // transitions under way. Only the vertical displacement counts.
+  pub fn crest_height(&self) -> f32 {
+    self.cascade.crest_height()
+  }
+
+  pub fn transition_parameters(
+    &mut self,
+    params: OceanCascadeParameters,
+    duration: f32,
+  ) -> Result<(), OceanError> {
+    params.validate()?;
+    self.cascade.transition_parameters(params, duration);
+    Ok(())
+  }
+
+  // Rebuilds the pipelines running `shader` from `source`. A source that does
+  // not compile or does not fit the pipeline layouts leaves the current
+  // pipelines in place. Returns whether any pipeline uses the shader.
+  pub async fn reload_shader(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    shader: Shader,
+    source: &str,
+  ) -> Result<bool, OceanError> {
+    let format = self.cascade.cascade_0.texture_format();
+    let module = shaders::try_create(device, || {
+      device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some(shader.path),
+        source: wgpu::ShaderSource::Wgsl(shaders::with_map_format(shader, source.into(), format)),
+      })
+    })
+    .await?;
+
+    if !self.cascade.reload_shader(device, shader, &module).await? {
+      return Ok(false);
+    }
+
+    // Precomputed data depends on the shaders too.
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+      label: Some("Ocean reload encoder"),
+    });
+    self.cascade.init(&mut encoder, queue);
+    queue.submit(std::iter::once(encoder.finish()));
+    Ok(true)
+  }
+
+  pub fn cascade(&self) -> &OceanCascade {
+    &self.cascade
+  }
+
+  // Writes a map sequence as described by `OceanCascade::export`. The
+  // simulation continues from the last exported frame on the next `update`.
+  pub fn export(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    settings: &ExportSettings,
+    directory: &std::path::Path,
+  ) -> Result<(), OceanError> {
+    self.cascade.export(device, queue, settings, directory)?;
+    self.last_time = settings.start_time
+      + (settings.frames + settings.loop_blend_frames - 1) as f32 * settings.time_step;
+    Ok(())
+  }
+
+  // Writes a mesh sequence as described by `OceanCascade::export_mesh`.
+  pub fn export_mesh(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    settings: &MeshExportSettings,
+    directory: &std::path::Path,
+  ) -> Result<(), OceanError> {
+    self
+      .cascade
+      .export_mesh(device, queue, settings, directory)?;
+    self.last_time = settings.start_time + (settings.frames - 1) as f32 * settings.time_step;
+    Ok(())
+  }
+
+  fn create_bindings(device: &wgpu::Device, cascade: &OceanCascade) -> OceanBindings {
+    let surfaces = [&cascade.cascade_0, &cascade.cascade_1, &cascade.cascade_2];
+
+    let displacement_views = surfaces.map(|surface| {
+      surface
+        .displacement_texture()
+        .create_view(&wgpu::TextureViewDescriptor::default())
+    });
+    let derivatives_views = surfaces.map(|surface| {
+      surface
+        .derivatives_texture()
+        .create_view(&wgpu::TextureViewDescriptor::default())
+    });
+
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+      label: Some("Ocean sampler"),
+      address_mode_u: wgpu::AddressMode::Repeat,
+      address_mode_v: wgpu::AddressMode::Repeat,
+      address_mode_w: wgpu::AddressMode::Repeat,
+      mag_filter: wgpu::FilterMode::Linear,
+      min_filter: wgpu::FilterMode::Linear,
+      mipmap_filter: wgpu::FilterMode::Linear,
+      anisotropy_clamp: std::num::NonZeroU8::new(8),
+      ..Default::default()
+    });
+
+    let texture_entry = |binding: u32, visibility: wgpu::ShaderStages| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility,
+      ty: wgpu::BindingType::Texture {
+        view_dimension: wgpu::TextureViewDimension::D2,
+        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        multisampled: false,
+      },
+      count: None,
+    };
+
+    let all_stages = wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT;
+
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("Ocean bind group layout"),
+      entries: &[
+        texture_entry(0, all_stages),
+        texture_entry(1, all_stages),
+        wgpu::BindGroupLayoutEntry {
+          binding: 2,
+          visibility: all_stages,
+          ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+          count: None,
+        },
+        texture_entry(3, all_stages),
+        texture_entry(4, all_stages),
+        texture_entry(5, all_stages),
+        texture_entry(6, all_stages),
+      ],
+    });
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Ocean bind group"),
+      layout: &bind_group_layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: wgpu::BindingResource::TextureView(&displacement_views[0]),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: wgpu::BindingResource::TextureView(&derivatives_views[0]),
+        },
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: wgpu::BindingResource::Sampler(&sampler),
+        },
+        wgpu::BindGroupEntry {
+          binding: 3,
+          resource: wgpu::BindingResource::TextureView(&displacement_views[1]),
+        },
+        wgpu::BindGroupEntry {
+          binding: 4,
+          resource: wgpu::BindingResource::TextureView(&derivatives_views[1]),
+        },
+        wgpu::BindGroupEntry {
+          binding: 5,
+          resource: wgpu::BindingResource::TextureView(&displacement_views[2]),
+        },
+        wgpu::BindGroupEntry {
+          binding: 6,
+          resource: wgpu::BindingResource::TextureView(&derivatives_views[2]),
+        },
+      ],
+    });
+
+    OceanBindings {
+      displacement_views,
+      derivatives_views,
+      sampler,
+      length_scales: LENGTH_SCALES,
+      bind_group_layout,
+      bind_group,
+    }
+  }
+}
@@ -1,191 +1,317 @@
+use crate::ocean::fft_simulation::FftSimulation;
+use crate::ocean::gerstner_simulation::GerstnerSimulation;
+use crate::ocean::ocean_backend::OceanBackend;
 use crate::ocean::ocean_parameters::OceanSpectrumParameters;
-use crate::ocean::pipelines::GenerateMipmapsPipeline;
-use crate::ocean::pipelines::InitialSpectrumPipeline;
-use crate::ocean::pipelines::TimeDependentSpectrumPipeline;
-use crate::ocean::pipelines::WavesDataMergePipeline;
-use crate::ocean::pipelines::FFT;
+use crate::ocean::pipelines::GerstnerWavesPipeline;
+use crate::ocean::shaders::Shader;
+use crate::ocean::spectrum::height_variance;
+use crate::ocean::uniform_staging::UniformStaging;
+use crate::ocean::utils::{clamp, read_rgba_texture};
+use crate::ocean::{OceanError, OceanMaps};
 
-pub struct OceanSurface {
-  params: OceanSpectrumParameters,
-  parameters_changed: bool,
+const LAMBDA: f32 = 1.2;
+const MIP_LEVEL_COUNT: u32 = 4;
+
+struct SpectrumTransition {
+  from: OceanSpectrumParameters,
+  start_time: f32,
+  duration: f32,
+}
 
-  h0_texture: wgpu::Texture,
-  h0k_texture: wgpu::Texture,
-  waves_data_texture: wgpu::Texture,
+impl SpectrumTransition {
+  fn blend(&self, time: f32) -> f32 {
+    if self.duration <= 0.0 {
+      return 1.0;
+    }
+
+    let t = clamp((time - self.start_time) / self.duration, 0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+  }
 
-  amp_dx_dz_texture: wgpu::Texture,
-  amp_dyx_dyz_texture: wgpu::Texture,
+  // The sea state at `time` of a transition heading for `to`.
+  fn parameters(&self, to: OceanSpectrumParameters, time: f32) -> OceanSpectrumParameters {
+    blend_parameters(self.from, to, self.blend(time))
+  }
+}
+
+// The sea state `blend` of the way from `from` to `to`. The wind direction
+// turns the short way round.
+fn blend_parameters(
+  from: OceanSpectrumParameters,
+  to: OceanSpectrumParameters,
+  blend: f32,
+) -> OceanSpectrumParameters {
+  let lerp = |a: f32, b: f32| a + (b - a) * blend;
+  let turn = (to.wind_direction - from.wind_direction + 180.0).rem_euclid(360.0) - 180.0;
+
+  OceanSpectrumParameters {
+    scale: lerp(from.scale, to.scale),
+    wind_speed: lerp(from.wind_speed, to.wind_speed),
+    wind_direction: (from.wind_direction + turn * blend).rem_euclid(360.0),
+    fetch: lerp(from.fetch, to.fetch),
+    spread_blend: lerp(from.spread_blend, to.spread_blend),
+    swell: lerp(from.swell, to.swell),
+    peak_enhancement: lerp(from.peak_enhancement, to.peak_enhancement),
+    short_waves_fade: lerp(from.short_waves_fade, to.short_waves_fade),
+    ..to
+  }
+}
+
+// Four standard deviations of the FFT height field, about the highest of a few
+// thousand crests.
+fn fft_crest_height(params: &OceanSpectrumParameters) -> f32 {
+  4.0 * height_variance(params).sqrt()
+}
+
+// The textures a cascade can be inspected through. All but the displacement
+// and derivatives maps are intermediate FFT results that the Gerstner backend
+// does not have. The jacobian is in the alpha channel of the displacement map.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimulationTexture {
+  // The initial spectrum.
+  H0,
+  H0k,
+  WavesData,
+  // Frequency domain amplitudes before the inverse FFT, spatial after it.
+  AmpDxDz,
+  AmpDyxDyz,
+  Displacement,
+  Derivatives,
+}
+
+impl SimulationTexture {
+  pub const ALL: [SimulationTexture; 7] = [
+    SimulationTexture::H0,
+    SimulationTexture::H0k,
+    SimulationTexture::WavesData,
+    SimulationTexture::AmpDxDz,
+    SimulationTexture::AmpDyxDyz,
+    SimulationTexture::Displacement,
+    SimulationTexture::Derivatives,
+  ];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      SimulationTexture::H0 => "h0",
+      SimulationTexture::H0k => "h0k",
+      SimulationTexture::WavesData => "waves data",
+      SimulationTexture::AmpDxDz => "dx / dz",
+      SimulationTexture::AmpDyxDyz => "dyx / dyz",
+      SimulationTexture::Displacement => "displacement",
+      SimulationTexture::Derivatives => "derivatives",
+    }
+  }
+
+  // Whether simulating with `backend` produces the texture.
+  pub fn is_available(self, backend: OceanBackend) -> bool {
+    backend == OceanBackend::Fft
+      || matches!(
+        self,
+        SimulationTexture::Displacement | SimulationTexture::Derivatives
+      )
+  }
+
+  pub fn mip_level_count(self) -> u32 {
+    match self {
+      SimulationTexture::Displacement | SimulationTexture::Derivatives => MIP_LEVEL_COUNT,
+      _ => 1,
+    }
+  }
+}
+
+#[allow(clippy::large_enum_variant)]
+enum Simulation {
+  Fft(FftSimulation),
+  Gerstner(GerstnerSimulation),
+}
 
+pub struct OceanSurface {
+  backend: OceanBackend,
+  params: OceanSpectrumParameters,
+  pending_transition: Option<(OceanSpectrumParameters, f32)>,
+  transition: Option<SpectrumTransition>,
+  // Covers the sea state being simulated and those it is heading for.
+  crest_height: f32,
+
+  texture_format: wgpu::TextureFormat,
   displacement_texture: wgpu::Texture,
   derivatives_texture: wgpu::Texture,
 
-  // pipelines
-  initial_spectrum_pipeline: InitialSpectrumPipeline,
-  time_dependent_spectrum_pipeline: TimeDependentSpectrumPipeline,
-  fft: FFT,
-  waves_data_merge_pipeline: WavesDataMergePipeline,
-  generate_mipmaps_pipeline: GenerateMipmapsPipeline,
+  simulation: Simulation,
 }
 
 impl OceanSurface {
-  pub fn new(device: &wgpu::Device, size: u32, params: OceanSpectrumParameters) -> OceanSurface {
+  // `seed` makes the generated sea reproducible, `None` picks a random one.
+  pub fn new(
+    device: &wgpu::Device,
+    size: u32,
+    params: OceanSpectrumParameters,
+    backend: OceanBackend,
+    seed: Option<u64>,
+  ) -> OceanSurface {
     let texture_size = wgpu::Extent3d {
       width: size,
       height: size,
       depth_or_array_layers: 1,
     };
-
-    let h0_texture = device.create_texture(&wgpu::TextureDescriptor {
-      label: Some("H0 texture"),
-      size: texture_size,
-      mip_level_count: 1,
-      sample_count: 1,
-      dimension: wgpu::TextureDimension::D2,
-      format: wgpu::TextureFormat::Rgba32Float,
-      usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::STORAGE_BINDING,
-    });
-
-    let h0k_texture = device.create_texture(&wgpu::TextureDescriptor {
-      label: Some("H0k texture"),
-      size: texture_size,
-      mip_level_count: 1,
-      sample_count: 1,
-      dimension: wgpu::TextureDimension::D2,
-      format: wgpu::TextureFormat::Rgba32Float,
-      usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::STORAGE_BINDING,
-    });
-
-    let waves_data_texture = device.create_texture(&wgpu::TextureDescriptor {
-      label: Some("Waves Data texture"),
-      size: texture_size,
-      mip_level_count: 1,
-      sample_count: 1,
-      dimension: wgpu::TextureDimension::D2,
-      format: wgpu::TextureFormat::Rgba32Float,
-      usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::STORAGE_BINDING,
-    });
-
-    let amp_dx_dz_texture = device.create_texture(&wgpu::TextureDescriptor {
-      label: Some("Dx / Dz"),
-      size: texture_size,
-      mip_level_count: 1,
-      sample_count: 1,
-      dimension: wgpu::TextureDimension::D2,
-      format: wgpu::TextureFormat::Rgba32Float,
-      usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::STORAGE_BINDING,
-    });
-
-    let amp_dyx_dyz_texture = device.create_texture(&wgpu::TextureDescriptor {
-      label: Some("Dyx / Dyz"),
-      size: texture_size,
-      mip_level_count: 1,
-      sample_count: 1,
-      dimension: wgpu::TextureDimension::D2,
-      format: wgpu::TextureFormat::Rgba32Float,
-      usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::STORAGE_BINDING,
-    });
+    let texture_format = backend.texture_format(device.features());
 
     let displacement_texture = device.create_texture(&wgpu::TextureDescriptor {
       label: Some("Displacement"),
       size: texture_size,
-      mip_level_count: 4,
+      mip_level_count: MIP_LEVEL_COUNT,
       sample_count: 1,
       dimension: wgpu::TextureDimension::D2,
-      format: wgpu::TextureFormat::Rgba32Float,
-      usage: wgpu::TextureUsages::COPY_SRC
-        | wgpu::TextureUsages::STORAGE_BINDING
-        | wgpu::TextureUsages::TEXTURE_BINDING,
+      format: texture_format,
+      usage: backend.texture_usage(),
     });
 
     let derivatives_texture = device.create_texture(&wgpu::TextureDescriptor {
       label: Some("Derivatives"),
       size: texture_size,
-      mip_level_count: 4,
+      mip_level_count: MIP_LEVEL_COUNT,
       sample_count: 1,
       dimension: wgpu::TextureDimension::D2,
-      format: wgpu::TextureFormat::Rgba32Float,
-      usage: wgpu::TextureUsages::COPY_SRC
-        | wgpu::TextureUsages::STORAGE_BINDING
-        | wgpu::TextureUsages::TEXTURE_BINDING,
+      format: texture_format,
+      usage: backend.texture_usage(),
     });
 
-    let initial_spectrum_pipeline = InitialSpectrumPipeline::init(
-      size,
+    let simulation = match backend {
+      OceanBackend::Fft => Simulation::Fft(FftSimulation::new(
+        device,
+        size,
+        params,
+        seed,
+        LAMBDA,
+        texture_format,
+        &displacement_texture,
+        &derivatives_texture,
+      )),
+      OceanBackend::Gerstner { wave_count } => {
+        let pipeline = GerstnerWavesPipeline::init(
+          device,
+          params.length_scale,
+          LAMBDA,
+          MIP_LEVEL_COUNT,
+          texture_format,
+          &displacement_texture,
+          &derivatives_texture,
+        );
+
+        Simulation::Gerstner(GerstnerSimulation::new(
+          pipeline,
+          params,
+          wave_count,
+          seed.unwrap_or_else(|| {
+            use rand::prelude::*;
+            rand::rngs::StdRng::from_entropy().gen()
+          }),
+        ))
+      }
+    };
+
+    let mut surface = OceanSurface {
+      backend,
       params,
-      &device,
-      &h0k_texture,
-      &waves_data_texture,
-      &h0_texture,
-    );
-
-    let time_dependent_spectrum_pipeline = TimeDependentSpectrumPipeline::init(
-      size,
-      &device,
-      &h0_texture,
-      &waves_data_texture,
-      &amp_dx_dz_texture,
-      &amp_dyx_dyz_texture,
-    );
-
-    let fft = FFT::init(size, &device, &amp_dx_dz_texture, &amp_dyx_dyz_texture);
-
-    let waves_data_merge_pipeline = WavesDataMergePipeline::init(
-      &device,
-      size,
-      1.2,
-      &amp_dx_dz_texture,
-      &amp_dyx_dyz_texture,
-      &displacement_texture,
-      &derivatives_texture,
-    );
-
-    let generate_mipmaps_pipeline =
-      GenerateMipmapsPipeline::init(&device, size, &displacement_texture, &derivatives_texture);
-
-    OceanSurface {
-      h0k_texture,
-      waves_data_texture,
-      h0_texture,
-      amp_dx_dz_texture,
-      amp_dyx_dyz_texture,
+      pending_transition: None,
+      transition: None,
+      crest_height: 0.0,
+      texture_format,
       displacement_texture,
       derivatives_texture,
+      simulation,
+    };
+    surface.crest_height = surface.sea_crest_height(&params);
+    surface
+  }
 
-      params,
-      initial_spectrum_pipeline,
-      time_dependent_spectrum_pipeline,
-      fft,
-      waves_data_merge_pipeline,
-      generate_mipmaps_pipeline,
-      parameters_changed: false,
+  pub fn init(&self, encoder: &mut wgpu::CommandEncoder, queue: &wgpu::Queue) {
+    if let Simulation::Fft(fft) = &self.simulation {
+      fft.init(encoder, queue);
     }
   }
 
-  pub fn init(&self, encoder: &mut wgpu::CommandEncoder, queue: &wgpu::Queue) {
-    self.initial_spectrum_pipeline.dispatch(encoder, &queue);
-    self.fft.precompute(encoder);
+  // Rebuilds the pipelines running `shader` and returns whether there were
+  // any. `init` has to run again before the next dispatch.
+  pub async fn reload_shader(
+    &mut self,
+    device: &wgpu::Device,
+    shader: Shader,
+    module: &wgpu::ShaderModule,
+  ) -> Result<bool, OceanError> {
+    match &mut self.simulation {
+      Simulation::Fft(fft) => fft.reload_shader(device, shader, module).await,
+      Simulation::Gerstner(gerstner) => gerstner.reload_shader(device, shader, module).await,
+    }
   }
 
   pub fn dispatch(
     &mut self,
     encoder: &mut wgpu::CommandEncoder,
-    queue: &wgpu::Queue,
+    staging: &mut UniformStaging,
     time: f32,
     dt: std::time::Duration,
   ) {
-    if self.parameters_changed {
-      self.initial_spectrum_pipeline.dispatch(encoder, queue);
-      self.parameters_changed = false;
+    if let Some((parameters, duration)) = self.pending_transition.take() {
+      let mut from = self.params;
+
+      // A running transition is cut short, the new one starts from the sea
+      // state it has reached.
+      if let Some(transition) = self.transition.take() {
+        let blend = transition.blend(time);
+        from = transition.parameters(self.params, time);
+        match &mut self.simulation {
+          Simulation::Fft(fft) => {
+            fft.start_transition(encoder, staging, from);
+            fft.finish_transition(encoder);
+          }
+          Simulation::Gerstner(gerstner) => gerstner.stop_transition(blend),
+        }
+      }
+
+      self.params = parameters;
+      match &mut self.simulation {
+        Simulation::Fft(fft) => fft.start_transition(encoder, staging, parameters),
+        Simulation::Gerstner(gerstner) => gerstner.start_transition(parameters),
+      }
+      self.transition = Some(SpectrumTransition {
+        from,
+        start_time: time,
+        duration,
+      });
+    }
+
+    let blend = match &self.transition {
+      Some(transition) => transition.blend(time),
+      None => 0.0,
+    };
+
+    match &self.simulation {
+      Simulation::Fft(fft) => fft.dispatch(encoder, staging, time + 10000.0, dt, blend),
+      Simulation::Gerstner(gerstner) => gerstner.dispatch(encoder, staging, time, blend),
     }
 
-    self
-      .time_dependent_spectrum_pipeline
-      .dispatch(encoder, time + 10000.0);
+    if blend >= 1.0 {
+      match &mut self.simulation {
+        Simulation::Fft(fft) => fft.finish_transition(encoder),
+        Simulation::Gerstner(gerstner) => gerstner.finish_transition(),
+      }
+      self.transition = None;
 
-    self.fft.dispatch(encoder);
+      self.crest_height = self.sea_crest_height(&self.params);
+      if let Some((parameters, _)) = self.pending_transition {
+        self.crest_height = f32::max(self.crest_height, self.sea_crest_height(&parameters));
+      }
+    }
+  }
+
+  pub fn backend(&self) -> OceanBackend {
+    self.backend
+  }
 
-    self.waves_data_merge_pipeline.dispatch(encoder, dt);
-    self.generate_mipmaps_pipeline.dispatch(encoder);
+  // The format of the displacement and derivatives textures.
+  pub fn texture_format(&self) -> wgpu::TextureFormat {
+    self.texture_format
   }
 
   pub fn displacement_texture(&self) -> &wgpu::Texture {
@@ -196,8 +322,151 @@ impl OceanSurface {
     &self.derivatives_texture
   }
 
+  // `None` when the backend does not use the texture.
+  pub fn texture(&self, texture: SimulationTexture) -> Option<&wgpu::Texture> {
+    match (texture, &self.simulation) {
+      (SimulationTexture::Displacement, _) => Some(&self.displacement_texture),
+      (SimulationTexture::Derivatives, _) => Some(&self.derivatives_texture),
+      (_, Simulation::Fft(fft)) => fft.texture(texture),
+      (_, Simulation::Gerstner(_)) => None,
+    }
+  }
+
+  // Reads the current displacement map back as `size * size` RGBA f32 texels.
+  // Blocks until the GPU is idle, so it is meant for exporting, not per frame.
+  pub fn read_displacement(
+    &self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+  ) -> Result<Vec<f32>, OceanError> {
+    read_rgba_texture(
+      device,
+      queue,
+      &self.displacement_texture,
+      self.texture_format,
+      self.params.size,
+    )
+    .map_err(OceanError::Readback)
+  }
+
+  pub fn read_derivatives(
+    &self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+  ) -> Result<Vec<f32>, OceanError> {
+    read_rgba_texture(
+      device,
+      queue,
+      &self.derivatives_texture,
+      self.texture_format,
+      self.params.size,
+    )
+    .map_err(OceanError::Readback)
+  }
+
+  pub fn read_maps(
+    &self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+  ) -> Result<OceanMaps, OceanError> {
+    Ok(OceanMaps {
+      size: self.params.size,
+      displacement: self.read_displacement(device, queue)?,
+      derivatives: self.read_derivatives(device, queue)?,
+    })
+  }
+
+  pub fn parameters(&self) -> OceanSpectrumParameters {
+    self.params
+  }
+
   pub fn change_parameters(&mut self, parameters: OceanSpectrumParameters) {
-    self.params = parameters;
-    self.parameters_changed = true;
+    self.transition_parameters(parameters, 0.0);
+  }
+
+  // Blends from the current spectrum to the one described by `parameters` over
+  // `duration` seconds of simulation time. A transition requested while another
+  // one is running starts from wherever the running one has got to.
+  pub fn transition_parameters(&mut self, parameters: OceanSpectrumParameters, duration: f32) {
+    self.pending_transition = Some((parameters, duration));
+    self.crest_height = f32::max(self.crest_height, self.sea_crest_height(&parameters));
+  }
+
+  // How high above the mean level the waves may rise, including the sea states
+  // of running and pending transitions.
+  pub fn crest_height(&self) -> f32 {
+    self.crest_height
+  }
+
+  fn sea_crest_height(&self, params: &OceanSpectrumParameters) -> f32 {
+    match &self.simulation {
+      Simulation::Fft(_) => fft_crest_height(params),
+      Simulation::Gerstner(gerstner) => gerstner.crest_height(params),
+    }
   }
+
+  pub fn is_transitioning(&self) -> bool {
+    self.transition.is_some() || self.pending_transition.is_some()
+  }
+}
+
+#[test]
+fn test_spectrum_transition_blend() {
+  let transition = SpectrumTransition {
+    from: OceanSpectrumParameters::default(),
+    start_time: 10.0,
+    duration: 4.0,
+  };
+
+  assert_eq!(transition.blend(8.0), 0.0);
+  assert_eq!(transition.blend(12.0), 0.5);
+  assert_eq!(transition.blend(20.0), 1.0);
+}
+
+#[test]
+fn test_spectrum_transition_retarget() {
+  let from = OceanSpectrumParameters {
+    wind_speed: 10.0,
+    wind_direction: 350.0,
+    ..Default::default()
+  };
+  let to = OceanSpectrumParameters {
+    wind_speed: 20.0,
+    wind_direction: 30.0,
+    ..from
+  };
+  let transition = SpectrumTransition {
+    from,
+    start_time: 10.0,
+    duration: 4.0,
+  };
+
+  // Retargeted halfway, the next transition starts between the two winds.
+  let current = transition.parameters(to, 12.0);
+  assert_eq!(current.wind_speed, 15.0);
+  assert_eq!(current.wind_direction, 10.0);
+
+  assert_eq!(transition.parameters(to, 8.0).wind_speed, from.wind_speed);
+  assert_eq!(transition.parameters(to, 20.0).wind_speed, to.wind_speed);
+}
+
+#[test]
+fn test_crest_height() {
+  use crate::ocean::gerstner_simulation::gerstner_crest_height;
+
+  let params = |wind_speed, scale| OceanSpectrumParameters {
+    wind_speed,
+    scale,
+    ..Default::default()
+  };
+
+  let calm = fft_crest_height(&params(3.0, 1.0));
+  let storm = fft_crest_height(&params(28.0, 1.0));
+  assert!(calm > 0.0 && storm > 5.0 * calm);
+
+  // Heights grow with the square root of the spectrum's scale on both backends.
+  let scaled = fft_crest_height(&params(28.0, 4.0));
+  assert!((scaled / storm - 2.0).abs() < 1e-3);
+  let gerstner = |scale| gerstner_crest_height(&params(28.0, scale), 64, 0);
+  assert!((gerstner(4.0) / gerstner(1.0) - 2.0).abs() < 1e-3);
 }
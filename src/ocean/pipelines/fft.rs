@@ -1,27 +1,32 @@
-use wgpu::util::DeviceExt;
+use crate::ocean::shaders;
+use crate::ocean::OceanError;
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 struct Parameters {
-  ping_pong: u32,
   step: u32,
   size: u32,
+  permute: u32,
+  _padding: u32,
 }
 
 pub struct FFT {
   size: u32,
   buffer: wgpu::Texture,
 
+  precompute_pipeline_layout: wgpu::PipelineLayout,
+  step_pipeline_layout: wgpu::PipelineLayout,
   precompute_pipeline: wgpu::ComputePipeline,
   horizontal_step_pipeline: wgpu::ComputePipeline,
   vertical_step_pipeline: wgpu::ComputePipeline,
-  scale_pipeline: wgpu::ComputePipeline,
-  permute_pipeline: wgpu::ComputePipeline,
-  swap_pipeline: wgpu::ComputePipeline,
 
+  // One entry per butterfly step, bound with a dynamic offset.
   parameters_buffer: wgpu::Buffer,
+  parameters_stride: u32,
   precompute_data_texture: wgpu::Texture,
-  texture_bind_group: wgpu::BindGroup,
+  precompute_bind_group: wgpu::BindGroup,
+  // Input -> buffer and buffer -> input.
+  step_bind_groups: [wgpu::BindGroup; 2],
   parameters_bind_group: wgpu::BindGroup,
 }
 
@@ -32,65 +37,52 @@ impl FFT {
     input: &wgpu::Texture,
     input_b: &wgpu::Texture,
   ) -> Self {
+    use wgpu::util::DeviceExt;
+
+    let log_size = (size as f64).log(2.0) as u32;
+
     let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
       label: Some("FFT shader"),
-      source: wgpu::ShaderSource::Wgsl(include_str!("./shaders/fft.wgsl").into()),
+      source: wgpu::ShaderSource::Wgsl(shaders::FFT.source()),
     });
 
-    let texture_bind_group_layout =
+    let sampled_texture_entry = |binding: u32| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::COMPUTE,
+      ty: wgpu::BindingType::Texture {
+        view_dimension: wgpu::TextureViewDimension::D2,
+        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+        multisampled: false,
+      },
+      count: None,
+    };
+
+    let storage_texture_entry = |binding: u32| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::COMPUTE,
+      ty: wgpu::BindingType::StorageTexture {
+        view_dimension: wgpu::TextureViewDimension::D2,
+        format: wgpu::TextureFormat::Rgba32Float,
+        access: wgpu::StorageTextureAccess::WriteOnly,
+      },
+      count: None,
+    };
+
+    let precompute_bind_group_layout =
+      device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("FFT precompute bind group layout"),
+        entries: &[storage_texture_entry(5)],
+      });
+
+    let step_bind_group_layout =
       device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        label: Some("FFT texture bind group layout"),
+        label: Some("FFT step bind group layout"),
         entries: &[
-          wgpu::BindGroupLayoutEntry {
-            binding: 0,
-            visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
-              view_dimension: wgpu::TextureViewDimension::D2,
-              format: wgpu::TextureFormat::Rgba32Float,
-              access: wgpu::StorageTextureAccess::ReadWrite,
-            },
-            count: None,
-          },
-          wgpu::BindGroupLayoutEntry {
-            binding: 1,
-            visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
-              view_dimension: wgpu::TextureViewDimension::D2,
-              format: wgpu::TextureFormat::Rgba32Float,
-              access: wgpu::StorageTextureAccess::ReadWrite,
-            },
-            count: None,
-          },
-          wgpu::BindGroupLayoutEntry {
-            binding: 2,
-            visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
-              view_dimension: wgpu::TextureViewDimension::D2,
-              format: wgpu::TextureFormat::Rgba32Float,
-              access: wgpu::StorageTextureAccess::ReadWrite,
-            },
-            count: None,
-          },
-          wgpu::BindGroupLayoutEntry {
-            binding: 3,
-            visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
-              view_dimension: wgpu::TextureViewDimension::D2,
-              format: wgpu::TextureFormat::Rgba32Float,
-              access: wgpu::StorageTextureAccess::ReadWrite,
-            },
-            count: None,
-          },
-          wgpu::BindGroupLayoutEntry {
-            binding: 4,
-            visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
-              view_dimension: wgpu::TextureViewDimension::D2,
-              format: wgpu::TextureFormat::Rgba32Float,
-              access: wgpu::StorageTextureAccess::ReadWrite,
-            },
-            count: None,
-          },
+          sampled_texture_entry(0),
+          sampled_texture_entry(1),
+          sampled_texture_entry(2),
+          storage_texture_entry(3),
+          storage_texture_entry(4),
         ],
       });
 
@@ -102,23 +94,36 @@ impl FFT {
           visibility: wgpu::ShaderStages::COMPUTE,
           ty: wgpu::BindingType::Buffer {
             ty: wgpu::BufferBindingType::Uniform,
-            has_dynamic_offset: false,
-            min_binding_size: None,
+            has_dynamic_offset: true,
+            min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<Parameters>() as u64),
           },
           count: None,
         }],
       });
 
-    let parameters = Parameters {
-      ping_pong: 0,
-      step: 0,
-      size,
-    };
+    // Horizontal steps come first, the last vertical step applies the
+    // permutation. The step count is even, so the result ends up back in the
+    // input textures.
+    let parameters_stride = device.limits().min_uniform_buffer_offset_alignment;
+    let mut parameters_data = vec![0u8; (parameters_stride * 2 * log_size) as usize];
+
+    for i in 0..2 * log_size {
+      let parameters = Parameters {
+        step: i % log_size,
+        size,
+        permute: (i == 2 * log_size - 1) as u32,
+        _padding: 0,
+      };
+
+      let offset = (i * parameters_stride) as usize;
+      parameters_data[offset..offset + std::mem::size_of::<Parameters>()]
+        .copy_from_slice(bytemuck::bytes_of(&parameters));
+    }
 
     let parameters_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
       label: Some("FFT parameters buffer"),
-      contents: bytemuck::cast_slice(&[parameters]),
-      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      contents: &parameters_data,
+      usage: wgpu::BufferUsages::UNIFORM,
     });
 
     let parameters_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -126,14 +131,18 @@ impl FFT {
       layout: &parameters_bind_group_layout,
       entries: &[wgpu::BindGroupEntry {
         binding: 0,
-        resource: parameters_buffer.as_entire_binding(),
+        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+          buffer: &parameters_buffer,
+          offset: 0,
+          size: wgpu::BufferSize::new(std::mem::size_of::<Parameters>() as u64),
+        }),
       }],
     });
 
     let precompute_data_texture = device.create_texture(&wgpu::TextureDescriptor {
       label: Some("FFT precompute buffer"),
       size: wgpu::Extent3d {
-        width: (size as f64).log(2.0) as u32,
+        width: log_size,
         height: size,
         depth_or_array_layers: 1,
       },
@@ -141,7 +150,9 @@ impl FFT {
       sample_count: 1,
       dimension: wgpu::TextureDimension::D2,
       format: wgpu::TextureFormat::Rgba32Float,
-      usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::STORAGE_BINDING,
+      usage: wgpu::TextureUsages::COPY_SRC
+        | wgpu::TextureUsages::STORAGE_BINDING
+        | wgpu::TextureUsages::TEXTURE_BINDING,
     });
 
     let buffer = device.create_texture(&wgpu::TextureDescriptor {
@@ -155,7 +166,9 @@ impl FFT {
       sample_count: 1,
       dimension: wgpu::TextureDimension::D2,
       format: wgpu::TextureFormat::Rgba32Float,
-      usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::STORAGE_BINDING,
+      usage: wgpu::TextureUsages::COPY_SRC
+        | wgpu::TextureUsages::STORAGE_BINDING
+        | wgpu::TextureUsages::TEXTURE_BINDING,
     });
 
     let buffer_b = device.create_texture(&wgpu::TextureDescriptor {
@@ -169,129 +182,158 @@ impl FFT {
       sample_count: 1,
       dimension: wgpu::TextureDimension::D2,
       format: wgpu::TextureFormat::Rgba32Float,
-      usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::STORAGE_BINDING,
+      usage: wgpu::TextureUsages::COPY_SRC
+        | wgpu::TextureUsages::STORAGE_BINDING
+        | wgpu::TextureUsages::TEXTURE_BINDING,
     });
 
-    let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-      label: Some("FFT texture bind group"),
-      layout: &texture_bind_group_layout,
-      entries: &[
-        wgpu::BindGroupEntry {
-          binding: 0,
-          resource: wgpu::BindingResource::TextureView(&precompute_data_texture.create_view(
-            &wgpu::TextureViewDescriptor {
-              format: Some(wgpu::TextureFormat::Rgba32Float),
-              ..Default::default()
-            },
-          )),
-        },
-        wgpu::BindGroupEntry {
-          binding: 1,
-          resource: wgpu::BindingResource::TextureView(&input.create_view(
-            &wgpu::TextureViewDescriptor {
-              format: Some(wgpu::TextureFormat::Rgba32Float),
-              ..Default::default()
-            },
-          )),
-        },
-        wgpu::BindGroupEntry {
-          binding: 2,
-          resource: wgpu::BindingResource::TextureView(&buffer.create_view(
-            &wgpu::TextureViewDescriptor {
-              format: Some(wgpu::TextureFormat::Rgba32Float),
-              ..Default::default()
-            },
-          )),
-        },
-        wgpu::BindGroupEntry {
-          binding: 3,
-          resource: wgpu::BindingResource::TextureView(&input_b.create_view(
-            &wgpu::TextureViewDescriptor {
-              format: Some(wgpu::TextureFormat::Rgba32Float),
-              ..Default::default()
-            },
-          )),
-        },
-        wgpu::BindGroupEntry {
-          binding: 4,
-          resource: wgpu::BindingResource::TextureView(&buffer_b.create_view(
-            &wgpu::TextureViewDescriptor {
-              format: Some(wgpu::TextureFormat::Rgba32Float),
-              ..Default::default()
-            },
-          )),
-        },
-      ],
-    });
+    let precompute_view =
+      precompute_data_texture.create_view(&wgpu::TextureViewDescriptor::default());
 
-    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-      label: Some("FFT pipeline layout"),
-      bind_group_layouts: &[&texture_bind_group_layout],
-      push_constant_ranges: &[wgpu::PushConstantRange {
-        stages: wgpu::ShaderStages::COMPUTE,
-        range: 0..12,
+    let precompute_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("FFT precompute bind group"),
+      layout: &precompute_bind_group_layout,
+      entries: &[wgpu::BindGroupEntry {
+        binding: 5,
+        resource: wgpu::BindingResource::TextureView(&precompute_view),
       }],
     });
 
-    let precompute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-      label: Some("FFT - Calculate twiddle factors and input indices"),
-      layout: Some(&pipeline_layout),
-      module: &shader,
-      entry_point: "calculat_twiddle_factors_and_input_indices",
-    });
-
-    let horizontal_step_pipeline =
-      device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-        label: Some("FFT - Horizontal step"),
-        layout: Some(&pipeline_layout),
-        module: &shader,
-        entry_point: "horizontal_step_inverse_fft",
-      });
+    let input_views = [
+      input.create_view(&wgpu::TextureViewDescriptor::default()),
+      input_b.create_view(&wgpu::TextureViewDescriptor::default()),
+    ];
+    let buffer_views = [
+      buffer.create_view(&wgpu::TextureViewDescriptor::default()),
+      buffer_b.create_view(&wgpu::TextureViewDescriptor::default()),
+    ];
+
+    let create_step_bind_group = |label: &str, src: &[wgpu::TextureView; 2], dst: &[wgpu::TextureView; 2]| {
+      device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some(label),
+        layout: &step_bind_group_layout,
+        entries: &[
+          wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&precompute_view),
+          },
+          wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::TextureView(&src[0]),
+          },
+          wgpu::BindGroupEntry {
+            binding: 2,
+            resource: wgpu::BindingResource::TextureView(&src[1]),
+          },
+          wgpu::BindGroupEntry {
+            binding: 3,
+            resource: wgpu::BindingResource::TextureView(&dst[0]),
+          },
+          wgpu::BindGroupEntry {
+            binding: 4,
+            resource: wgpu::BindingResource::TextureView(&dst[1]),
+          },
+        ],
+      })
+    };
 
-    let vertical_step_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-      label: Some("FFT - Vertical step"),
-      layout: Some(&pipeline_layout),
-      module: &shader,
-      entry_point: "vertical_step_inverse_fft",
-    });
+    let step_bind_groups = [
+      create_step_bind_group("FFT step bind group - ping", &input_views, &buffer_views),
+      create_step_bind_group("FFT step bind group - pong", &buffer_views, &input_views),
+    ];
 
-    let scale_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-      label: Some("FFT - Scale"),
-      layout: Some(&pipeline_layout),
-      module: &shader,
-      entry_point: "scale",
-    });
+    let precompute_pipeline_layout =
+      device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("FFT precompute pipeline layout"),
+        bind_group_layouts: &[&precompute_bind_group_layout, &parameters_bind_group_layout],
+        push_constant_ranges: &[],
+      });
 
-    let permute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-      label: Some("FFT - Permute"),
-      layout: Some(&pipeline_layout),
-      module: &shader,
-      entry_point: "permute",
+    let step_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("FFT step pipeline layout"),
+      bind_group_layouts: &[&step_bind_group_layout, &parameters_bind_group_layout],
+      push_constant_ranges: &[],
     });
 
-    let swap_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-      label: Some("FFT - Swap"),
-      layout: Some(&pipeline_layout),
-      module: &shader,
-      entry_point: "swap",
-    });
+    let [precompute_pipeline, horizontal_step_pipeline, vertical_step_pipeline] =
+      Self::create_pipelines(
+        device,
+        &precompute_pipeline_layout,
+        &step_pipeline_layout,
+        &shader,
+      );
 
     return Self {
       size,
       buffer,
+      precompute_pipeline_layout,
+      step_pipeline_layout,
       precompute_pipeline,
       parameters_buffer,
+      parameters_stride,
       precompute_data_texture,
-      texture_bind_group,
+      precompute_bind_group,
+      step_bind_groups,
       parameters_bind_group,
       horizontal_step_pipeline,
       vertical_step_pipeline,
-      scale_pipeline,
-      permute_pipeline,
-      swap_pipeline,
     };
   }
 
+  // Rebuilds the pipelines from a new version of their shader. The current
+  // ones are kept if the new one does not match the pipeline layouts. The
+  // precomputed data has to be computed again afterwards.
+  pub async fn reload(
+    &mut self,
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+  ) -> Result<(), OceanError> {
+    let [precompute, horizontal_step, vertical_step] = shaders::try_create(device, || {
+      Self::create_pipelines(
+        device,
+        &self.precompute_pipeline_layout,
+        &self.step_pipeline_layout,
+        shader,
+      )
+    })
+    .await?;
+
+    self.precompute_pipeline = precompute;
+    self.horizontal_step_pipeline = horizontal_step;
+    self.vertical_step_pipeline = vertical_step;
+    Ok(())
+  }
+
+  fn create_pipelines(
+    device: &wgpu::Device,
+    precompute_layout: &wgpu::PipelineLayout,
+    step_layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+  ) -> [wgpu::ComputePipeline; 3] {
+    let precompute = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+      label: Some("FFT - Calculate twiddle factors and input indices"),
+      layout: Some(precompute_layout),
+      module: shader,
+      entry_point: "calculat_twiddle_factors_and_input_indices",
+    });
+
+    let horizontal_step = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+      label: Some("FFT - Horizontal step"),
+      layout: Some(step_layout),
+      module: shader,
+      entry_point: "horizontal_step_inverse_fft",
+    });
+
+    let vertical_step = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+      label: Some("FFT - Vertical step"),
+      layout: Some(step_layout),
+      module: shader,
+      entry_point: "vertical_step_inverse_fft",
+    });
+
+    [precompute, horizontal_step, vertical_step]
+  }
+
   pub fn precompute(&self, encoder: &mut wgpu::CommandEncoder) {
     let log_size = (self.size as f64).log(2.0) as u32;
 
@@ -299,77 +341,29 @@ impl FFT {
       label: Some("FFT precompute"),
     });
 
-    let parameters = Parameters {
-      ping_pong: 0,
-      size: self.size,
-      step: 0,
-    };
-
     compute_pass.set_pipeline(&self.precompute_pipeline);
-    compute_pass.set_bind_group(0, &self.texture_bind_group, &[]);
-
-    compute_pass.set_push_constants(0, bytemuck::cast_slice(&[parameters]));
+    compute_pass.set_bind_group(0, &self.precompute_bind_group, &[]);
+    compute_pass.set_bind_group(1, &self.parameters_bind_group, &[0]);
 
     compute_pass.dispatch(log_size, self.size / 2 / 8, 1);
   }
 
   pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder) {
     let log_size = (self.size as f64).log(2.0) as u32;
-    let mut ping_pong = 0u32;
 
     let mut compute_pass =
       encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("FFT") });
 
-    compute_pass.set_bind_group(0, &self.texture_bind_group, &[]);
-
-    compute_pass.set_pipeline(&self.horizontal_step_pipeline);
-    for i in 0..log_size {
-      ping_pong = if ping_pong == 0 { 1 } else { 0 };
-      let parameters = Parameters {
-        ping_pong,
-        size: self.size,
-        step: i,
-      };
-
-      compute_pass.set_push_constants(0, bytemuck::cast_slice(&[parameters]));
-      compute_pass.dispatch(self.size / 16, self.size / 16, 1);
-    }
-
-    compute_pass.set_pipeline(&self.vertical_step_pipeline);
-    for i in 0..log_size {
-      ping_pong = if ping_pong == 0 { 1 } else { 0 };
-      let parameters = Parameters {
-        ping_pong,
-        size: self.size,
-        step: i,
-      };
+    for i in 0..2 * log_size {
+      if i == 0 {
+        compute_pass.set_pipeline(&self.horizontal_step_pipeline);
+      } else if i == log_size {
+        compute_pass.set_pipeline(&self.vertical_step_pipeline);
+      }
 
-      compute_pass.set_push_constants(0, bytemuck::cast_slice(&[parameters]));
+      compute_pass.set_bind_group(0, &self.step_bind_groups[(i % 2) as usize], &[]);
+      compute_pass.set_bind_group(1, &self.parameters_bind_group, &[i * self.parameters_stride]);
       compute_pass.dispatch(self.size / 16, self.size / 16, 1);
     }
-
-    if ping_pong == 1 {
-      compute_pass.set_pipeline(&self.swap_pipeline);
-      compute_pass.set_push_constants(
-        0,
-        bytemuck::cast_slice(&[Parameters {
-          ping_pong: 0,
-          size: self.size,
-          step: 0,
-        }]),
-      );
-      compute_pass.dispatch(self.size / 16, self.size / 16, 1);
-    }
-
-    compute_pass.set_pipeline(&self.permute_pipeline);
-    compute_pass.set_push_constants(
-      0,
-      bytemuck::cast_slice(&[Parameters {
-        ping_pong: 0,
-        size: self.size,
-        step: 0,
-      }]),
-    );
-    compute_pass.dispatch(self.size / 16, self.size / 16, 1);
   }
 }
@@ -1,6 +1,11 @@
+use crate::ocean::shaders;
+use crate::ocean::OceanError;
+
 pub struct GenerateMipmapsPipeline {
   size: u32,
-  textures_bind_group: wgpu::BindGroup,
+  displacement_bind_group: wgpu::BindGroup,
+  derivatives_bind_group: wgpu::BindGroup,
+  pipeline_layout: wgpu::PipelineLayout,
   pipeline: wgpu::ComputePipeline,
 }
 
@@ -8,226 +13,148 @@ impl GenerateMipmapsPipeline {
   pub fn init<'a>(
     device: &wgpu::Device,
     size: u32,
+    format: wgpu::TextureFormat,
     displacement_texture: &'a wgpu::Texture,
     derivatives_texture: &'a wgpu::Texture,
   ) -> Self {
+    let mip_entry = |binding: u32| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::COMPUTE,
+      ty: wgpu::BindingType::StorageTexture {
+        view_dimension: wgpu::TextureViewDimension::D2,
+        format,
+        access: wgpu::StorageTextureAccess::WriteOnly,
+      },
+      count: None,
+    };
+
     let textures_bind_group_layout =
       device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
         label: Some("generate mipmaps - texture bind group layout"),
         entries: &[
-          // displacement
+          // source
           wgpu::BindGroupLayoutEntry {
             binding: 0,
             visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
-              view_dimension: wgpu::TextureViewDimension::D2,
-              format: wgpu::TextureFormat::Rgba32Float,
-              access: wgpu::StorageTextureAccess::ReadOnly,
-            },
-            count: None,
-          },
-          // displacement_0
-          wgpu::BindGroupLayoutEntry {
-            binding: 1,
-            visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
-              view_dimension: wgpu::TextureViewDimension::D2,
-              format: wgpu::TextureFormat::Rgba32Float,
-              access: wgpu::StorageTextureAccess::WriteOnly,
-            },
-            count: None,
-          },
-          // displacement_1
-          wgpu::BindGroupLayoutEntry {
-            binding: 2,
-            visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
-              view_dimension: wgpu::TextureViewDimension::D2,
-              format: wgpu::TextureFormat::Rgba32Float,
-              access: wgpu::StorageTextureAccess::WriteOnly,
-            },
-            count: None,
-          },
-          // displacement_2
-          wgpu::BindGroupLayoutEntry {
-            binding: 3,
-            visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
-              view_dimension: wgpu::TextureViewDimension::D2,
-              format: wgpu::TextureFormat::Rgba32Float,
-              access: wgpu::StorageTextureAccess::WriteOnly,
-            },
-            count: None,
-          },
-          // derivatives
-          wgpu::BindGroupLayoutEntry {
-            binding: 4,
-            visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
-              view_dimension: wgpu::TextureViewDimension::D2,
-              format: wgpu::TextureFormat::Rgba32Float,
-              access: wgpu::StorageTextureAccess::ReadOnly,
-            },
-            count: None,
-          },
-          // derivatives_1
-          wgpu::BindGroupLayoutEntry {
-            binding: 5,
-            visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
-              view_dimension: wgpu::TextureViewDimension::D2,
-              format: wgpu::TextureFormat::Rgba32Float,
-              access: wgpu::StorageTextureAccess::WriteOnly,
-            },
-            count: None,
-          },
-          // derivatives_2
-          wgpu::BindGroupLayoutEntry {
-            binding: 6,
-            visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
+            ty: wgpu::BindingType::Texture {
               view_dimension: wgpu::TextureViewDimension::D2,
-              format: wgpu::TextureFormat::Rgba32Float,
-              access: wgpu::StorageTextureAccess::WriteOnly,
-            },
-            count: None,
-          },
-          // derivatives_3
-          wgpu::BindGroupLayoutEntry {
-            binding: 7,
-            visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
-              view_dimension: wgpu::TextureViewDimension::D2,
-              format: wgpu::TextureFormat::Rgba32Float,
-              access: wgpu::StorageTextureAccess::WriteOnly,
+              sample_type: wgpu::TextureSampleType::Float { filterable: false },
+              multisampled: false,
             },
             count: None,
           },
+          // mip_1
+          mip_entry(1),
+          // mip_2
+          mip_entry(2),
+          // mip_3
+          mip_entry(3),
         ],
       });
 
     let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
       label: Some("Generate mipmaps pipeline layout"),
       bind_group_layouts: &[&textures_bind_group_layout],
-      push_constant_ranges: &[wgpu::PushConstantRange {
-        stages: wgpu::ShaderStages::COMPUTE,
-        range: 0..8,
-      }],
+      push_constant_ranges: &[],
     });
 
     let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
       label: Some("Generate mipmaps shader"),
-      source: wgpu::ShaderSource::Wgsl(include_str!("./shaders/generate_mipmaps.wgsl").into()),
+      source: wgpu::ShaderSource::Wgsl(shaders::with_map_format(
+        shaders::GENERATE_MIPMAPS,
+        shaders::GENERATE_MIPMAPS.source(),
+        format,
+      )),
     });
 
-    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-      label: Some("Generate mipmaps pipeline"),
-      layout: Some(&pipeline_layout),
-      module: &shader,
-      entry_point: "main",
-    });
+    let pipeline = Self::create_pipeline(device, &pipeline_layout, &shader);
 
-    let textures_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-      label: Some("Generate mipmaps textures"),
-      layout: &textures_bind_group_layout,
-      entries: &[
-        wgpu::BindGroupEntry {
-          binding: 0,
-          resource: wgpu::BindingResource::TextureView(&displacement_texture.create_view(
-            &wgpu::TextureViewDescriptor {
-              base_mip_level: 0,
-              mip_level_count: std::num::NonZeroU32::new(1),
-              ..Default::default()
-            },
-          )),
-        },
-        wgpu::BindGroupEntry {
-          binding: 1,
-          resource: wgpu::BindingResource::TextureView(&displacement_texture.create_view(
-            &wgpu::TextureViewDescriptor {
-              base_mip_level: 1,
-              mip_level_count: std::num::NonZeroU32::new(1),
-              ..Default::default()
-            },
-          )),
-        },
-        wgpu::BindGroupEntry {
-          binding: 2,
-          resource: wgpu::BindingResource::TextureView(&displacement_texture.create_view(
-            &wgpu::TextureViewDescriptor {
-              base_mip_level: 2,
-              mip_level_count: std::num::NonZeroU32::new(1),
-              ..Default::default()
-            },
-          )),
-        },
-        wgpu::BindGroupEntry {
-          binding: 3,
-          resource: wgpu::BindingResource::TextureView(&displacement_texture.create_view(
-            &wgpu::TextureViewDescriptor {
-              base_mip_level: 3,
-              mip_level_count: std::num::NonZeroU32::new(1),
-              ..Default::default()
-            },
-          )),
-        },
-        wgpu::BindGroupEntry {
-          binding: 4,
-          resource: wgpu::BindingResource::TextureView(&derivatives_texture.create_view(
-            &wgpu::TextureViewDescriptor {
-              base_mip_level: 0,
-              mip_level_count: std::num::NonZeroU32::new(1),
-              ..Default::default()
-            },
-          )),
-        },
-        wgpu::BindGroupEntry {
-          binding: 5,
-          resource: wgpu::BindingResource::TextureView(&derivatives_texture.create_view(
-            &wgpu::TextureViewDescriptor {
-              base_mip_level: 1,
-              mip_level_count: std::num::NonZeroU32::new(1),
-              ..Default::default()
-            },
-          )),
-        },
-        wgpu::BindGroupEntry {
-          binding: 6,
-          resource: wgpu::BindingResource::TextureView(&derivatives_texture.create_view(
-            &wgpu::TextureViewDescriptor {
-              base_mip_level: 2,
-              mip_level_count: std::num::NonZeroU32::new(1),
-              ..Default::default()
-            },
-          )),
-        },
-        wgpu::BindGroupEntry {
-          binding: 7,
-          resource: wgpu::BindingResource::TextureView(&derivatives_texture.create_view(
-            &wgpu::TextureViewDescriptor {
-              base_mip_level: 3,
-              mip_level_count: std::num::NonZeroU32::new(1),
-              ..Default::default()
-            },
-          )),
-        },
-      ],
-    });
+    let create_bind_group = |label: &str, texture: &wgpu::Texture| {
+      let mip_view = |mip_level: u32| {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+          base_mip_level: mip_level,
+          mip_level_count: std::num::NonZeroU32::new(1),
+          ..Default::default()
+        })
+      };
+
+      device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some(label),
+        layout: &textures_bind_group_layout,
+        entries: &[
+          wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&mip_view(0)),
+          },
+          wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::TextureView(&mip_view(1)),
+          },
+          wgpu::BindGroupEntry {
+            binding: 2,
+            resource: wgpu::BindingResource::TextureView(&mip_view(2)),
+          },
+          wgpu::BindGroupEntry {
+            binding: 3,
+            resource: wgpu::BindingResource::TextureView(&mip_view(3)),
+          },
+        ],
+      })
+    };
+
+    // One bind group per texture keeps the pass within the downlevel limit
+    // of four storage textures per shader stage.
+    let displacement_bind_group =
+      create_bind_group("Generate mipmaps - displacement", displacement_texture);
+    let derivatives_bind_group =
+      create_bind_group("Generate mipmaps - derivatives", derivatives_texture);
 
     Self {
       size,
-      textures_bind_group,
+      displacement_bind_group,
+      derivatives_bind_group,
+      pipeline_layout,
       pipeline,
     }
   }
 
+  // Rebuilds the pipeline from a new version of its shader. The current one is
+  // kept if the new one does not match the pipeline layout.
+  pub async fn reload(
+    &mut self,
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+  ) -> Result<(), OceanError> {
+    self.pipeline = shaders::try_create(device, || {
+      Self::create_pipeline(device, &self.pipeline_layout, shader)
+    })
+    .await?;
+    Ok(())
+  }
+
+  fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+  ) -> wgpu::ComputePipeline {
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+      label: Some("Generate mipmaps pipeline"),
+      layout: Some(layout),
+      module: shader,
+      entry_point: "main",
+    })
+  }
+
   pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder) {
     let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
       label: Some("Generate mipmaps"),
     });
 
     compute_pass.set_pipeline(&self.pipeline);
-    compute_pass.set_bind_group(0, &self.textures_bind_group, &[]);
-    compute_pass.dispatch(self.size / 16, self.size / 16, 1);
+
+    for bind_group in [&self.displacement_bind_group, &self.derivatives_bind_group] {
+      compute_pass.set_bind_group(0, bind_group, &[]);
+      compute_pass.dispatch(self.size / 16, self.size / 16, 1);
+    }
   }
 }
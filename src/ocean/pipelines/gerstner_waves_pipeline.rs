@@ -0,0 +1,233 @@
+use crate::ocean::ocean_backend::MAX_GERSTNER_WAVES;
+use crate::ocean::shaders;
+use crate::ocean::uniform_staging::UniformStaging;
+use crate::ocean::OceanError;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GerstnerWave {
+  pub k: [f32; 2],
+  pub amplitude: f32,
+  pub omega: f32,
+  pub phase: f32,
+  pub _padding: [f32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct Parameters {
+  time: f32,
+  lambda: f32,
+  length_scale: f32,
+  wave_count: u32,
+  waves: [GerstnerWave; MAX_GERSTNER_WAVES as usize],
+}
+
+pub struct GerstnerWavesPipeline {
+  length_scale: f32,
+  lambda: f32,
+  parameters_buffer: wgpu::Buffer,
+  parameters_bind_group: wgpu::BindGroup,
+  format: wgpu::TextureFormat,
+  pipeline_layout: wgpu::PipelineLayout,
+  pipeline: wgpu::RenderPipeline,
+
+  displacement_views: Vec<wgpu::TextureView>,
+  derivatives_views: Vec<wgpu::TextureView>,
+}
+
+impl GerstnerWavesPipeline {
+  pub fn init<'a>(
+    device: &wgpu::Device,
+    length_scale: f32,
+    lambda: f32,
+    mip_level_count: u32,
+    format: wgpu::TextureFormat,
+    displacement_texture: &'a wgpu::Texture,
+    derivatives_texture: &'a wgpu::Texture,
+  ) -> Self {
+    let parameters_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Gerstner waves parameters buffer"),
+      size: std::mem::size_of::<Parameters>() as wgpu::BufferAddress,
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    });
+
+    let parameters_bind_group_layout =
+      device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Gerstner waves - parameters bind group layout"),
+        entries: &[wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        }],
+      });
+
+    let parameters_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Gerstner waves parameters"),
+      layout: &parameters_bind_group_layout,
+      entries: &[wgpu::BindGroupEntry {
+        binding: 0,
+        resource: parameters_buffer.as_entire_binding(),
+      }],
+    });
+
+    let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+      label: Some("Gerstner waves shader"),
+      source: wgpu::ShaderSource::Wgsl(shaders::GERSTNER_WAVES.source()),
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Gerstner waves pipeline layout"),
+      bind_group_layouts: &[&parameters_bind_group_layout],
+      push_constant_ranges: &[],
+    });
+
+    let pipeline = Self::create_pipeline(device, &pipeline_layout, &shader, format);
+
+    let mip_view = |texture: &wgpu::Texture, mip_level: u32| {
+      texture.create_view(&wgpu::TextureViewDescriptor {
+        base_mip_level: mip_level,
+        mip_level_count: std::num::NonZeroU32::new(1),
+        ..Default::default()
+      })
+    };
+
+    let displacement_views = (0..mip_level_count)
+      .map(|mip_level| mip_view(displacement_texture, mip_level))
+      .collect();
+
+    let derivatives_views = (0..mip_level_count)
+      .map(|mip_level| mip_view(derivatives_texture, mip_level))
+      .collect();
+
+    Self {
+      length_scale,
+      lambda,
+      parameters_buffer,
+      parameters_bind_group,
+      format,
+      pipeline_layout,
+      pipeline,
+      displacement_views,
+      derivatives_views,
+    }
+  }
+
+  // Rebuilds the pipeline from a new version of its shader. The current one is
+  // kept if the new one does not match the pipeline layout.
+  pub async fn reload(
+    &mut self,
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+  ) -> Result<(), OceanError> {
+    self.pipeline = shaders::try_create(device, || {
+      Self::create_pipeline(device, &self.pipeline_layout, shader, self.format)
+    })
+    .await?;
+    Ok(())
+  }
+
+  fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+  ) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Gerstner waves pipeline"),
+      layout: Some(layout),
+      vertex: wgpu::VertexState {
+        module: shader,
+        entry_point: "vs_main",
+        buffers: &[],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: shader,
+        entry_point: "fs_main",
+        targets: &[
+          wgpu::ColorTargetState {
+            format,
+            blend: None,
+            write_mask: wgpu::ColorWrites::ALL,
+          },
+          wgpu::ColorTargetState {
+            format,
+            blend: None,
+            write_mask: wgpu::ColorWrites::ALL,
+          },
+        ],
+      }),
+      primitive: wgpu::PrimitiveState::default(),
+      depth_stencil: None,
+      multisample: wgpu::MultisampleState::default(),
+      multiview: None,
+    })
+  }
+
+  pub fn dispatch(
+    &self,
+    encoder: &mut wgpu::CommandEncoder,
+    staging: &mut UniformStaging,
+    time: f32,
+    waves: &[GerstnerWave],
+  ) {
+    let mut parameters = Parameters {
+      time,
+      lambda: self.lambda,
+      length_scale: self.length_scale,
+      wave_count: 0,
+      waves: [bytemuck::Zeroable::zeroed(); MAX_GERSTNER_WAVES as usize],
+    };
+
+    for (dst, src) in parameters.waves.iter_mut().zip(waves) {
+      *dst = *src;
+      parameters.wave_count += 1;
+    }
+
+    staging.write(
+      encoder,
+      &self.parameters_buffer,
+      bytemuck::cast_slice(&[parameters]),
+    );
+
+    // Every mip level is evaluated analytically instead of being downsampled,
+    // at the texel generate_mipmaps.wgsl keeps, so both backends fill the mips
+    // with the same values.
+    for (displacement_view, derivatives_view) in
+      self.displacement_views.iter().zip(&self.derivatives_views)
+    {
+      let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Gerstner waves"),
+        color_attachments: &[
+          wgpu::RenderPassColorAttachment {
+            view: displacement_view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+              load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+              store: true,
+            },
+          },
+          wgpu::RenderPassColorAttachment {
+            view: derivatives_view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+              load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+              store: true,
+            },
+          },
+        ],
+        depth_stencil_attachment: None,
+      });
+
+      render_pass.set_pipeline(&self.pipeline);
+      render_pass.set_bind_group(0, &self.parameters_bind_group, &[]);
+      render_pass.draw(0..3, 0..1);
+    }
+  }
+}
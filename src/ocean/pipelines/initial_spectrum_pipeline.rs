@@ -1,5 +1,9 @@
 use crate::ocean::ocean_parameters::OceanSpectrumParameters;
+use crate::ocean::shaders;
+use crate::ocean::spectrum::{jonswap_alpha, jonswap_peak_frequency};
+use crate::ocean::uniform_staging::UniformStaging;
 use crate::ocean::utils::clamp;
+use crate::ocean::OceanError;
 
 const WG_COUNT: u32 = 16;
 
@@ -14,6 +18,19 @@ struct Parameters {
   depth: f32,
 }
 
+impl Parameters {
+  fn from_ocean_parameters(o: OceanSpectrumParameters) -> Self {
+    Self {
+      size: o.size,
+      length_scale: o.length_scale,
+      cut_off_low: o.cut_off_low,
+      cut_off_high: o.cut_off_high,
+      gravity_acceleration: o.gravity_acceleration,
+      depth: o.depth,
+    }
+  }
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 struct SpectrumParamers {
@@ -34,31 +51,27 @@ impl SpectrumParamers {
       angle: o.wind_direction / 180.0 * std::f32::consts::PI,
       spread_blend: o.spread_blend,
       swell: clamp(o.swell, 0.01, 1.0),
-      alpha: Self::jonswap_alpha(9.81, o.fetch, o.wind_speed),
-      peak_omega: Self::jonswap_peak_frequency(9.81, o.fetch, o.wind_speed),
+      alpha: jonswap_alpha(9.81, o.fetch, o.wind_speed),
+      peak_omega: jonswap_peak_frequency(9.81, o.fetch, o.wind_speed),
       gamma: o.peak_enhancement,
       short_waves_fade: o.short_waves_fade,
     }
   }
-
-  fn jonswap_alpha(g: f32, fetch: f32, wind_speed: f32) -> f32 {
-    0.076 * f32::powf(g * fetch / wind_speed / wind_speed, -0.22)
-  }
-
-  fn jonswap_peak_frequency(g: f32, fetch: f32, wind_speed: f32) -> f32 {
-    22.0 * f32::powf(wind_speed * fetch / g / g, -0.33)
-  }
 }
 
 pub struct InitialSpectrumPipeline {
   size: u32,
-  textures_bind_group: wgpu::BindGroup,
+  initial_textures_bind_group: wgpu::BindGroup,
+  conjugated_textures_bind_group: wgpu::BindGroup,
+  initial_pipeline_layout: wgpu::PipelineLayout,
+  conjugated_pipeline_layout: wgpu::PipelineLayout,
   calculate_initial_spectrum_pipeline: wgpu::ComputePipeline,
   calculate_conjugated_spectrum_pipeline: wgpu::ComputePipeline,
 
   texture_size: wgpu::Extent3d,
   noise_texture: wgpu::Texture,
   parameters_buffer: wgpu::Buffer,
+  spectrum_parameters_buffer: wgpu::Buffer,
   parameters_bind_group: wgpu::BindGroup,
 
   noise_data: Vec<f32>,
@@ -68,6 +81,7 @@ impl InitialSpectrumPipeline {
   pub fn init(
     size: u32,
     wave_params: OceanSpectrumParameters,
+    seed: Option<u64>,
     device: &wgpu::Device,
     h0k_texture: &wgpu::Texture,
     waves_data_texture: &wgpu::Texture,
@@ -81,15 +95,7 @@ impl InitialSpectrumPipeline {
       depth_or_array_layers: 1,
     };
 
-    let parameters = Parameters {
-      size: wave_params.size,
-      length_scale: wave_params.length_scale,
-      cut_off_low: wave_params.cut_off_low,
-      cut_off_high: wave_params.cut_off_high,
-      gravity_acceleration: wave_params.gravity_acceleration,
-      depth: wave_params.depth,
-    };
-
+    let parameters = Parameters::from_ocean_parameters(wave_params);
     let spectrum_parameters = SpectrumParamers::from_ocean_parameters(wave_params);
 
     let parameters_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -156,85 +162,85 @@ impl InitialSpectrumPipeline {
       usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
     });
 
-    let texture_bind_group_layout =
+    let storage_texture_entry = |binding: u32| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::COMPUTE,
+      ty: wgpu::BindingType::StorageTexture {
+        view_dimension: wgpu::TextureViewDimension::D2,
+        format: wgpu::TextureFormat::Rgba32Float,
+        access: wgpu::StorageTextureAccess::WriteOnly,
+      },
+      count: None,
+    };
+
+    let sampled_texture_entry = |binding: u32| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::COMPUTE,
+      ty: wgpu::BindingType::Texture {
+        view_dimension: wgpu::TextureViewDimension::D2,
+        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+        multisampled: false,
+      },
+      count: None,
+    };
+
+    // h0k is written by the first pass and read by the second one, so each
+    // pass gets its own bind group.
+    let initial_texture_bind_group_layout =
       device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        label: Some("IS - Texture bind group layout"),
+        label: Some("IS - Initial spectrum texture bind group layout"),
         entries: &[
-          wgpu::BindGroupLayoutEntry {
-            binding: 0,
-            visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::Texture {
-              view_dimension: wgpu::TextureViewDimension::D2,
-              sample_type: wgpu::TextureSampleType::Float { filterable: false },
-              multisampled: false,
-            },
-            count: None,
-          },
-          wgpu::BindGroupLayoutEntry {
-            binding: 1,
-            visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
-              view_dimension: wgpu::TextureViewDimension::D2,
-              format: wgpu::TextureFormat::Rgba32Float,
-              access: wgpu::StorageTextureAccess::ReadWrite,
-            },
-            count: None,
-          },
-          wgpu::BindGroupLayoutEntry {
-            binding: 2,
-            visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
-              view_dimension: wgpu::TextureViewDimension::D2,
-              format: wgpu::TextureFormat::Rgba32Float,
-              access: wgpu::StorageTextureAccess::ReadWrite,
-            },
-            count: None,
-          },
-          wgpu::BindGroupLayoutEntry {
-            binding: 3,
-            visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
-              view_dimension: wgpu::TextureViewDimension::D2,
-              format: wgpu::TextureFormat::Rgba32Float,
-              access: wgpu::StorageTextureAccess::ReadWrite,
-            },
-            count: None,
-          },
+          sampled_texture_entry(0),
+          storage_texture_entry(1),
+          storage_texture_entry(2),
         ],
       });
 
-    let noise_data = generate_noise_data(size as usize);
+    let conjugated_texture_bind_group_layout =
+      device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("IS - Conjugated spectrum texture bind group layout"),
+        entries: &[sampled_texture_entry(3), storage_texture_entry(4)],
+      });
+
+    let noise_data = generate_noise_data(size as usize, seed);
 
     let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
       label: Some("Initial spectrum shader"),
-      source: wgpu::ShaderSource::Wgsl(include_str!("./shaders/initial_spectrum.wgsl").into()),
+      source: wgpu::ShaderSource::Wgsl(shaders::INITIAL_SPECTRUM.source()),
     });
 
-    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+    let initial_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
       label: Some("Initial spectrum pipeline layout"),
-      bind_group_layouts: &[&texture_bind_group_layout, &parameters_bind_group_layout],
+      bind_group_layouts: &[
+        &initial_texture_bind_group_layout,
+        &parameters_bind_group_layout,
+      ],
       push_constant_ranges: &[],
     });
 
-    let calculate_initial_spectrum_pipeline =
-      device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-        label: Some("Initial spectrum pipeline"),
-        layout: Some(&pipeline_layout),
-        module: &shader,
-        entry_point: "calculate_initial_spectrum",
+    let conjugated_pipeline_layout =
+      device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Conjugated spectrum pipeline layout"),
+        bind_group_layouts: &[
+          &conjugated_texture_bind_group_layout,
+          &parameters_bind_group_layout,
+        ],
+        push_constant_ranges: &[],
       });
 
-    let calculate_conjugated_spectrum_pipeline =
-      device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-        label: Some("Calculate conjugated spectrum pipeline"),
-        layout: Some(&pipeline_layout),
-        module: &shader,
-        entry_point: "calculate_conjugated_spectrum",
-      });
+    let (calculate_initial_spectrum_pipeline, calculate_conjugated_spectrum_pipeline) =
+      Self::create_pipelines(
+        device,
+        &initial_pipeline_layout,
+        &conjugated_pipeline_layout,
+        &shader,
+      );
+
+    let h0k_view = h0k_texture.create_view(&wgpu::TextureViewDescriptor::default());
 
-    let textures_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-      label: Some("IS - Texture bind group"),
-      layout: &texture_bind_group_layout,
+    let initial_textures_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("IS - Initial spectrum texture bind group"),
+      layout: &initial_texture_bind_group_layout,
       entries: &[
         wgpu::BindGroupEntry {
           binding: 0,
@@ -246,12 +252,7 @@ impl InitialSpectrumPipeline {
         },
         wgpu::BindGroupEntry {
           binding: 1,
-          resource: wgpu::BindingResource::TextureView(&h0_texture.create_view(
-            &wgpu::TextureViewDescriptor {
-              format: Some(wgpu::TextureFormat::Rgba32Float),
-              ..Default::default()
-            },
-          )),
+          resource: wgpu::BindingResource::TextureView(&h0k_view),
         },
         wgpu::BindGroupEntry {
           binding: 2,
@@ -262,9 +263,20 @@ impl InitialSpectrumPipeline {
             },
           )),
         },
+      ],
+    });
+
+    let conjugated_textures_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("IS - Conjugated spectrum texture bind group"),
+      layout: &conjugated_texture_bind_group_layout,
+      entries: &[
         wgpu::BindGroupEntry {
           binding: 3,
-          resource: wgpu::BindingResource::TextureView(&h0k_texture.create_view(
+          resource: wgpu::BindingResource::TextureView(&h0k_view),
+        },
+        wgpu::BindGroupEntry {
+          binding: 4,
+          resource: wgpu::BindingResource::TextureView(&h0_texture.create_view(
             &wgpu::TextureViewDescriptor {
               format: Some(wgpu::TextureFormat::Rgba32Float),
               ..Default::default()
@@ -279,15 +291,85 @@ impl InitialSpectrumPipeline {
       noise_data,
       texture_size,
       noise_texture,
-      textures_bind_group,
+      initial_textures_bind_group,
+      conjugated_textures_bind_group,
+      initial_pipeline_layout,
+      conjugated_pipeline_layout,
       calculate_initial_spectrum_pipeline,
       calculate_conjugated_spectrum_pipeline,
       parameters_buffer,
+      spectrum_parameters_buffer,
       parameters_bind_group,
     }
   }
 
-  pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder, queue: &wgpu::Queue) {
+  // Rebuilds the pipelines from a new version of their shader. The current
+  // ones are kept if the new one does not match the pipeline layouts.
+  pub async fn reload(
+    &mut self,
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+  ) -> Result<(), OceanError> {
+    let (initial, conjugated) = shaders::try_create(device, || {
+      Self::create_pipelines(
+        device,
+        &self.initial_pipeline_layout,
+        &self.conjugated_pipeline_layout,
+        shader,
+      )
+    })
+    .await?;
+
+    self.calculate_initial_spectrum_pipeline = initial;
+    self.calculate_conjugated_spectrum_pipeline = conjugated;
+    Ok(())
+  }
+
+  fn create_pipelines(
+    device: &wgpu::Device,
+    initial_layout: &wgpu::PipelineLayout,
+    conjugated_layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+  ) -> (wgpu::ComputePipeline, wgpu::ComputePipeline) {
+    let initial = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+      label: Some("Initial spectrum pipeline"),
+      layout: Some(initial_layout),
+      module: shader,
+      entry_point: "calculate_initial_spectrum",
+    });
+
+    let conjugated = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+      label: Some("Calculate conjugated spectrum pipeline"),
+      layout: Some(conjugated_layout),
+      module: shader,
+      entry_point: "calculate_conjugated_spectrum",
+    });
+
+    (initial, conjugated)
+  }
+
+  pub fn update_parameters(
+    &self,
+    encoder: &mut wgpu::CommandEncoder,
+    staging: &mut UniformStaging,
+    wave_params: OceanSpectrumParameters,
+  ) {
+    let parameters = Parameters::from_ocean_parameters(wave_params);
+    let spectrum_parameters = SpectrumParamers::from_ocean_parameters(wave_params);
+
+    staging.write(
+      encoder,
+      &self.parameters_buffer,
+      bytemuck::cast_slice(&[parameters]),
+    );
+    staging.write(
+      encoder,
+      &self.spectrum_parameters_buffer,
+      bytemuck::cast_slice(&[spectrum_parameters]),
+    );
+  }
+
+  pub fn upload_noise(&self, queue: &wgpu::Queue) {
     queue.write_texture(
       wgpu::ImageCopyTexture {
         texture: &self.noise_texture,
@@ -303,7 +385,9 @@ impl InitialSpectrumPipeline {
       },
       self.texture_size,
     );
+  }
 
+  pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder) {
     {
       let (dispatch_width, dispatch_height) = compute_work_group_count(
         (self.texture_size.width, self.texture_size.height),
@@ -313,7 +397,7 @@ impl InitialSpectrumPipeline {
         label: Some("Calculate Initial Spectrum"),
       });
       compute_pass.set_pipeline(&self.calculate_initial_spectrum_pipeline);
-      compute_pass.set_bind_group(0, &self.textures_bind_group, &[]);
+      compute_pass.set_bind_group(0, &self.initial_textures_bind_group, &[]);
       compute_pass.set_bind_group(1, &self.parameters_bind_group, &[]);
       compute_pass.dispatch(dispatch_width, dispatch_height, 1);
     }
@@ -329,17 +413,21 @@ impl InitialSpectrumPipeline {
       });
 
       compute_pass.set_pipeline(&self.calculate_conjugated_spectrum_pipeline);
-      compute_pass.set_bind_group(0, &self.textures_bind_group, &[]);
+      compute_pass.set_bind_group(0, &self.conjugated_textures_bind_group, &[]);
       compute_pass.set_bind_group(1, &self.parameters_bind_group, &[]);
       compute_pass.dispatch(dispatch_width, dispatch_height, 1);
     }
   }
 }
 
-fn generate_noise_data(size: usize) -> Vec<f32> {
+// Without a seed every run gets a different sea.
+fn generate_noise_data(size: usize, seed: Option<u64>) -> Vec<f32> {
   use rand::prelude::*;
 
-  let mut rng = rand::thread_rng();
+  let mut rng = match seed {
+    Some(seed) => rand::rngs::StdRng::seed_from_u64(seed),
+    None => rand::rngs::StdRng::from_entropy(),
+  };
   let mut buf: Vec<f32> = vec![0 as f32; 4 * size * size];
   for i in 0..4 * size * size {
     buf[i] = rng.gen();
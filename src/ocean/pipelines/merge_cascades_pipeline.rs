@@ -1,12 +1,5 @@
 use std::rc::Rc;
 
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct Parameters {
-  lambda: f32,
-  delta_time: f32,
-}
-
 pub struct MergeCascadesPipeline {
   size: u32,
 
@@ -30,16 +23,16 @@ impl MergeCascadesPipeline {
   ) -> Self {
     let textures_bind_group_layout =
       device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        label: Some("Waves data merge - texture bind group layout"),
+        label: Some("Merge cascades - texture bind group layout"),
         entries: &[
           // cascade_0
           wgpu::BindGroupLayoutEntry {
             binding: 0,
             visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
+            ty: wgpu::BindingType::Texture {
               view_dimension: wgpu::TextureViewDimension::D2,
-              format: wgpu::TextureFormat::Rgba32Float,
-              access: wgpu::StorageTextureAccess::ReadOnly,
+              sample_type: wgpu::TextureSampleType::Float { filterable: false },
+              multisampled: false,
             },
             count: None,
           },
@@ -47,10 +40,10 @@ impl MergeCascadesPipeline {
           wgpu::BindGroupLayoutEntry {
             binding: 1,
             visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
+            ty: wgpu::BindingType::Texture {
               view_dimension: wgpu::TextureViewDimension::D2,
-              format: wgpu::TextureFormat::Rgba32Float,
-              access: wgpu::StorageTextureAccess::ReadOnly,
+              sample_type: wgpu::TextureSampleType::Float { filterable: false },
+              multisampled: false,
             },
             count: None,
           },
@@ -58,10 +51,10 @@ impl MergeCascadesPipeline {
           wgpu::BindGroupLayoutEntry {
             binding: 2,
             visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
+            ty: wgpu::BindingType::Texture {
               view_dimension: wgpu::TextureViewDimension::D2,
-              format: wgpu::TextureFormat::Rgba32Float,
-              access: wgpu::StorageTextureAccess::ReadOnly,
+              sample_type: wgpu::TextureSampleType::Float { filterable: false },
+              multisampled: false,
             },
             count: None,
           },
@@ -72,7 +65,7 @@ impl MergeCascadesPipeline {
             ty: wgpu::BindingType::StorageTexture {
               view_dimension: wgpu::TextureViewDimension::D2,
               format: wgpu::TextureFormat::Rgba32Float,
-              access: wgpu::StorageTextureAccess::ReadOnly,
+              access: wgpu::StorageTextureAccess::WriteOnly,
             },
             count: None,
           },
@@ -82,22 +75,19 @@ impl MergeCascadesPipeline {
     let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
       label: Some("Cascade merge pipeline layout"),
       bind_group_layouts: &[&textures_bind_group_layout],
-      push_constant_ranges: &[wgpu::PushConstantRange {
-        stages: wgpu::ShaderStages::COMPUTE,
-        range: 0..8,
-      }],
+      push_constant_ranges: &[],
     });
 
     let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
       label: Some("Merge cascades shader"),
-      source: wgpu::ShaderSource::Wgsl(include_str!("./shaders/waves_data_merge.wgsl").into()),
+      source: wgpu::ShaderSource::Wgsl(crate::ocean::shaders::MERGE_CASCADES.source()),
     });
 
     let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
       label: Some("Merge cascades pipeline"),
       layout: Some(&pipeline_layout),
       module: &shader,
-      entry_point: "merge",
+      entry_point: "merge_cascades",
     });
 
     let texture_size = wgpu::Extent3d {
@@ -107,7 +97,7 @@ impl MergeCascadesPipeline {
     };
 
     let merged_displacement = device.create_texture(&wgpu::TextureDescriptor {
-      label: Some("Cascade 0"),
+      label: Some("Merged displacement"),
       size: texture_size,
       mip_level_count: 1,
       sample_count: 1,
@@ -168,7 +158,7 @@ impl MergeCascadesPipeline {
 
   pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder) {
     let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-      label: Some("Waves data merge"),
+      label: Some("Merge cascades"),
     });
 
     compute_pass.set_pipeline(&self.pipeline);
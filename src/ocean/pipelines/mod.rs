@@ -4,6 +4,7 @@ mod initial_spectrum_pipeline;
 mod waves_data_merge_pipeline;
 mod fft;
 mod generate_mipmaps_pipeline;
+mod gerstner_waves_pipeline;
 
 pub use waves_data_merge_pipeline::WavesDataMergePipeline;
 pub use time_dependent_spectrum_pipeline::TimeDependentSpectrumPipeline;
@@ -11,3 +12,4 @@ pub use initial_spectrum_pipeline::InitialSpectrumPipeline;
 pub use merge_cascades_pipeline::MergeCascadesPipeline;
 pub use fft::FFT;
 pub use generate_mipmaps_pipeline::GenerateMipmapsPipeline;
+pub use gerstner_waves_pipeline::{GerstnerWave, GerstnerWavesPipeline};
@@ -0,0 +1,80 @@
+struct Wave {
+    // k.x, k.z, amplitude, omega
+    k_amplitude_omega: vec4<f32>;
+    // phase, padding
+    phase: vec4<f32>;
+};
+
+struct Parameters {
+    time: f32;
+    lambda: f32;
+    length_scale: f32;
+    wave_count: u32;
+    waves: array<Wave, 64>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> params: Parameters;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+struct FragmentOutput {
+    [[location(0)]] displacement: vec4<f32>;
+    [[location(1)]] derivatives: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    // Evaluate at the texel corner to line up with the FFT output.
+    let texel = vec2<f32>(dpdx(in.uv.x), dpdy(in.uv.y));
+    let x = (in.uv - 0.5 * texel) * params.length_scale;
+    let l = params.lambda;
+
+    var displacement = vec3<f32>(0.0);
+    var dyx = 0.0;
+    var dyz = 0.0;
+    var dxx = 0.0;
+    var dzz = 0.0;
+    var dxz = 0.0;
+
+    for (var i = 0u; i < params.wave_count; i = i + 1u) {
+        let wave = params.waves[i].k_amplitude_omega;
+        let k = wave.xy;
+        let a = wave.z;
+        let k_length = length(k);
+
+        let theta = dot(k, x) - wave.w * params.time + params.waves[i].phase.x;
+        let c = cos(theta) * a;
+        let s = sin(theta) * a;
+
+        displacement = displacement + vec3<f32>(-k.x / k_length * s, c, -k.y / k_length * s);
+
+        dyx = dyx - k.x * s;
+        dyz = dyz - k.y * s;
+        dxx = dxx - k.x * k.x / k_length * c;
+        dzz = dzz - k.y * k.y / k_length * c;
+        dxz = dxz - k.x * k.y / k_length * c;
+    }
+
+    // This frame's jacobian, as waves_data_merge.wgsl stores it. Neither
+    // backend accumulates it over time, the foam reads the same from both.
+    let jacobian = (1.0 + l * dxx) * (1.0 + l * dzz) - l * l * dxz * dxz;
+
+    var out: FragmentOutput;
+    out.displacement = vec4<f32>(l * displacement.x, displacement.y, l * displacement.z, jacobian);
+    out.derivatives = vec4<f32>(dyx, dyz, l * dxx, l * dzz);
+    return out;
+}
@@ -25,13 +25,17 @@ struct SpectrumParamers {
 var noise: texture_2d<f32>;
 
 [[group(0), binding(1)]]
-var h0_texture: texture_storage_2d<rgba32float, read_write>;
+var h0k_texture: texture_storage_2d<rgba32float, write>;
 
 [[group(0), binding(2)]]
-var waves_data_texture: texture_storage_2d<rgba32float, read_write>;
+var waves_data_texture: texture_storage_2d<rgba32float, write>;
 
+// conjugated spectrum
 [[group(0), binding(3)]]
-var h0k_texture: texture_storage_2d<rgba32float, read_write>;
+var h0k_input_texture: texture_2d<f32>;
+
+[[group(0), binding(4)]]
+var h0_texture: texture_storage_2d<rgba32float, write>;
 
 [[group(1), binding(0)]]
 var<uniform> parameters: Parameters;
@@ -198,12 +202,12 @@ fn calculate_conjugated_spectrum(
     [[builtin(global_invocation_id)]] id: vec3<u32>,
 ) {
     let coords = vec2<i32>(id.xy);
-    let h0k = textureLoad(h0k_texture, coords).xy;
+    let h0k = textureLoad(h0k_input_texture, coords, 0).xy;
     let h0minusk_coords = vec2<i32>(
         i32((parameters.size - id.x) % parameters.size),
         i32((parameters.size - id.y) % parameters.size)
     );
-    let h0minusk = textureLoad(h0k_texture, h0minusk_coords).xy;
+    let h0minusk = textureLoad(h0k_input_texture, h0minusk_coords, 0).xy;
 
     textureStore(h0_texture, coords, vec4<f32>(h0k.x, h0k.y, h0minusk.x, -h0minusk.y));
 }
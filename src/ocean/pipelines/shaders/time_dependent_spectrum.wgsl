@@ -1,8 +1,8 @@
 [[group(0), binding(0)]]
-var h0_texture: texture_storage_2d<rgba32float, read>;
+var h0_texture: texture_2d<f32>;
 
 [[group(0), binding(1)]]
-var waves_data_texture: texture_storage_2d<rgba32float, read>;
+var waves_data_texture: texture_2d<f32>;
 
 [[group(0), binding(2)]]
 var amp_dx_dz__dy_dxz_texture: texture_storage_2d<rgba32float, write>;
@@ -10,11 +10,16 @@ var amp_dx_dz__dy_dxz_texture: texture_storage_2d<rgba32float, write>;
 [[group(0), binding(3)]]
 var amp_dyx_dyz__dxx_dzz_texture: texture_storage_2d<rgba32float, write>;
 
+[[group(0), binding(4)]]
+var h0_target_texture: texture_2d<f32>;
+
 struct Params {
     time: f32;
+    blend: f32;
 };
 
-var<push_constant> params: Params;
+[[group(1), binding(0)]]
+var<uniform> params: Params;
 
 fn complex_mult(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
     return vec2<f32>(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x);
@@ -26,8 +31,10 @@ fn calculate_amplitudes(
 ) {
     let coords = vec2<i32>(id.xy);
 
-    let wave = textureLoad(waves_data_texture, coords);
-    let h0 = textureLoad(h0_texture, coords);
+    let wave = textureLoad(waves_data_texture, coords, 0);
+    // Both spectra share the noise and dispersion, so blending the amplitudes
+    // keeps every wave in phase while the wind changes.
+    let h0 = mix(textureLoad(h0_texture, coords, 0), textureLoad(h0_target_texture, coords, 0), params.blend);
 
     let phase = wave.w * params.time;
     let exponent = vec2<f32>(cos(phase), sin(phase));
@@ -1,14 +1,21 @@
+use crate::ocean::shaders;
+use crate::ocean::uniform_staging::UniformStaging;
 use crate::ocean::utils::compute_work_group_count;
+use crate::ocean::OceanError;
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 struct Params {
   time: f32,
+  blend: f32,
 }
 
 pub struct TimeDependentSpectrumPipeline {
   size: u32,
   textures_bind_group: wgpu::BindGroup,
+  params_buffer: wgpu::Buffer,
+  params_bind_group: wgpu::BindGroup,
+  pipeline_layout: wgpu::PipelineLayout,
   pipeline: wgpu::ComputePipeline,
 }
 
@@ -18,6 +25,7 @@ impl TimeDependentSpectrumPipeline {
     device: &wgpu::Device,
 
     h0_texture: &'a wgpu::Texture,
+    h0_target_texture: &'a wgpu::Texture,
     waves_data_texture: &'a wgpu::Texture,
     amp_dx_dz_texture: &'a wgpu::Texture,
     amp_dyx_dyz_texture: &'a wgpu::Texture,
@@ -30,10 +38,10 @@ impl TimeDependentSpectrumPipeline {
           wgpu::BindGroupLayoutEntry {
             binding: 0,
             visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
+            ty: wgpu::BindingType::Texture {
               view_dimension: wgpu::TextureViewDimension::D2,
-              format: wgpu::TextureFormat::Rgba32Float,
-              access: wgpu::StorageTextureAccess::ReadOnly,
+              sample_type: wgpu::TextureSampleType::Float { filterable: false },
+              multisampled: false,
             },
             count: None,
           },
@@ -41,10 +49,10 @@ impl TimeDependentSpectrumPipeline {
           wgpu::BindGroupLayoutEntry {
             binding: 1,
             visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
+            ty: wgpu::BindingType::Texture {
               view_dimension: wgpu::TextureViewDimension::D2,
-              format: wgpu::TextureFormat::Rgba32Float,
-              access: wgpu::StorageTextureAccess::ReadOnly,
+              sample_type: wgpu::TextureSampleType::Float { filterable: false },
+              multisampled: false,
             },
             count: None,
           },
@@ -70,6 +78,17 @@ impl TimeDependentSpectrumPipeline {
             },
             count: None,
           },
+          // h0_target_texture
+          wgpu::BindGroupLayoutEntry {
+            binding: 4,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+              view_dimension: wgpu::TextureViewDimension::D2,
+              sample_type: wgpu::TextureSampleType::Float { filterable: false },
+              multisampled: false,
+            },
+            count: None,
+          },
         ],
       });
 
@@ -109,40 +128,112 @@ impl TimeDependentSpectrumPipeline {
             },
           )),
         },
+        wgpu::BindGroupEntry {
+          binding: 4,
+          resource: wgpu::BindingResource::TextureView(&h0_target_texture.create_view(
+            &wgpu::TextureViewDescriptor {
+              ..Default::default()
+            },
+          )),
+        },
       ],
     });
 
+    let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Time-dependent spectrum params buffer"),
+      size: std::mem::size_of::<Params>() as wgpu::BufferAddress,
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    });
+
+    let params_bind_group_layout =
+      device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Time-dependent spectrum - params bind group layout"),
+        entries: &[wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        }],
+      });
+
+    let params_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Time-dependent spectrum params"),
+      layout: &params_bind_group_layout,
+      entries: &[wgpu::BindGroupEntry {
+        binding: 0,
+        resource: params_buffer.as_entire_binding(),
+      }],
+    });
+
     let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
       label: Some("Time-dependent spectrum shader"),
-      source: wgpu::ShaderSource::Wgsl(
-        include_str!("./shaders/time_dependent_spectrum.wgsl").into(),
-      ),
+      source: wgpu::ShaderSource::Wgsl(shaders::TIME_DEPENDENT_SPECTRUM.source()),
     });
 
     let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
       label: Some("Time-dependent spectrum pipeline layout"),
-      bind_group_layouts: &[&texture_bind_group_layout],
-      push_constant_ranges: &[wgpu::PushConstantRange {
-        stages: wgpu::ShaderStages::COMPUTE,
-        range: 0..4,
-      }],
+      bind_group_layouts: &[&texture_bind_group_layout, &params_bind_group_layout],
+      push_constant_ranges: &[],
     });
 
-    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-      label: Some("Time-dependent spectrum pipeline"),
-      layout: Some(&pipeline_layout),
-      module: &shader,
-      entry_point: "calculate_amplitudes",
-    });
+    let pipeline = Self::create_pipeline(device, &pipeline_layout, &shader);
 
     Self {
       size,
       textures_bind_group,
+      params_buffer,
+      params_bind_group,
+      pipeline_layout,
       pipeline,
     }
   }
 
-  pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder, time: f32) {
+  // Rebuilds the pipeline from a new version of its shader. The current one is
+  // kept if the new one does not match the pipeline layout.
+  pub async fn reload(
+    &mut self,
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+  ) -> Result<(), OceanError> {
+    self.pipeline = shaders::try_create(device, || {
+      Self::create_pipeline(device, &self.pipeline_layout, shader)
+    })
+    .await?;
+    Ok(())
+  }
+
+  fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+  ) -> wgpu::ComputePipeline {
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+      label: Some("Time-dependent spectrum pipeline"),
+      layout: Some(layout),
+      module: shader,
+      entry_point: "calculate_amplitudes",
+    })
+  }
+
+  pub fn dispatch(
+    &self,
+    encoder: &mut wgpu::CommandEncoder,
+    staging: &mut UniformStaging,
+    time: f32,
+    blend: f32,
+  ) {
+    let params = Params { time, blend };
+    staging.write(
+      encoder,
+      &self.params_buffer,
+      bytemuck::cast_slice(&[params]),
+    );
+
     let (dispatch_width, dispatch_height) =
       compute_work_group_count((self.size, self.size), (16, 16));
 
@@ -150,11 +241,9 @@ impl TimeDependentSpectrumPipeline {
       label: Some("Calculate time-dependent spectrum"),
     });
 
-    let params = Params { time };
-
     compute_pass.set_pipeline(&self.pipeline);
     compute_pass.set_bind_group(0, &self.textures_bind_group, &[]);
-    compute_pass.set_push_constants(0, bytemuck::cast_slice(&[params]));
+    compute_pass.set_bind_group(1, &self.params_bind_group, &[]);
     compute_pass.dispatch(dispatch_width, dispatch_height, 1);
   }
 }
@@ -1,3 +1,7 @@
+use crate::ocean::shaders;
+use crate::ocean::uniform_staging::UniformStaging;
+use crate::ocean::OceanError;
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 struct Parameters {
@@ -9,15 +13,19 @@ pub struct WavesDataMergePipeline {
   size: u32,
   lambda: f32,
   textures_bind_group: wgpu::BindGroup,
+  parameters_buffer: wgpu::Buffer,
+  parameters_bind_group: wgpu::BindGroup,
+  pipeline_layout: wgpu::PipelineLayout,
   pipeline: wgpu::ComputePipeline,
-  blur_turbulence_pipeline: wgpu::ComputePipeline,
 }
 
 impl WavesDataMergePipeline {
+  #[allow(clippy::too_many_arguments)]
   pub fn init<'a>(
     device: &wgpu::Device,
     size: u32,
     lambda: f32,
+    format: wgpu::TextureFormat,
     amp_dx_dz_texture: &'a wgpu::Texture,
     amp_dyx_dyz_texture: &'a wgpu::Texture,
     displacement_texture: &'a wgpu::Texture,
@@ -31,10 +39,10 @@ impl WavesDataMergePipeline {
           wgpu::BindGroupLayoutEntry {
             binding: 0,
             visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
+            ty: wgpu::BindingType::Texture {
               view_dimension: wgpu::TextureViewDimension::D2,
-              format: wgpu::TextureFormat::Rgba32Float,
-              access: wgpu::StorageTextureAccess::ReadOnly,
+              sample_type: wgpu::TextureSampleType::Float { filterable: false },
+              multisampled: false,
             },
             count: None,
           },
@@ -42,10 +50,10 @@ impl WavesDataMergePipeline {
           wgpu::BindGroupLayoutEntry {
             binding: 1,
             visibility: wgpu::ShaderStages::COMPUTE,
-            ty: wgpu::BindingType::StorageTexture {
+            ty: wgpu::BindingType::Texture {
               view_dimension: wgpu::TextureViewDimension::D2,
-              format: wgpu::TextureFormat::Rgba32Float,
-              access: wgpu::StorageTextureAccess::ReadOnly,
+              sample_type: wgpu::TextureSampleType::Float { filterable: false },
+              multisampled: false,
             },
             count: None,
           },
@@ -55,8 +63,8 @@ impl WavesDataMergePipeline {
             visibility: wgpu::ShaderStages::COMPUTE,
             ty: wgpu::BindingType::StorageTexture {
               view_dimension: wgpu::TextureViewDimension::D2,
-              format: wgpu::TextureFormat::Rgba32Float,
-              access: wgpu::StorageTextureAccess::ReadWrite,
+              format,
+              access: wgpu::StorageTextureAccess::WriteOnly,
             },
             count: None,
           },
@@ -66,7 +74,7 @@ impl WavesDataMergePipeline {
             visibility: wgpu::ShaderStages::COMPUTE,
             ty: wgpu::BindingType::StorageTexture {
               view_dimension: wgpu::TextureViewDimension::D2,
-              format: wgpu::TextureFormat::Rgba32Float,
+              format,
               access: wgpu::StorageTextureAccess::WriteOnly,
             },
             count: None,
@@ -74,33 +82,53 @@ impl WavesDataMergePipeline {
         ],
       });
 
+    let parameters_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Waves data merge parameters buffer"),
+      size: std::mem::size_of::<Parameters>() as wgpu::BufferAddress,
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    });
+
+    let parameters_bind_group_layout =
+      device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Waves data merge - parameters bind group layout"),
+        entries: &[wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        }],
+      });
+
+    let parameters_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Waves data merge - parameters"),
+      layout: &parameters_bind_group_layout,
+      entries: &[wgpu::BindGroupEntry {
+        binding: 0,
+        resource: parameters_buffer.as_entire_binding(),
+      }],
+    });
+
     let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
       label: Some("Waves data merge pipeline layout"),
-      bind_group_layouts: &[&textures_bind_group_layout],
-      push_constant_ranges: &[wgpu::PushConstantRange {
-        stages: wgpu::ShaderStages::COMPUTE,
-        range: 0..8,
-      }],
+      bind_group_layouts: &[&textures_bind_group_layout, &parameters_bind_group_layout],
+      push_constant_ranges: &[],
     });
 
     let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
       label: Some("Waves data merge shader"),
-      source: wgpu::ShaderSource::Wgsl(include_str!("./shaders/waves_data_merge.wgsl").into()),
-    });
-
-    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-      label: Some("Waves data merge pipeline"),
-      layout: Some(&pipeline_layout),
-      module: &shader,
-      entry_point: "merge",
+      source: wgpu::ShaderSource::Wgsl(shaders::with_map_format(
+        shaders::WAVES_DATA_MERGE,
+        shaders::WAVES_DATA_MERGE.source(),
+        format,
+      )),
     });
 
-    let blur_turbulence_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-      label: Some("Blur turbulence pipeline"),
-      layout: Some(&pipeline_layout),
-      module: &shader,
-      entry_point: "blur_turbulence",
-    });
+    let pipeline = Self::create_pipeline(device, &pipeline_layout, &shader);
 
     let textures_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
       label: Some("Waves data merge - textures"),
@@ -141,7 +169,7 @@ impl WavesDataMergePipeline {
               ..Default::default()
             },
           )),
-        },     
+        },
       ],
     });
 
@@ -149,24 +177,64 @@ impl WavesDataMergePipeline {
       size,
       lambda,
       textures_bind_group,
+      parameters_buffer,
+      parameters_bind_group,
+      pipeline_layout,
       pipeline,
-      blur_turbulence_pipeline,
     }
   }
 
-  pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder, dt: std::time::Duration) {
-    let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-      label: Some("Waves data merge"),
-    });
+  // Rebuilds the pipeline from a new version of its shader. The current one is
+  // kept if the new one does not match the pipeline layout.
+  pub async fn reload(
+    &mut self,
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+  ) -> Result<(), OceanError> {
+    self.pipeline = shaders::try_create(device, || {
+      Self::create_pipeline(device, &self.pipeline_layout, shader)
+    })
+    .await?;
+    Ok(())
+  }
+
+  fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+  ) -> wgpu::ComputePipeline {
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+      label: Some("Waves data merge pipeline"),
+      layout: Some(layout),
+      module: shader,
+      entry_point: "merge",
+    })
+  }
 
+  pub fn dispatch(
+    &self,
+    encoder: &mut wgpu::CommandEncoder,
+    staging: &mut UniformStaging,
+    dt: std::time::Duration,
+  ) {
     let parameters = Parameters {
       lambda: self.lambda,
       delta_time: dt.as_secs_f32(),
     };
 
+    staging.write(
+      encoder,
+      &self.parameters_buffer,
+      bytemuck::cast_slice(&[parameters]),
+    );
+
+    let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+      label: Some("Waves data merge"),
+    });
+
     compute_pass.set_pipeline(&self.pipeline);
     compute_pass.set_bind_group(0, &self.textures_bind_group, &[]);
-    compute_pass.set_push_constants(0, bytemuck::cast_slice(&[parameters]));
+    compute_pass.set_bind_group(1, &self.parameters_bind_group, &[]);
     compute_pass.dispatch(self.size / 16, self.size / 16, 1);
   }
 }
@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ocean::{OceanError, SCHEMA_VERSION};
+
+// Parameters tagged with the schema version they were written with, the form
+// presets are stored in:
+//
+//   { "version": 1, "parameters": { "size": 256, ... } }
+//
+// Loading a preset written with another version fails instead of silently
+// misreading it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Unchecked<T>")]
+pub struct Versioned<T> {
+  version: u32,
+  pub parameters: T,
+}
+
+impl<T> Versioned<T> {
+  pub fn new(parameters: T) -> Versioned<T> {
+    Versioned {
+      version: SCHEMA_VERSION,
+      parameters,
+    }
+  }
+
+  pub fn version(&self) -> u32 {
+    self.version
+  }
+}
+
+#[derive(Deserialize)]
+struct Unchecked<T> {
+  version: u32,
+  parameters: T,
+}
+
+impl<T> TryFrom<Unchecked<T>> for Versioned<T> {
+  type Error = OceanError;
+
+  fn try_from(unchecked: Unchecked<T>) -> Result<Self, Self::Error> {
+    if unchecked.version != SCHEMA_VERSION {
+      return Err(OceanError::UnsupportedSchemaVersion(unchecked.version));
+    }
+
+    Ok(Versioned::new(unchecked.parameters))
+  }
+}
+
+#[test]
+fn test_round_trip() {
+  use crate::ocean::{
+    ExportSettings, MapFormat, MeshExportSettings, MeshFormat, OceanBackend, OceanConfig,
+    OceanSpectrumParameters,
+  };
+
+  // Serializing what was deserialized gives back the same text, for both
+  // formats tools are expected to use.
+  fn round_trip<T: Serialize + for<'de> Deserialize<'de>>(parameters: T) {
+    let json = serde_json::to_string(&Versioned::new(parameters)).unwrap();
+    let parsed: Versioned<T> = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed.version(), SCHEMA_VERSION);
+    assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+
+    let ron = ron::to_string(&parsed).unwrap();
+    let parsed: Versioned<T> = ron::from_str(&ron).unwrap();
+    assert_eq!(ron::to_string(&parsed).unwrap(), ron);
+  }
+
+  round_trip(OceanSpectrumParameters {
+    wind_speed: 12.5,
+    ..Default::default()
+  });
+  round_trip(OceanConfig {
+    backend: OceanBackend::Gerstner { wave_count: 32 },
+    seed: Some(7),
+    ..Default::default()
+  });
+  round_trip(ExportSettings {
+    format: MapFormat::Png16,
+    loop_blend_frames: 4,
+    ..Default::default()
+  });
+  round_trip(MeshExportSettings {
+    format: MeshFormat::Gltf,
+    ..Default::default()
+  });
+}
+
+#[test]
+fn test_versions() {
+  use crate::ocean::OceanCascadeParameters;
+
+  let calm = r#"{ "version": 1, "parameters": { "size": 128, "wind_speed": 3.0,
+    "wind_direction": 90.0, "swell": 0.1 } }"#;
+  let parsed: Versioned<OceanCascadeParameters> = serde_json::from_str(calm).unwrap();
+  assert_eq!(parsed.parameters.size, 128);
+
+  let future = calm.replace("\"version\": 1", "\"version\": 2");
+  let error = serde_json::from_str::<Versioned<OceanCascadeParameters>>(&future).unwrap_err();
+  assert!(error.to_string().contains("schema version 2"));
+
+  // Missing fields fall back to their defaults.
+  let partial = r#"(version: 1, parameters: (wind_speed: 4.0))"#;
+  let parsed: Versioned<crate::ocean::OceanSpectrumParameters> = ron::from_str(partial).unwrap();
+  assert_eq!(parsed.parameters.wind_speed, 4.0);
+  assert_eq!(parsed.parameters.size, 256);
+}
@@ -0,0 +1,95 @@
+use std::time::{Duration, Instant};
+
+use crate::ocean::shaders::Shader;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Re-reads shader files every `POLL_INTERVAL` and hands out the ones that
+// changed, once naga accepts them. Sources that fail validation are logged
+// with their diagnostic and skipped until the file changes again, so the
+// pipelines built from the last valid version keep running.
+pub struct ShaderWatcher {
+  shaders: Vec<(Shader, String)>,
+  last_poll: Instant,
+}
+
+impl ShaderWatcher {
+  pub fn new<I: IntoIterator<Item = Shader>>(shaders: I) -> ShaderWatcher {
+    ShaderWatcher {
+      shaders: shaders
+        .into_iter()
+        .map(|shader| (shader, shader.source().into_owned()))
+        .collect(),
+      last_poll: Instant::now(),
+    }
+  }
+
+  pub fn poll(&mut self) -> Vec<(Shader, String)> {
+    if self.last_poll.elapsed() < POLL_INTERVAL {
+      return Vec::new();
+    }
+    self.last_poll = Instant::now();
+    self.reload()
+  }
+
+  fn reload(&mut self) -> Vec<(Shader, String)> {
+    let mut changed = Vec::new();
+
+    for (shader, last_source) in &mut self.shaders {
+      // Editors often replace the file, so it can briefly be missing.
+      let source = match std::fs::read_to_string(shader.file_path()) {
+        Ok(source) if source != *last_source => source,
+        _ => continue,
+      };
+      *last_source = source.clone();
+
+      match validate(&source) {
+        Ok(()) => changed.push((*shader, source)),
+        Err(diagnostic) => log::error!(
+          "{} is invalid, keeping the current pipelines:\n{}",
+          shader.path,
+          diagnostic
+        ),
+      }
+    }
+
+    changed
+  }
+}
+
+// Parses and validates WGSL the way wgpu does, returning the diagnostic on
+// failure.
+pub fn validate(source: &str) -> Result<(), String> {
+  let module = naga::front::wgsl::parse_str(source).map_err(|e| e.emit_to_string(source))?;
+
+  let mut validator = naga::valid::Validator::new(
+    naga::valid::ValidationFlags::all(),
+    naga::valid::Capabilities::empty(),
+  );
+  validator.validate(&module).map_err(|e| {
+    // The error only names the failing function, the cause follows in the
+    // source chain.
+    let mut diagnostic = e.to_string();
+    let mut source = std::error::Error::source(&e);
+    while let Some(cause) = source {
+      diagnostic += &format!("\n  caused by: {}", cause);
+      source = cause.source();
+    }
+    diagnostic
+  })?;
+
+  Ok(())
+}
+
+#[test]
+fn test_validate() {
+  use crate::ocean::shaders;
+
+  for shader in shaders::OCEAN_SHADERS {
+    assert_eq!(validate(&shader.source()), Ok(()), "{}", shader.path);
+  }
+
+  let diagnostic = validate("fn main() -> f32 { return 1; }").unwrap_err();
+  assert!(!diagnostic.is_empty());
+  assert!(validate("fn broken( {").is_err());
+}
@@ -0,0 +1,122 @@
+use std::borrow::Cow;
+use std::future::Future;
+use std::path::PathBuf;
+
+use crate::ocean::OceanError;
+
+// A WGSL shader embedded in the binary. With the `shader-hot-reload` feature
+// the file it was embedded from is read instead, so edits show up without a
+// rebuild.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Shader {
+  // Relative to the crate root.
+  pub path: &'static str,
+  embedded: &'static str,
+}
+
+impl Shader {
+  pub const fn new(path: &'static str, embedded: &'static str) -> Shader {
+    Shader { path, embedded }
+  }
+
+  pub fn file_path(&self) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(self.path)
+  }
+
+  pub fn source(&self) -> Cow<'static, str> {
+    #[cfg(feature = "shader-hot-reload")]
+    match std::fs::read_to_string(self.file_path()) {
+      Ok(source) => return Cow::Owned(source),
+      Err(e) => log::warn!("Using embedded {}: {}", self.path, e),
+    }
+
+    Cow::Borrowed(self.embedded)
+  }
+}
+
+pub const FFT: Shader = Shader::new(
+  "src/ocean/pipelines/shaders/fft.wgsl",
+  include_str!("pipelines/shaders/fft.wgsl"),
+);
+pub const GENERATE_MIPMAPS: Shader = Shader::new(
+  "src/ocean/pipelines/shaders/generate_mipmaps.wgsl",
+  include_str!("pipelines/shaders/generate_mipmaps.wgsl"),
+);
+pub const GERSTNER_WAVES: Shader = Shader::new(
+  "src/ocean/pipelines/shaders/gerstner_waves.wgsl",
+  include_str!("pipelines/shaders/gerstner_waves.wgsl"),
+);
+pub const INITIAL_SPECTRUM: Shader = Shader::new(
+  "src/ocean/pipelines/shaders/initial_spectrum.wgsl",
+  include_str!("pipelines/shaders/initial_spectrum.wgsl"),
+);
+pub const MERGE_CASCADES: Shader = Shader::new(
+  "src/ocean/pipelines/shaders/merge_cascades.wgsl",
+  include_str!("pipelines/shaders/merge_cascades.wgsl"),
+);
+pub const TIME_DEPENDENT_SPECTRUM: Shader = Shader::new(
+  "src/ocean/pipelines/shaders/time_dependent_spectrum.wgsl",
+  include_str!("pipelines/shaders/time_dependent_spectrum.wgsl"),
+);
+pub const WAVES_DATA_MERGE: Shader = Shader::new(
+  "src/ocean/pipelines/shaders/waves_data_merge.wgsl",
+  include_str!("pipelines/shaders/waves_data_merge.wgsl"),
+);
+
+// Every shader the simulation runs.
+pub const OCEAN_SHADERS: [Shader; 6] = [
+  FFT,
+  GENERATE_MIPMAPS,
+  GERSTNER_WAVES,
+  INITIAL_SPECTRUM,
+  TIME_DEPENDENT_SPECTRUM,
+  WAVES_DATA_MERGE,
+];
+
+// The shaders writing the displacement and derivatives maps declare them as
+// `rgba32float` storage. Returns the source of `shader` for maps of `format`.
+pub fn with_map_format(
+  shader: Shader,
+  source: Cow<'_, str>,
+  format: wgpu::TextureFormat,
+) -> Cow<'_, str> {
+  let writes_maps = shader == GENERATE_MIPMAPS || shader == WAVES_DATA_MERGE;
+
+  if writes_maps && format == wgpu::TextureFormat::Rgba16Float {
+    Cow::Owned(source.replace("rgba32float", "rgba16float"))
+  } else {
+    source
+  }
+}
+
+// Runs `create` in a validation error scope, so a pipeline that does not match
+// its layout is reported instead of reaching the device's uncaptured error
+// handler. `create` runs right away, the returned future resolves once the
+// device has checked what it created.
+pub fn try_create<T>(
+  device: &wgpu::Device,
+  create: impl FnOnce() -> T,
+) -> impl Future<Output = Result<T, OceanError>> {
+  device.push_error_scope(wgpu::ErrorFilter::Validation);
+  let created = create();
+  let error = device.pop_error_scope();
+
+  async move {
+    match error.await {
+      Some(error) => Err(OceanError::PipelineCreation(error.to_string())),
+      None => Ok(created),
+    }
+  }
+}
+
+#[test]
+fn test_with_map_format() {
+  let source = |shader: Shader, format| with_map_format(shader, shader.source(), format);
+
+  let half = source(WAVES_DATA_MERGE, wgpu::TextureFormat::Rgba16Float);
+  assert!(half.contains("rgba16float") && !half.contains("rgba32float"));
+  let full = source(GENERATE_MIPMAPS, wgpu::TextureFormat::Rgba32Float);
+  assert!(full.contains("rgba32float") && !full.contains("rgba16float"));
+  // The intermediate FFT textures stay at full precision.
+  assert!(source(FFT, wgpu::TextureFormat::Rgba16Float).contains("rgba32float"));
+}
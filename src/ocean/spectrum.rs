@@ -0,0 +1,171 @@
+// CPU port of the spectrum functions in `shaders/initial_spectrum.wgsl`.
+
+use crate::ocean::ocean_parameters::OceanSpectrumParameters;
+use crate::ocean::utils::clamp;
+
+use std::f32::consts::PI;
+
+pub fn jonswap_alpha(g: f32, fetch: f32, wind_speed: f32) -> f32 {
+  0.076 * f32::powf(g * fetch / wind_speed / wind_speed, -0.22)
+}
+
+pub fn jonswap_peak_frequency(g: f32, fetch: f32, wind_speed: f32) -> f32 {
+  22.0 * f32::powf(wind_speed * fetch / g / g, -0.33)
+}
+
+pub fn frequency(k: f32, g: f32, depth: f32) -> f32 {
+  (g * k * f32::tanh(f32::min(k * depth, 20.0))).sqrt()
+}
+
+pub fn frequency_derivative(k: f32, g: f32, depth: f32) -> f32 {
+  let th = f32::tanh(f32::min(k * depth, 20.0));
+  let ch = f32::cosh(k * depth);
+
+  g * (depth * k / ch / ch + th) / frequency(k, g, depth) / 2.0
+}
+
+fn normalization_factor(s: f32) -> f32 {
+  let s2 = s * s;
+  let s3 = s2 * s;
+  let s4 = s3 * s;
+
+  if s < 5.0 {
+    -0.000564 * s4 + 0.00776 * s3 - 0.044 * s2 + 0.192 * s + 0.163
+  } else {
+    -4.80e-08 * s4 + 1.07e-05 * s3 - 9.53e-04 * s2 + 5.90e-02 * s + 3.93e-01
+  }
+}
+
+fn cosine_2s(theta: f32, s: f32) -> f32 {
+  normalization_factor(s) * f32::powf(f32::abs(f32::cos(0.5 * theta)), 2.0 * s)
+}
+
+fn spread_power(omega: f32, peak_omega: f32) -> f32 {
+  if omega > peak_omega {
+    9.77 * f32::powf(f32::abs(omega / peak_omega), -2.5)
+  } else {
+    6.97 * f32::powf(f32::abs(omega / peak_omega), 5.0)
+  }
+}
+
+fn tma_correction(omega: f32, g: f32, depth: f32) -> f32 {
+  let omega_h = omega * (depth / g).sqrt();
+
+  if omega_h <= 1.0 {
+    return 0.5 * omega_h * omega_h;
+  }
+
+  if omega_h < 2.0 {
+    return 1.0 - 0.5 * (2.0 - omega_h) * (2.0 - omega_h);
+  }
+
+  1.0
+}
+
+pub struct Spectrum {
+  gravity_acceleration: f32,
+  depth: f32,
+  scale: f32,
+  angle: f32,
+  spread_blend: f32,
+  swell: f32,
+  alpha: f32,
+  peak_omega: f32,
+  gamma: f32,
+  short_waves_fade: f32,
+}
+
+impl Spectrum {
+  pub fn new(o: &OceanSpectrumParameters) -> Self {
+    Self {
+      gravity_acceleration: o.gravity_acceleration,
+      depth: o.depth,
+      scale: o.scale,
+      angle: o.wind_direction / 180.0 * PI,
+      spread_blend: o.spread_blend,
+      swell: clamp(o.swell, 0.01, 1.0),
+      alpha: jonswap_alpha(9.81, o.fetch, o.wind_speed),
+      peak_omega: jonswap_peak_frequency(9.81, o.fetch, o.wind_speed),
+      gamma: o.peak_enhancement,
+      short_waves_fade: o.short_waves_fade,
+    }
+  }
+
+  fn jonswap(&self, omega: f32) -> f32 {
+    let g = self.gravity_acceleration;
+    let sigma = if omega <= self.peak_omega { 0.07 } else { 0.09 };
+
+    let r = f32::exp(
+      -(omega - self.peak_omega) * (omega - self.peak_omega)
+        / 2.0
+        / sigma
+        / sigma
+        / self.peak_omega
+        / self.peak_omega,
+    );
+
+    let peak_omega_over_omega = self.peak_omega / omega;
+
+    self.scale * tma_correction(omega, g, self.depth) * self.alpha * g * g
+      / omega.powi(5)
+      * f32::exp(-1.25 * peak_omega_over_omega.powi(4))
+      * f32::powf(f32::abs(self.gamma), r)
+  }
+
+  fn direction_spectrum(&self, theta: f32, omega: f32) -> f32 {
+    let s = spread_power(omega, self.peak_omega)
+      + 16.0 * f32::tanh(f32::min(omega / self.peak_omega, 20.0)) * self.swell * self.swell;
+
+    let cos_theta = f32::cos(theta);
+    let a = 2.0 / PI * cos_theta * cos_theta;
+    let b = cosine_2s(theta - self.angle, s);
+
+    a + (b - a) * self.spread_blend
+  }
+
+  fn short_waves_fade(&self, k_length: f32) -> f32 {
+    f32::exp(-self.short_waves_fade * self.short_waves_fade * k_length * k_length)
+  }
+
+  // Wave energy density per unit area of the wave vector plane.
+  pub fn density(&self, kx: f32, kz: f32) -> f32 {
+    let k_length = (kx * kx + kz * kz).sqrt();
+    if k_length <= 0.0 {
+      return 0.0;
+    }
+
+    let g = self.gravity_acceleration;
+    let omega = frequency(k_length, g, self.depth);
+    let d_omega_dk = frequency_derivative(k_length, g, self.depth);
+
+    self.jonswap(omega)
+      * self.direction_spectrum(f32::atan2(kz, kx), omega)
+      * self.short_waves_fade(k_length)
+      * d_omega_dk.abs()
+      / k_length
+  }
+}
+
+// Variance of the height field the FFT simulation generates from `params`,
+// summed over the same wave vector grid as `shaders/initial_spectrum.wgsl`.
+// Each wave there gets an amplitude of `sqrt(2 * density) * delta_k` scaled by
+// two uniform noise values in [0, 1), whose squares average 1/3, and is paired
+// with its conjugate.
+pub fn height_variance(params: &OceanSpectrumParameters) -> f32 {
+  let spectrum = Spectrum::new(params);
+  let delta_k = 2.0 * PI / params.length_scale;
+  let half_size = (params.size / 2) as i32;
+
+  let mut density_sum = 0.0;
+  for nz in -half_size..half_size {
+    for nx in -half_size..half_size {
+      let (kx, kz) = (nx as f32 * delta_k, nz as f32 * delta_k);
+      let k_length = (kx * kx + kz * kz).sqrt();
+      if k_length >= params.cut_off_low && k_length <= params.cut_off_high {
+        density_sum += spectrum.density(kx, kz);
+      }
+    }
+  }
+
+  8.0 / 3.0 * density_sum * delta_k * delta_k
+}
@@ -0,0 +1,141 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+
+// Staging buffers that can be in flight at once. One frame of either backend
+// fits in a single buffer, a frame finding all of them in use keeps the
+// previous uniforms.
+const STAGING_BUFFERS: usize = 4;
+const STAGING_BUFFER_SIZE: wgpu::BufferAddress = 8 * 1024;
+
+type Mapping = Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>> + Send>>;
+
+enum StagingState {
+  Mapped,
+  // Written to by the current frame.
+  Writing,
+  // Holds data the GPU may not have copied yet.
+  Copied,
+  Mapping(Mapping),
+}
+
+struct StagingBuffer {
+  buffer: wgpu::Buffer,
+  state: StagingState,
+  offset: wgpu::BufferAddress,
+}
+
+// Mappings finish on `Queue::submit` or `Device::poll`, nothing needs waking.
+struct NoopWaker;
+
+impl Wake for NoopWaker {
+  fn wake(self: Arc<Self>) {}
+}
+
+// Writes uniform buffers through copies recorded in a command encoder, so a
+// frame of the simulation only needs the encoder. Data goes into a ring of
+// mapped staging buffers, which are mapped again once the GPU has copied from
+// them. Between `begin` and `finish`, the encoder of the previous `finish` must
+// have been submitted.
+pub struct UniformStaging {
+  buffers: Vec<StagingBuffer>,
+}
+
+impl UniformStaging {
+  pub fn new(device: &wgpu::Device) -> UniformStaging {
+    let buffers = (0..STAGING_BUFFERS)
+      .map(|_| StagingBuffer {
+        buffer: device.create_buffer(&wgpu::BufferDescriptor {
+          label: Some("Ocean uniform staging buffer"),
+          size: STAGING_BUFFER_SIZE,
+          usage: wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::COPY_SRC,
+          mapped_at_creation: true,
+        }),
+        state: StagingState::Mapped,
+        offset: 0,
+      })
+      .collect();
+
+    UniformStaging { buffers }
+  }
+
+  // Maps the buffers earlier frames copied from again, those the GPU is done
+  // with become available.
+  pub fn begin(&mut self) {
+    let waker = Waker::from(Arc::new(NoopWaker));
+    let mut context = Context::from_waker(&waker);
+
+    for staging in &mut self.buffers {
+      if let StagingState::Copied = staging.state {
+        let mapping = staging.buffer.slice(..).map_async(wgpu::MapMode::Write);
+        staging.state = StagingState::Mapping(Box::pin(mapping));
+      }
+
+      if let StagingState::Mapping(mapping) = &mut staging.state {
+        staging.state = match mapping.as_mut().poll(&mut context) {
+          Poll::Ready(Ok(())) => {
+            staging.offset = 0;
+            StagingState::Mapped
+          }
+          // The mapping is requested again next frame.
+          Poll::Ready(Err(_)) => StagingState::Copied,
+          Poll::Pending => continue,
+        };
+      }
+    }
+  }
+
+  // Records a copy of `data` to the start of `target`. Copies run in the order
+  // they were recorded, so a buffer can be written more than once a frame.
+  pub fn write(&mut self, encoder: &mut wgpu::CommandEncoder, target: &wgpu::Buffer, data: &[u8]) {
+    let size = data.len() as wgpu::BufferAddress;
+    debug_assert!(size <= STAGING_BUFFER_SIZE);
+
+    // Buffers already written to this frame are filled up first.
+    let index = self
+      .buffers
+      .iter()
+      .position(|staging| {
+        matches!(staging.state, StagingState::Writing)
+          && staging.offset + size <= STAGING_BUFFER_SIZE
+      })
+      .or_else(|| {
+        self
+          .buffers
+          .iter()
+          .position(|staging| matches!(staging.state, StagingState::Mapped))
+      });
+
+    let staging = match index {
+      Some(index) => &mut self.buffers[index],
+      None => {
+        log::warn!("Ocean uniform staging buffers in use, keeping the previous uniforms");
+        return;
+      }
+    };
+
+    let range = staging.offset..staging.offset + size;
+    staging
+      .buffer
+      .slice(range)
+      .get_mapped_range_mut()
+      .copy_from_slice(data);
+    encoder.copy_buffer_to_buffer(&staging.buffer, staging.offset, target, 0, size);
+
+    // Mapped ranges have to start at a multiple of `MAP_ALIGNMENT`.
+    let align = wgpu::MAP_ALIGNMENT;
+    staging.offset += size + (align - size % align) % align;
+    staging.state = StagingState::Writing;
+  }
+
+  // Unmaps the buffers written to, `encoder` can be submitted afterwards.
+  pub fn finish(&mut self) {
+    for staging in &mut self.buffers {
+      if let StagingState::Writing = staging.state {
+        staging.buffer.unmap();
+        staging.state = StagingState::Copied;
+      }
+    }
+  }
+}
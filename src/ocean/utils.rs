@@ -20,3 +20,107 @@ pub fn clamp<T: PartialOrd>(input: T, min: T, max: T) -> T {
     }
 }
 
+
+// Copies mip 0 of a square Rgba16Float or Rgba32Float texture to the CPU as
+// RGBA f32 texels, top row first. Blocks until all submitted work has finished.
+pub fn read_rgba_texture(
+  device: &wgpu::Device,
+  queue: &wgpu::Queue,
+  texture: &wgpu::Texture,
+  format: wgpu::TextureFormat,
+  size: u32,
+) -> Result<Vec<f32>, wgpu::BufferAsyncError> {
+  let half_float = format == wgpu::TextureFormat::Rgba16Float;
+  let bytes_per_channel = if half_float { 2 } else { 4 };
+  let unpadded_bytes_per_row = 4 * bytes_per_channel * size;
+  let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+  let padding = (align - unpadded_bytes_per_row % align) % align;
+  let padded_bytes_per_row = unpadded_bytes_per_row + padding;
+
+  let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+    label: Some("Ocean map readback buffer"),
+    size: (padded_bytes_per_row * size) as wgpu::BufferAddress,
+    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+    mapped_at_creation: false,
+  });
+
+  let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+    label: Some("Ocean map readback encoder"),
+  });
+
+  encoder.copy_texture_to_buffer(
+    wgpu::ImageCopyTexture {
+      texture,
+      mip_level: 0,
+      origin: wgpu::Origin3d::ZERO,
+      aspect: wgpu::TextureAspect::All,
+    },
+    wgpu::ImageCopyBuffer {
+      buffer: &buffer,
+      layout: wgpu::ImageDataLayout {
+        offset: 0,
+        bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+        rows_per_image: std::num::NonZeroU32::new(size),
+      },
+    },
+    wgpu::Extent3d {
+      width: size,
+      height: size,
+      depth_or_array_layers: 1,
+    },
+  );
+
+  queue.submit(std::iter::once(encoder.finish()));
+
+  let slice = buffer.slice(..);
+  let mapping = slice.map_async(wgpu::MapMode::Read);
+  device.poll(wgpu::Maintain::Wait);
+  pollster::block_on(mapping)?;
+
+  let mut texels = Vec::with_capacity((4 * size * size) as usize);
+  {
+    let data = slice.get_mapped_range();
+    for row in data.chunks(padded_bytes_per_row as usize) {
+      let row = &row[..unpadded_bytes_per_row as usize];
+      if half_float {
+        texels.extend(
+          row
+            .chunks_exact(2)
+            .map(|half| f16_to_f32(u16::from_le_bytes([half[0], half[1]]))),
+        );
+      } else {
+        texels.extend(
+          row
+            .chunks_exact(4)
+            .map(|float| f32::from_le_bytes([float[0], float[1], float[2], float[3]])),
+        );
+      }
+    }
+  }
+  buffer.unmap();
+
+  Ok(texels)
+}
+
+pub fn f16_to_f32(bits: u16) -> f32 {
+  let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+  let exponent = ((bits >> 10) & 0x1f) as i32;
+  let mantissa = (bits & 0x3ff) as f32;
+
+  match exponent {
+    0 => sign * mantissa * f32::powi(2.0, -24),
+    0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+    0x1f => f32::NAN,
+    _ => sign * (1.0 + mantissa / 1024.0) * f32::powi(2.0, exponent - 15),
+  }
+}
+
+#[test]
+fn test_f16_to_f32() {
+  assert_eq!(f16_to_f32(0x3c00), 1.0);
+  assert_eq!(f16_to_f32(0xc000), -2.0);
+  assert_eq!(f16_to_f32(0x3555), 0.33325195);
+  assert_eq!(f16_to_f32(0x0001), f32::powi(2.0, -24));
+  assert_eq!(f16_to_f32(0x7c00), f32::INFINITY);
+  assert!(f16_to_f32(0x7e00).is_nan());
+}
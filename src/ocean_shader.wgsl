@@ -11,6 +11,48 @@ struct CameraUniform {
 [[group(0), binding(0)]]
 var<uniform> camera: CameraUniform;
 
+// Perez coefficients and zenith values of luminance and chromaticity, as Yxy
+// in each vec3.
+struct SkyUniform {
+    a: vec3<f32>;
+    b: vec3<f32>;
+    c: vec3<f32>;
+    d: vec3<f32>;
+    e: vec3<f32>;
+    zenith: vec3<f32>;
+};
+
+struct LightingUniform {
+    sun_direction: vec3<f32>;
+    sun_color: vec3<f32>;
+    sky_color: vec3<f32>;
+    sky: SkyUniform;
+};
+
+[[group(0), binding(1)]]
+var<uniform> lighting: LightingUniform;
+
+struct ShadingUniform {
+    // The `WaterMaterial`.
+    scatter_color: vec3<f32>;
+    ior: f32;
+    absorption: vec3<f32>;
+    subsurface_strength: f32;
+    foam_albedo: vec3<f32>;
+    foam_roughness: f32;
+    // 1.0 for the cascades that are drawn, 0.0 for the others.
+    cascade_weights: vec3<f32>;
+    lod_scale: f32;
+    // Of the whole sea surface, including waves too small to be simulated.
+    slope_variance: f32;
+    // 0 for none, 1 for planar, 2 for screen-space reflections of the scene.
+    reflection_mode: u32;
+    foam_coverage: f32;
+};
+
+[[group(0), binding(2)]]
+var<uniform> shading: ShadingUniform;
+
 [[group(1), binding(0)]]
 var t_displacement_0: texture_2d<f32>;
 
@@ -32,19 +74,56 @@ var t_displacement_2: texture_2d<f32>;
 [[group(1), binding(6)]]
 var t_derivatives_2: texture_2d<f32>;
 
-[[group(1), binding(7)]]
+// Shapes foam takes, brighter texels foaming first.
+[[group(2), binding(0)]]
 var t_foam: texture_2d<f32>;
 
-let SKY_COLOR = vec3<f32>(0.9, 0.9, 0.9);
+// The objects of the scene, transparent where there are none. Seen from the
+// mirrored camera for planar reflections, from the camera otherwise.
+[[group(2), binding(1)]]
+var t_reflection: texture_2d<f32>;
+
+// Depth of `t_reflection`, for screen-space reflections only.
+[[group(2), binding(2)]]
+var t_reflection_depth: texture_2d<f32>;
+
+// Detail of the foam up close.
+[[group(2), binding(3)]]
+var t_foam_bubbles: texture_2d<f32>;
 
-let OCEAN_BASE_COLOR = vec3<f32>(0.0, 0.10, 0.18);
-let OCEAN_WATER_COLOR = vec3<f32>(0.48, 0.54, 0.36);
+// Radiance around the ocean, blurred for rougher surfaces in lower mip
+// levels.
+[[group(3), binding(0)]]
+var t_environment: texture_cube<f32>;
+
+[[group(3), binding(1)]]
+var s_environment: sampler;
 
-let OCEAN_COLOR = vec3<f32>(0.0, 0.38, 0.53);
-// let OCEAN_COLOR = vec3<f32>(0.0, 0.0, 1.0);
-let SUN_DIR = vec3<f32>(-1.0, 1.0, 1.0);
-let LOD_SCALE = 15.0;
 let LENGTH_SCALE = vec3<f32>(500.0, 85.0, 10.0);
+// Part of the slope variance carried by each cascade, the rest comes from
+// capillary waves that are never drawn.
+let CASCADE_SLOPE_SHARE = vec3<f32>(0.15, 0.3, 0.45);
+
+// How far the waves shift the planar reflection, in parts of the screen.
+let PLANAR_DISTORTION = 0.03;
+let SSR_STEPS: i32 = 48;
+// Each step of the screen-space ray is longer than the last.
+let SSR_STEP_GROWTH = 1.1;
+let SSR_THICKNESS = 1.0;
+// Keeps the reflection of the sun from collapsing into a single bright pixel.
+let MIN_ROUGHNESS = 0.03;
+// Bends the sunlight shining through crests towards the viewer.
+let SUBSURFACE_DISTORTION = 0.3;
+let SUBSURFACE_POWER = 4.0;
+// Average value of a foam pattern, where it is too far away to be resolved.
+let FOAM_PATTERN_MEAN = 0.5;
+let FOAM_SOFTNESS = 0.15;
+let FOAM_BUBBLES_MEAN = 0.35;
+// Bubble texture repeats per tile of the smallest cascade.
+let FOAM_BUBBLES_TILING = 4.0;
+// Part of the light falling on foam that gets through, all that is seen of
+// it from below.
+let FOAM_TRANSLUCENCY = 0.3;
 
 let PI: f32 = 3.14159265358979323846264338;
 let INFINITE = 100000.0;
@@ -64,6 +143,9 @@ struct VertexOutput {
     [[location(4)]] world_pos: vec3<f32>;
     [[location(5)]] view_vector: vec3<f32>;
     [[location(6)]] lod_scales: vec3<f32>;
+    // How far below the surface the view ray starts, negative above it.
+    // Interpolated across the screen like the near plane it is measured on.
+    [[location(7), interpolate(linear)]] submersion: f32;
 };
 
 fn screen_to_world(screen_uv: vec3<f32>) -> vec3<f32> {
@@ -84,6 +166,16 @@ fn intercept_plane(source: vec3<f32>, dir: vec3<f32>, normal: vec3<f32>, height:
     }
 }
 
+// Height of the water over `xz`, leaving out the horizontal part of the
+// displacement, as it is only needed right next to the camera. Matches
+// `surface_height` in underwater.wgsl.
+fn surface_height(xz: vec2<f32>) -> f32 {
+    let weights = shading.cascade_weights;
+    return textureSampleLevel(t_displacement_0, s_derivatives, xz / LENGTH_SCALE.x, 0.0).y * weights.x
+        + textureSampleLevel(t_displacement_1, s_derivatives, xz / LENGTH_SCALE.y, 0.0).y * weights.y
+        + textureSampleLevel(t_displacement_2, s_derivatives, xz / LENGTH_SCALE.z, 0.0).y * weights.z;
+}
+
 [[stage(vertex)]]
 fn vs_main(model: VertexInput) -> VertexOutput {
     var out: VertexOutput;
@@ -101,13 +193,17 @@ fn vs_main(model: VertexInput) -> VertexOutput {
     if (dist < 0.0) {
         world_pos = ray_origin + dist * ray;
     } else {
-        world_pos = ray_origin - INFINITE * ray;
+        // Rays leaving the surface, up above it or down below it, end on its
+        // horizon.
+        let horizon = vec3<f32>(-ray.x, 0.0, -ray.z);
+        world_pos = vec3<f32>(ray_origin.x, 0.0, ray_origin.z) + horizon * (INFINITE / max(length(horizon), 1e-6));
     }
 
     let view_dist = abs(length(camera.pos - world_pos));
-    let lod_c0 = min(LOD_SCALE * LENGTH_SCALE.x / view_dist, 1.0);
-    let lod_c1 = min(LOD_SCALE * LENGTH_SCALE.y / view_dist, 1.0);
-    let lod_c2 = min(LOD_SCALE * LENGTH_SCALE.z / view_dist, 1.0);
+    let lod_scales = min(shading.lod_scale * LENGTH_SCALE / view_dist, vec3<f32>(1.0));
+    let lod_c0 = lod_scales.x * shading.cascade_weights.x;
+    let lod_c1 = lod_scales.y * shading.cascade_weights.y;
+    let lod_c2 = lod_scales.z * shading.cascade_weights.z;
 
     let near = view_dist < 200.0;
     let mid = view_dist < 2000.0;
@@ -152,44 +248,163 @@ fn vs_main(model: VertexInput) -> VertexOutput {
     out.world_pos = pos;
     out.view_vector = normalize(camera.pos - pos);
     out.lod_scales = vec3<f32>(lod_c0, lod_c1, lod_c2);
+    out.submersion = surface_height(ray_origin.xz) - ray_origin.y;
 
     return out;
 }
 
-fn hdr(color: vec3<f32>, exposure: f32) -> vec3<f32> {
-    return 1.0 - exp(-color * exposure);
+// Reflectance of unpolarized light arriving at `cos_theta` to the normal,
+// `ior` being the index of refraction it enters over the one it leaves.
+fn fresnel(cos_theta: f32, ior: f32) -> f32 {
+    let c = clamp(cos_theta, 0.0, 1.0);
+    let g2 = ior * ior - 1.0 + c * c;
+    // Total internal reflection.
+    if (g2 <= 0.0) {
+        return 1.0;
+    }
+    let g = sqrt(g2);
+    let a = (g - c) / (g + c);
+    let b = (c * (g + c) - 1.0) / (c * (g - c) + 1.0);
+    return 0.5 * a * a * (1.0 + b * b);
+}
+
+fn ggx_distribution(n_dot_h: f32, alpha: f32) -> f32 {
+    let a2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// Height correlated Smith masking and shadowing, divided by 4 n.l n.v.
+fn smith_visibility(n_dot_v: f32, n_dot_l: f32, alpha: f32) -> f32 {
+    let a2 = alpha * alpha;
+    let v = n_dot_l * sqrt(n_dot_v * n_dot_v * (1.0 - a2) + a2);
+    let l = n_dot_v * sqrt(n_dot_l * n_dot_l * (1.0 - a2) + a2);
+    return 0.5 / max(v + l, 1e-5);
+}
+
+// The environment seen in `dir` by a surface of the given GGX roughness.
+fn environment_radiance(dir: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let lod = roughness * f32(textureNumLevels(t_environment) - 1);
+    return textureSampleLevel(t_environment, s_environment, dir, lod).rgb;
+}
+
+// Where the mirrored camera saw the objects, shifted by the waves.
+fn planar_reflection(frag_coord: vec2<f32>, n: vec3<f32>) -> vec4<f32> {
+    let size = textureDimensions(t_reflection);
+    let uv = frag_coord / vec2<f32>(size) + n.xz * PLANAR_DISTORTION;
+    let texel = clamp(vec2<i32>(uv * vec2<f32>(size)), vec2<i32>(0), size - 1);
+    return textureLoad(t_reflection, texel, 0);
+}
+
+// Marches along the reflected ray until it passes behind an object drawn on
+// screen.
+fn screen_space_reflection(origin: vec3<f32>, dir: vec3<f32>) -> vec4<f32> {
+    if (dir.y <= 0.0) {
+        return vec4<f32>(0.0);
+    }
+
+    let size = textureDimensions(t_reflection_depth);
+    var step_length = max(length(origin - camera.pos) * 0.01, 0.1);
+    var p = origin;
+    for (var i = 0; i < SSR_STEPS; i = i + 1) {
+        p = p + dir * step_length;
+        step_length = step_length * SSR_STEP_GROWTH;
+
+        let clip = camera.view_proj * vec4<f32>(p, 1.0);
+        if (clip.w <= 0.0) {
+            break;
+        }
+        let ndc = clip.xyz / clip.w;
+        let uv = vec2<f32>(ndc.x, -ndc.y) * 0.5 + 0.5;
+        if (any(uv < vec2<f32>(0.0)) || any(uv >= vec2<f32>(1.0))) {
+            break;
+        }
+
+        let texel = vec2<i32>(uv * vec2<f32>(size));
+        let depth = textureLoad(t_reflection_depth, texel, 0).r;
+        if (depth < 1.0) {
+            let hit = screen_to_world(vec3<f32>(ndc.xy, depth));
+            let behind = length(p - camera.pos) - length(hit - camera.pos);
+            if (behind > 0.0 && behind < SSR_THICKNESS + step_length) {
+                // Fades out where the ray is about to leave the screen.
+                let edge = min(uv, 1.0 - uv);
+                let fade = clamp(min(edge.x, edge.y) * 10.0, 0.0, 1.0);
+                let color = textureLoad(t_reflection, texel, 0);
+                return vec4<f32>(color.rgb, color.a * fade);
+            }
+        }
+    }
+    return vec4<f32>(0.0);
 }
 
-fn diffuse(n: vec3<f32>, l: vec3<f32>, p: f32) -> f32 {
-    return pow(dot(n, l) * 0.4 + 0.6, p);
+// Sunlight reflected towards `v` by a surface of the given GGX roughness.
+fn sun_specular(n: vec3<f32>, l: vec3<f32>, v: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let n_dot_v = max(dot(n, v), 1e-4);
+    let n_dot_l = max(dot(n, l), 0.0);
+    let alpha = max(roughness, MIN_ROUGHNESS) * max(roughness, MIN_ROUGHNESS);
+    let h = normalize(l + v);
+    return ggx_distribution(max(dot(n, h), 0.0), alpha) * smith_visibility(n_dot_v, n_dot_l, alpha)
+        * fresnel(dot(h, v), shading.ior) * n_dot_l * lighting.sun_color;
 }
 
-fn specular(n: vec3<f32>, l: vec3<f32>, e: vec3<f32>, s: f32) -> f32 {
-    let nrm = (s + 8.0) / (PI * 8.0);
-    return pow(max(dot(reflect(e, n), l), 0.0), s) * nrm;
+// Light reflected by the surface and scattered back out of the water, with
+// what is not reflected entering it.
+fn getSeaColor(p: vec3<f32>, n: vec3<f32>, l: vec3<f32>, eye: vec3<f32>, roughness: f32, scene: vec4<f32>) -> vec3<f32> {
+    let v = -eye;
+    let reflectance = fresnel(max(dot(n, v), 0.0), shading.ior);
+
+    // Objects in front of the environment.
+    let reflected = mix(environment_radiance(reflect(eye, n), roughness), scene.rgb, scene.a);
+
+    // Deep water scatters back part of the sun and sky light falling on it.
+    let sky_irradiance = environment_radiance(vec3<f32>(0.0, 1.0, 0.0), 1.0);
+    let irradiance = lighting.sun_color * max(l.y, 0.0) + sky_irradiance;
+    let scattered = shading.scatter_color * irradiance;
+
+    // Crests above the mean level let the sun through from behind, reddest
+    // light absorbed first.
+    let thickness = max(p.y, 0.0);
+    let through = pow(max(dot(eye, normalize(l + n * SUBSURFACE_DISTORTION)), 0.0), SUBSURFACE_POWER);
+    let subsurface = shading.subsurface_strength * through * thickness * exp(-shading.absorption * thickness)
+        * shading.scatter_color * lighting.sun_color;
+
+    return reflectance * reflected + (1.0 - reflectance) * (scattered + subsurface) + sun_specular(n, l, v, roughness);
 }
 
-fn getSkyColor(e: vec3<f32>) -> vec3<f32> {
-    let ey = (max(e.y, 0.0) * 0.8 + 0.2) * 0.8;
-    return vec3<f32>(pow(1.0 - ey, 2.0), 1.0 - ey, 0.6 + (1.0 - ey) * 0.4) * 1.1;
+// Direction of the ray `i` going through a surface of normal `n`, `eta`
+// being the index of refraction it leaves over the one it enters. Grazes the
+// surface past the critical angle.
+fn refraction(i: vec3<f32>, n: vec3<f32>, eta: f32) -> vec3<f32> {
+    let cos_i = dot(n, i);
+    let k = 1.0 - eta * eta * (1.0 - cos_i * cos_i);
+    return eta * i - (eta * cos_i + sqrt(max(k, 0.0))) * n;
 }
 
-fn getSeaColor(p: vec3<f32>, n: vec3<f32>, l: vec3<f32>, eye: vec3<f32>, dist: vec3<f32>) -> vec3<f32> {  
-    var fresnel_factor = dot(n, -eye);
-    fresnel_factor = max(fresnel_factor, 0.0);
-    fresnel_factor = 1.0 - fresnel_factor;
-    fresnel_factor = pow(fresnel_factor, 5.0) * 0.5;
+// The surface seen from the water. Sky and sun only show through Snell's
+// window above the viewer, past its edge the surface totally reflects the
+// depths.
+fn getUndersideColor(n: vec3<f32>, l: vec3<f32>, eye: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let down = -n;
+    let reflectance = fresnel(max(dot(down, -eye), 0.0), 1.0 / shading.ior);
 
-    let reflected = SKY_COLOR;
-    let refracted = OCEAN_BASE_COLOR + diffuse(n, l, 80.0) * OCEAN_WATER_COLOR * 0.12;
+    // The depths scatter back the light that came in from above.
+    let sky_irradiance = environment_radiance(vec3<f32>(0.0, 1.0, 0.0), 1.0);
+    let depths = shading.scatter_color * (lighting.sun_color * max(l.y, 0.0) + sky_irradiance);
 
-    var color = (1.0 - fresnel_factor) * refracted + fresnel_factor * reflected;
+    // The sun spread by the waves like its reflection is.
+    let refracted = refraction(eye, down, shading.ior);
+    let alpha = max(roughness, MIN_ROUGHNESS) * max(roughness, MIN_ROUGHNESS);
+    let sun = ggx_distribution(max(dot(refracted, l), 0.0), alpha) * lighting.sun_color;
+    let window = environment_radiance(refracted, roughness) + sun;
 
-    let atten = max(1.0 - dot(dist, dist) * 0.00001, 0.0);
-    color = color + OCEAN_WATER_COLOR * (p.y - 0.1) * 0.04 * atten;
-    color = color + vec3<f32>(1.0, 1.0, 1.0) * 0.05 * specular(n,l,eye,1200.0);
+    return reflectance * depths + (1.0 - reflectance) * window;
+}
 
-    return color;
+// Foam as a rough and mostly diffuse layer over the water.
+fn getFoamColor(n: vec3<f32>, l: vec3<f32>, eye: vec3<f32>, albedo: vec3<f32>) -> vec3<f32> {
+    let sky_irradiance = environment_radiance(vec3<f32>(0.0, 1.0, 0.0), 1.0);
+    let diffuse = albedo * (lighting.sun_color * max(dot(n, l), 0.0) + sky_irradiance);
+    return diffuse + sun_specular(n, l, -eye, shading.foam_roughness);
 }
 
 [[stage(fragment)]]
@@ -213,22 +428,70 @@ fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
         d = d + d2 * in.lod_scales.z;
     }
 
-    let j0 = textureSample(t_displacement_0, s_derivatives, in.uv_0).w * 0.6;
-    let j1 = textureSample(t_displacement_1, s_derivatives, in.uv_1).w * 0.17;
-    let j2 = textureSample(t_displacement_2, s_derivatives, in.uv_2).w * 0.23;
+    // The displacement's w is the jacobian of the current frame on either
+    // backend. Hidden cascades count as flat water, whose jacobian is 1.
+    let weights = shading.cascade_weights;
+    let j0 = mix(1.0, textureSample(t_displacement_0, s_derivatives, in.uv_0).w, weights.x) * 0.6;
+    let j1 = mix(1.0, textureSample(t_displacement_1, s_derivatives, in.uv_1).w, weights.y) * 0.17;
+    let j2 = mix(1.0, textureSample(t_displacement_2, s_derivatives, in.uv_2).w, weights.z) * 0.23;
 
     let turbulence = clamp((-(j0 + j1 + j2) + 0.84) * 2.4, 0.0, 1.0);
     var slope = vec2<f32>(d.x / (1.0 + d.z), d.y / (1.0 + d.w));
     var normal = normalize(vec3<f32>(-slope.x, 1.0, -slope.y));
 
-    let fog_range = vec2<f32>(200.0, 10000.0);
-    let fog_factor = clamp((distance - fog_range.x) / (fog_range.y - fog_range.x), 0.0, 1.0);
+    // Each scale of the pattern fades to its mean with its cascade, where it
+    // would only flicker.
+    var mid_fade = 0.0;
+    if (mid) {
+        mid_fade = in.lod_scales.y;
+    }
+    var near_fade = 0.0;
+    if (near) {
+        near_fade = in.lod_scales.z;
+    }
+    let pattern_mid = textureSample(t_foam, s_derivatives, in.uv_1).r;
+    let pattern_near = textureSample(t_foam, s_derivatives, in.uv_2).r;
+    let pattern = mix(FOAM_PATTERN_MEAN, pattern_mid, mid_fade) * 0.6 + mix(FOAM_PATTERN_MEAN, pattern_near, near_fade) * 0.4;
 
-    let foam_color = textureSample(t_foam, s_derivatives, in.uv_1);
-    let foam = turbulence;
+    // The more the surface folds, the more of the pattern foams, all of it
+    // at full coverage.
+    let coverage = clamp(turbulence * shading.foam_coverage, 0.0, 1.0);
+    let foam = smoothStep(1.0 - coverage, 1.0 - coverage + FOAM_SOFTNESS, pattern) * min(coverage * 4.0, 1.0);
+
+    let bubbles = textureSample(t_foam_bubbles, s_derivatives, in.uv_2 * FOAM_BUBBLES_TILING).rgb;
+    let foam_albedo = min(shading.foam_albedo * mix(vec3<f32>(1.0), bubbles / FOAM_BUBBLES_MEAN, near_fade), vec3<f32>(1.0));
+
+    // Slopes of the cascades faded out with distance are no longer in the
+    // normal, they roughen the reflection instead.
+    var resolved = in.lod_scales.x * CASCADE_SLOPE_SHARE.x;
+    if (mid) {
+        resolved = resolved + in.lod_scales.y * CASCADE_SLOPE_SHARE.y;
+    }
+    if (near) {
+        resolved = resolved + in.lod_scales.z * CASCADE_SLOPE_SHARE.z;
+    }
+    let variance = shading.slope_variance * (1.0 - resolved);
+    // Beckmann to GGX, then to perceptual roughness.
+    let roughness = clamp(sqrt(sqrt(2.0 * variance)), 0.0, 1.0);
+
+    let light = normalize(lighting.sun_direction);
+    let eye = normalize(in.world_pos - camera.pos);
+
+    if (in.submersion > 0.0) {
+        let sky_irradiance = environment_radiance(vec3<f32>(0.0, 1.0, 0.0), 1.0);
+        let foam_color = foam_albedo * FOAM_TRANSLUCENCY * (lighting.sun_color * max(light.y, 0.0) + sky_irradiance);
+        return vec4<f32>(mix(getUndersideColor(normal, light, eye, roughness), foam_color, foam), 1.0);
+    }
+
+    var scene = vec4<f32>(0.0);
+    if (shading.reflection_mode == 1u) {
+        scene = planar_reflection(in.clip_position.xy, normal);
+    } else if (shading.reflection_mode == 2u) {
+        scene = screen_space_reflection(in.world_pos, reflect(eye, normal));
+    }
 
-    let light = normalize(SUN_DIR);
-    let color = getSeaColor(in.world_pos, normal, light, normalize(in.world_pos - camera.pos), camera.pos - in.world_pos);
+    let water = getSeaColor(in.world_pos, normal, light, eye, roughness, scene);
+    let color = mix(water, getFoamColor(normal, light, eye, foam_albedo), foam);
 
-    return vec4<f32>(color + fog_factor + foam, 1.0);
+    return vec4<f32>(color, 1.0);
 }
@@ -0,0 +1,330 @@
+use cgmath::{InnerSpace, Matrix, SquareMatrix};
+
+use crate::ocean::shaders::{self, Shader};
+use crate::ocean::OceanError;
+use crate::scene::PassInfo;
+
+pub const REFLECTIONS_SHADER: Shader =
+  Shader::new("src/reflections.wgsl", include_str!("reflections.wgsl"));
+
+// Depth is copied out as a color, depth formats cannot be read after a
+// multisampled pass without resolving them by hand.
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+
+// How objects added to the scene show up in the water. The sky is always
+// reflected through the environment cubemap.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum ReflectionMode {
+  #[default]
+  None,
+  // The objects are drawn a second time, seen from a camera mirrored around
+  // the mean sea level. Only works from above the water.
+  Planar,
+  // The ocean traces its reflections through the depth of the objects as
+  // seen by the camera, missing whatever is off screen or hidden.
+  ScreenSpace,
+}
+
+impl ReflectionMode {
+  pub const ALL: [ReflectionMode; 3] = [
+    ReflectionMode::None,
+    ReflectionMode::Planar,
+    ReflectionMode::ScreenSpace,
+  ];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      ReflectionMode::None => "none",
+      ReflectionMode::Planar => "planar",
+      ReflectionMode::ScreenSpace => "screen-space",
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<ReflectionMode> {
+    Self::ALL.into_iter().find(|mode| mode.name() == name)
+  }
+
+  // What the ocean shader expects in `reflection_mode`.
+  pub(crate) fn index(self) -> u32 {
+    match self {
+      ReflectionMode::None => 0,
+      ReflectionMode::Planar => 1,
+      ReflectionMode::ScreenSpace => 2,
+    }
+  }
+}
+
+// `view` seeing the world mirrored around y = 0.
+pub fn mirrored_view(view: cgmath::Matrix4<f32>) -> cgmath::Matrix4<f32> {
+  view * cgmath::Matrix4::from_nonuniform_scale(1.0, -1.0, 1.0)
+}
+
+// Moves the near plane of `projection` onto `plane`, given in view space with
+// the camera on its negative side, so that what lies behind it is clipped.
+// Lengyel, "Oblique View Frustum Depth Projection and Clipping", adapted to
+// depth in 0..1.
+pub fn oblique_projection(
+  projection: cgmath::Matrix4<f32>,
+  plane: cgmath::Vector4<f32>,
+) -> cgmath::Matrix4<f32> {
+  let inverse = match projection.invert() {
+    Some(inverse) => inverse,
+    None => return projection,
+  };
+  // The far corner of the frustum opposite the plane.
+  let corner = inverse * cgmath::vec4(plane.x.signum(), plane.y.signum(), 1.0, 1.0);
+  let row = plane * (projection.row(3).dot(corner) / plane.dot(corner));
+
+  let mut oblique = projection;
+  oblique.x.z = row.x;
+  oblique.y.z = row.y;
+  oblique.z.z = row.z;
+  oblique.w.z = row.w;
+  oblique
+}
+
+// The mean sea level in the view space of `mirrored_view`.
+pub fn water_plane(mirrored_view: cgmath::Matrix4<f32>) -> cgmath::Vector4<f32> {
+  let inverse = mirrored_view
+    .invert()
+    .unwrap_or_else(cgmath::Matrix4::identity);
+  inverse.transpose() * cgmath::vec4(0.0, 1.0, 0.0, 0.0)
+}
+
+// What the ocean shader reads its reflections of the scene from: the objects
+// drawn in the frame's color format, transparent where there are none, and
+// for screen-space reflections their depth.
+pub(crate) struct Reflections {
+  pub color_view: wgpu::TextureView,
+  pub depth_view: wgpu::TextureView,
+
+  resolve_pipeline_layout: wgpu::PipelineLayout,
+  resolve_bind_group_layout: wgpu::BindGroupLayout,
+  resolve_pipeline: wgpu::RenderPipeline,
+  resolve_bind_group: wgpu::BindGroup,
+  pass_info: PassInfo,
+}
+
+impl Reflections {
+  // `scene_depth_view` is the depth the objects are drawn with, see
+  // `scene::create_depth_texture`.
+  pub fn new(
+    device: &wgpu::Device,
+    pass_info: PassInfo,
+    size: winit::dpi::PhysicalSize<u32>,
+    scene_depth_view: &wgpu::TextureView,
+  ) -> Reflections {
+    let multisampled = pass_info.sample_count > 1;
+    let resolve_bind_group_layout =
+      device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("depth_resolve_bind_group_layout"),
+        entries: &[wgpu::BindGroupLayoutEntry {
+          // Each entry point reads its own binding.
+          binding: if multisampled { 0 } else { 1 },
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Texture {
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Depth,
+            multisampled,
+          },
+          count: None,
+        }],
+      });
+
+    let resolve_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Depth Resolve Pipeline Layout"),
+      bind_group_layouts: &[&resolve_bind_group_layout],
+      push_constant_ranges: &[],
+    });
+
+    let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+      label: Some("Reflections shader"),
+      source: wgpu::ShaderSource::Wgsl(REFLECTIONS_SHADER.source()),
+    });
+    let resolve_pipeline =
+      Self::create_resolve_pipeline(device, &resolve_pipeline_layout, &shader, multisampled);
+
+    let (color_view, depth_view, resolve_bind_group) = Self::create_targets(
+      device,
+      &resolve_bind_group_layout,
+      pass_info,
+      size,
+      scene_depth_view,
+    );
+
+    Reflections {
+      color_view,
+      depth_view,
+      resolve_pipeline_layout,
+      resolve_bind_group_layout,
+      resolve_pipeline,
+      resolve_bind_group,
+      pass_info,
+    }
+  }
+
+  pub fn resize(
+    &mut self,
+    device: &wgpu::Device,
+    size: winit::dpi::PhysicalSize<u32>,
+    scene_depth_view: &wgpu::TextureView,
+  ) {
+    let (color_view, depth_view, resolve_bind_group) = Self::create_targets(
+      device,
+      &self.resolve_bind_group_layout,
+      self.pass_info,
+      size,
+      scene_depth_view,
+    );
+    self.color_view = color_view;
+    self.depth_view = depth_view;
+    self.resolve_bind_group = resolve_bind_group;
+  }
+
+  pub async fn reload(
+    &mut self,
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+  ) -> Result<(), OceanError> {
+    self.resolve_pipeline = shaders::try_create(device, || {
+      Self::create_resolve_pipeline(
+        device,
+        &self.resolve_pipeline_layout,
+        shader,
+        self.pass_info.sample_count > 1,
+      )
+    })
+    .await?;
+    Ok(())
+  }
+
+  // Copies the scene's depth into `depth_view`, once the objects are drawn.
+  pub fn resolve_depth(&self, encoder: &mut wgpu::CommandEncoder) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some("Depth Resolve Pass"),
+      color_attachments: &[wgpu::RenderPassColorAttachment {
+        view: &self.depth_view,
+        resolve_target: None,
+        ops: wgpu::Operations {
+          load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+          store: true,
+        },
+      }],
+      depth_stencil_attachment: None,
+    });
+    render_pass.set_pipeline(&self.resolve_pipeline);
+    render_pass.set_bind_group(0, &self.resolve_bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+  }
+
+  fn create_targets(
+    device: &wgpu::Device,
+    resolve_bind_group_layout: &wgpu::BindGroupLayout,
+    pass_info: PassInfo,
+    size: winit::dpi::PhysicalSize<u32>,
+    scene_depth_view: &wgpu::TextureView,
+  ) -> (wgpu::TextureView, wgpu::TextureView, wgpu::BindGroup) {
+    let create_view = |label, format| {
+      device
+        .create_texture(&wgpu::TextureDescriptor {
+          label: Some(label),
+          size: wgpu::Extent3d {
+            width: size.width,
+            height: size.height,
+            depth_or_array_layers: 1,
+          },
+          mip_level_count: 1,
+          sample_count: 1,
+          dimension: wgpu::TextureDimension::D2,
+          format,
+          usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+    };
+    let color_view = create_view("Reflection color", pass_info.color_format);
+    let depth_view = create_view("Reflection depth", DEPTH_FORMAT);
+
+    let resolve_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Depth resolve bind group"),
+      layout: resolve_bind_group_layout,
+      entries: &[wgpu::BindGroupEntry {
+        binding: if pass_info.sample_count > 1 { 0 } else { 1 },
+        resource: wgpu::BindingResource::TextureView(scene_depth_view),
+      }],
+    });
+
+    (color_view, depth_view, resolve_bind_group)
+  }
+
+  fn create_resolve_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    multisampled: bool,
+  ) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Depth Resolve Pipeline"),
+      layout: Some(layout),
+      vertex: wgpu::VertexState {
+        module: shader,
+        entry_point: "vs_main",
+        buffers: &[],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: shader,
+        entry_point: if multisampled {
+          "fs_resolve_multisampled"
+        } else {
+          "fs_copy"
+        },
+        targets: &[wgpu::ColorTargetState {
+          format: DEPTH_FORMAT,
+          blend: None,
+          write_mask: wgpu::ColorWrites::ALL,
+        }],
+      }),
+      primitive: wgpu::PrimitiveState::default(),
+      depth_stencil: None,
+      multisample: wgpu::MultisampleState::default(),
+      multiview: None,
+    })
+  }
+}
+
+#[test]
+fn test_oblique_projection() {
+  use crate::camera::OPENGL_TO_WGPU_MATRIX;
+
+  let view = cgmath::Matrix4::look_to_rh(
+    cgmath::point3(0.0, 10.0, 0.0),
+    cgmath::vec3(1.0, -0.3, 0.0),
+    cgmath::Vector3::unit_y(),
+  );
+  let projection = OPENGL_TO_WGPU_MATRIX * cgmath::perspective(cgmath::Deg(45.0), 1.5, 1.0, 1000.0);
+  let mirrored = mirrored_view(view);
+  let oblique = oblique_projection(projection, water_plane(mirrored));
+
+  let depth = |[x, y, z]: [f32; 3]| {
+    let point = cgmath::vec4(x, y, z, 1.0);
+    let clip = oblique * mirrored * point;
+    // Only depth changes, the image stays where it was.
+    let unchanged = projection * mirrored * point;
+    assert!((clip.x - unchanged.x).abs() < 1e-3 && (clip.w - unchanged.w).abs() < 1e-3);
+    clip.z / clip.w
+  };
+
+  // Clipped at the water, kept above it up to the far plane.
+  assert!(depth([20.0, 0.0, 0.0]).abs() < 1e-3);
+  assert!(depth([40.0, 0.0, 5.0]).abs() < 1e-3);
+  let above = depth([20.0, 5.0, 0.0]);
+  assert!(above > 0.0 && above < 1.0);
+  assert!(depth([20.0, -2.0, 0.0]) < 0.0);
+
+  assert_eq!(
+    ReflectionMode::from_name("screen-space"),
+    Some(ReflectionMode::ScreenSpace)
+  );
+  assert_eq!(ReflectionMode::from_name("mirror"), None);
+}
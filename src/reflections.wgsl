@@ -0,0 +1,26 @@
+// Copies the depth of the scene captured for screen-space reflections into a
+// color texture the ocean shader can read, see `DepthResolve`.
+
+[[group(0), binding(0)]]
+var t_depth_multisampled: texture_depth_multisampled_2d;
+
+[[group(0), binding(1)]]
+var t_depth: texture_depth_2d;
+
+// A triangle covering the viewport.
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32) -> [[builtin(position)]] vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+// The first sample is as good as any, the reflection is blurry anyway.
+[[stage(fragment)]]
+fn fs_resolve_multisampled([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] f32 {
+    return textureLoad(t_depth_multisampled, vec2<i32>(position.xy), 0);
+}
+
+[[stage(fragment)]]
+fn fs_copy([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] f32 {
+    return textureLoad(t_depth, vec2<i32>(position.xy), 0);
+}
@@ -1,12 +1,94 @@
 use image::GenericImageView;
 use wgpu::util::DeviceExt;
 
+use crate::bloom::{self, Bloom, BloomPass, BLOOM_SHADER};
 use crate::camera;
+use crate::debug_ui::{DebugInfo, DebugSettings, DebugUi};
+use crate::debug_view::{DebugView, DebugViewPass, DEBUG_VIEW_SHADER};
+use crate::environment::{Environment, EnvironmentSource, ENVIRONMENT_SHADER};
+use crate::fog::{Fog, FogPass, FOG_SHADER};
 use crate::generate_plane::generate_plane;
-use crate::ocean::{OceanCascade, OceanCascadeParameters};
+use crate::gpu_timer::GpuTimer;
+use crate::ocean::shaders::{self, Shader};
+use crate::ocean::{Ocean, OceanBackend, OceanCascadeParameters, OceanConfig, OceanError};
+use crate::reflections::{self, ReflectionMode, Reflections, REFLECTIONS_SHADER};
+use crate::scene::{
+  self, Drawable, Mesh, MeshPipeline, ObjectId, PassInfo, SceneObject, MESH_SHADER,
+};
+use crate::sky::{self, SkyModel, SkyPipeline, SkyUniform, SunPosition, SKY_SHADER};
+use crate::tonemap::{TonemapPass, Tonemapping, EXPOSURE_SHADER, HDR_FORMAT, TONEMAP_SHADER};
+use crate::underwater::{Underwater, UnderwaterPass, UNDERWATER_SHADER};
 use crate::vertex::Vertex;
 
-const SAMPLE_COUNT: u32 = 4;
+// Offscreen frames are read back as tightly packed, sRGB encoded RGBA bytes.
+const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+// Seconds over which sea state edits made in the debug UI blend in.
+const DEBUG_UI_OCEAN_TRANSITION: f32 = 0.5;
+// Side of the built-in lens dirt texture.
+const LENS_DIRT_SIZE: u32 = 512;
+
+#[derive(Debug)]
+pub enum RendererError {
+  NoAdapter,
+  RequestDevice(wgpu::RequestDeviceError),
+  Image(image::ImageError),
+  Ocean(OceanError),
+  PipelineCreation(String),
+  // Only 1 (no multisampling) and 4 samples are supported everywhere.
+  UnsupportedSampleCount(u32),
+  // Frames can only be read back from a headless renderer.
+  NotHeadless,
+  Readback(wgpu::BufferAsyncError),
+  // The environment panorama cannot be used.
+  InvalidEnvironment(String),
+  // The requested debug view shows something the ocean does not have.
+  InvalidDebugView(String),
+}
+
+impl std::fmt::Display for RendererError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      RendererError::NoAdapter => write!(f, "no suitable graphics adapter found"),
+      RendererError::RequestDevice(error) => write!(f, "failed to create device: {}", error),
+      RendererError::Image(error) => write!(f, "failed to load texture: {}", error),
+      RendererError::Ocean(error) => write!(f, "{}", error),
+      RendererError::PipelineCreation(description) => {
+        write!(f, "failed to create render pipeline: {}", description)
+      }
+      RendererError::UnsupportedSampleCount(count) => {
+        write!(f, "unsupported sample count {}, use 1 or 4", count)
+      }
+      RendererError::NotHeadless => write!(f, "renderer does not target an offscreen texture"),
+      RendererError::Readback(error) => write!(f, "failed to read back frame: {}", error),
+      RendererError::InvalidEnvironment(description) => {
+        write!(f, "invalid environment: {}", description)
+      }
+      RendererError::InvalidDebugView(description) => {
+        write!(f, "invalid debug view: {}", description)
+      }
+    }
+  }
+}
+
+impl std::error::Error for RendererError {}
+
+impl From<wgpu::RequestDeviceError> for RendererError {
+  fn from(error: wgpu::RequestDeviceError) -> Self {
+    RendererError::RequestDevice(error)
+  }
+}
+
+impl From<image::ImageError> for RendererError {
+  fn from(error: image::ImageError) -> Self {
+    RendererError::Image(error)
+  }
+}
+
+impl From<OceanError> for RendererError {
+  fn from(error: OceanError) -> Self {
+    RendererError::Ocean(error)
+  }
+}
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
@@ -43,16 +125,261 @@ impl CameraUniform {
       .unwrap_or(cgmath::Matrix4::identity())
       .into();
   }
+
+  // The camera of the planar reflection, mirrored around the water and
+  // clipping what lies below it.
+  fn update_mirrored_view_proj(
+    &mut self,
+    camera: &camera::Camera,
+    projection: &camera::Projection,
+  ) {
+    use cgmath::prelude::*;
+    use cgmath::Matrix;
+
+    let view = reflections::mirrored_view(camera.calc_matrix());
+    let proj =
+      reflections::oblique_projection(projection.calc_matrix(), reflections::water_plane(view));
+    let mut position = camera.position.to_homogeneous();
+    position.y = -position.y;
+
+    self.view_position = position.into();
+    self.view_proj = (proj * view).into();
+    self.view = view.transpose().into();
+    self.proj = proj.transpose().into();
+    self.inverse_view_proj = (proj * view)
+      .inverse_transform()
+      .unwrap_or(cgmath::Matrix4::identity())
+      .into();
+  }
+}
+
+pub const OCEAN_SHADER: Shader =
+  Shader::new("src/ocean_shader.wgsl", include_str!("ocean_shader.wgsl"));
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Lighting {
+  // Points towards the sun, does not need to be normalized. Ignored when the
+  // sun is placed by `sun`.
+  pub sun_direction: [f32; 3],
+  pub sun: Option<SunPosition>,
+  // Color of the sun above the atmosphere, which tints it towards the
+  // horizon.
+  pub sun_color: [f32; 3],
+  // Ambient light on meshes added to the scene.
+  pub sky_color: [f32; 3],
+  // Haziness of the sky, from about 2 for a clear day to 10.
+  pub turbidity: f32,
+  pub fog: Fog,
+}
+
+impl Default for Lighting {
+  fn default() -> Lighting {
+    Lighting {
+      sun_direction: [-1.0, 1.0, 1.0],
+      sun: None,
+      sun_color: [1.0, 1.0, 1.0],
+      sky_color: [0.9, 0.9, 0.9],
+      turbidity: 2.5,
+      fog: Fog::default(),
+    }
+  }
+}
+
+impl Lighting {
+  pub fn sun_direction(&self) -> [f32; 3] {
+    match self.sun {
+      Some(sun) => sun.direction(),
+      None => self.sun_direction,
+    }
+  }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightingUniform {
+  sun_direction: [f32; 4],
+  sun_color: [f32; 4],
+  sky_color: [f32; 4],
+  sky: SkyUniform,
+  fog: [f32; 4],
+}
+
+impl From<Lighting> for LightingUniform {
+  fn from(lighting: Lighting) -> Self {
+    let extend = |[x, y, z]: [f32; 3]| [x, y, z, 0.0];
+    let sun_direction = lighting.sun_direction();
+    let transmittance = sky::sun_transmittance(sun_direction, lighting.turbidity);
+    let sun_color = [0, 1, 2].map(|i| lighting.sun_color[i] * transmittance[i]);
+    LightingUniform {
+      sun_direction: extend(sun_direction),
+      sun_color: extend(sun_color),
+      sky_color: extend(lighting.sky_color),
+      sky: SkyModel::new(sun_direction, lighting.turbidity).into(),
+      fog: lighting.fog.uniform(),
+    }
+  }
+}
+
+// Artistic controls of the surface shading.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Shading {
+  // Scales the distance up to which each cascade is displayed at full
+  // strength.
+  pub lod_scale: f32,
+  // Whether each cascade, from the largest to the smallest, is drawn.
+  pub cascades: [bool; 3],
+}
+
+impl Default for Shading {
+  fn default() -> Shading {
+    Shading {
+      lod_scale: 15.0,
+      cascades: [true; 3],
+    }
+  }
+}
+
+// Optical properties of the water. Its roughness comes from the sea state.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct WaterMaterial {
+  // Index of refraction, which sets how much light the surface reflects.
+  pub ior: f32,
+  // Part of the light entering deep water that is scattered back out.
+  pub scatter_color: [f32; 3],
+  // Per meter of water the light travels through.
+  pub absorption: [f32; 3],
+  // Scales the sunlight shining through the crests of waves.
+  pub subsurface_strength: f32,
+  pub foam_albedo: [f32; 3],
+  pub foam_roughness: f32,
+  // Scales how readily folding waves foam.
+  pub foam_coverage: f32,
+}
+
+impl Default for WaterMaterial {
+  fn default() -> WaterMaterial {
+    WaterMaterial {
+      ior: 1.33,
+      scatter_color: [0.0, 0.08, 0.13],
+      absorption: [0.45, 0.06, 0.04],
+      subsurface_strength: 2.0,
+      foam_albedo: [0.9, 0.9, 0.9],
+      foam_roughness: 0.7,
+      foam_coverage: 1.0,
+    }
+  }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadingUniform {
+  scatter_color: [f32; 3],
+  ior: f32,
+  absorption: [f32; 3],
+  subsurface_strength: f32,
+  foam_albedo: [f32; 3],
+  foam_roughness: f32,
+  cascade_weights: [f32; 3],
+  lod_scale: f32,
+  slope_variance: f32,
+  reflection_mode: u32,
+  foam_coverage: f32,
+  _padding: f32,
+}
+
+impl ShadingUniform {
+  fn new(
+    shading: Shading,
+    material: WaterMaterial,
+    wind_speed: f32,
+    reflection_mode: ReflectionMode,
+  ) -> Self {
+    ShadingUniform {
+      scatter_color: material.scatter_color,
+      ior: material.ior,
+      absorption: material.absorption,
+      subsurface_strength: material.subsurface_strength,
+      foam_albedo: material.foam_albedo,
+      foam_roughness: material.foam_roughness,
+      cascade_weights: shading.cascades.map(|drawn| if drawn { 1.0 } else { 0.0 }),
+      lod_scale: shading.lod_scale,
+      // Cox and Munk's fit of the slope variance of the sea surface, which
+      // the shader turns into roughness where waves are too small to draw.
+      slope_variance: 0.003 + 0.00512 * wind_speed,
+      reflection_mode: reflection_mode.index(),
+      foam_coverage: material.foam_coverage,
+      _padding: 0.0,
+    }
+  }
+}
+
+// Everything the renderer needs to know at startup.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct RendererConfig {
+  pub ocean: OceanCascadeParameters,
+  // `None` picks the best backend the adapter supports.
+  pub ocean_backend: Option<OceanBackend>,
+  pub seed: Option<u64>,
+  pub camera: camera::CameraConfig,
+  pub lighting: Lighting,
+  pub water_material: WaterMaterial,
+  pub sample_count: u32,
+  // Windowed only: waits for vertical blank when set, presents immediately
+  // when the platform allows it otherwise.
+  pub vsync: bool,
+  pub reflections: ReflectionMode,
+  pub underwater: Underwater,
+  pub bloom: Bloom,
+  pub tonemapping: Tonemapping,
+}
+
+impl Default for RendererConfig {
+  fn default() -> RendererConfig {
+    RendererConfig {
+      ocean: OceanConfig::default().parameters,
+      ocean_backend: None,
+      seed: None,
+      camera: camera::CameraConfig::default(),
+      lighting: Lighting::default(),
+      water_material: WaterMaterial::default(),
+      sample_count: 4,
+      vsync: true,
+      reflections: ReflectionMode::None,
+      underwater: Underwater::default(),
+      bloom: Bloom::default(),
+      tonemapping: Tonemapping::default(),
+    }
+  }
+}
+
+enum RenderTarget {
+  Surface {
+    surface: wgpu::Surface,
+    config: wgpu::SurfaceConfiguration,
+  },
+  Texture(wgpu::Texture),
 }
 
 pub struct Renderer {
-  surface: wgpu::Surface,
+  target: RenderTarget,
   device: wgpu::Device,
   queue: wgpu::Queue,
-  config: wgpu::SurfaceConfiguration,
   pub size: winit::dpi::PhysicalSize<u32>,
-  multisampled_framebuffer: wgpu::TextureView,
+  sample_count: u32,
+  // Only used when multisampling, resolved into the HDR target.
+  multisampled_framebuffer: Option<wgpu::TextureView>,
+  // Shared by the ocean and everything added to the scene.
+  depth_view: wgpu::TextureView,
 
+  render_pipeline_layout: wgpu::PipelineLayout,
   render_pipeline: wgpu::RenderPipeline,
   vertex_buffer: wgpu::Buffer,
   index_buffer: wgpu::Buffer,
@@ -64,17 +391,52 @@ pub struct Renderer {
   pub camera_controller: camera::CameraController,
   camera_uniform: CameraUniform,
   camera_buffer: wgpu::Buffer,
+  camera_bind_group_layout: wgpu::BindGroupLayout,
   camera_bind_group: wgpu::BindGroup,
+  lighting: Lighting,
+  lighting_buffer: wgpu::Buffer,
+  shading: Shading,
+  water_material: WaterMaterial,
+  shading_buffer: wgpu::Buffer,
 
-  heightmap_bind_group: wgpu::BindGroup,
-  ocean_surface: OceanCascade,
-  ocean_params: OceanCascadeParameters,
-  ocean_initialized: bool,
+  // Foam and the reflections of the scene, rebuilt with the reflection
+  // targets.
+  surface_bind_group_layout: wgpu::BindGroupLayout,
+  surface_bind_group: wgpu::BindGroup,
+  foam_view: wgpu::TextureView,
+  foam_bubbles_view: wgpu::TextureView,
+  reflection_mode: ReflectionMode,
+  reflections: Reflections,
+  reflection_camera_buffer: wgpu::Buffer,
+  reflection_camera_bind_group: wgpu::BindGroup,
+  environment: Environment,
+  ocean: Ocean,
   pub mouse_pressed: bool,
+
+  sky_pipeline: SkyPipeline,
+  // Drawn after the ocean in the same pass, in the order they were added.
+  mesh_pipeline: MeshPipeline,
+  objects: Vec<(ObjectId, SceneObject)>,
+  next_object_id: u64,
+
+  // Everything is drawn into its HDR target, then fogged, seen through the
+  // water, bloomed and tonemapped into the render target.
+  fog: FogPass,
+  underwater: UnderwaterPass,
+  tonemap: TonemapPass,
+  bloom: BloomPass,
+  debug_view: DebugViewPass,
+  // Windowed only.
+  debug_ui: Option<DebugUi>,
+  // Only when the adapter supports timestamp queries.
+  gpu_timer: Option<GpuTimer>,
 }
 
 impl Renderer {
-  pub async fn new<'b>(window: &'b winit::window::Window) -> Renderer {
+  pub async fn new<'b>(
+    window: &'b winit::window::Window,
+    config: &RendererConfig,
+  ) -> Result<Renderer, RendererError> {
     let size = window.inner_size();
 
     let instance = wgpu::Instance::new(wgpu::Backends::all());
@@ -86,47 +448,160 @@ impl Renderer {
         force_fallback_adapter: false,
       })
       .await
-      .unwrap();
+      .ok_or(RendererError::NoAdapter)?;
+
+    let (device, queue, ocean_backend) = Self::request_device(&adapter, config).await?;
+
+    // sRGB formats are preferred where the platform has them, the tonemapping
+    // pass encodes the frame itself otherwise.
+    let format = surface
+      .get_preferred_format(&adapter)
+      .unwrap_or(wgpu::TextureFormat::Bgra8UnormSrgb);
+    let surface_config = wgpu::SurfaceConfiguration {
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+      format,
+      width: size.width,
+      height: size.height,
+      present_mode: Self::present_mode(config.vsync),
+    };
+    surface.configure(&device, &surface_config);
+
+    let mut renderer = Self::with_target(
+      device,
+      queue,
+      ocean_backend,
+      RenderTarget::Surface {
+        surface,
+        config: surface_config,
+      },
+      size,
+      config,
+    )
+    .await?;
+
+    renderer.debug_ui = Some(DebugUi::new(
+      &renderer.device,
+      renderer.target.format(),
+      size,
+      window.scale_factor(),
+    ));
+    Ok(renderer)
+  }
+
+  // Renders into an offscreen texture instead of a window, e.g. on CI or
+  // render farms. `force_fallback_adapter` picks a software adapter when the
+  // platform has one.
+  pub async fn new_headless(
+    width: u32,
+    height: u32,
+    force_fallback_adapter: bool,
+    config: &RendererConfig,
+  ) -> Result<Renderer, RendererError> {
+    let size = winit::dpi::PhysicalSize::new(width, height);
+
+    let instance = wgpu::Instance::new(wgpu::Backends::all());
+    let adapter = instance
+      .request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::default(),
+        compatible_surface: None,
+        force_fallback_adapter,
+      })
+      .await
+      .ok_or(RendererError::NoAdapter)?;
+
+    let (device, queue, ocean_backend) = Self::request_device(&adapter, config).await?;
+    let texture = Self::create_offscreen_texture(&device, size);
+
+    Self::with_target(
+      device,
+      queue,
+      ocean_backend,
+      RenderTarget::Texture(texture),
+      size,
+      config,
+    )
+    .await
+  }
+
+  async fn request_device(
+    adapter: &wgpu::Adapter,
+    config: &RendererConfig,
+  ) -> Result<(wgpu::Device, wgpu::Queue, OceanBackend), RendererError> {
+    if config.sample_count != 1 && config.sample_count != 4 {
+      return Err(RendererError::UnsupportedSampleCount(config.sample_count));
+    }
+
+    let ocean_backend = match config.ocean_backend {
+      Some(backend) => {
+        let missing = backend.missing_capabilities(adapter);
+        if !missing.is_empty() {
+          return Err(OceanError::UnsupportedDevice(missing).into());
+        }
+        backend
+      }
+      None => {
+        for capability in OceanBackend::Fft.missing_capabilities(adapter) {
+          log::warn!("FFT ocean backend unavailable: {}", capability);
+        }
+        OceanBackend::select(adapter)
+      }
+    };
+    log::info!(
+      "Using {:?} ocean backend on {}",
+      ocean_backend,
+      adapter.get_info().name
+    );
 
     let (device, queue) = adapter
       .request_device(
         &wgpu::DeviceDescriptor {
-          features: wgpu::Features::POLYGON_MODE_LINE
-            | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
-            | wgpu::Features::PUSH_CONSTANTS,
-          limits: wgpu::Limits {
-            max_push_constant_size: 256,
-            ..wgpu::Limits::default()
-          },
+          features: ocean_backend.features(adapter)
+            | (adapter.features()
+              & (wgpu::Features::POLYGON_MODE_LINE | wgpu::Features::TIMESTAMP_QUERY)),
+          limits: ocean_backend.limits().using_resolution(adapter.limits()),
           label: None,
         },
         None, // Trace path
       )
-      .await
-      .unwrap();
+      .await?;
 
-    let config = wgpu::SurfaceConfiguration {
-      usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-      format: wgpu::TextureFormat::Bgra8Unorm,
-      width: size.width,
-      height: size.height,
-      present_mode: wgpu::PresentMode::Fifo,
+    Ok((device, queue, ocean_backend))
+  }
+
+  async fn with_target(
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    ocean_backend: OceanBackend,
+    target: RenderTarget,
+    size: winit::dpi::PhysicalSize<u32>,
+    config: &RendererConfig,
+  ) -> Result<Renderer, RendererError> {
+    let format = target.format();
+    let sample_count = config.sample_count;
+    // The FFT backend is only picked on adapters with compute shaders, the
+    // Gerstner backend's limits leave no room for storage bindings. Passes with
+    // a compute and a render path follow the ocean.
+    let compute = ocean_backend == OceanBackend::Fft;
+    let pass_info = PassInfo {
+      color_format: HDR_FORMAT,
+      depth_format: scene::DEPTH_FORMAT,
+      sample_count,
     };
-    surface.configure(&device, &config);
 
     let multisampled_framebuffer =
-      Self::create_multisampled_framebuffer(&device, &config, SAMPLE_COUNT);
+      Self::create_multisampled_framebuffer(&device, HDR_FORMAT, size, sample_count);
+    let depth_view = scene::create_depth_texture(&device, size, sample_count);
 
     // Render pipeline (shaders)
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
     let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
       label: Some("Shader"),
-      source: wgpu::ShaderSource::Wgsl(include_str!("ocean_shader.wgsl").into()),
+      source: wgpu::ShaderSource::Wgsl(OCEAN_SHADER.source()),
     });
 
-    let camera = camera::Camera::new((0.0, 40.0, 0.0), cgmath::Deg(160.0), cgmath::Deg(-20.0));
-    let projection =
-      camera::Projection::new(config.width, config.height, cgmath::Deg(45.0), 1.0, 5000.0);
-    let camera_controller = camera::CameraController::new(20.0, 1.0);
+    let camera = config.camera.camera();
+    let projection = config.camera.projection(size.width, size.height);
+    let camera_controller = config.camera.controller();
 
     let mut camera_uniform = CameraUniform::new();
     camera_uniform.update_view_proj(&camera, &projection);
@@ -137,166 +612,147 @@ impl Renderer {
       usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
     });
 
-    let camera_bind_group_layout =
-      device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        entries: &[wgpu::BindGroupLayoutEntry {
-          binding: 0,
-          visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-          ty: wgpu::BindingType::Buffer {
-            ty: wgpu::BufferBindingType::Uniform,
-            has_dynamic_offset: false,
-            min_binding_size: None,
-          },
-          count: None,
-        }],
-        label: Some("camera_bind_group_layout"),
-      });
-
-    let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-      layout: &camera_bind_group_layout,
-      entries: &[wgpu::BindGroupEntry {
-        binding: 0,
-        resource: camera_buffer.as_entire_binding(),
-      }],
-      label: Some("camera_bind_group"),
-    });
-
-    // foam texture
-    let foam_img = image::load_from_memory(include_bytes!("./assets/foam.jpg")).unwrap();
-    let foam_bytes = foam_img.to_rgba8().to_vec();
-    let (foam_width, foam_height) = foam_img.dimensions();
-
-    let foam_texture = device.create_texture(&wgpu::TextureDescriptor {
-      label: Some("Foam texture"),
-      size: wgpu::Extent3d {
-        width: foam_width,
-        height: foam_height,
-        depth_or_array_layers: 1,
-      },
-      mip_level_count: 6,
-      sample_count: 1,
-      dimension: wgpu::TextureDimension::D2,
-      format: wgpu::TextureFormat::Rgba8UnormSrgb,
-      usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+    let lighting_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Lighting Buffer"),
+      contents: bytemuck::cast_slice(&[LightingUniform::from(config.lighting)]),
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
     });
 
-    queue.write_texture(
-      wgpu::ImageCopyTexture {
-        texture: &foam_texture,
-        mip_level: 0,
-        origin: wgpu::Origin3d::ZERO,
-        aspect: wgpu::TextureAspect::All,
-      },
-      &foam_bytes,
-      wgpu::ImageDataLayout {
-        offset: 0,
-        bytes_per_row: std::num::NonZeroU32::new(4 * foam_width),
-        rows_per_image: std::num::NonZeroU32::new(foam_height),
-      },
-      wgpu::Extent3d {
-        width: foam_width,
-        height: foam_height,
-        depth_or_array_layers: 1,
-      },
-    );
-
-    for i in 1..=5 {
-      let downsample_factor = u32::pow(2, i);
-      let width = foam_width / downsample_factor;
-      let height = foam_height / downsample_factor;
-
-      let mip_bytes = foam_img.thumbnail(width, height).to_rgba8().to_vec();
-
-      queue.write_texture(
-        wgpu::ImageCopyTexture {
-          texture: &foam_texture,
-          mip_level: i,
-          origin: wgpu::Origin3d::ZERO,
-          aspect: wgpu::TextureAspect::All,
-        },
-        &mip_bytes,
-        wgpu::ImageDataLayout {
-          offset: 0,
-          bytes_per_row: std::num::NonZeroU32::new(4 * width),
-          rows_per_image: std::num::NonZeroU32::new(height),
-        },
-        wgpu::Extent3d {
-          width: width,
-          height: height,
-          depth_or_array_layers: 1,
-        },
-      );
-    }
-
-    // ocean
-    let ocean_size = 256;
-    let ocean_initialized = false;
-
-    let ocean_params = OceanCascadeParameters {
-      size: ocean_size,
-      wind_speed: 10.0,
-      wind_direction: 180.0,
-      swell: 0.3,
-    };
+    let environment = Environment::new(&device, &lighting_buffer, compute);
+    environment.capture(&device, &queue);
 
-    let derivatives_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-      address_mode_u: wgpu::AddressMode::Repeat,
-      address_mode_v: wgpu::AddressMode::Repeat,
-      address_mode_w: wgpu::AddressMode::Repeat,
-      mag_filter: wgpu::FilterMode::Linear,
-      min_filter: wgpu::FilterMode::Linear,
-      mipmap_filter: wgpu::FilterMode::Linear,
-      anisotropy_clamp: std::num::NonZeroU8::new(8),
-      ..Default::default()
+    let shading = Shading::default();
+    let shading_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Shading Buffer"),
+      contents: bytemuck::cast_slice(&[ShadingUniform::new(
+        shading,
+        config.water_material,
+        config.ocean.wind_speed,
+        config.reflections,
+      )]),
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
     });
 
-    let ocean_surface = OceanCascade::new(&device, ocean_size, ocean_params);
-    let texture_bind_group_layout =
+    let camera_bind_group_layout =
       device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
         entries: &[
-          // displacement 0
           wgpu::BindGroupLayoutEntry {
             binding: 0,
             visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-            ty: wgpu::BindingType::Texture {
-              view_dimension: wgpu::TextureViewDimension::D2,
-              sample_type: wgpu::TextureSampleType::Float { filterable: true },
-              multisampled: false,
+            ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Uniform,
+              has_dynamic_offset: false,
+              min_binding_size: None,
             },
             count: None,
           },
-          // derivatives 0
+          // lighting
           wgpu::BindGroupLayoutEntry {
             binding: 1,
             visibility: wgpu::ShaderStages::FRAGMENT,
-            ty: wgpu::BindingType::Texture {
-              view_dimension: wgpu::TextureViewDimension::D2,
-              sample_type: wgpu::TextureSampleType::Float { filterable: true },
-              multisampled: false,
+            ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Uniform,
+              has_dynamic_offset: false,
+              min_binding_size: None,
             },
             count: None,
           },
-          // derivatives sampler
+          // shading
           wgpu::BindGroupLayoutEntry {
             binding: 2,
             visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-            count: None,
-          },
-          // displacement 1
-          wgpu::BindGroupLayoutEntry {
-            binding: 3,
-            visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-            ty: wgpu::BindingType::Texture {
-              view_dimension: wgpu::TextureViewDimension::D2,
-              sample_type: wgpu::TextureSampleType::Float { filterable: true },
-              multisampled: false,
+            ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Uniform,
+              has_dynamic_offset: false,
+              min_binding_size: None,
             },
             count: None,
           },
-          // derivatives 1
+        ],
+        label: Some("camera_bind_group_layout"),
+      });
+
+    let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      layout: &camera_bind_group_layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: camera_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: lighting_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: shading_buffer.as_entire_binding(),
+        },
+      ],
+      label: Some("camera_bind_group"),
+    });
+
+    let mut reflection_camera_uniform = CameraUniform::new();
+    reflection_camera_uniform.update_mirrored_view_proj(&camera, &projection);
+    let reflection_camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Reflection Camera Buffer"),
+      contents: bytemuck::cast_slice(&[reflection_camera_uniform]),
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    });
+    let reflection_camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      layout: &camera_bind_group_layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: reflection_camera_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: lighting_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: shading_buffer.as_entire_binding(),
+        },
+      ],
+      label: Some("reflection_camera_bind_group"),
+    });
+
+    // Foam shapes, and the bubbles they are made of up close.
+    let foam_view = Self::create_image_texture(
+      &device,
+      &queue,
+      &image::load_from_memory(include_bytes!("./assets/foam2.jpg"))?,
+      "Foam texture",
+      wgpu::TextureFormat::Rgba8Unorm,
+    );
+    let foam_bubbles_view = Self::create_image_texture(
+      &device,
+      &queue,
+      &image::load_from_memory(include_bytes!("./assets/foam.jpg"))?,
+      "Foam bubbles texture",
+      wgpu::TextureFormat::Rgba8UnormSrgb,
+    );
+
+    // ocean
+    let ocean = Ocean::new(
+      &device,
+      &queue,
+      OceanConfig {
+        backend: ocean_backend,
+        parameters: config.ocean,
+        seed: config.seed,
+      },
+    )
+    .await?;
+
+    let reflections = Reflections::new(&device, pass_info, size, &depth_view);
+
+    let surface_bind_group_layout =
+      device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+          // foam
           wgpu::BindGroupLayoutEntry {
-            binding: 4,
+            binding: 0,
             visibility: wgpu::ShaderStages::FRAGMENT,
             ty: wgpu::BindingType::Texture {
               view_dimension: wgpu::TextureViewDimension::D2,
@@ -305,10 +761,10 @@ impl Renderer {
             },
             count: None,
           },
-          // displacement 2
+          // reflection color
           wgpu::BindGroupLayoutEntry {
-            binding: 5,
-            visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+            binding: 1,
+            visibility: wgpu::ShaderStages::FRAGMENT,
             ty: wgpu::BindingType::Texture {
               view_dimension: wgpu::TextureViewDimension::D2,
               sample_type: wgpu::TextureSampleType::Float { filterable: true },
@@ -316,20 +772,20 @@ impl Renderer {
             },
             count: None,
           },
-          // derivatives 2
+          // reflection depth
           wgpu::BindGroupLayoutEntry {
-            binding: 6,
+            binding: 2,
             visibility: wgpu::ShaderStages::FRAGMENT,
             ty: wgpu::BindingType::Texture {
               view_dimension: wgpu::TextureViewDimension::D2,
-              sample_type: wgpu::TextureSampleType::Float { filterable: true },
+              sample_type: wgpu::TextureSampleType::Float { filterable: false },
               multisampled: false,
             },
             count: None,
           },
-          // foam
+          // foam bubbles
           wgpu::BindGroupLayoutEntry {
-            binding: 7,
+            binding: 3,
             visibility: wgpu::ShaderStages::FRAGMENT,
             ty: wgpu::BindingType::Texture {
               view_dimension: wgpu::TextureViewDimension::D2,
@@ -339,131 +795,38 @@ impl Renderer {
             count: None,
           },
         ],
-        label: Some("texture_bind_group_layout"),
+        label: Some("surface_bind_group_layout"),
       });
 
-    let heightmap_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-      layout: &texture_bind_group_layout,
-      entries: &[
-        wgpu::BindGroupEntry {
-          binding: 0,
-          resource: wgpu::BindingResource::TextureView(
-            &ocean_surface.cascade_0.displacement_texture().create_view(
-              &wgpu::TextureViewDescriptor {
-                ..Default::default()
-              },
-            ),
-          ),
-        },
-        wgpu::BindGroupEntry {
-          binding: 1,
-          resource: wgpu::BindingResource::TextureView(
-            &ocean_surface.cascade_0.derivatives_texture().create_view(
-              &wgpu::TextureViewDescriptor {
-                ..Default::default()
-              },
-            ),
-          ),
-        },
-        wgpu::BindGroupEntry {
-          binding: 2,
-          resource: wgpu::BindingResource::Sampler(&derivatives_sampler),
-        },
-        wgpu::BindGroupEntry {
-          binding: 3,
-          resource: wgpu::BindingResource::TextureView(
-            &ocean_surface.cascade_1.displacement_texture().create_view(
-              &wgpu::TextureViewDescriptor {
-                ..Default::default()
-              },
-            ),
-          ),
-        },
-        wgpu::BindGroupEntry {
-          binding: 4,
-          resource: wgpu::BindingResource::TextureView(
-            &ocean_surface.cascade_1.derivatives_texture().create_view(
-              &wgpu::TextureViewDescriptor {
-                ..Default::default()
-              },
-            ),
-          ),
-        },
-        wgpu::BindGroupEntry {
-          binding: 5,
-          resource: wgpu::BindingResource::TextureView(
-            &ocean_surface.cascade_2.displacement_texture().create_view(
-              &wgpu::TextureViewDescriptor {
-                ..Default::default()
-              },
-            ),
-          ),
-        },
-        wgpu::BindGroupEntry {
-          binding: 6,
-          resource: wgpu::BindingResource::TextureView(
-            &ocean_surface.cascade_2.derivatives_texture().create_view(
-              &wgpu::TextureViewDescriptor {
-                ..Default::default()
-              },
-            ),
-          ),
-        },
-        wgpu::BindGroupEntry {
-          binding: 7,
-          resource: wgpu::BindingResource::TextureView(&foam_texture.create_view(
-            &wgpu::TextureViewDescriptor {
-              ..Default::default()
-            },
-          )),
-        },
-      ],
-      label: Some("Texture bind group"),
-    });
+    let surface_bind_group = Self::create_surface_bind_group(
+      &device,
+      &surface_bind_group_layout,
+      [&foam_view, &foam_bubbles_view],
+      &reflections,
+    );
 
     let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
       label: Some("Render Pipeline Layout"),
-      bind_group_layouts: &[&camera_bind_group_layout, &texture_bind_group_layout],
+      bind_group_layouts: &[
+        &camera_bind_group_layout,
+        &ocean.bindings().bind_group_layout,
+        &surface_bind_group_layout,
+        &environment.bind_group_layout,
+      ],
       push_constant_ranges: &[],
     });
 
-    let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-      label: Some("Render Pipeline"),
-      layout: Some(&render_pipeline_layout),
-      vertex: wgpu::VertexState {
-        module: &shader,
-        entry_point: "vs_main",
-        buffers: &[Vertex::desc()],
-      },
-      fragment: Some(wgpu::FragmentState {
-        module: &shader,
-        entry_point: "fs_main",
-        targets: &[wgpu::ColorTargetState {
-          format: config.format,
-          blend: Some(wgpu::BlendState::REPLACE),
-          write_mask: wgpu::ColorWrites::ALL,
-        }],
-      }),
-      primitive: wgpu::PrimitiveState {
-        topology: wgpu::PrimitiveTopology::TriangleList,
-        strip_index_format: None,
-        front_face: wgpu::FrontFace::default(),
-        cull_mode: Some(wgpu::Face::Front),
-        // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-        polygon_mode: wgpu::PolygonMode::Fill,
-        // Requires Features::DEPTH_CLIP_CONTROL
-        unclipped_depth: false,
-        // Requires Features::CONSERVATIVE_RASTERIZATION
-        conservative: false,
-      },
-      depth_stencil: None,
-      multisample: wgpu::MultisampleState {
-        count: SAMPLE_COUNT,
-        mask: !0,
-        alpha_to_coverage_enabled: false,
-      },
-      multiview: None,
-    });
+    let render_pipeline = Self::create_render_pipeline(
+      &device,
+      &render_pipeline_layout,
+      &shader,
+      HDR_FORMAT,
+      sample_count,
+    );
+
+    if let Some(error) = device.pop_error_scope().await {
+      return Err(RendererError::PipelineCreation(error.to_string()));
+    }
 
     let (plane_mesh_vertices, plane_mesh_indices) = generate_plane(2.3, 512);
 
@@ -481,13 +844,52 @@ impl Renderer {
 
     let num_indices = plane_mesh_indices.len() as u32;
 
-    Renderer {
-      surface,
+    let sky_pipeline = SkyPipeline::new(&device, &camera_bind_group_layout, pass_info);
+    let mesh_pipeline = MeshPipeline::new(&device, &camera_bind_group_layout, pass_info);
+
+    let fog = FogPass::new(
+      &device,
+      &camera_bind_group_layout,
+      &environment.bind_group_layout,
+      pass_info,
+      &depth_view,
+    );
+    let underwater = UnderwaterPass::new(
+      &device,
+      &camera_bind_group_layout,
+      &ocean.bindings().bind_group_layout,
+      &environment.bind_group_layout,
+      pass_info,
+      &depth_view,
+      config.underwater,
+    );
+    let tonemap = TonemapPass::new(&device, format, size, config.tonemapping, compute);
+    let lens_dirt_view = Self::create_image_texture(
+      &device,
+      &queue,
+      &image::DynamicImage::ImageRgba8(bloom::lens_dirt(LENS_DIRT_SIZE)),
+      "Lens dirt texture",
+      wgpu::TextureFormat::Rgba8Unorm,
+    );
+    let bloom = BloomPass::new(
+      &device,
+      size,
+      &tonemap.hdr_view,
+      lens_dirt_view,
+      config.bloom,
+    );
+    let debug_view = DebugViewPass::new(&device, format);
+
+    // Before and after the simulation and the surface pass.
+    let gpu_timer = GpuTimer::new(&device, &queue, 3);
+
+    Ok(Renderer {
+      target,
       device,
       queue,
-      config,
       size,
 
+      render_pipeline_layout,
       render_pipeline,
       vertex_buffer,
       index_buffer,
@@ -496,28 +898,83 @@ impl Renderer {
       projection,
       camera_controller,
       camera_uniform,
+      camera_bind_group_layout,
       camera_bind_group,
       camera_buffer,
+      lighting: config.lighting,
+      lighting_buffer,
+      shading,
+      water_material: config.water_material,
+      shading_buffer,
 
-      heightmap_bind_group,
-      ocean_params,
-      ocean_surface,
-      ocean_initialized,
+      surface_bind_group_layout,
+      surface_bind_group,
+      foam_view,
+      foam_bubbles_view,
+      reflection_mode: config.reflections,
+      reflections,
+      reflection_camera_buffer,
+      reflection_camera_bind_group,
+      environment,
+      ocean,
 
       mouse_pressed: false,
+      sample_count,
       multisampled_framebuffer,
-    }
+      depth_view,
+
+      sky_pipeline,
+      mesh_pipeline,
+      objects: Vec::new(),
+      next_object_id: 0,
+
+      fog,
+      underwater,
+      tonemap,
+      bloom,
+      debug_view,
+      debug_ui: None,
+      gpu_timer,
+    })
   }
 
   pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
     if new_size.width > 0 && new_size.height > 0 {
       self.size = new_size;
-      self.config.width = new_size.width;
-      self.config.height = new_size.height;
       self.projection.resize(new_size.width, new_size.height);
-      self.surface.configure(&self.device, &self.config);
-      self.multisampled_framebuffer =
-        Self::create_multisampled_framebuffer(&self.device, &self.config, SAMPLE_COUNT);
+
+      match &mut self.target {
+        RenderTarget::Surface { surface, config } => {
+          config.width = new_size.width;
+          config.height = new_size.height;
+          surface.configure(&self.device, config);
+        }
+        RenderTarget::Texture(texture) => {
+          *texture = Self::create_offscreen_texture(&self.device, new_size);
+        }
+      }
+
+      self.multisampled_framebuffer = Self::create_multisampled_framebuffer(
+        &self.device,
+        HDR_FORMAT,
+        new_size,
+        self.sample_count,
+      );
+      self.depth_view = scene::create_depth_texture(&self.device, new_size, self.sample_count);
+      self
+        .reflections
+        .resize(&self.device, new_size, &self.depth_view);
+      self.write_surface_bind_group();
+      self.fog.resize(&self.device, &self.depth_view);
+      self.underwater.resize(&self.device, &self.depth_view);
+      self.tonemap.resize(&self.device, new_size);
+      self
+        .bloom
+        .resize(&self.device, new_size, &self.tonemap.hdr_view);
+
+      if let Some(debug_ui) = &mut self.debug_ui {
+        debug_ui.resize(new_size);
+      }
     }
   }
 
@@ -526,6 +983,12 @@ impl Renderer {
       event::ElementState, event::KeyboardInput, event::MouseButton, event::WindowEvent,
     };
 
+    if let Some(debug_ui) = &mut self.debug_ui {
+      if debug_ui.handle_event(event) {
+        return true;
+      }
+    }
+
     match event {
       WindowEvent::KeyboardInput {
         input:
@@ -553,7 +1016,379 @@ impl Renderer {
   }
 
   pub fn update(&mut self, dt: std::time::Duration) {
+    if let Some(debug_ui) = &mut self.debug_ui {
+      debug_ui.record_frame(dt);
+    }
+
     self.camera_controller.update_camera(&mut self.camera, dt);
+    self.write_camera_uniform();
+  }
+
+  // Places the camera directly instead of going through the controller.
+  pub fn set_camera(&mut self, camera: camera::Camera) {
+    self.camera = camera;
+    self.write_camera_uniform();
+  }
+
+  pub fn set_projection(&mut self, projection: camera::Projection) {
+    self.projection = projection;
+    self.write_camera_uniform();
+  }
+
+  pub fn set_camera_controller(&mut self, controller: camera::CameraController) {
+    self.camera_controller = controller;
+  }
+
+  // Moves the sea state to `params` over `duration` seconds, see
+  // `Ocean::transition_parameters`.
+  pub fn transition_ocean(
+    &mut self,
+    params: OceanCascadeParameters,
+    duration: f32,
+  ) -> Result<(), OceanError> {
+    self.ocean.transition_parameters(params, duration)?;
+    self.write_shading_uniform();
+    Ok(())
+  }
+
+  // Has no effect on a headless renderer.
+  pub fn set_vsync(&mut self, vsync: bool) {
+    if let RenderTarget::Surface { surface, config } = &mut self.target {
+      config.present_mode = Self::present_mode(vsync);
+      surface.configure(&self.device, config);
+    }
+  }
+
+  pub fn set_lighting(&mut self, lighting: Lighting) {
+    self.lighting = lighting;
+    self.queue.write_buffer(
+      &self.lighting_buffer,
+      0,
+      bytemuck::cast_slice(&[LightingUniform::from(lighting)]),
+    );
+    if self.environment.follows_sky() {
+      self.environment.capture(&self.device, &self.queue);
+    }
+  }
+
+  pub fn set_shading(&mut self, shading: Shading) {
+    self.shading = shading;
+    self.write_shading_uniform();
+  }
+
+  pub fn set_water_material(&mut self, material: WaterMaterial) {
+    self.water_material = material;
+    self.write_shading_uniform();
+  }
+
+  pub fn set_reflection_mode(&mut self, mode: ReflectionMode) {
+    self.reflection_mode = mode;
+    self.write_shading_uniform();
+  }
+
+  pub fn reflection_mode(&self) -> ReflectionMode {
+    self.reflection_mode
+  }
+
+  pub fn set_underwater(&mut self, underwater: Underwater) {
+    self.underwater.set_settings(&self.queue, underwater);
+  }
+
+  pub fn underwater(&self) -> Underwater {
+    self.underwater.settings()
+  }
+
+  // Whether the camera may be under the waves, or close enough to them for
+  // the waterline to cross the frame. Only the GPU knows where the surface
+  // is, so this goes by how high the simulated waves can rise, with a meter to
+  // spare.
+  pub fn may_be_submerged(&self) -> bool {
+    self.camera.position.y < self.ocean.crest_height() + 1.0
+  }
+
+  pub fn set_tonemapping(&mut self, tonemapping: Tonemapping) {
+    self.tonemap.set_settings(tonemapping);
+  }
+
+  pub fn tonemapping(&self) -> Tonemapping {
+    self.tonemap.settings()
+  }
+
+  pub fn set_bloom(&mut self, bloom: Bloom) {
+    self.bloom.set_settings(&self.queue, bloom);
+  }
+
+  pub fn bloom(&self) -> Bloom {
+    self.bloom.settings()
+  }
+
+  // Replaces the built-in smudges bloom shows on the lens, read from any
+  // format the image crate supports. Only seen with `Bloom::lens_dirt` set.
+  pub fn set_lens_dirt_texture(&mut self, path: &std::path::Path) -> Result<(), RendererError> {
+    let image = image::open(path)?;
+    let lens_dirt_view = Self::create_image_texture(
+      &self.device,
+      &self.queue,
+      &image,
+      "Lens dirt texture",
+      wgpu::TextureFormat::Rgba8UnormSrgb,
+    );
+    self
+      .bloom
+      .set_lens_dirt(&self.device, lens_dirt_view, &self.tonemap.hdr_view);
+    Ok(())
+  }
+
+  // Replaces the shapes foam takes where waves break, read from any format
+  // the image crate decodes. Brighter texels foam first.
+  pub fn set_foam_texture(&mut self, path: &std::path::Path) -> Result<(), RendererError> {
+    let image = image::open(path)?;
+    self.foam_view = Self::create_image_texture(
+      &self.device,
+      &self.queue,
+      &image,
+      "Foam texture",
+      wgpu::TextureFormat::Rgba8Unorm,
+    );
+    self.write_surface_bind_group();
+    Ok(())
+  }
+
+  // Replaces what the water reflects, the sky by default.
+  pub fn set_environment(&mut self, source: EnvironmentSource) -> Result<(), RendererError> {
+    self
+      .environment
+      .set_source(&self.device, &self.queue, source)
+  }
+
+  // Rebuilds the pipelines running `shader` from `source`, keeping the current
+  // ones if it does not compile or fit their layouts. Returns whether any
+  // pipeline uses the shader.
+  pub async fn reload_shader(
+    &mut self,
+    shader: Shader,
+    source: &str,
+  ) -> Result<bool, RendererError> {
+    let device = &self.device;
+    let create_module = || {
+      device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some(shader.path),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+      })
+    };
+
+    match shader {
+      OCEAN_SHADER => {
+        self.render_pipeline = shaders::try_create(device, || {
+          Self::create_render_pipeline(
+            device,
+            &self.render_pipeline_layout,
+            &create_module(),
+            HDR_FORMAT,
+            self.sample_count,
+          )
+        })
+        .await?;
+      }
+      DEBUG_VIEW_SHADER => {
+        let module = shaders::try_create(device, create_module).await?;
+        self.debug_view.reload(device, &module).await?;
+      }
+      ENVIRONMENT_SHADER => {
+        let module = shaders::try_create(device, create_module).await?;
+        self.environment.reload(device, &module).await?;
+        self.environment.capture(device, &self.queue);
+      }
+      SKY_SHADER => {
+        let module = shaders::try_create(device, create_module).await?;
+        self.sky_pipeline.reload(device, &module).await?;
+      }
+      MESH_SHADER => {
+        let module = shaders::try_create(device, create_module).await?;
+        self.mesh_pipeline.reload(device, &module).await?;
+      }
+      REFLECTIONS_SHADER => {
+        let module = shaders::try_create(device, create_module).await?;
+        self.reflections.reload(device, &module).await?;
+      }
+      FOG_SHADER => {
+        let module = shaders::try_create(device, create_module).await?;
+        self.fog.reload(device, &module).await?;
+      }
+      UNDERWATER_SHADER => {
+        let module = shaders::try_create(device, create_module).await?;
+        self.underwater.reload(device, &module).await?;
+      }
+      BLOOM_SHADER => {
+        let module = shaders::try_create(device, create_module).await?;
+        self.bloom.reload(device, &module).await?;
+      }
+      TONEMAP_SHADER => {
+        let module = shaders::try_create(device, create_module).await?;
+        self.tonemap.reload_tonemap(device, &module).await?;
+      }
+      EXPOSURE_SHADER => {
+        let module = shaders::try_create(device, create_module).await?;
+        self.tonemap.reload_exposure(device, &module).await?;
+      }
+      _ => {
+        let reloaded = self
+          .ocean
+          .reload_shader(&self.device, &self.queue, shader, source)
+          .await?;
+        return Ok(reloaded);
+      }
+    }
+    Ok(true)
+  }
+
+  // Draws a simulation texture over the frame, `None` goes back to the
+  // ocean alone.
+  pub fn set_debug_view(&mut self, view: Option<DebugView>) -> Result<(), RendererError> {
+    self
+      .debug_view
+      .set_view(&self.device, &self.queue, &self.ocean, view)
+  }
+
+  pub fn debug_view(&self) -> Option<DebugView> {
+    self.debug_view.view()
+  }
+
+  // Adds `mesh` to the scene, lit by the sun and sky, placed by `transform`.
+  pub fn add_mesh(
+    &mut self,
+    mesh: &Mesh,
+    transform: cgmath::Matrix4<f32>,
+    color: [f32; 3],
+  ) -> ObjectId {
+    let instance = self
+      .mesh_pipeline
+      .upload(&self.device, mesh, transform, color);
+    self.add_object(SceneObject::Mesh(Box::new(instance)))
+  }
+
+  // Returns false when `id` is not a mesh in the scene.
+  pub fn set_mesh_transform(&mut self, id: ObjectId, transform: cgmath::Matrix4<f32>) -> bool {
+    match self.objects.iter().find(|(object_id, _)| *object_id == id) {
+      Some((_, SceneObject::Mesh(instance))) => {
+        instance.set_transform(&self.queue, transform);
+        true
+      }
+      _ => false,
+    }
+  }
+
+  // Adds custom drawing code to the scene, see `Drawable`.
+  pub fn add_drawable(&mut self, drawable: Box<dyn Drawable>) -> ObjectId {
+    self.add_object(SceneObject::Custom(drawable))
+  }
+
+  // Returns false when `id` was not in the scene.
+  pub fn remove_object(&mut self, id: ObjectId) -> bool {
+    let count = self.objects.len();
+    self.objects.retain(|(object_id, _)| *object_id != id);
+    self.objects.len() != count
+  }
+
+  // The layout of group 0 when a `Drawable` is drawn.
+  pub fn camera_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+    &self.camera_bind_group_layout
+  }
+
+  pub fn pass_info(&self) -> PassInfo {
+    PassInfo {
+      color_format: HDR_FORMAT,
+      depth_format: scene::DEPTH_FORMAT,
+      sample_count: self.sample_count,
+    }
+  }
+
+  fn add_object(&mut self, object: SceneObject) -> ObjectId {
+    let id = ObjectId(self.next_object_id);
+    self.next_object_id += 1;
+    self.objects.push((id, object));
+    id
+  }
+
+  pub fn device(&self) -> &wgpu::Device {
+    &self.device
+  }
+
+  pub fn queue(&self) -> &wgpu::Queue {
+    &self.queue
+  }
+
+  pub fn ocean(&self) -> &Ocean {
+    &self.ocean
+  }
+
+  fn create_render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+  ) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Render Pipeline"),
+      layout: Some(layout),
+      vertex: wgpu::VertexState {
+        module: shader,
+        entry_point: "vs_main",
+        buffers: &[Vertex::desc()],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: shader,
+        entry_point: "fs_main",
+        targets: &[wgpu::ColorTargetState {
+          format,
+          blend: Some(wgpu::BlendState::REPLACE),
+          write_mask: wgpu::ColorWrites::ALL,
+        }],
+      }),
+      primitive: wgpu::PrimitiveState {
+        topology: wgpu::PrimitiveTopology::TriangleList,
+        strip_index_format: None,
+        front_face: wgpu::FrontFace::default(),
+        cull_mode: Some(wgpu::Face::Front),
+        // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
+        polygon_mode: wgpu::PolygonMode::Fill,
+        // Requires Features::DEPTH_CLIP_CONTROL
+        unclipped_depth: false,
+        // Requires Features::CONSERVATIVE_RASTERIZATION
+        conservative: false,
+      },
+      depth_stencil: Some(scene::depth_stencil_state(scene::DEPTH_FORMAT)),
+      multisample: wgpu::MultisampleState {
+        count: sample_count,
+        mask: !0,
+        alpha_to_coverage_enabled: false,
+      },
+      multiview: None,
+    })
+  }
+
+  fn present_mode(vsync: bool) -> wgpu::PresentMode {
+    if vsync {
+      wgpu::PresentMode::Fifo
+    } else {
+      wgpu::PresentMode::Immediate
+    }
+  }
+
+  fn write_shading_uniform(&self) {
+    let uniform = ShadingUniform::new(
+      self.shading,
+      self.water_material,
+      self.ocean.parameters().wind_speed,
+      self.reflection_mode,
+    );
+    self
+      .queue
+      .write_buffer(&self.shading_buffer, 0, bytemuck::cast_slice(&[uniform]));
+  }
+
+  fn write_camera_uniform(&mut self) {
     self
       .camera_uniform
       .update_view_proj(&self.camera, &self.projection);
@@ -562,13 +1397,64 @@ impl Renderer {
       0,
       bytemuck::cast_slice(&[self.camera_uniform]),
     );
+
+    let mut reflection_camera_uniform = CameraUniform::new();
+    reflection_camera_uniform.update_mirrored_view_proj(&self.camera, &self.projection);
+    self.queue.write_buffer(
+      &self.reflection_camera_buffer,
+      0,
+      bytemuck::cast_slice(&[reflection_camera_uniform]),
+    );
   }
 
-  pub fn render(&mut self, time: f32, dt: std::time::Duration) -> Result<(), wgpu::SurfaceError> {
-    let output = self.surface.get_current_texture()?;
-    let view = output.texture.create_view(&wgpu::TextureViewDescriptor {
-      ..Default::default()
-    });
+  pub fn render(&mut self, time: f32) -> Result<(), wgpu::SurfaceError> {
+    let (output, view) = match &self.target {
+      RenderTarget::Surface { surface, .. } => {
+        let output = surface.get_current_texture()?;
+        let view = output.texture.create_view(&wgpu::TextureViewDescriptor {
+          ..Default::default()
+        });
+        (Some(output), view)
+      }
+      RenderTarget::Texture(texture) => (
+        None,
+        texture.create_view(&wgpu::TextureViewDescriptor::default()),
+      ),
+    };
+
+    self.draw(&view, time);
+
+    if let Some(output) = output {
+      output.present();
+    }
+
+    Ok(())
+  }
+
+  // Renders a frame of a headless renderer and returns it as tightly packed
+  // RGBA8 rows, top row first.
+  pub fn render_frame(&mut self, time: f32) -> Result<Vec<u8>, RendererError> {
+    let view = match &self.target {
+      RenderTarget::Texture(texture) => {
+        texture.create_view(&wgpu::TextureViewDescriptor::default())
+      }
+      RenderTarget::Surface { .. } => return Err(RendererError::NotHeadless),
+    };
+
+    self.draw(&view, time);
+
+    match &self.target {
+      RenderTarget::Texture(texture) => self.read_texture(texture),
+      RenderTarget::Surface { .. } => Err(RendererError::NotHeadless),
+    }
+  }
+
+  fn draw(&mut self, view: &wgpu::TextureView, time: f32) {
+    let debug_ui_visible = matches!(&self.debug_ui, Some(ui) if ui.visible);
+    // Frames are only timed while someone looks at the numbers.
+    let gpu_timer = self.gpu_timer.as_ref().filter(|_| debug_ui_visible);
+    let debug_settings = self.debug_settings();
+    let mut edited_settings = debug_settings;
 
     let mut encoder = self
       .device
@@ -576,61 +1462,443 @@ impl Renderer {
         label: Some("Render Encoder"),
       });
 
-    if !self.ocean_initialized {
-      self.ocean_surface.init(&mut encoder, &self.queue);
-      self.ocean_initialized = true;
+    if let Some(timer) = gpu_timer {
+      timer.write_timestamp(&mut encoder, 0);
     }
 
-    {
-      self
-        .ocean_surface
-        .dispatch(&mut encoder, &self.queue, time, dt);
+    self.ocean.update(&mut encoder, time);
+
+    if let Some(timer) = gpu_timer {
+      timer.write_timestamp(&mut encoder, 1);
+    }
+
+    if self.reflection_mode != ReflectionMode::None {
+      self.draw_reflections(&mut encoder);
     }
 
     {
       let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
         label: Some("Render Pass"),
         color_attachments: &[wgpu::RenderPassColorAttachment {
-          view: &self.multisampled_framebuffer,
-          resolve_target: Some(&view),
+          view: self
+            .multisampled_framebuffer
+            .as_ref()
+            .unwrap_or(&self.tonemap.hdr_view),
+          resolve_target: self
+            .multisampled_framebuffer
+            .as_ref()
+            .map(|_| &self.tonemap.hdr_view),
           ops: wgpu::Operations {
-            load: wgpu::LoadOp::Clear(wgpu::Color {
-              r: 1.0,
-              g: 1.0,
-              b: 1.0,
-              a: 1.0,
-            }),
+            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
             store: true,
           },
         }],
-        depth_stencil_attachment: None,
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+          view: &self.depth_view,
+          depth_ops: Some(wgpu::Operations {
+            load: wgpu::LoadOp::Clear(1.0),
+            store: true,
+          }),
+          stencil_ops: None,
+        }),
       });
 
-      render_pass.set_pipeline(&self.render_pipeline);
-
       render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
-      render_pass.set_bind_group(1, &self.heightmap_bind_group, &[]);
+      self.sky_pipeline.draw(&mut render_pass);
+
+      render_pass.set_pipeline(&self.render_pipeline);
+      render_pass.set_bind_group(1, &self.ocean.bindings().bind_group, &[]);
+      render_pass.set_bind_group(2, &self.surface_bind_group, &[]);
+      render_pass.set_bind_group(3, &self.environment.bind_group, &[]);
       render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
       render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
 
       render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+
+      for (_, object) in &self.objects {
+        // Drawables may have bound their own groups or pipelines.
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        match object {
+          SceneObject::Mesh(instance) => self.mesh_pipeline.draw(&mut render_pass, instance, false),
+          SceneObject::Custom(drawable) => drawable.draw(&mut render_pass),
+        }
+      }
+    }
+
+    if let Some(timer) = gpu_timer {
+      timer.write_timestamp(&mut encoder, 2);
+    }
+
+    if self.lighting.fog.density > 0.0 {
+      self.fog.draw(
+        &mut encoder,
+        &self.tonemap.hdr_view,
+        &self.camera_bind_group,
+        &self.environment.bind_group,
+      );
+    }
+    if self.underwater.settings().enabled && self.may_be_submerged() {
+      self.underwater.draw(
+        &mut encoder,
+        &self.tonemap.hdr_view,
+        &self.camera_bind_group,
+        &self.ocean.bindings().bind_group,
+        &self.environment.bind_group,
+      );
+    }
+    self.bloom.draw(&mut encoder, &self.tonemap.hdr_view);
+    self.tonemap.draw(&mut encoder, &self.queue, view, time);
+    self.debug_view.draw(&mut encoder, view, self.size);
+
+    if let Some(debug_ui) = self.debug_ui.as_mut().filter(|ui| ui.visible) {
+      let info = DebugInfo {
+        backend: self.ocean.backend(),
+        camera_position: self.camera.position.into(),
+      };
+      if let Err(e) = debug_ui.draw(
+        &self.device,
+        &self.queue,
+        &mut encoder,
+        view,
+        &mut edited_settings,
+        &info,
+      ) {
+        log::error!("Failed to draw the debug UI: {}", e);
+      }
+    }
+
+    let gpu_timer = self.gpu_timer.as_mut().filter(|_| debug_ui_visible);
+    if let Some(timer) = gpu_timer {
+      timer.resolve(&mut encoder);
     }
 
     // submit will accept anything that implements IntoIter
     self.queue.submit(std::iter::once(encoder.finish()));
-    output.present();
 
-    Ok(())
+    // The times shown lag a frame or two behind, the last ones stay until the
+    // GPU is done with newer ones.
+    if let (Some(timer), Some(debug_ui)) = (self.gpu_timer.as_mut(), &mut self.debug_ui) {
+      if let Some(gpu_times) = timer.read(&self.device).filter(|_| debug_ui_visible) {
+        debug_ui.record_gpu_times(Some(gpu_times));
+      }
+    }
+
+    if edited_settings != debug_settings {
+      self.apply_debug_settings(&debug_settings, &edited_settings);
+    }
+  }
+
+  // Draws the objects into the reflection targets, before the ocean samples
+  // them. The depth buffer is cleared again by the main pass.
+  fn draw_reflections(&self, encoder: &mut wgpu::CommandEncoder) {
+    let planar = self.reflection_mode == ReflectionMode::Planar;
+    let camera_bind_group = if planar {
+      &self.reflection_camera_bind_group
+    } else {
+      &self.camera_bind_group
+    };
+
+    {
+      let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Reflection Pass"),
+        color_attachments: &[wgpu::RenderPassColorAttachment {
+          view: self
+            .multisampled_framebuffer
+            .as_ref()
+            .unwrap_or(&self.reflections.color_view),
+          resolve_target: self
+            .multisampled_framebuffer
+            .as_ref()
+            .map(|_| &self.reflections.color_view),
+          ops: wgpu::Operations {
+            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+            store: true,
+          },
+        }],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+          view: &self.depth_view,
+          depth_ops: Some(wgpu::Operations {
+            load: wgpu::LoadOp::Clear(1.0),
+            store: true,
+          }),
+          stencil_ops: None,
+        }),
+      });
+
+      // The mirrored camera only sees the reflection from above the water.
+      if !planar || self.camera.position.y > 0.0 {
+        for (_, object) in &self.objects {
+          render_pass.set_bind_group(0, camera_bind_group, &[]);
+          match object {
+            SceneObject::Mesh(instance) => {
+              self.mesh_pipeline.draw(&mut render_pass, instance, planar)
+            }
+            SceneObject::Custom(drawable) if planar => drawable.draw_reflection(&mut render_pass),
+            SceneObject::Custom(drawable) => drawable.draw(&mut render_pass),
+          }
+        }
+      }
+    }
+
+    if !planar {
+      self.reflections.resolve_depth(encoder);
+    }
+  }
+
+  fn debug_settings(&self) -> DebugSettings {
+    DebugSettings {
+      ocean: self.ocean.parameters(),
+      lighting: self.lighting,
+      shading: self.shading,
+      water_material: self.water_material,
+      fov: cgmath::Deg::from(self.projection.fovy).0,
+      camera_speed: self.camera_controller.speed,
+      camera_sensitivity: self.camera_controller.sensitivity,
+      vsync: match &self.target {
+        RenderTarget::Surface { config, .. } => config.present_mode == wgpu::PresentMode::Fifo,
+        RenderTarget::Texture(_) => false,
+      },
+      debug_view: self.debug_view.view(),
+      reflections: self.reflection_mode,
+      underwater: self.underwater.settings(),
+      bloom: self.bloom.settings(),
+      tonemapping: self.tonemap.settings(),
+    }
+  }
+
+  fn apply_debug_settings(&mut self, previous: &DebugSettings, settings: &DebugSettings) {
+    if previous.ocean != settings.ocean {
+      if let Err(e) = self.transition_ocean(settings.ocean, DEBUG_UI_OCEAN_TRANSITION) {
+        log::error!("Ignoring ocean parameters: {}", e);
+      }
+    }
+    if previous.lighting != settings.lighting {
+      self.set_lighting(settings.lighting);
+    }
+    if previous.shading != settings.shading {
+      self.set_shading(settings.shading);
+    }
+    if previous.water_material != settings.water_material {
+      self.set_water_material(settings.water_material);
+    }
+    if previous.fov != settings.fov {
+      self.projection.fovy = cgmath::Deg(settings.fov).into();
+      self.write_camera_uniform();
+    }
+    self.camera_controller.speed = settings.camera_speed;
+    self.camera_controller.sensitivity = settings.camera_sensitivity;
+    if previous.vsync != settings.vsync {
+      self.set_vsync(settings.vsync);
+    }
+    if previous.reflections != settings.reflections {
+      self.set_reflection_mode(settings.reflections);
+    }
+    if previous.underwater != settings.underwater {
+      self.set_underwater(settings.underwater);
+    }
+    if previous.bloom != settings.bloom {
+      self.set_bloom(settings.bloom);
+    }
+    self.tonemap.set_settings(settings.tonemapping);
+    if previous.debug_view != settings.debug_view {
+      if let Err(e) = self.set_debug_view(settings.debug_view) {
+        log::error!("{}", e);
+      }
+    }
+  }
+
+  fn read_texture(&self, texture: &wgpu::Texture) -> Result<Vec<u8>, RendererError> {
+    let (width, height) = (self.size.width, self.size.height);
+    let unpadded_bytes_per_row = 4 * width;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let padding = (align - unpadded_bytes_per_row % align) % align;
+    let padded_bytes_per_row = unpadded_bytes_per_row + padding;
+
+    let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Frame readback buffer"),
+      size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+      usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+      mapped_at_creation: false,
+    });
+
+    let mut encoder = self
+      .device
+      .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Readback Encoder"),
+      });
+
+    encoder.copy_texture_to_buffer(
+      wgpu::ImageCopyTexture {
+        texture,
+        mip_level: 0,
+        origin: wgpu::Origin3d::ZERO,
+        aspect: wgpu::TextureAspect::All,
+      },
+      wgpu::ImageCopyBuffer {
+        buffer: &buffer,
+        layout: wgpu::ImageDataLayout {
+          offset: 0,
+          bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+          rows_per_image: std::num::NonZeroU32::new(height),
+        },
+      },
+      wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+      },
+    );
+
+    self.queue.submit(std::iter::once(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    let mapping = slice.map_async(wgpu::MapMode::Read);
+    self.device.poll(wgpu::Maintain::Wait);
+    pollster::block_on(mapping).map_err(RendererError::Readback)?;
+
+    let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+    {
+      let data = slice.get_mapped_range();
+      for row in data.chunks(padded_bytes_per_row as usize) {
+        pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+      }
+    }
+    buffer.unmap();
+
+    Ok(pixels)
+  }
+
+  fn write_surface_bind_group(&mut self) {
+    self.surface_bind_group = Self::create_surface_bind_group(
+      &self.device,
+      &self.surface_bind_group_layout,
+      [&self.foam_view, &self.foam_bubbles_view],
+      &self.reflections,
+    );
+  }
+
+  fn create_surface_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    [foam_view, foam_bubbles_view]: [&wgpu::TextureView; 2],
+    reflections: &Reflections,
+  ) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+      layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: wgpu::BindingResource::TextureView(foam_view),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: wgpu::BindingResource::TextureView(&reflections.color_view),
+        },
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: wgpu::BindingResource::TextureView(&reflections.depth_view),
+        },
+        wgpu::BindGroupEntry {
+          binding: 3,
+          resource: wgpu::BindingResource::TextureView(foam_bubbles_view),
+        },
+      ],
+      label: Some("Surface bind group"),
+    })
+  }
+
+  // Uploads `image` with its whole mip chain, foam and lens dirt being seen
+  // smaller than they are more often than not.
+  fn create_image_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    image: &image::DynamicImage,
+    label: &str,
+    format: wgpu::TextureFormat,
+  ) -> wgpu::TextureView {
+    let (width, height) = image.dimensions();
+    let mip_level_count = 32 - width.max(height).leading_zeros();
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some(label),
+      size: wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+      },
+      mip_level_count,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format,
+      usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+    });
+
+    for level in 0..mip_level_count {
+      let (width, height) = ((width >> level).max(1), (height >> level).max(1));
+      let bytes = if level == 0 {
+        image.to_rgba8()
+      } else {
+        image
+          .resize_exact(width, height, image::imageops::FilterType::Triangle)
+          .to_rgba8()
+      };
+
+      queue.write_texture(
+        wgpu::ImageCopyTexture {
+          texture: &texture,
+          mip_level: level,
+          origin: wgpu::Origin3d::ZERO,
+          aspect: wgpu::TextureAspect::All,
+        },
+        &bytes,
+        wgpu::ImageDataLayout {
+          offset: 0,
+          bytes_per_row: std::num::NonZeroU32::new(4 * width),
+          rows_per_image: std::num::NonZeroU32::new(height),
+        },
+        wgpu::Extent3d {
+          width,
+          height,
+          depth_or_array_layers: 1,
+        },
+      );
+    }
+
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+  }
+
+  fn create_offscreen_texture(
+    device: &wgpu::Device,
+    size: winit::dpi::PhysicalSize<u32>,
+  ) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+      label: Some("Offscreen target"),
+      size: wgpu::Extent3d {
+        width: size.width,
+        height: size.height,
+        depth_or_array_layers: 1,
+      },
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: OFFSCREEN_FORMAT,
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+    })
   }
 
   fn create_multisampled_framebuffer(
     device: &wgpu::Device,
-    config: &wgpu::SurfaceConfiguration,
+    format: wgpu::TextureFormat,
+    size: winit::dpi::PhysicalSize<u32>,
     sample_count: u32,
-  ) -> wgpu::TextureView {
+  ) -> Option<wgpu::TextureView> {
+    if sample_count == 1 {
+      return None;
+    }
+
     let multisampled_texture_extent = wgpu::Extent3d {
-      width: config.width,
-      height: config.height,
+      width: size.width,
+      height: size.height,
       depth_or_array_layers: 1,
     };
     let multisampled_frame_descriptor = &wgpu::TextureDescriptor {
@@ -638,13 +1906,81 @@ impl Renderer {
       mip_level_count: 1,
       sample_count,
       dimension: wgpu::TextureDimension::D2,
-      format: config.format,
+      format,
       usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
       label: None,
     };
 
-    device
-      .create_texture(multisampled_frame_descriptor)
-      .create_view(&wgpu::TextureViewDescriptor::default())
+    Some(
+      device
+        .create_texture(multisampled_frame_descriptor)
+        .create_view(&wgpu::TextureViewDescriptor::default()),
+    )
   }
 }
+
+impl RenderTarget {
+  fn format(&self) -> wgpu::TextureFormat {
+    match self {
+      RenderTarget::Surface { config, .. } => config.format,
+      RenderTarget::Texture(_) => OFFSCREEN_FORMAT,
+    }
+  }
+}
+
+#[test]
+fn test_shading_uniform() {
+  // Matches the layout of `ShadingUniform` in the shader.
+  assert_eq!(std::mem::size_of::<ShadingUniform>(), 80);
+
+  let shading = Shading {
+    cascades: [true, false, true],
+    ..Default::default()
+  };
+  let material = WaterMaterial::default();
+  let uniform = ShadingUniform::new(shading, material, 10.0, ReflectionMode::ScreenSpace);
+  assert_eq!(uniform.cascade_weights, [1.0, 0.0, 1.0]);
+  assert_eq!(uniform.lod_scale, 15.0);
+  assert_eq!(uniform.ior, 1.33);
+  assert_eq!(uniform.reflection_mode, 2);
+  // Rougher with more wind.
+  let windier = ShadingUniform::new(shading, material, 20.0, ReflectionMode::ScreenSpace);
+  assert!(windier.slope_variance > uniform.slope_variance);
+}
+
+#[test]
+fn test_lighting_uniform() {
+  // Matches the layout of `LightingUniform` in the shaders.
+  assert_eq!(std::mem::size_of::<LightingUniform>(), 160);
+
+  let sun = SunPosition::default();
+  let uniform = LightingUniform::from(Lighting {
+    sun: Some(sun),
+    ..Default::default()
+  });
+  let [x, y, z] = sun.direction();
+  assert_eq!(uniform.sun_direction, [x, y, z, 0.0]);
+  // Sunlight loses more blue than red through the atmosphere.
+  assert!(uniform.sun_color[2] < uniform.sun_color[0]);
+}
+
+#[test]
+fn test_headless_gerstner() {
+  // Without storage bindings the environment is drawn with render passes and
+  // the exposure stays manual.
+  let config = RendererConfig {
+    ocean_backend: Some(OceanBackend::Gerstner { wave_count: 16 }),
+    sample_count: 1,
+    tonemapping: Tonemapping {
+      auto_exposure: true,
+      ..Default::default()
+    },
+    ..Default::default()
+  };
+  let mut renderer = match pollster::block_on(Renderer::new_headless(64, 32, false, &config)) {
+    // Nothing to render with here.
+    Err(RendererError::NoAdapter) => return,
+    renderer => renderer.unwrap(),
+  };
+  assert_eq!(renderer.render_frame(0.0).unwrap().len(), 64 * 32 * 4);
+}
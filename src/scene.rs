@@ -0,0 +1,378 @@
+use wgpu::util::DeviceExt;
+
+use crate::ocean::shaders::{self, Shader};
+use crate::ocean::OceanError;
+
+pub const MESH_SHADER: Shader =
+  Shader::new("src/mesh_shader.wgsl", include_str!("mesh_shader.wgsl"));
+
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+// Identifies something added to the scene with `Renderer::add_mesh` or
+// `Renderer::add_drawable`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ObjectId(pub(crate) u64);
+
+// What a pipeline drawing in the ocean's render pass has to target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PassInfo {
+  pub color_format: wgpu::TextureFormat,
+  pub depth_format: wgpu::TextureFormat,
+  pub sample_count: u32,
+}
+
+// Custom drawing code run in the ocean's render pass, after the water and
+// with its depth buffer. Group 0 is bound to the camera and lighting uniforms,
+// laid out as `Renderer::camera_bind_group_layout`, and pipelines have to
+// match `Renderer::pass_info`.
+pub trait Drawable {
+  fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>);
+
+  // Draws into the planar reflection, with group 0 bound to a camera mirrored
+  // around the water, which turns front faces clockwise. Drawables are left
+  // out of the reflection unless they implement it.
+  fn draw_reflection<'a>(&'a self, _render_pass: &mut wgpu::RenderPass<'a>) {}
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MeshVertex {
+  pub position: [f32; 3],
+  pub normal: [f32; 3],
+}
+
+impl MeshVertex {
+  pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+    wgpu::VertexBufferLayout {
+      array_stride: std::mem::size_of::<MeshVertex>() as wgpu::BufferAddress,
+      step_mode: wgpu::VertexStepMode::Vertex,
+      attributes: &[
+        wgpu::VertexAttribute {
+          offset: 0,
+          shader_location: 0,
+          format: wgpu::VertexFormat::Float32x3,
+        },
+        wgpu::VertexAttribute {
+          offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+          shader_location: 1,
+          format: wgpu::VertexFormat::Float32x3,
+        },
+      ],
+    }
+  }
+}
+
+// An indexed triangle list in model space.
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+  pub vertices: Vec<MeshVertex>,
+  pub indices: Vec<u32>,
+}
+
+impl Mesh {
+  // An axis-aligned box centered on the origin, with flat faces.
+  pub fn cuboid(half_extents: [f32; 3]) -> Mesh {
+    let [x, y, z] = half_extents;
+    let mut mesh = Mesh::default();
+
+    // Each face as its normal and two axes spanning it, counter-clockwise
+    // seen from outside.
+    let faces = [
+      ([1.0, 0.0, 0.0], [0.0, 0.0, -z], [0.0, y, 0.0]),
+      ([-1.0, 0.0, 0.0], [0.0, 0.0, z], [0.0, y, 0.0]),
+      ([0.0, 1.0, 0.0], [x, 0.0, 0.0], [0.0, 0.0, -z]),
+      ([0.0, -1.0, 0.0], [x, 0.0, 0.0], [0.0, 0.0, z]),
+      ([0.0, 0.0, 1.0], [x, 0.0, 0.0], [0.0, y, 0.0]),
+      ([0.0, 0.0, -1.0], [-x, 0.0, 0.0], [0.0, y, 0.0]),
+    ];
+
+    for (normal, u, v) in faces {
+      let center = [normal[0] * x, normal[1] * y, normal[2] * z];
+      let base = mesh.vertices.len() as u32;
+
+      for (su, sv) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+        let position = [0, 1, 2].map(|i| center[i] + su * u[i] + sv * v[i]);
+        mesh.vertices.push(MeshVertex { position, normal });
+      }
+      mesh
+        .indices
+        .extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+
+    mesh
+  }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct MeshUniform {
+  model: [[f32; 4]; 4],
+  color: [f32; 4],
+}
+
+impl MeshUniform {
+  fn new(transform: cgmath::Matrix4<f32>, [r, g, b]: [f32; 3]) -> MeshUniform {
+    MeshUniform {
+      model: transform.into(),
+      color: [r, g, b, 1.0],
+    }
+  }
+}
+
+// A `Mesh` uploaded to the GPU, drawn with `MeshPipeline`.
+pub(crate) struct MeshInstance {
+  vertex_buffer: wgpu::Buffer,
+  index_buffer: wgpu::Buffer,
+  num_indices: u32,
+  uniform_buffer: wgpu::Buffer,
+  bind_group: wgpu::BindGroup,
+  color: [f32; 3],
+}
+
+impl MeshInstance {
+  pub fn set_transform(&self, queue: &wgpu::Queue, transform: cgmath::Matrix4<f32>) {
+    queue.write_buffer(
+      &self.uniform_buffer,
+      0,
+      bytemuck::cast_slice(&[MeshUniform::new(transform, self.color)]),
+    );
+  }
+}
+
+pub(crate) enum SceneObject {
+  Mesh(Box<MeshInstance>),
+  Custom(Box<dyn Drawable>),
+}
+
+pub(crate) struct MeshPipeline {
+  bind_group_layout: wgpu::BindGroupLayout,
+  pipeline_layout: wgpu::PipelineLayout,
+  pipeline: wgpu::RenderPipeline,
+  // For the planar reflection's mirrored camera.
+  mirrored_pipeline: wgpu::RenderPipeline,
+  pass_info: PassInfo,
+}
+
+impl MeshPipeline {
+  pub fn new(
+    device: &wgpu::Device,
+    camera_bind_group_layout: &wgpu::BindGroupLayout,
+    pass_info: PassInfo,
+  ) -> MeshPipeline {
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("mesh_bind_group_layout"),
+      entries: &[wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+          ty: wgpu::BufferBindingType::Uniform,
+          has_dynamic_offset: false,
+          min_binding_size: None,
+        },
+        count: None,
+      }],
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Mesh Pipeline Layout"),
+      bind_group_layouts: &[camera_bind_group_layout, &bind_group_layout],
+      push_constant_ranges: &[],
+    });
+
+    let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+      label: Some("Mesh shader"),
+      source: wgpu::ShaderSource::Wgsl(MESH_SHADER.source()),
+    });
+    let pipeline = Self::create_pipeline(device, &pipeline_layout, &shader, pass_info, false);
+    let mirrored_pipeline =
+      Self::create_pipeline(device, &pipeline_layout, &shader, pass_info, true);
+
+    MeshPipeline {
+      bind_group_layout,
+      pipeline_layout,
+      pipeline,
+      mirrored_pipeline,
+      pass_info,
+    }
+  }
+
+  pub fn upload(
+    &self,
+    device: &wgpu::Device,
+    mesh: &Mesh,
+    transform: cgmath::Matrix4<f32>,
+    color: [f32; 3],
+  ) -> MeshInstance {
+    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Mesh Vertex Buffer"),
+      contents: bytemuck::cast_slice(&mesh.vertices),
+      usage: wgpu::BufferUsages::VERTEX,
+    });
+    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Mesh Index Buffer"),
+      contents: bytemuck::cast_slice(&mesh.indices),
+      usage: wgpu::BufferUsages::INDEX,
+    });
+    let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Mesh Uniform Buffer"),
+      contents: bytemuck::cast_slice(&[MeshUniform::new(transform, color)]),
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Mesh bind group"),
+      layout: &self.bind_group_layout,
+      entries: &[wgpu::BindGroupEntry {
+        binding: 0,
+        resource: uniform_buffer.as_entire_binding(),
+      }],
+    });
+
+    MeshInstance {
+      vertex_buffer,
+      index_buffer,
+      num_indices: mesh.indices.len() as u32,
+      uniform_buffer,
+      bind_group,
+      color,
+    }
+  }
+
+  pub async fn reload(
+    &mut self,
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+  ) -> Result<(), OceanError> {
+    let create = |mirrored| {
+      Self::create_pipeline(
+        device,
+        &self.pipeline_layout,
+        shader,
+        self.pass_info,
+        mirrored,
+      )
+    };
+    let pipeline = shaders::try_create(device, || create(false)).await?;
+    self.mirrored_pipeline = shaders::try_create(device, || create(true)).await?;
+    self.pipeline = pipeline;
+    Ok(())
+  }
+
+  // Expects group 0 to be bound already, to the mirrored camera when
+  // `mirrored` is set.
+  pub fn draw<'a>(
+    &'a self,
+    render_pass: &mut wgpu::RenderPass<'a>,
+    mesh: &'a MeshInstance,
+    mirrored: bool,
+  ) {
+    render_pass.set_pipeline(if mirrored {
+      &self.mirrored_pipeline
+    } else {
+      &self.pipeline
+    });
+    render_pass.set_bind_group(1, &mesh.bind_group, &[]);
+    render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+    render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+    render_pass.draw_indexed(0..mesh.num_indices, 0, 0..1);
+  }
+
+  fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    pass_info: PassInfo,
+    mirrored: bool,
+  ) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Mesh Pipeline"),
+      layout: Some(layout),
+      vertex: wgpu::VertexState {
+        module: shader,
+        entry_point: "vs_main",
+        buffers: &[MeshVertex::desc()],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: shader,
+        entry_point: "fs_main",
+        targets: &[wgpu::ColorTargetState {
+          format: pass_info.color_format,
+          blend: Some(wgpu::BlendState::REPLACE),
+          write_mask: wgpu::ColorWrites::ALL,
+        }],
+      }),
+      primitive: wgpu::PrimitiveState {
+        front_face: if mirrored {
+          wgpu::FrontFace::Cw
+        } else {
+          wgpu::FrontFace::Ccw
+        },
+        cull_mode: Some(wgpu::Face::Back),
+        ..Default::default()
+      },
+      depth_stencil: Some(depth_stencil_state(pass_info.depth_format)),
+      multisample: wgpu::MultisampleState {
+        count: pass_info.sample_count,
+        mask: !0,
+        alpha_to_coverage_enabled: false,
+      },
+      multiview: None,
+    })
+  }
+}
+
+// Opaque geometry: nearer fragments win and are written.
+pub fn depth_stencil_state(format: wgpu::TextureFormat) -> wgpu::DepthStencilState {
+  wgpu::DepthStencilState {
+    format,
+    depth_write_enabled: true,
+    depth_compare: wgpu::CompareFunction::Less,
+    stencil: wgpu::StencilState::default(),
+    bias: wgpu::DepthBiasState::default(),
+  }
+}
+
+// Has to match the color target's size and sample count. Screen-space
+// reflections read it back.
+pub fn create_depth_texture(
+  device: &wgpu::Device,
+  size: winit::dpi::PhysicalSize<u32>,
+  sample_count: u32,
+) -> wgpu::TextureView {
+  device
+    .create_texture(&wgpu::TextureDescriptor {
+      label: Some("Depth texture"),
+      size: wgpu::Extent3d {
+        width: size.width,
+        height: size.height,
+        depth_or_array_layers: 1,
+      },
+      mip_level_count: 1,
+      sample_count,
+      dimension: wgpu::TextureDimension::D2,
+      format: DEPTH_FORMAT,
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    })
+    .create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+#[test]
+fn test_cuboid() {
+  use cgmath::InnerSpace;
+
+  let mesh = Mesh::cuboid([1.0, 2.0, 3.0]);
+  assert_eq!(mesh.vertices.len(), 24);
+  assert_eq!(mesh.indices.len(), 36);
+
+  for triangle in mesh.indices.chunks(3) {
+    let [a, b, c] =
+      [0, 1, 2].map(|i| cgmath::Vector3::from(mesh.vertices[triangle[i] as usize].position));
+    let normal = cgmath::Vector3::from(mesh.vertices[triangle[0] as usize].normal);
+    // Wound counter-clockwise seen from outside, as back face culling expects.
+    assert!((b - a).cross(c - a).normalize().dot(normal) > 0.99);
+    // Every vertex lies on the face its normal points out of.
+    let half_extent = normal.map(f32::abs).dot(cgmath::vec3(1.0, 2.0, 3.0));
+    for vertex in [a, b, c] {
+      assert_eq!(vertex.dot(normal), half_extent);
+    }
+  }
+}
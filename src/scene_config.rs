@@ -0,0 +1,672 @@
+use std::path::{Path, PathBuf};
+
+use crate::bloom::MAX_BLOOM_LEVELS;
+use crate::ocean::{OceanBackend, OceanCascadeParameters};
+use crate::reflections::ReflectionMode;
+use crate::renderer::RendererConfig;
+use crate::sky::SunPosition;
+use crate::tonemap::Tonemapper;
+
+// Everything the demo needs at startup, read from a TOML scene file:
+//
+//   [window]
+//   width = 1280
+//   height = 800
+//
+//   [ocean]
+//   preset = "moderate"       # applied first, the keys below override it
+//   backend = "auto"          # "auto", "fft" or "gerstner"
+//   gerstner_waves = 64
+//   size = 256
+//   wind_speed = 10.0
+//   wind_direction = 180.0
+//   swell = 0.3
+//   seed = 42
+//
+//   [camera]
+//   position = [0.0, 40.0, 0.0]
+//   yaw = 160.0
+//   pitch = -20.0
+//   fov = 45.0
+//   near = 1.0
+//   far = 5000.0
+//   speed = 20.0
+//   sensitivity = 1.0
+//
+//   [lighting]
+//   sun_direction = [-1.0, 1.0, 1.0]
+//   sun_color = [1.0, 1.0, 1.0]
+//   sky_color = [0.9, 0.9, 0.9]
+//   turbidity = 2.5
+//   # instead of sun_direction, place the sun for a time and place on Earth
+//   latitude = 45.0
+//   day_of_year = 172
+//   time_of_day = 16.0
+//
+//   [fog]
+//   density = 0.00015         # extinction per meter at sea level, 0 for none
+//   height_falloff = 0.002    # per meter of altitude
+//   anisotropy = 0.7          # how tightly the haze glows around the sun
+//
+//   [water]
+//   ior = 1.33
+//   scatter_color = [0.0, 0.08, 0.13]
+//   absorption = [0.45, 0.06, 0.04]  # per meter
+//   subsurface_strength = 2.0
+//   foam_albedo = [0.9, 0.9, 0.9]
+//   foam_roughness = 0.7
+//   foam_coverage = 1.0
+//
+//   [underwater]
+//   enabled = true
+//   scattering = 0.04         # per meter, on top of the water's absorption
+//   anisotropy = 0.8          # how tightly the water glows around the sun
+//   waterline = 0.6           # darkness of the line where the water crosses the lens
+//
+//   [rendering]
+//   sample_count = 4
+//   vsync = true
+//   reflections = "none"      # of added objects: "none", "planar" or "screen-space"
+//
+//   [tonemapping]
+//   tonemapper = "aces"       # "aces" or "agx"
+//   auto_exposure = false
+//   exposure = 0.0            # stops, corrects the metered exposure when automatic
+//   min_log_luminance = -10.0 # log2 of the luminances automatic exposure meters
+//   max_log_luminance = 6.0
+//   adaptation_rate = 1.5     # per second
+//
+//   [bloom]
+//   enabled = true
+//   threshold = 2.0           # linear brightness from which pixels bloom
+//   knee = 1.0                # below the threshold, over which bloom fades in
+//   intensity = 0.1
+//   levels = 6                # how far it spreads, up to 8
+//   lens_dirt = 0.0           # brightens bloom where the lens is dirty
+//
+// Every key is optional. Unknown sections and keys are rejected so typos do
+// not go unnoticed. With the `serde` feature, files ending in .ron hold the
+// same sections as a RON map, `(window: (width: 1280), ocean: (seed: 42))`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct SceneConfig {
+  pub width: u32,
+  pub height: u32,
+  pub renderer: RendererConfig,
+}
+
+impl Default for SceneConfig {
+  fn default() -> SceneConfig {
+    SceneConfig {
+      width: 1280,
+      height: 800,
+      renderer: RendererConfig::default(),
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+  Io(PathBuf, std::io::Error),
+  Parse(toml::de::Error),
+  #[cfg(feature = "serde")]
+  ParseRon(ron::error::SpannedError),
+  Invalid(String),
+}
+
+impl std::fmt::Display for ConfigError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ConfigError::Io(path, error) => write!(f, "failed to read {}: {}", path.display(), error),
+      ConfigError::Parse(error) => write!(f, "invalid scene file: {}", error),
+      #[cfg(feature = "serde")]
+      ConfigError::ParseRon(error) => write!(f, "invalid scene file: {}", error),
+      ConfigError::Invalid(description) => write!(f, "invalid scene file: {}", description),
+    }
+  }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<toml::de::Error> for ConfigError {
+  fn from(error: toml::de::Error) -> Self {
+    ConfigError::Parse(error)
+  }
+}
+
+#[cfg(feature = "serde")]
+impl From<ron::error::SpannedError> for ConfigError {
+  fn from(error: ron::error::SpannedError) -> Self {
+    ConfigError::ParseRon(error)
+  }
+}
+
+impl SceneConfig {
+  pub fn load(path: &Path) -> Result<SceneConfig, ConfigError> {
+    let source =
+      std::fs::read_to_string(path).map_err(|error| ConfigError::Io(path.to_path_buf(), error))?;
+    Self::from_source(path, &source)
+  }
+
+  // Parses `source` as RON or TOML depending on the extension of `path`.
+  pub fn from_source(path: &Path, source: &str) -> Result<SceneConfig, ConfigError> {
+    #[cfg(feature = "serde")]
+    if path.extension() == Some(std::ffi::OsStr::new("ron")) {
+      return Self::from_ron(source);
+    }
+    #[cfg(not(feature = "serde"))]
+    let _ = path;
+    Self::from_toml(source)
+  }
+
+  pub fn from_toml(source: &str) -> Result<SceneConfig, ConfigError> {
+    match source.parse::<toml::Value>()? {
+      toml::Value::Table(table) => Self::from_table(table),
+      _ => Err(ConfigError::Invalid(String::from("expected a table"))),
+    }
+  }
+
+  // The RON map is read as the TOML table it stands for, so both formats
+  // accept the same keys and values.
+  #[cfg(feature = "serde")]
+  pub fn from_ron(source: &str) -> Result<SceneConfig, ConfigError> {
+    match ron_to_toml(ron::from_str(source)?)? {
+      toml::Value::Table(table) => Self::from_table(table),
+      _ => Err(ConfigError::Invalid(String::from("expected a map"))),
+    }
+  }
+
+  fn from_table(mut root: toml::value::Table) -> Result<SceneConfig, ConfigError> {
+    let mut config = SceneConfig::default();
+    let renderer = &mut config.renderer;
+
+    let mut window = Section::take(&mut root, "window")?;
+    window.uint("width", &mut config.width)?;
+    window.uint("height", &mut config.height)?;
+    window.finish()?;
+    if config.width == 0 || config.height == 0 {
+      return Err(ConfigError::Invalid(String::from(
+        "window size must be at least 1x1",
+      )));
+    }
+
+    let mut ocean = Section::take(&mut root, "ocean")?;
+    if let Some(preset) = ocean.string("preset")? {
+      renderer.ocean = parse_preset(&preset).map_err(ConfigError::Invalid)?;
+    }
+    let mut wave_count = None;
+    ocean.uint_option("gerstner_waves", &mut wave_count)?;
+    if let Some(backend) = ocean.string("backend")? {
+      renderer.ocean_backend = parse_backend(&backend).map_err(ConfigError::Invalid)?;
+    }
+    match (&mut renderer.ocean_backend, wave_count) {
+      (Some(OceanBackend::Gerstner { wave_count }), Some(count)) => *wave_count = count,
+      (_, Some(_)) => {
+        return Err(ConfigError::Invalid(String::from(
+          "ocean.gerstner_waves requires backend = \"gerstner\"",
+        )))
+      }
+      _ => {}
+    }
+    if let Some(backend) = renderer.ocean_backend {
+      backend
+        .validate()
+        .map_err(|e| ConfigError::Invalid(format!("ocean.gerstner_waves: {}", e)))?;
+    }
+    ocean.uint("size", &mut renderer.ocean.size)?;
+    ocean.float("wind_speed", &mut renderer.ocean.wind_speed)?;
+    ocean.float("wind_direction", &mut renderer.ocean.wind_direction)?;
+    ocean.float("swell", &mut renderer.ocean.swell)?;
+    if let Some(seed) = ocean.integer("seed")? {
+      let seed = u64::try_from(seed)
+        .map_err(|_| ConfigError::Invalid(String::from("ocean.seed must not be negative")))?;
+      renderer.seed = Some(seed);
+    }
+    ocean.finish()?;
+    renderer
+      .ocean
+      .validate()
+      .map_err(|e| ConfigError::Invalid(format!("ocean: {}", e)))?;
+
+    let mut camera = Section::take(&mut root, "camera")?;
+    let camera_config = &mut renderer.camera;
+    camera.vec3("position", &mut camera_config.position)?;
+    camera.float("yaw", &mut camera_config.yaw)?;
+    camera.float("pitch", &mut camera_config.pitch)?;
+    camera.float("fov", &mut camera_config.fov)?;
+    camera.float("near", &mut camera_config.near)?;
+    camera.float("far", &mut camera_config.far)?;
+    camera.float("speed", &mut camera_config.speed)?;
+    camera.float("sensitivity", &mut camera_config.sensitivity)?;
+    camera.finish()?;
+
+    let mut lighting = Section::take(&mut root, "lighting")?;
+    const SUN_POSITION_KEYS: [&str; 3] = ["latitude", "day_of_year", "time_of_day"];
+    let sun_placed = SUN_POSITION_KEYS
+      .iter()
+      .any(|key| lighting.table.contains_key(*key));
+    if sun_placed && lighting.table.contains_key("sun_direction") {
+      return Err(ConfigError::Invalid(String::from(
+        "lighting.sun_direction cannot be combined with latitude, day_of_year and time_of_day",
+      )));
+    }
+    lighting.vec3("sun_direction", &mut renderer.lighting.sun_direction)?;
+    lighting.vec3("sun_color", &mut renderer.lighting.sun_color)?;
+    lighting.vec3("sky_color", &mut renderer.lighting.sky_color)?;
+    lighting.float("turbidity", &mut renderer.lighting.turbidity)?;
+    if sun_placed {
+      let mut sun = SunPosition::default();
+      lighting.float("latitude", &mut sun.latitude)?;
+      lighting.uint("day_of_year", &mut sun.day_of_year)?;
+      lighting.float("time_of_day", &mut sun.time_of_day)?;
+      renderer.lighting.sun = Some(sun);
+    }
+    lighting.finish()?;
+
+    let mut fog = Section::take(&mut root, "fog")?;
+    let settings = &mut renderer.lighting.fog;
+    fog.float("density", &mut settings.density)?;
+    fog.float("height_falloff", &mut settings.height_falloff)?;
+    fog.float("anisotropy", &mut settings.anisotropy)?;
+    fog.finish()?;
+    if settings.density < 0.0 || settings.height_falloff < 0.0 {
+      return Err(ConfigError::Invalid(String::from(
+        "fog.density and fog.height_falloff must not be negative",
+      )));
+    }
+
+    let mut water = Section::take(&mut root, "water")?;
+    let material = &mut renderer.water_material;
+    water.float("ior", &mut material.ior)?;
+    water.vec3("scatter_color", &mut material.scatter_color)?;
+    water.vec3("absorption", &mut material.absorption)?;
+    water.float("subsurface_strength", &mut material.subsurface_strength)?;
+    water.vec3("foam_albedo", &mut material.foam_albedo)?;
+    water.float("foam_roughness", &mut material.foam_roughness)?;
+    water.float("foam_coverage", &mut material.foam_coverage)?;
+    water.finish()?;
+    if material.ior < 1.0 {
+      return Err(ConfigError::Invalid(String::from(
+        "water.ior must be at least 1",
+      )));
+    }
+
+    let mut underwater = Section::take(&mut root, "underwater")?;
+    let settings = &mut renderer.underwater;
+    underwater.boolean("enabled", &mut settings.enabled)?;
+    underwater.float("scattering", &mut settings.scattering)?;
+    underwater.float("anisotropy", &mut settings.anisotropy)?;
+    underwater.float("waterline", &mut settings.waterline)?;
+    underwater.finish()?;
+    if settings.scattering < 0.0 {
+      return Err(ConfigError::Invalid(String::from(
+        "underwater.scattering must not be negative",
+      )));
+    }
+
+    let mut rendering = Section::take(&mut root, "rendering")?;
+    rendering.uint("sample_count", &mut renderer.sample_count)?;
+    rendering.boolean("vsync", &mut renderer.vsync)?;
+    if let Some(reflections) = rendering.string("reflections")? {
+      renderer.reflections = parse_reflections(&reflections).map_err(ConfigError::Invalid)?;
+    }
+    rendering.finish()?;
+
+    let mut tonemapping = Section::take(&mut root, "tonemapping")?;
+    let settings = &mut renderer.tonemapping;
+    if let Some(tonemapper) = tonemapping.string("tonemapper")? {
+      settings.tonemapper = parse_tonemapper(&tonemapper).map_err(ConfigError::Invalid)?;
+    }
+    tonemapping.boolean("auto_exposure", &mut settings.auto_exposure)?;
+    tonemapping.float("exposure", &mut settings.exposure)?;
+    tonemapping.float("min_log_luminance", &mut settings.min_log_luminance)?;
+    tonemapping.float("max_log_luminance", &mut settings.max_log_luminance)?;
+    tonemapping.float("adaptation_rate", &mut settings.adaptation_rate)?;
+    tonemapping.finish()?;
+    if settings.min_log_luminance >= settings.max_log_luminance {
+      return Err(ConfigError::Invalid(String::from(
+        "tonemapping.min_log_luminance must be below max_log_luminance",
+      )));
+    }
+
+    let mut bloom = Section::take(&mut root, "bloom")?;
+    let settings = &mut renderer.bloom;
+    bloom.boolean("enabled", &mut settings.enabled)?;
+    bloom.float("threshold", &mut settings.threshold)?;
+    bloom.float("knee", &mut settings.knee)?;
+    bloom.float("intensity", &mut settings.intensity)?;
+    bloom.uint("levels", &mut settings.levels)?;
+    bloom.float("lens_dirt", &mut settings.lens_dirt)?;
+    bloom.finish()?;
+    if !(1..=MAX_BLOOM_LEVELS).contains(&settings.levels) {
+      return Err(ConfigError::Invalid(format!(
+        "bloom.levels must be between 1 and {}",
+        MAX_BLOOM_LEVELS
+      )));
+    }
+
+    if let Some(section) = root.keys().next() {
+      return Err(ConfigError::Invalid(format!(
+        "unknown section [{}]",
+        section
+      )));
+    }
+
+    Ok(config)
+  }
+}
+
+pub fn parse_preset(name: &str) -> Result<OceanCascadeParameters, String> {
+  OceanCascadeParameters::preset(name).ok_or_else(|| {
+    format!(
+      "unknown preset {}, expected one of {}",
+      name,
+      OceanCascadeParameters::PRESETS.join(", ")
+    )
+  })
+}
+
+// `None` stands for "auto".
+pub fn parse_backend(name: &str) -> Result<Option<OceanBackend>, String> {
+  match name {
+    "auto" => Ok(None),
+    "fft" => Ok(Some(OceanBackend::Fft)),
+    "gerstner" => Ok(Some(OceanBackend::gerstner())),
+    _ => Err(format!(
+      "unknown backend {}, expected auto, fft or gerstner",
+      name
+    )),
+  }
+}
+
+pub fn parse_reflections(name: &str) -> Result<ReflectionMode, String> {
+  ReflectionMode::from_name(name).ok_or_else(|| {
+    format!(
+      "unknown reflections {}, expected none, planar or screen-space",
+      name
+    )
+  })
+}
+
+pub fn parse_tonemapper(name: &str) -> Result<Tonemapper, String> {
+  Tonemapper::from_name(name)
+    .ok_or_else(|| format!("unknown tonemapper {}, expected aces or agx", name))
+}
+
+#[cfg(feature = "serde")]
+fn ron_to_toml(value: ron::Value) -> Result<toml::Value, ConfigError> {
+  Ok(match value {
+    ron::Value::Bool(value) => toml::Value::Boolean(value),
+    ron::Value::Char(value) => toml::Value::String(value.to_string()),
+    ron::Value::String(value) => toml::Value::String(value),
+    ron::Value::Number(ron::Number::Integer(value)) => toml::Value::Integer(value),
+    ron::Value::Number(ron::Number::Float(value)) => toml::Value::Float(value.get()),
+    ron::Value::Option(Some(value)) => ron_to_toml(*value)?,
+    ron::Value::Seq(values) => toml::Value::Array(
+      values
+        .into_iter()
+        .map(ron_to_toml)
+        .collect::<Result<_, _>>()?,
+    ),
+    ron::Value::Map(map) => {
+      let mut table = toml::value::Table::new();
+      for (key, value) in map {
+        match key {
+          ron::Value::String(key) => table.insert(key, ron_to_toml(value)?),
+          _ => return Err(ConfigError::Invalid(String::from("keys must be names"))),
+        };
+      }
+      toml::Value::Table(table)
+    }
+    // What `()` parses to, an empty struct.
+    ron::Value::Unit => toml::Value::Table(toml::value::Table::new()),
+    ron::Value::Option(None) => {
+      return Err(ConfigError::Invalid(String::from("values cannot be None")))
+    }
+  })
+}
+
+// A table of the scene file whose keys are removed as they are read, so the
+// ones left over can be reported.
+struct Section {
+  name: &'static str,
+  table: toml::value::Table,
+}
+
+impl Section {
+  fn take(root: &mut toml::value::Table, name: &'static str) -> Result<Section, ConfigError> {
+    let table = match root.remove(name) {
+      None => toml::value::Table::new(),
+      Some(toml::Value::Table(table)) => table,
+      Some(_) => return Err(ConfigError::Invalid(format!("{} must be a table", name))),
+    };
+    Ok(Section { name, table })
+  }
+
+  fn invalid(&self, key: &str, expected: &str) -> ConfigError {
+    ConfigError::Invalid(format!("{}.{} must be {}", self.name, key, expected))
+  }
+
+  fn float(&mut self, key: &str, target: &mut f32) -> Result<(), ConfigError> {
+    match self.table.remove(key) {
+      None => {}
+      Some(toml::Value::Float(value)) => *target = value as f32,
+      Some(toml::Value::Integer(value)) => *target = value as f32,
+      Some(_) => return Err(self.invalid(key, "a number")),
+    }
+    Ok(())
+  }
+
+  fn integer(&mut self, key: &str) -> Result<Option<i64>, ConfigError> {
+    match self.table.remove(key) {
+      None => Ok(None),
+      Some(toml::Value::Integer(value)) => Ok(Some(value)),
+      Some(_) => Err(self.invalid(key, "an integer")),
+    }
+  }
+
+  fn uint_option(&mut self, key: &str, target: &mut Option<u32>) -> Result<(), ConfigError> {
+    if let Some(value) = self.integer(key)? {
+      let value = u32::try_from(value).map_err(|_| self.invalid(key, "a positive integer"))?;
+      *target = Some(value);
+    }
+    Ok(())
+  }
+
+  fn uint(&mut self, key: &str, target: &mut u32) -> Result<(), ConfigError> {
+    let mut value = None;
+    self.uint_option(key, &mut value)?;
+    *target = value.unwrap_or(*target);
+    Ok(())
+  }
+
+  fn boolean(&mut self, key: &str, target: &mut bool) -> Result<(), ConfigError> {
+    match self.table.remove(key) {
+      None => {}
+      Some(toml::Value::Boolean(value)) => *target = value,
+      Some(_) => return Err(self.invalid(key, "true or false")),
+    }
+    Ok(())
+  }
+
+  fn string(&mut self, key: &str) -> Result<Option<String>, ConfigError> {
+    match self.table.remove(key) {
+      None => Ok(None),
+      Some(toml::Value::String(value)) => Ok(Some(value)),
+      Some(_) => Err(self.invalid(key, "a string")),
+    }
+  }
+
+  fn vec3(&mut self, key: &str, target: &mut [f32; 3]) -> Result<(), ConfigError> {
+    let values = match self.table.remove(key) {
+      None => return Ok(()),
+      Some(toml::Value::Array(values)) => values,
+      Some(_) => return Err(self.invalid(key, "an array of 3 numbers")),
+    };
+    if values.len() != 3 {
+      return Err(self.invalid(key, "an array of 3 numbers"));
+    }
+
+    for (target, value) in target.iter_mut().zip(values) {
+      *target = match value {
+        toml::Value::Float(value) => value as f32,
+        toml::Value::Integer(value) => value as f32,
+        _ => return Err(self.invalid(key, "an array of 3 numbers")),
+      };
+    }
+    Ok(())
+  }
+
+  fn finish(self) -> Result<(), ConfigError> {
+    match self.table.keys().next() {
+      Some(key) => Err(ConfigError::Invalid(format!(
+        "unknown key {}.{}",
+        self.name, key
+      ))),
+      None => Ok(()),
+    }
+  }
+}
+
+#[test]
+fn test_scene_config_from_toml() {
+  let config = SceneConfig::from_toml(
+    r#"
+      [window]
+      width = 640
+      height = 480
+
+      [ocean]
+      preset = "storm"
+      backend = "gerstner"
+      gerstner_waves = 16
+      swell = 0.2
+      seed = 7
+
+      [camera]
+      position = [1, 2.5, 3]
+      fov = 60
+
+      [lighting]
+      sun_color = [1.0, 0.8, 0.6]
+      turbidity = 4
+      time_of_day = 9.5
+
+      [fog]
+      density = 0.001
+
+      [underwater]
+      scattering = 0.1
+      waterline = 0
+
+      [water]
+      ior = 1.34
+      foam_coverage = 1.5
+      absorption = [0.5, 0.1, 0.05]
+
+      [rendering]
+      sample_count = 1
+      vsync = false
+      reflections = "planar"
+
+      [tonemapping]
+      tonemapper = "agx"
+      auto_exposure = true
+      exposure = -0.5
+
+      [bloom]
+      threshold = 4
+      lens_dirt = 0.5
+    "#,
+  )
+  .unwrap();
+
+  let storm = OceanCascadeParameters::preset("storm").unwrap();
+  assert_eq!((config.width, config.height), (640, 480));
+  assert_eq!(config.renderer.ocean.wind_speed, storm.wind_speed);
+  assert_eq!(config.renderer.ocean.swell, 0.2);
+  assert_eq!(
+    config.renderer.ocean_backend,
+    Some(OceanBackend::Gerstner { wave_count: 16 })
+  );
+  assert_eq!(config.renderer.seed, Some(7));
+  assert_eq!(config.renderer.camera.position, [1.0, 2.5, 3.0]);
+  assert_eq!(config.renderer.camera.fov, 60.0);
+  assert_eq!(config.renderer.lighting.sun_color, [1.0, 0.8, 0.6]);
+  assert_eq!(config.renderer.lighting.turbidity, 4.0);
+  assert_eq!(
+    config.renderer.lighting.sun,
+    Some(SunPosition {
+      time_of_day: 9.5,
+      ..Default::default()
+    })
+  );
+  assert_eq!(config.renderer.lighting.fog.density, 0.001);
+  assert_eq!(config.renderer.underwater.scattering, 0.1);
+  assert_eq!(config.renderer.underwater.waterline, 0.0);
+  assert!(config.renderer.underwater.enabled);
+  assert_eq!(config.renderer.water_material.ior, 1.34);
+  assert_eq!(config.renderer.water_material.foam_coverage, 1.5);
+  assert_eq!(config.renderer.water_material.absorption, [0.5, 0.1, 0.05]);
+  assert_eq!(config.renderer.sample_count, 1);
+  assert!(!config.renderer.vsync);
+  assert_eq!(config.renderer.reflections, ReflectionMode::Planar);
+  assert_eq!(config.renderer.tonemapping.tonemapper, Tonemapper::Agx);
+  assert!(config.renderer.tonemapping.auto_exposure);
+  assert_eq!(config.renderer.tonemapping.exposure, -0.5);
+  assert_eq!(config.renderer.bloom.threshold, 4.0);
+  assert_eq!(config.renderer.bloom.lens_dirt, 0.5);
+  assert!(config.renderer.bloom.enabled);
+
+  assert!(SceneConfig::from_toml("").is_ok());
+  assert!(SceneConfig::from_toml("[ocean]\nwind = 3").is_err());
+  assert!(SceneConfig::from_toml("[sky]").is_err());
+  assert!(SceneConfig::from_toml("[ocean]\npreset = \"glassy\"").is_err());
+  assert!(SceneConfig::from_toml("[ocean]\nseed = -1").is_err());
+  assert!(SceneConfig::from_toml("[camera]\nposition = [1, 2]").is_err());
+  assert!(SceneConfig::from_toml("[ocean]\ngerstner_waves = 8").is_err());
+  assert!(SceneConfig::from_toml("[ocean]\nbackend = \"gerstner\"\ngerstner_waves = 0").is_err());
+  assert!(SceneConfig::from_toml("[ocean]\nbackend = \"gerstner\"\ngerstner_waves = 65").is_err());
+  assert!(SceneConfig::from_toml("[water]\nior = 0.5").is_err());
+  assert!(SceneConfig::from_toml("[rendering]\nreflections = \"mirror\"").is_err());
+  assert!(SceneConfig::from_toml("[tonemapping]\ntonemapper = \"filmic\"").is_err());
+  assert!(SceneConfig::from_toml("[tonemapping]\nmin_log_luminance = 8").is_err());
+  assert!(SceneConfig::from_toml("[bloom]\nlevels = 0").is_err());
+  assert!(SceneConfig::from_toml("[fog]\ndensity = -1").is_err());
+  assert!(SceneConfig::from_toml("[underwater]\nscattering = -1").is_err());
+  assert!(SceneConfig::from_toml("[lighting]\nsun_direction = [0, 1, 0]\nlatitude = 10").is_err());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_scene_config_from_ron() {
+  let config = SceneConfig::from_ron(
+    r#"(
+      window: (width: 640, height: 480),
+      ocean: (backend: "gerstner", gerstner_waves: 16, seed: 7),
+      camera: (position: (1, 2.5, 3), fov: 60),
+      rendering: (vsync: false),
+    )"#,
+  )
+  .unwrap();
+  let expected = SceneConfig::from_toml(
+    r#"
+      window = { width = 640, height = 480 }
+      ocean = { backend = "gerstner", gerstner_waves = 16, seed = 7 }
+      camera = { position = [1, 2.5, 3], fov = 60 }
+      rendering = { vsync = false }
+    "#,
+  )
+  .unwrap();
+  assert_eq!(config, expected);
+
+  let path = Path::new("scene.ron");
+  assert_eq!(
+    SceneConfig::from_source(path, "()").unwrap(),
+    SceneConfig::default()
+  );
+  assert!(SceneConfig::from_source(path, "(window: (width: 0))").is_err());
+  assert!(SceneConfig::from_source(path, "(ocean: (seed: None))").is_err());
+  assert!(SceneConfig::from_source(path, "(window: (width: 640)").is_err());
+}
@@ -0,0 +1,345 @@
+use std::f32::consts::PI;
+
+use crate::ocean::shaders::{self, Shader};
+use crate::ocean::OceanError;
+use crate::scene::PassInfo;
+
+pub const SKY_SHADER: Shader = Shader::new("src/sky_shader.wgsl", include_str!("sky_shader.wgsl"));
+
+// Scales sky luminance, in kcd/m², to the scene's linear values.
+const SKY_EXPOSURE: f32 = 0.15;
+// Sines of the sun elevation over which the sky fades out at dusk.
+const DUSK: (f32, f32) = (-0.1, 0.05);
+
+// Where the sun is seen from a point on Earth. The world's x axis points east,
+// y up and z south.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct SunPosition {
+  // Degrees, positive north of the equator.
+  pub latitude: f32,
+  // 1 is January 1st.
+  pub day_of_year: u32,
+  // Local solar time in hours, the sun is highest at 12.
+  pub time_of_day: f32,
+}
+
+impl Default for SunPosition {
+  fn default() -> SunPosition {
+    SunPosition {
+      latitude: 45.0,
+      day_of_year: 172,
+      time_of_day: 16.0,
+    }
+  }
+}
+
+impl SunPosition {
+  // Unit vector pointing towards the sun.
+  pub fn direction(&self) -> [f32; 3] {
+    let latitude = self.latitude.to_radians();
+    let declination =
+      -23.44f32.to_radians() * (2.0 * PI / 365.0 * (self.day_of_year as f32 + 10.0)).cos();
+    let hour_angle = (15.0 * (self.time_of_day - 12.0)).to_radians();
+
+    let east = -declination.cos() * hour_angle.sin();
+    let north =
+      latitude.cos() * declination.sin() - latitude.sin() * declination.cos() * hour_angle.cos();
+    let up =
+      latitude.sin() * declination.sin() + latitude.cos() * declination.cos() * hour_angle.cos();
+    [east, up, -north]
+  }
+}
+
+fn normalize([x, y, z]: [f32; 3]) -> [f32; 3] {
+  let length = (x * x + y * y + z * z).sqrt();
+  if length > 0.0 {
+    [x / length, y / length, z / length]
+  } else {
+    [0.0, 1.0, 0.0]
+  }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+  let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+  t * t * (3.0 - 2.0 * t)
+}
+
+// Fraction of the sunlight of each RGB channel that reaches the ground
+// through Rayleigh and aerosol scattering, as in Preetham et al.
+pub fn sun_transmittance(sun_direction: [f32; 3], turbidity: f32) -> [f32; 3] {
+  let [_, up, _] = normalize(sun_direction);
+  let zenith_angle = up.clamp(0.0, 1.0).acos();
+  // Kasten and Young's relative air mass.
+  let air_mass =
+    1.0 / (zenith_angle.cos() + 0.50572 * (96.07995 - zenith_angle.to_degrees()).powf(-1.6364));
+
+  let beta = 0.04608 * turbidity - 0.04586;
+  let dusk = smoothstep(DUSK.0, DUSK.1, up);
+  // Wavelengths of the RGB channels in micrometers.
+  [0.68f32, 0.55, 0.44].map(|lambda| {
+    let rayleigh = 0.008569 * lambda.powi(-4) * (1.0 + 0.0113 * lambda.powi(-2));
+    let aerosol = beta * lambda.powf(-1.3);
+    (-air_mass * (rayleigh + aerosol)).exp() * dusk
+  })
+}
+
+// Preetham, Shirley and Smits' analytic daylight model, evaluated in Yxy.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SkyModel {
+  // A to E for luminance and both chromaticity coordinates.
+  coefficients: [[f32; 3]; 5],
+  zenith: [f32; 3],
+}
+
+impl SkyModel {
+  // `turbidity` goes from about 2 for a clear sky to 10 for haze.
+  pub fn new(sun_direction: [f32; 3], turbidity: f32) -> SkyModel {
+    let t = turbidity;
+    let coefficients = [
+      [
+        0.1787 * t - 1.4630,
+        -0.0193 * t - 0.2592,
+        -0.0167 * t - 0.2608,
+      ],
+      [
+        -0.3554 * t + 0.4275,
+        -0.0665 * t + 0.0008,
+        -0.0950 * t + 0.0092,
+      ],
+      [
+        -0.0227 * t + 5.3251,
+        -0.0004 * t + 0.2125,
+        -0.0079 * t + 0.2102,
+      ],
+      [
+        0.1206 * t - 2.5771,
+        -0.0641 * t - 0.8989,
+        -0.0441 * t - 1.6537,
+      ],
+      [
+        -0.0670 * t + 0.3703,
+        -0.0033 * t + 0.0452,
+        -0.0109 * t + 0.0529,
+      ],
+    ];
+
+    let [_, up, _] = normalize(sun_direction);
+    // The fit does not hold below the horizon, where the sky fades out
+    // instead.
+    let theta = up.clamp(0.0, 1.0).acos();
+    let (theta2, theta3) = (theta * theta, theta * theta * theta);
+
+    let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta);
+    let luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+    let x = t * t * (0.00166 * theta3 - 0.00375 * theta2 + 0.00209 * theta)
+      + t * (-0.02903 * theta3 + 0.06377 * theta2 - 0.03202 * theta + 0.00394)
+      + (0.11693 * theta3 - 0.21196 * theta2 + 0.06052 * theta + 0.25886);
+    let y = t * t * (0.00275 * theta3 - 0.00610 * theta2 + 0.00317 * theta)
+      + t * (-0.04214 * theta3 + 0.08970 * theta2 - 0.04153 * theta + 0.00516)
+      + (0.15346 * theta3 - 0.26756 * theta2 + 0.06670 * theta + 0.26688);
+
+    let mut model = SkyModel {
+      coefficients,
+      zenith: [0.0; 3],
+    };
+    // Normalized so the Perez function is 1 at the zenith.
+    let at_zenith = model.perez(1.0, theta);
+    let exposure = SKY_EXPOSURE * smoothstep(DUSK.0, DUSK.1, up);
+    model.zenith = [
+      luminance * exposure / at_zenith[0],
+      x / at_zenith[1],
+      y / at_zenith[2],
+    ];
+    model
+  }
+
+  // Linear color of the sky in `direction`, as the sky shader computes it.
+  pub fn radiance(&self, direction: [f32; 3], sun_direction: [f32; 3]) -> [f32; 3] {
+    let [dx, dy, dz] = normalize(direction);
+    let [sx, sy, sz] = normalize(sun_direction);
+    let cos_gamma = (dx * sx + dy * sy + dz * sz).clamp(-1.0, 1.0);
+
+    let perez = self.perez(dy.max(0.01), cos_gamma.acos());
+    let [luminance, x, y] = [0, 1, 2].map(|i| self.zenith[i] * perez[i]);
+
+    let xyz = [x / y * luminance, luminance, (1.0 - x - y) / y * luminance];
+    let rgb = [
+      3.2406 * xyz[0] - 1.5372 * xyz[1] - 0.4986 * xyz[2],
+      -0.9689 * xyz[0] + 1.8758 * xyz[1] + 0.0415 * xyz[2],
+      0.0557 * xyz[0] - 0.2040 * xyz[1] + 1.0570 * xyz[2],
+    ];
+    rgb.map(|c| c.max(0.0))
+  }
+
+  fn perez(&self, cos_theta: f32, gamma: f32) -> [f32; 3] {
+    let [a, b, c, d, e] = self.coefficients;
+    [0, 1, 2].map(|i| {
+      (1.0 + a[i] * (b[i] / cos_theta).exp())
+        * (1.0 + c[i] * (d[i] * gamma).exp() + e[i] * gamma.cos().powi(2))
+    })
+  }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SkyUniform {
+  coefficients: [[f32; 4]; 5],
+  zenith: [f32; 4],
+}
+
+impl From<SkyModel> for SkyUniform {
+  fn from(model: SkyModel) -> Self {
+    let extend = |[x, y, z]: [f32; 3]| [x, y, z, 0.0];
+    SkyUniform {
+      coefficients: model.coefficients.map(extend),
+      zenith: extend(model.zenith),
+    }
+  }
+}
+
+// Fills the background of the ocean's render pass with the sky.
+pub struct SkyPipeline {
+  pipeline_layout: wgpu::PipelineLayout,
+  pipeline: wgpu::RenderPipeline,
+  pass_info: PassInfo,
+}
+
+impl SkyPipeline {
+  pub fn new(
+    device: &wgpu::Device,
+    camera_bind_group_layout: &wgpu::BindGroupLayout,
+    pass_info: PassInfo,
+  ) -> SkyPipeline {
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Sky Pipeline Layout"),
+      bind_group_layouts: &[camera_bind_group_layout],
+      push_constant_ranges: &[],
+    });
+
+    let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+      label: Some("Sky shader"),
+      source: wgpu::ShaderSource::Wgsl(SKY_SHADER.source()),
+    });
+    let pipeline = Self::create_pipeline(device, &pipeline_layout, &shader, pass_info);
+
+    SkyPipeline {
+      pipeline_layout,
+      pipeline,
+      pass_info,
+    }
+  }
+
+  pub async fn reload(
+    &mut self,
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+  ) -> Result<(), OceanError> {
+    self.pipeline = shaders::try_create(device, || {
+      Self::create_pipeline(device, &self.pipeline_layout, shader, self.pass_info)
+    })
+    .await?;
+    Ok(())
+  }
+
+  // Expects group 0 to be bound already.
+  pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+    render_pass.set_pipeline(&self.pipeline);
+    render_pass.draw(0..3, 0..1);
+  }
+
+  fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    pass_info: PassInfo,
+  ) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Sky Pipeline"),
+      layout: Some(layout),
+      vertex: wgpu::VertexState {
+        module: shader,
+        entry_point: "vs_main",
+        buffers: &[],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: shader,
+        entry_point: "fs_main",
+        targets: &[wgpu::ColorTargetState {
+          format: pass_info.color_format,
+          blend: Some(wgpu::BlendState::REPLACE),
+          write_mask: wgpu::ColorWrites::ALL,
+        }],
+      }),
+      primitive: wgpu::PrimitiveState::default(),
+      // Drawn first, everything else covers it.
+      depth_stencil: Some(wgpu::DepthStencilState {
+        format: pass_info.depth_format,
+        depth_write_enabled: false,
+        depth_compare: wgpu::CompareFunction::Always,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+      }),
+      multisample: wgpu::MultisampleState {
+        count: pass_info.sample_count,
+        mask: !0,
+        alpha_to_coverage_enabled: false,
+      },
+      multiview: None,
+    })
+  }
+}
+
+#[test]
+fn test_sun_position() {
+  let close = |a: [f32; 3], b: [f32; 3]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 0.05);
+
+  // At an equinox on the equator the sun rises in the east, passes overhead
+  // and sets in the west.
+  let mut sun = SunPosition {
+    latitude: 0.0,
+    day_of_year: 80,
+    time_of_day: 6.0,
+  };
+  assert!(close(sun.direction(), [1.0, 0.0, 0.0]));
+  sun.time_of_day = 12.0;
+  assert!(close(sun.direction(), [0.0, 1.0, 0.0]));
+  sun.time_of_day = 18.0;
+  assert!(close(sun.direction(), [-1.0, 0.0, 0.0]));
+
+  // At noon in the northern summer it stands south, 23.44° higher than at the
+  // equinox.
+  let sun = SunPosition {
+    latitude: 45.0,
+    day_of_year: 172,
+    time_of_day: 12.0,
+  };
+  let [x, y, z] = sun.direction();
+  assert!(x.abs() < 1e-3 && z > 0.0);
+  assert!((y.asin().to_degrees() - 68.44).abs() < 0.1);
+}
+
+#[test]
+fn test_sky_model() {
+  assert_eq!(std::mem::size_of::<SkyUniform>(), 96);
+
+  let sun = SunPosition::default().direction();
+  let sky = SkyModel::new(sun, 2.5);
+  let toward_sun = sky.radiance([sun[0], sun[1] + 0.1, sun[2]], sun);
+  let away = sky.radiance([-sun[0], 0.5, -sun[2]], sun);
+  let sum = |rgb: [f32; 3]| rgb.iter().sum::<f32>();
+  assert!(sum(toward_sun) > sum(away));
+  // A clear sky is blue.
+  assert!(away[2] > away[0]);
+
+  // The sun reddens towards the horizon and is gone at night.
+  let high = sun_transmittance([0.0, 1.0, 0.0], 2.5);
+  let low = sun_transmittance([1.0, 0.05, 0.0], 2.5);
+  assert!(high[2] / high[0] > low[2] / low[0]);
+  assert_eq!(sun_transmittance([1.0, -0.5, 0.0], 2.5), [0.0; 3]);
+  assert_eq!(
+    SkyModel::new([1.0, -0.5, 0.0], 2.5).radiance([0.0, 1.0, 0.0], [1.0, -0.5, 0.0]),
+    [0.0; 3]
+  );
+}
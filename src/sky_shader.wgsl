@@ -0,0 +1,97 @@
+// Preetham sky drawn behind everything else, see `SkyModel`.
+
+struct CameraUniform {
+    pos: vec3<f32>;
+    view_proj: mat4x4<f32>;
+    view: mat4x4<f32>;
+    proj: mat4x4<f32>;
+    inverse_view_proj: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+// Perez coefficients and zenith values of luminance and chromaticity, as Yxy
+// in each vec3.
+struct SkyUniform {
+    a: vec3<f32>;
+    b: vec3<f32>;
+    c: vec3<f32>;
+    d: vec3<f32>;
+    e: vec3<f32>;
+    // Already divided by the Perez function at the zenith and scaled by the
+    // exposure.
+    zenith: vec3<f32>;
+};
+
+struct LightingUniform {
+    sun_direction: vec3<f32>;
+    sun_color: vec3<f32>;
+    sky_color: vec3<f32>;
+    sky: SkyUniform;
+};
+
+[[group(0), binding(1)]]
+var<uniform> lighting: LightingUniform;
+
+// Cosine of the angular radius of the sun, made a bit larger than the real
+// one to be visible.
+let SUN_DISK_COS: f32 = 0.99996;
+// Radiance of the sun disk relative to the light it casts, far brighter than
+// anything else so it stays white whatever the exposure.
+let SUN_DISK_INTENSITY: f32 = 50.0;
+
+fn screen_to_world(screen_uv: vec3<f32>) -> vec3<f32> {
+    let w = camera.inverse_view_proj * vec4<f32>(screen_uv.xyz, 1.0);
+    return w.xyz * (1.0 / w.w);
+}
+
+fn perez(cos_theta: f32, gamma: f32, cos_gamma: f32) -> vec3<f32> {
+    let sky = lighting.sky;
+    return (1.0 + sky.a * exp(sky.b / cos_theta))
+        * (1.0 + sky.c * exp(sky.d * gamma) + sky.e * cos_gamma * cos_gamma);
+}
+
+fn sky_radiance(dir: vec3<f32>) -> vec3<f32> {
+    // The model is only defined above the horizon.
+    let cos_theta = max(dir.y, 0.01);
+    let cos_gamma = clamp(dot(dir, normalize(lighting.sun_direction)), -1.0, 1.0);
+    let yxy = lighting.sky.zenith * perez(cos_theta, acos(cos_gamma), cos_gamma);
+
+    let xyz = vec3<f32>(yxy.y / yxy.z * yxy.x, yxy.x, (1.0 - yxy.y - yxy.z) / yxy.z * yxy.x);
+    let rgb = vec3<f32>(
+        3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+        -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+        0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z,
+    );
+    return max(rgb, vec3<f32>(0.0));
+}
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] screen_uv: vec2<f32>;
+};
+
+// A triangle covering the viewport.
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.screen_uv = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    out.clip_position = vec4<f32>(out.screen_uv, 1.0, 1.0);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let dir = normalize(screen_to_world(vec3<f32>(in.screen_uv, 1.0)) - camera.pos);
+    var color = sky_radiance(dir);
+
+    let sun = normalize(lighting.sun_direction);
+    if (dot(dir, sun) > SUN_DISK_COS && dir.y > 0.0) {
+        color = color + lighting.sun_color * SUN_DISK_INTENSITY;
+    }
+
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,637 @@
+use wgpu::util::DeviceExt;
+
+use crate::ocean::shaders::{self, Shader};
+use crate::ocean::OceanError;
+
+pub const TONEMAP_SHADER: Shader = Shader::new("src/tonemap.wgsl", include_str!("tonemap.wgsl"));
+pub const EXPOSURE_SHADER: Shader = Shader::new("src/exposure.wgsl", include_str!("exposure.wgsl"));
+
+// What the scene is drawn into, in linear values before exposure.
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+// Matches the histogram arrays in exposure.wgsl.
+const HISTOGRAM_BINS: u64 = 256;
+// Matches the workgroup size of `build_histogram`.
+const HISTOGRAM_TILE: u32 = 16;
+
+// How the exposed frame is compressed into what the display shows.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum Tonemapper {
+  // Contrasty, and saturated colors shift hue as they brighten.
+  #[default]
+  Aces,
+  // Flatter, desaturates highlights towards white.
+  Agx,
+}
+
+impl Tonemapper {
+  pub const ALL: [Tonemapper; 2] = [Tonemapper::Aces, Tonemapper::Agx];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Tonemapper::Aces => "aces",
+      Tonemapper::Agx => "agx",
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<Tonemapper> {
+    Self::ALL
+      .into_iter()
+      .find(|tonemapper| tonemapper.name() == name)
+  }
+
+  // What the tonemapping shader expects in `tonemapper`.
+  fn index(self) -> u32 {
+    match self {
+      Tonemapper::Aces => 0,
+      Tonemapper::Agx => 1,
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Tonemapping {
+  pub tonemapper: Tonemapper,
+  // Meters every frame to bring its average luminance to middle grey.
+  pub auto_exposure: bool,
+  // Stops, each one doubles the brightness. Corrects the metered exposure
+  // when `auto_exposure` is set.
+  pub exposure: f32,
+  // Log2 of the luminances the meter considers, darker pixels are ignored
+  // and brighter ones clamped.
+  pub min_log_luminance: f32,
+  pub max_log_luminance: f32,
+  // How quickly the metered exposure follows the frame, per second.
+  pub adaptation_rate: f32,
+}
+
+impl Default for Tonemapping {
+  fn default() -> Tonemapping {
+    Tonemapping {
+      tonemapper: Tonemapper::Aces,
+      auto_exposure: false,
+      exposure: 0.0,
+      min_log_luminance: -10.0,
+      max_log_luminance: 6.0,
+      adaptation_rate: 1.5,
+    }
+  }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct TonemapUniform {
+  min_log_luminance: f32,
+  log_luminance_range: f32,
+  // Fraction of the way to the metered luminance covered this frame.
+  adaptation: f32,
+  exposure: f32,
+  auto_exposure: u32,
+  tonemapper: u32,
+  encode_srgb: u32,
+  _padding: u32,
+}
+
+impl TonemapUniform {
+  // Without `metered` the exposure is manual whatever the settings.
+  fn new(
+    settings: Tonemapping,
+    dt: Option<f32>,
+    output_format: wgpu::TextureFormat,
+    metered: bool,
+  ) -> Self {
+    TonemapUniform {
+      min_log_luminance: settings.min_log_luminance,
+      log_luminance_range: (settings.max_log_luminance - settings.min_log_luminance).max(1e-3),
+      adaptation: dt.map_or(1.0, |dt| 1.0 - (-dt * settings.adaptation_rate).exp()),
+      exposure: settings.exposure,
+      auto_exposure: (settings.auto_exposure && metered) as u32,
+      tonemapper: settings.tonemapper.index(),
+      encode_srgb: !output_format.describe().srgb as u32,
+      _padding: 0,
+    }
+  }
+}
+
+// The compute passes of automatic exposure and what they write.
+struct Metering {
+  histogram_buffer: wgpu::Buffer,
+  luminance_buffer: wgpu::Buffer,
+
+  exposure_bind_group_layout: wgpu::BindGroupLayout,
+  exposure_pipeline_layout: wgpu::PipelineLayout,
+  histogram_pipeline: wgpu::ComputePipeline,
+  average_pipeline: wgpu::ComputePipeline,
+  exposure_bind_group: wgpu::BindGroup,
+}
+
+// Owns the HDR target the scene is drawn into and resolves it to the output
+// format, optionally metering it first.
+pub(crate) struct TonemapPass {
+  pub hdr_view: wgpu::TextureView,
+  settings: Tonemapping,
+  output_format: wgpu::TextureFormat,
+  size: winit::dpi::PhysicalSize<u32>,
+  // Of the last frame, so exposure adapts at the same speed whatever the
+  // frame rate.
+  last_time: Option<f32>,
+
+  uniform_buffer: wgpu::Buffer,
+  // Only on devices with compute shaders and storage buffers, the exposure is
+  // always manual otherwise.
+  metering: Option<Metering>,
+
+  tonemap_bind_group_layout: wgpu::BindGroupLayout,
+  tonemap_pipeline_layout: wgpu::PipelineLayout,
+  tonemap_pipeline: wgpu::RenderPipeline,
+  tonemap_bind_group: wgpu::BindGroup,
+}
+
+impl TonemapPass {
+  // `metered` is whether the device can run the compute passes of automatic
+  // exposure.
+  pub fn new(
+    device: &wgpu::Device,
+    output_format: wgpu::TextureFormat,
+    size: winit::dpi::PhysicalSize<u32>,
+    settings: Tonemapping,
+    metered: bool,
+  ) -> TonemapPass {
+    if settings.auto_exposure && !metered {
+      log::warn!("Automatic exposure needs compute shaders, using the manual exposure");
+    }
+    let uniform = TonemapUniform::new(settings, None, output_format, metered);
+    let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Tonemap Buffer"),
+      contents: bytemuck::cast_slice(&[uniform]),
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    });
+
+    let hdr_texture_entry = |visibility| wgpu::BindGroupLayoutEntry {
+      binding: 0,
+      visibility,
+      ty: wgpu::BindingType::Texture {
+        view_dimension: wgpu::TextureViewDimension::D2,
+        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+        multisampled: false,
+      },
+      count: None,
+    };
+    let buffer_entry = |binding, visibility, ty| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility,
+      ty: wgpu::BindingType::Buffer {
+        ty,
+        has_dynamic_offset: false,
+        min_binding_size: None,
+      },
+      count: None,
+    };
+    let storage = |read_only| wgpu::BufferBindingType::Storage { read_only };
+
+    let fragment = wgpu::ShaderStages::FRAGMENT;
+    let tonemap_entries = [
+      hdr_texture_entry(fragment),
+      buffer_entry(1, fragment, wgpu::BufferBindingType::Uniform),
+      buffer_entry(2, fragment, storage(true)),
+    ];
+    let tonemap_bind_group_layout =
+      device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("tonemap_bind_group_layout"),
+        // The average luminance is left out without metering.
+        entries: &tonemap_entries[..if metered { 3 } else { 2 }],
+      });
+    let tonemap_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Tonemap Pipeline Layout"),
+      bind_group_layouts: &[&tonemap_bind_group_layout],
+      push_constant_ranges: &[],
+    });
+
+    let tonemap_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+      label: Some("Tonemap shader"),
+      source: wgpu::ShaderSource::Wgsl(TONEMAP_SHADER.source()),
+    });
+    let tonemap_pipeline = Self::create_tonemap_pipeline(
+      device,
+      &tonemap_pipeline_layout,
+      &tonemap_shader,
+      output_format,
+      metered,
+    );
+
+    let hdr_view = Self::create_hdr_view(device, size);
+    let metering = metered.then(|| Metering::new(device, &hdr_view, &uniform_buffer));
+    let tonemap_bind_group = Self::create_tonemap_bind_group(
+      device,
+      &tonemap_bind_group_layout,
+      &hdr_view,
+      &uniform_buffer,
+      metering.as_ref(),
+    );
+
+    TonemapPass {
+      hdr_view,
+      settings,
+      output_format,
+      size,
+      last_time: None,
+      uniform_buffer,
+      metering,
+      tonemap_bind_group_layout,
+      tonemap_pipeline_layout,
+      tonemap_pipeline,
+      tonemap_bind_group,
+    }
+  }
+
+  pub fn resize(&mut self, device: &wgpu::Device, size: winit::dpi::PhysicalSize<u32>) {
+    self.size = size;
+    self.hdr_view = Self::create_hdr_view(device, size);
+    if let Some(metering) = &mut self.metering {
+      metering.exposure_bind_group = Metering::create_bind_group(
+        device,
+        &metering.exposure_bind_group_layout,
+        &self.hdr_view,
+        [
+          &self.uniform_buffer,
+          &metering.histogram_buffer,
+          &metering.luminance_buffer,
+        ],
+      );
+    }
+    self.tonemap_bind_group = Self::create_tonemap_bind_group(
+      device,
+      &self.tonemap_bind_group_layout,
+      &self.hdr_view,
+      &self.uniform_buffer,
+      self.metering.as_ref(),
+    );
+  }
+
+  pub fn settings(&self) -> Tonemapping {
+    self.settings
+  }
+
+  // Takes effect from the next frame.
+  pub fn set_settings(&mut self, settings: Tonemapping) {
+    if settings.auto_exposure && !self.settings.auto_exposure && self.metering.is_none() {
+      log::warn!("Automatic exposure needs compute shaders, using the manual exposure");
+    }
+    self.settings = settings;
+  }
+
+  pub async fn reload_tonemap(
+    &mut self,
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+  ) -> Result<(), OceanError> {
+    self.tonemap_pipeline = shaders::try_create(device, || {
+      Self::create_tonemap_pipeline(
+        device,
+        &self.tonemap_pipeline_layout,
+        shader,
+        self.output_format,
+        self.metering.is_some(),
+      )
+    })
+    .await?;
+    Ok(())
+  }
+
+  pub async fn reload_exposure(
+    &mut self,
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+  ) -> Result<(), OceanError> {
+    let metering = match &mut self.metering {
+      Some(metering) => metering,
+      None => return Ok(()),
+    };
+    let (histogram_pipeline, average_pipeline) = shaders::try_create(device, || {
+      Metering::create_pipelines(device, &metering.exposure_pipeline_layout, shader)
+    })
+    .await?;
+    metering.histogram_pipeline = histogram_pipeline;
+    metering.average_pipeline = average_pipeline;
+    Ok(())
+  }
+
+  // Meters the HDR target when exposure is automatic and tonemaps it into
+  // `view`, which must be in the output format.
+  pub fn draw(
+    &mut self,
+    encoder: &mut wgpu::CommandEncoder,
+    queue: &wgpu::Queue,
+    view: &wgpu::TextureView,
+    time: f32,
+  ) {
+    let dt = self.last_time.map(|last_time| (time - last_time).max(0.0));
+    self.last_time = Some(time);
+    queue.write_buffer(
+      &self.uniform_buffer,
+      0,
+      bytemuck::cast_slice(&[TonemapUniform::new(
+        self.settings,
+        dt,
+        self.output_format,
+        self.metering.is_some(),
+      )]),
+    );
+
+    if let Some(metering) = self
+      .metering
+      .as_ref()
+      .filter(|_| self.settings.auto_exposure)
+    {
+      let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+        label: Some("Exposure Pass"),
+      });
+      compute_pass.set_bind_group(0, &metering.exposure_bind_group, &[]);
+      compute_pass.set_pipeline(&metering.histogram_pipeline);
+      compute_pass.dispatch(
+        (self.size.width + HISTOGRAM_TILE - 1) / HISTOGRAM_TILE,
+        (self.size.height + HISTOGRAM_TILE - 1) / HISTOGRAM_TILE,
+        1,
+      );
+      compute_pass.set_pipeline(&metering.average_pipeline);
+      compute_pass.dispatch(1, 1, 1);
+    }
+
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some("Tonemap Pass"),
+      color_attachments: &[wgpu::RenderPassColorAttachment {
+        view,
+        resolve_target: None,
+        ops: wgpu::Operations {
+          load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+          store: true,
+        },
+      }],
+      depth_stencil_attachment: None,
+    });
+    render_pass.set_pipeline(&self.tonemap_pipeline);
+    render_pass.set_bind_group(0, &self.tonemap_bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+  }
+
+  fn create_hdr_view(
+    device: &wgpu::Device,
+    size: winit::dpi::PhysicalSize<u32>,
+  ) -> wgpu::TextureView {
+    device
+      .create_texture(&wgpu::TextureDescriptor {
+        label: Some("HDR target"),
+        size: wgpu::Extent3d {
+          width: size.width,
+          height: size.height,
+          depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: HDR_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+      })
+      .create_view(&wgpu::TextureViewDescriptor::default())
+  }
+
+  // Binds the average luminance only with `metering`.
+  fn create_tonemap_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    hdr_view: &wgpu::TextureView,
+    uniform_buffer: &wgpu::Buffer,
+    metering: Option<&Metering>,
+  ) -> wgpu::BindGroup {
+    let mut entries = vec![
+      wgpu::BindGroupEntry {
+        binding: 0,
+        resource: wgpu::BindingResource::TextureView(hdr_view),
+      },
+      wgpu::BindGroupEntry {
+        binding: 1,
+        resource: uniform_buffer.as_entire_binding(),
+      },
+    ];
+    if let Some(metering) = metering {
+      entries.push(wgpu::BindGroupEntry {
+        binding: 2,
+        resource: metering.luminance_buffer.as_entire_binding(),
+      });
+    }
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Tonemap bind group"),
+      layout,
+      entries: &entries,
+    })
+  }
+
+  fn create_tonemap_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    output_format: wgpu::TextureFormat,
+    metered: bool,
+  ) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+      label: Some("Tonemap Pipeline"),
+      layout: Some(layout),
+      vertex: wgpu::VertexState {
+        module: shader,
+        entry_point: "vs_main",
+        buffers: &[],
+      },
+      fragment: Some(wgpu::FragmentState {
+        module: shader,
+        entry_point: if metered { "fs_main" } else { "fs_manual" },
+        targets: &[wgpu::ColorTargetState {
+          format: output_format,
+          blend: None,
+          write_mask: wgpu::ColorWrites::ALL,
+        }],
+      }),
+      primitive: wgpu::PrimitiveState::default(),
+      depth_stencil: None,
+      multisample: wgpu::MultisampleState::default(),
+      multiview: None,
+    })
+  }
+}
+
+impl Metering {
+  fn new(
+    device: &wgpu::Device,
+    hdr_view: &wgpu::TextureView,
+    uniform_buffer: &wgpu::Buffer,
+  ) -> Metering {
+    let histogram_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Luminance histogram"),
+      size: HISTOGRAM_BINS * 4,
+      usage: wgpu::BufferUsages::STORAGE,
+      mapped_at_creation: false,
+    });
+    let luminance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Average luminance"),
+      size: 4,
+      usage: wgpu::BufferUsages::STORAGE,
+      mapped_at_creation: false,
+    });
+
+    let compute = wgpu::ShaderStages::COMPUTE;
+    let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: compute,
+      ty: wgpu::BindingType::Buffer {
+        ty,
+        has_dynamic_offset: false,
+        min_binding_size: None,
+      },
+      count: None,
+    };
+    let storage = wgpu::BufferBindingType::Storage { read_only: false };
+    let exposure_bind_group_layout =
+      device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("exposure_bind_group_layout"),
+        entries: &[
+          wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: compute,
+            ty: wgpu::BindingType::Texture {
+              view_dimension: wgpu::TextureViewDimension::D2,
+              sample_type: wgpu::TextureSampleType::Float { filterable: false },
+              multisampled: false,
+            },
+            count: None,
+          },
+          buffer_entry(1, wgpu::BufferBindingType::Uniform),
+          buffer_entry(2, storage),
+          buffer_entry(3, storage),
+        ],
+      });
+    let exposure_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Exposure Pipeline Layout"),
+      bind_group_layouts: &[&exposure_bind_group_layout],
+      push_constant_ranges: &[],
+    });
+
+    let exposure_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+      label: Some("Exposure shader"),
+      source: wgpu::ShaderSource::Wgsl(EXPOSURE_SHADER.source()),
+    });
+    let (histogram_pipeline, average_pipeline) =
+      Self::create_pipelines(device, &exposure_pipeline_layout, &exposure_shader);
+
+    let exposure_bind_group = Self::create_bind_group(
+      device,
+      &exposure_bind_group_layout,
+      hdr_view,
+      [uniform_buffer, &histogram_buffer, &luminance_buffer],
+    );
+
+    Metering {
+      histogram_buffer,
+      luminance_buffer,
+      exposure_bind_group_layout,
+      exposure_pipeline_layout,
+      histogram_pipeline,
+      average_pipeline,
+      exposure_bind_group,
+    }
+  }
+
+  // `buffers` are the uniform, histogram and luminance buffers.
+  fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    hdr_view: &wgpu::TextureView,
+    [uniform_buffer, histogram_buffer, luminance_buffer]: [&wgpu::Buffer; 3],
+  ) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Exposure bind group"),
+      layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: wgpu::BindingResource::TextureView(hdr_view),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: uniform_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: histogram_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 3,
+          resource: luminance_buffer.as_entire_binding(),
+        },
+      ],
+    })
+  }
+
+  fn create_pipelines(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+  ) -> (wgpu::ComputePipeline, wgpu::ComputePipeline) {
+    let create_pipeline = |label, entry_point| {
+      device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        module: shader,
+        entry_point,
+      })
+    };
+    (
+      create_pipeline("Histogram Pipeline", "build_histogram"),
+      create_pipeline("Average Luminance Pipeline", "average_luminance"),
+    )
+  }
+}
+
+#[test]
+fn test_tonemap_uniform() {
+  // Matches the layout of `TonemapUniform` in the shaders.
+  assert_eq!(std::mem::size_of::<TonemapUniform>(), 32);
+
+  let settings = Tonemapping {
+    tonemapper: Tonemapper::Agx,
+    ..Default::default()
+  };
+  let format = wgpu::TextureFormat::Bgra8UnormSrgb;
+  let uniform = TonemapUniform::new(settings, None, format, true);
+  assert_eq!(uniform.log_luminance_range, 16.0);
+  assert_eq!(uniform.tonemapper, 1);
+  // The first frame is metered without easing in, later ones adapt by less
+  // the shorter they are.
+  assert_eq!(uniform.adaptation, 1.0);
+  let short = TonemapUniform::new(settings, Some(0.01), format, true);
+  let long = TonemapUniform::new(settings, Some(0.1), format, true);
+  assert!(short.adaptation > 0.0 && short.adaptation < long.adaptation);
+
+  // Only formats without hardware sRGB encoding are encoded in the shader.
+  assert_eq!(uniform.encode_srgb, 0);
+  let linear = TonemapUniform::new(settings, None, wgpu::TextureFormat::Bgra8Unorm, true);
+  assert_eq!(linear.encode_srgb, 1);
+
+  // Exposure falls back to manual without metering.
+  let automatic = Tonemapping {
+    auto_exposure: true,
+    ..settings
+  };
+  assert_eq!(
+    TonemapUniform::new(automatic, None, format, true).auto_exposure,
+    1
+  );
+  assert_eq!(
+    TonemapUniform::new(automatic, None, format, false).auto_exposure,
+    0
+  );
+
+  assert_eq!(Tonemapper::from_name("agx"), Some(Tonemapper::Agx));
+  assert_eq!(Tonemapper::from_name("reinhard"), None);
+}
@@ -0,0 +1,118 @@
+// Exposes the HDR frame and maps it to the display, see `TonemapPass`.
+
+struct TonemapUniform {
+    min_log_luminance: f32;
+    log_luminance_range: f32;
+    adaptation: f32;
+    // Stops, added to the metered exposure when `auto_exposure` is set.
+    exposure: f32;
+    auto_exposure: u32;
+    // 0 for ACES, 1 for AgX.
+    tonemapper: u32;
+    // Set when the target format does not encode sRGB itself.
+    encode_srgb: u32;
+};
+
+struct Luminance {
+    average: f32;
+};
+
+[[group(0), binding(0)]]
+var t_hdr: texture_2d<f32>;
+
+[[group(0), binding(1)]]
+var<uniform> params: TonemapUniform;
+
+// Only bound when the exposure is metered.
+[[group(0), binding(2)]]
+var<storage, read> luminance: Luminance;
+
+// Where the metered average luminance ends up, middle grey.
+let EXPOSURE_KEY: f32 = 0.18;
+
+// Stephen Hill's fit of the ACES reference rendering and output transforms.
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let input = mat3x3<f32>(
+        vec3<f32>(0.59719, 0.07600, 0.02840),
+        vec3<f32>(0.35458, 0.90834, 0.13383),
+        vec3<f32>(0.04823, 0.01566, 0.83777),
+    );
+    let output = mat3x3<f32>(
+        vec3<f32>(1.60475, -0.10208, -0.00327),
+        vec3<f32>(-0.53108, 1.10813, -0.07276),
+        vec3<f32>(-0.07367, -0.00605, 1.07602),
+    );
+    let v = input * color;
+    let a = v * (v + 0.0245786) - 0.000090537;
+    let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+    return clamp(output * (a / b), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// Troy Sobotka's AgX with the polynomial contrast curve of Benjamin Wrensch's
+// minimal version, brought back to linear values.
+fn agx(color: vec3<f32>) -> vec3<f32> {
+    let inset = mat3x3<f32>(
+        vec3<f32>(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+        vec3<f32>(0.0784335999999992, 0.878468636469772, 0.0784336),
+        vec3<f32>(0.0792237451477643, 0.0791661274605434, 0.879142973793104),
+    );
+    let outset = mat3x3<f32>(
+        vec3<f32>(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+        vec3<f32>(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+        vec3<f32>(-0.0990297440797205, -0.0989611768448433, 1.15107367264116),
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    var v = log2(max(inset * color, vec3<f32>(1e-10)));
+    v = (clamp(v, vec3<f32>(min_ev), vec3<f32>(max_ev)) - min_ev) / (max_ev - min_ev);
+
+    let v2 = v * v;
+    let v4 = v2 * v2;
+    v = 15.5 * v4 * v2 - 40.14 * v4 * v + 31.96 * v4 - 6.868 * v2 * v + 0.4298 * v2 + 0.1191 * v - 0.00232;
+
+    return pow(clamp(outset * v, vec3<f32>(0.0), vec3<f32>(1.0)), vec3<f32>(2.2));
+}
+
+fn encode_srgb(color: vec3<f32>) -> vec3<f32> {
+    let curve = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(curve, color * 12.92, color <= vec3<f32>(0.0031308));
+}
+
+// A triangle covering the viewport.
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32) -> [[builtin(position)]] vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+fn tonemap(position: vec4<f32>, exposure: f32) -> vec4<f32> {
+    let hdr = textureLoad(t_hdr, vec2<i32>(position.xy), 0).rgb;
+
+    var color: vec3<f32>;
+    if (params.tonemapper == 1u) {
+        color = agx(hdr * exposure);
+    } else {
+        color = aces(hdr * exposure);
+    }
+
+    if (params.encode_srgb != 0u) {
+        color = encode_srgb(color);
+    }
+    return vec4<f32>(color, 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_main([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {
+    var exposure = exp2(params.exposure);
+    if (params.auto_exposure != 0u) {
+        exposure = exposure * EXPOSURE_KEY / max(luminance.average, 1e-4);
+    }
+    return tonemap(position, exposure);
+}
+
+// Without metering, where `luminance` is not bound.
+[[stage(fragment)]]
+fn fs_manual([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {
+    return tonemap(position, exp2(params.exposure));
+}
@@ -0,0 +1,315 @@
+use wgpu::util::DeviceExt;
+
+use crate::ocean::shaders::{self, Shader};
+use crate::ocean::OceanError;
+use crate::scene::PassInfo;
+use crate::tonemap::HDR_FORMAT;
+
+pub const UNDERWATER_SHADER: Shader =
+  Shader::new("src/underwater.wgsl", include_str!("underwater.wgsl"));
+
+// The water between the camera and what it sees once the camera dips below
+// the surface. How much light the water absorbs is `WaterMaterial::absorption`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Underwater {
+  pub enabled: bool,
+  // Per meter, how much light particles in the water scatter.
+  pub scattering: f32,
+  // Henyey-Greenstein asymmetry of that light. Water scatters mostly
+  // forwards, which makes the sun glow through the surface.
+  pub anisotropy: f32,
+  // How dark the line is where the water crosses the lens, 0 for none.
+  pub waterline: f32,
+}
+
+impl Default for Underwater {
+  fn default() -> Underwater {
+    Underwater {
+      enabled: true,
+      scattering: 0.04,
+      anisotropy: 0.8,
+      waterline: 0.6,
+    }
+  }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct UnderwaterUniform {
+  scattering: f32,
+  anisotropy: f32,
+  waterline: f32,
+  _padding: f32,
+}
+
+impl From<Underwater> for UnderwaterUniform {
+  fn from(underwater: Underwater) -> Self {
+    UnderwaterUniform {
+      scattering: underwater.scattering.max(0.0),
+      anisotropy: underwater.anisotropy.clamp(0.0, 0.99),
+      waterline: underwater.waterline.clamp(0.0, 1.0),
+      _padding: 0.0,
+    }
+  }
+}
+
+// Dims and scatters the HDR target where the view rays start under the
+// waves, once everything is drawn and fogged. Each pixel tests the surface
+// on its own, so that the frame splits along the waterline when it crosses
+// the lens.
+pub(crate) struct UnderwaterPass {
+  settings: Underwater,
+  uniform_buffer: wgpu::Buffer,
+  bind_group_layout: wgpu::BindGroupLayout,
+  bind_group: wgpu::BindGroup,
+  pipeline_layout: wgpu::PipelineLayout,
+  // Multiplies the frame by what is left of its light, then adds the light
+  // scattered towards the camera.
+  transmittance_pipeline: wgpu::RenderPipeline,
+  scattering_pipeline: wgpu::RenderPipeline,
+  pass_info: PassInfo,
+}
+
+impl UnderwaterPass {
+  // `scene_depth_view` is the depth everything is drawn with, see
+  // `scene::create_depth_texture`.
+  pub fn new(
+    device: &wgpu::Device,
+    camera_bind_group_layout: &wgpu::BindGroupLayout,
+    ocean_bind_group_layout: &wgpu::BindGroupLayout,
+    environment_bind_group_layout: &wgpu::BindGroupLayout,
+    pass_info: PassInfo,
+    scene_depth_view: &wgpu::TextureView,
+    settings: Underwater,
+  ) -> UnderwaterPass {
+    let multisampled = pass_info.sample_count > 1;
+    let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Underwater Buffer"),
+      contents: bytemuck::cast_slice(&[UnderwaterUniform::from(settings)]),
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    });
+
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+      label: Some("underwater_bind_group_layout"),
+      entries: &[
+        wgpu::BindGroupLayoutEntry {
+          // Each entry point reads its own binding.
+          binding: if multisampled { 0 } else { 1 },
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Texture {
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Depth,
+            multisampled,
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 2,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        },
+      ],
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+      label: Some("Underwater Pipeline Layout"),
+      bind_group_layouts: &[
+        camera_bind_group_layout,
+        ocean_bind_group_layout,
+        &bind_group_layout,
+        environment_bind_group_layout,
+      ],
+      push_constant_ranges: &[],
+    });
+
+    let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+      label: Some("Underwater shader"),
+      source: wgpu::ShaderSource::Wgsl(UNDERWATER_SHADER.source()),
+    });
+    let [transmittance_pipeline, scattering_pipeline] =
+      Self::create_pipelines(device, &pipeline_layout, &shader, multisampled);
+    let bind_group = Self::create_bind_group(
+      device,
+      &bind_group_layout,
+      pass_info,
+      scene_depth_view,
+      &uniform_buffer,
+    );
+
+    UnderwaterPass {
+      settings,
+      uniform_buffer,
+      bind_group_layout,
+      bind_group,
+      pipeline_layout,
+      transmittance_pipeline,
+      scattering_pipeline,
+      pass_info,
+    }
+  }
+
+  pub fn resize(&mut self, device: &wgpu::Device, scene_depth_view: &wgpu::TextureView) {
+    self.bind_group = Self::create_bind_group(
+      device,
+      &self.bind_group_layout,
+      self.pass_info,
+      scene_depth_view,
+      &self.uniform_buffer,
+    );
+  }
+
+  pub fn settings(&self) -> Underwater {
+    self.settings
+  }
+
+  pub fn set_settings(&mut self, queue: &wgpu::Queue, settings: Underwater) {
+    self.settings = settings;
+    queue.write_buffer(
+      &self.uniform_buffer,
+      0,
+      bytemuck::cast_slice(&[UnderwaterUniform::from(settings)]),
+    );
+  }
+
+  pub async fn reload(
+    &mut self,
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+  ) -> Result<(), OceanError> {
+    let [transmittance_pipeline, scattering_pipeline] = shaders::try_create(device, || {
+      Self::create_pipelines(
+        device,
+        &self.pipeline_layout,
+        shader,
+        self.pass_info.sample_count > 1,
+      )
+    })
+    .await?;
+    self.transmittance_pipeline = transmittance_pipeline;
+    self.scattering_pipeline = scattering_pipeline;
+    Ok(())
+  }
+
+  pub fn draw(
+    &self,
+    encoder: &mut wgpu::CommandEncoder,
+    hdr_view: &wgpu::TextureView,
+    camera_bind_group: &wgpu::BindGroup,
+    ocean_bind_group: &wgpu::BindGroup,
+    environment_bind_group: &wgpu::BindGroup,
+  ) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some("Underwater Pass"),
+      color_attachments: &[wgpu::RenderPassColorAttachment {
+        view: hdr_view,
+        resolve_target: None,
+        ops: wgpu::Operations {
+          load: wgpu::LoadOp::Load,
+          store: true,
+        },
+      }],
+      depth_stencil_attachment: None,
+    });
+    render_pass.set_bind_group(0, camera_bind_group, &[]);
+    render_pass.set_bind_group(1, ocean_bind_group, &[]);
+    render_pass.set_bind_group(2, &self.bind_group, &[]);
+    render_pass.set_bind_group(3, environment_bind_group, &[]);
+    for pipeline in [&self.transmittance_pipeline, &self.scattering_pipeline] {
+      render_pass.set_pipeline(pipeline);
+      render_pass.draw(0..3, 0..1);
+    }
+  }
+
+  fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    pass_info: PassInfo,
+    scene_depth_view: &wgpu::TextureView,
+    uniform_buffer: &wgpu::Buffer,
+  ) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+      label: Some("Underwater bind group"),
+      layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: if pass_info.sample_count > 1 { 0 } else { 1 },
+          resource: wgpu::BindingResource::TextureView(scene_depth_view),
+        },
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: uniform_buffer.as_entire_binding(),
+        },
+      ],
+    })
+  }
+
+  fn create_pipelines(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    multisampled: bool,
+  ) -> [wgpu::RenderPipeline; 2] {
+    // The alpha of the frame is kept.
+    let keep_alpha = wgpu::BlendComponent {
+      src_factor: wgpu::BlendFactor::Zero,
+      dst_factor: wgpu::BlendFactor::One,
+      operation: wgpu::BlendOperation::Add,
+    };
+    let multiply = wgpu::BlendComponent {
+      src_factor: wgpu::BlendFactor::Zero,
+      dst_factor: wgpu::BlendFactor::Src,
+      operation: wgpu::BlendOperation::Add,
+    };
+    let add = wgpu::BlendComponent {
+      src_factor: wgpu::BlendFactor::One,
+      dst_factor: wgpu::BlendFactor::One,
+      operation: wgpu::BlendOperation::Add,
+    };
+
+    [("fs_transmittance", multiply), ("fs_scattering", add)].map(|(entry_point, color)| {
+      let entry_point = if multisampled {
+        format!("{}_multisampled", entry_point)
+      } else {
+        entry_point.to_string()
+      };
+      device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Underwater Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+          module: shader,
+          entry_point: "vs_main",
+          buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+          module: shader,
+          entry_point: &entry_point,
+          targets: &[wgpu::ColorTargetState {
+            format: HDR_FORMAT,
+            blend: Some(wgpu::BlendState {
+              color,
+              alpha: keep_alpha,
+            }),
+            write_mask: wgpu::ColorWrites::ALL,
+          }],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+      })
+    })
+  }
+}
+
+#[test]
+fn test_underwater() {
+  // Matches the layout of `UnderwaterUniform` in the shader.
+  assert_eq!(std::mem::size_of::<UnderwaterUniform>(), 16);
+}
@@ -0,0 +1,220 @@
+// The water between the camera and what it sees, see `UnderwaterPass`.
+// Each pixel tests whether its view ray starts below the waves, light is
+// lost along the ones that do and the sun and sky coming down through the
+// surface scatter back into them.
+
+struct CameraUniform {
+    pos: vec3<f32>;
+    view_proj: mat4x4<f32>;
+    view: mat4x4<f32>;
+    proj: mat4x4<f32>;
+    inverse_view_proj: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+struct SkyUniform {
+    a: vec3<f32>;
+    b: vec3<f32>;
+    c: vec3<f32>;
+    d: vec3<f32>;
+    e: vec3<f32>;
+    zenith: vec3<f32>;
+};
+
+struct LightingUniform {
+    sun_direction: vec3<f32>;
+    sun_color: vec3<f32>;
+    sky_color: vec3<f32>;
+    sky: SkyUniform;
+    fog_density: f32;
+    fog_height_falloff: f32;
+    fog_anisotropy: f32;
+};
+
+[[group(0), binding(1)]]
+var<uniform> lighting: LightingUniform;
+
+struct ShadingUniform {
+    scatter_color: vec3<f32>;
+    ior: f32;
+    absorption: vec3<f32>;
+    subsurface_strength: f32;
+    foam_albedo: vec3<f32>;
+    foam_roughness: f32;
+    cascade_weights: vec3<f32>;
+    lod_scale: f32;
+    slope_variance: f32;
+    reflection_mode: u32;
+    foam_coverage: f32;
+};
+
+[[group(0), binding(2)]]
+var<uniform> shading: ShadingUniform;
+
+[[group(1), binding(0)]]
+var t_displacement_0: texture_2d<f32>;
+
+[[group(1), binding(2)]]
+var s_derivatives: sampler;
+
+[[group(1), binding(3)]]
+var t_displacement_1: texture_2d<f32>;
+
+[[group(1), binding(5)]]
+var t_displacement_2: texture_2d<f32>;
+
+[[group(2), binding(0)]]
+var t_depth_multisampled: texture_depth_multisampled_2d;
+
+[[group(2), binding(1)]]
+var t_depth: texture_depth_2d;
+
+// The `Underwater` settings.
+struct UnderwaterUniform {
+    scattering: f32;
+    anisotropy: f32;
+    waterline: f32;
+};
+
+[[group(2), binding(2)]]
+var<uniform> underwater: UnderwaterUniform;
+
+[[group(3), binding(0)]]
+var t_environment: texture_cube<f32>;
+
+[[group(3), binding(1)]]
+var s_environment: sampler;
+
+let LENGTH_SCALE = vec3<f32>(500.0, 85.0, 10.0);
+let PI: f32 = 3.14159265359;
+let INFINITE = 100000.0;
+// Width of the line where the water crosses the lens.
+let WATERLINE_PIXELS = 3.0;
+
+fn screen_to_world(ndc: vec3<f32>) -> vec3<f32> {
+    let w = camera.inverse_view_proj * vec4<f32>(ndc, 1.0);
+    return w.xyz / w.w;
+}
+
+// Height of the water over `xz`, leaving out the horizontal part of the
+// displacement, as the surface is drawn right next to the camera. Matches
+// `surface_height` in ocean_shader.wgsl.
+fn surface_height(xz: vec2<f32>) -> f32 {
+    let weights = shading.cascade_weights;
+    return textureSampleLevel(t_displacement_0, s_derivatives, xz / LENGTH_SCALE.x, 0.0).y * weights.x
+        + textureSampleLevel(t_displacement_1, s_derivatives, xz / LENGTH_SCALE.y, 0.0).y * weights.y
+        + textureSampleLevel(t_displacement_2, s_derivatives, xz / LENGTH_SCALE.z, 0.0).y * weights.z;
+}
+
+// Direction of the ray `i` going through a surface of normal `n`, `eta`
+// being the index of refraction it leaves over the one it enters.
+fn refraction(i: vec3<f32>, n: vec3<f32>, eta: f32) -> vec3<f32> {
+    let cos_i = dot(n, i);
+    let k = 1.0 - eta * eta * (1.0 - cos_i * cos_i);
+    return eta * i - (eta * cos_i + sqrt(max(k, 0.0))) * n;
+}
+
+fn henyey_greenstein(cos_theta: f32, g: f32) -> f32 {
+    let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    return (1.0 - g * g) / (4.0 * PI * denominator * sqrt(denominator));
+}
+
+// Light scattered towards the camera along `dir` over `distance`, starting
+// `depth` under the surface. The sun and sky light coming down is dimmed by
+// the water above as if it went straight down.
+fn in_scattering(dir: vec3<f32>, distance: f32, depth: f32, extinction: vec3<f32>) -> vec3<f32> {
+    let l = normalize(lighting.sun_direction);
+    var sunlight = vec3<f32>(0.0);
+    if (l.y > 0.0) {
+        // Bent down by the surface.
+        let sun = refraction(-l, vec3<f32>(0.0, 1.0, 0.0), 1.0 / shading.ior);
+        sunlight = lighting.sun_color * henyey_greenstein(dot(-sun, dir), underwater.anisotropy);
+    }
+    // The sky lights the water from every direction above, and the water
+    // scatters it evenly.
+    let blurred = f32(textureNumLevels(t_environment) - 1);
+    let skylight = textureSampleLevel(t_environment, s_environment, vec3<f32>(0.0, 1.0, 0.0), blurred).rgb * 0.25;
+
+    // Rays going up reach the surface, taken as flat, and get closer to the
+    // light on the way.
+    var path = distance;
+    if (dir.y > 0.0) {
+        path = min(path, depth / dir.y);
+    }
+    let rate = max(extinction * (1.0 - dir.y), vec3<f32>(1e-4));
+    let integral = (vec3<f32>(1.0) - exp(-rate * path)) / rate;
+    return underwater.scattering * (sunlight + skylight) * exp(-extinction * depth) * integral;
+}
+
+struct Medium {
+    // Of the light coming from what the pixel shows.
+    transmittance: vec3<f32>;
+    scattering: vec3<f32>;
+};
+
+// `size` is the frame's.
+fn medium(position: vec4<f32>, size: vec2<f32>, depth: f32) -> Medium {
+    let ndc = vec2<f32>(position.x / size.x * 2.0 - 1.0, 1.0 - position.y / size.y * 2.0);
+    // The view ray starts on the near plane, which the waterline crosses.
+    let near = screen_to_world(vec3<f32>(ndc, 0.0));
+    let submersion = surface_height(near.xz) - near.y;
+    let from_waterline = abs(submersion) / max(fwidth(submersion), 1e-6);
+    let waterline = 1.0 - underwater.waterline * (1.0 - smoothStep(0.0, WATERLINE_PIXELS, from_waterline));
+
+    var out: Medium;
+    out.transmittance = vec3<f32>(waterline);
+    out.scattering = vec3<f32>(0.0);
+    if (submersion <= 0.0) {
+        return out;
+    }
+
+    // Nothing drawn, the view ray goes down into the depths.
+    var distance = INFINITE;
+    if (depth < 1.0) {
+        distance = length(screen_to_world(vec3<f32>(ndc, depth)) - near);
+    }
+    let dir = normalize(screen_to_world(vec3<f32>(ndc, 1.0)) - near);
+    let extinction = shading.absorption + vec3<f32>(underwater.scattering);
+    out.transmittance = exp(-extinction * distance) * waterline;
+    out.scattering = in_scattering(dir, distance, submersion, extinction) * waterline;
+    return out;
+}
+
+// A triangle covering the viewport.
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32) -> [[builtin(position)]] vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+// Multiplied into the frame, then the scattering is added. Only the first
+// sample is read, the water blurs edges anyway.
+[[stage(fragment)]]
+fn fs_transmittance_multisampled([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {
+    let size = vec2<f32>(textureDimensions(t_depth_multisampled));
+    let depth = textureLoad(t_depth_multisampled, vec2<i32>(position.xy), 0);
+    return vec4<f32>(medium(position, size, depth).transmittance, 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_scattering_multisampled([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {
+    let size = vec2<f32>(textureDimensions(t_depth_multisampled));
+    let depth = textureLoad(t_depth_multisampled, vec2<i32>(position.xy), 0);
+    return vec4<f32>(medium(position, size, depth).scattering, 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_transmittance([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {
+    let size = vec2<f32>(textureDimensions(t_depth));
+    let depth = textureLoad(t_depth, vec2<i32>(position.xy), 0);
+    return vec4<f32>(medium(position, size, depth).transmittance, 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_scattering([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {
+    let size = vec2<f32>(textureDimensions(t_depth));
+    let depth = textureLoad(t_depth, vec2<i32>(position.xy), 0);
+    return vec4<f32>(medium(position, size, depth).scattering, 1.0);
+}